@@ -1,7 +1,100 @@
-use serde::Deserialize;
+use std::{collections::HashMap, path::Path};
+
+use bincode::Options;
+use enum_map::{Enum, EnumMap};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// The kinds of airports that are loaded and can be shown on the map, from most to least important
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Enum)]
+pub enum AirportCategory {
+    Large,
+    Medium,
+    Small,
+    Heliport,
+}
+
+impl AirportCategory {
+    /// Parses the `airport_type` field of an [`Airport`]
+    pub fn from_type(airport_type: &str) -> Option<Self> {
+        match airport_type {
+            "large_airport" => Some(AirportCategory::Large),
+            "medium_airport" => Some(AirportCategory::Medium),
+            "small_airport" => Some(AirportCategory::Small),
+            "heliport" => Some(AirportCategory::Heliport),
+            _ => None,
+        }
+    }
+
+    /// A human readable name for this category
+    pub fn name(self) -> &'static str {
+        match self {
+            AirportCategory::Large => "Large",
+            AirportCategory::Medium => "Medium",
+            AirportCategory::Small => "Small",
+            AirportCategory::Heliport => "Heliports",
+        }
+    }
+
+    /// The minimum [`crate::TileView::get_zoom`] where airports of this category are drawn, so
+    /// that small airfields don't clutter the map when zoomed out
+    pub fn min_zoom(self) -> f64 {
+        match self {
+            AirportCategory::Large => 0.0,
+            AirportCategory::Medium => 15.0,
+            AirportCategory::Small => 18.0,
+            AirportCategory::Heliport => 20.0,
+        }
+    }
+}
+
+/// Which categories of airports the user wants to see
+pub type AirportFilter = EnumMap<AirportCategory, bool>;
+
+/// How far the map must be zoomed in before airports are labeled, set in the `[airport-labels]`
+/// table of the config file
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct AirportLabelSettings {
+    /// Airport codes are only drawn once [`crate::TileView::get_zoom`] reaches this value
+    pub code_min_zoom: f64,
+    /// Airport names are drawn after their codes once [`crate::TileView::get_zoom`] reaches this
+    /// value
+    pub name_min_zoom: f64,
+}
+
+impl Default for AirportLabelSettings {
+    fn default() -> Self {
+        Self {
+            code_min_zoom: 19.0,
+            name_min_zoom: 21.0,
+        }
+    }
+}
+
+/// User settings controlling which airports are drawn and how
+#[derive(Debug, Clone)]
+pub struct AirportSettings {
+    /// The categories of airports to draw
+    pub categories: AirportFilter,
+    pub labels: AirportLabelSettings,
+    /// How much larger than normal airport icons are drawn, independent of the zoom, between
+    /// [`crate::MIN_MARKER_SCALE`] and [`crate::MAX_MARKER_SCALE`]
+    pub icon_scale: f64,
+}
+
+impl Default for AirportSettings {
+    fn default() -> Self {
+        Self {
+            categories: enum_map::enum_map! { _ => true },
+            labels: AirportLabelSettings::default(),
+            icon_scale: 1.0,
+        }
+    }
+}
 
 /// Represents an Airport that will be deserialized
-#[derive(Debug, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Airport {
     pub id: u32,
     pub ident: String,
@@ -21,74 +114,1075 @@ pub struct Airport {
     pub gps_code: String,
     pub iata_code: String,
     pub local_code: String,
+    /// Parsed from `airport_type` after loading
+    #[serde(skip)]
+    pub category: Option<AirportCategory>,
 }
 
-/// Deserializes a Vec<Airport> from a &[u8] using serde Postcard
+impl Airport {
+    /// The code travelers know this airport by. Uses the IATA code if it has one, otherwise the
+    /// ICAO or local identifier
+    pub fn code(&self) -> &str {
+        match self.iata_code.trim() {
+            "" => &self.ident,
+            iata => iata,
+        }
+    }
+}
+
+/// Deserializes the airports written by [`airports_to_bytes`], leaving out airports of types which
+/// aren't an [`AirportCategory`].
+///
+/// Truncated or corrupt input returns an error
 pub fn airports_from_bytes(bytes: &[u8]) -> Result<Vec<Airport>, Box<bincode::ErrorKind>> {
-    // Deserialize all of the airports
-    let airports: Vec<Airport> = bincode::deserialize(bytes)?;
+    //The same options as `bincode::deserialize`, but never reading more than the input holds so
+    //that a corrupt length can't cause a huge allocation
+    let airports: Vec<Airport> = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(bytes.len() as u64)
+        .deserialize(bytes)?;
 
     let airports = airports
         .into_iter()
-        .filter(|airport| {
-            matches!(
-                airport.airport_type.as_str(),
-                "medium_airport" | "large_airport"
-            )
+        .filter_map(|mut airport| {
+            airport.category = Some(AirportCategory::from_type(&airport.airport_type)?);
+            Some(airport)
         })
         .collect();
 
     Ok(airports)
 }
 
+/// Serializes `airports` into the format of `assets/data/airports.bin`.
+///
+/// The file is `Vec<Airport>` encoded with bincode 1's default options, so every integer is
+/// little endian and fixed size:
+///
+/// - The number of airports as a `u64`
+/// - Each airport's fields in the order they are declared in [`Airport`], skipping `category`:
+///   - `id` as a `u32`
+///   - `ident`, `airport_type`, `name` as strings
+///   - `latitude`, `longitude` as `f32`s
+///   - `elevation` as an `i32`
+///   - `continent`, `country_name`, `iso_country`, `region_name`, `iso_region`, `local_region`,
+///     `municipality` as strings
+///   - `scheduled_service` as one byte, 0 or 1
+///   - `gps_code`, `iata_code`, `local_code` as strings
+///
+/// Strings are their length in bytes as a `u64` followed by their UTF-8 bytes. The file can be
+/// regenerated from OurAirports' data with the `build_airports` binary
+pub fn airports_to_bytes(airports: &[Airport]) -> Result<Vec<u8>, Box<bincode::ErrorKind>> {
+    bincode::serialize(airports)
+}
+
+/// Fills in the `country_name` and `region_name` of `airports` using OurAirports'
+/// `countries.csv` and `regions.csv`, which map the `code` column to the `name` column.
+///
+/// Airports with codes missing from the files are left unchanged
+pub fn add_region_names(
+    airports: &mut [Airport],
+    countries_csv: &str,
+    regions_csv: &str,
+) -> Result<(), AirportLoadError> {
+    let countries = names_by_code(countries_csv)?;
+    let regions = names_by_code(regions_csv)?;
+    for airport in airports {
+        if let Some(name) = countries.get(&airport.iso_country) {
+            airport.country_name = name.clone();
+        }
+        if let Some(name) = regions.get(&airport.iso_region) {
+            airport.region_name = name.clone();
+        }
+    }
+    Ok(())
+}
+
+/// Reads the `code` and `name` columns of a CSV file into a map
+fn names_by_code(text: &str) -> Result<HashMap<String, String>, AirportLoadError> {
+    let mut records = parse_csv(text).into_iter();
+    let header = records.next().map(|(_, header)| header).unwrap_or_default();
+    let column = |name: &'static str| {
+        header
+            .iter()
+            .position(|column| column.trim() == name)
+            .ok_or(AirportLoadError::MissingColumn(name))
+    };
+    let (code, name) = (column("code")?, column("name")?);
+
+    Ok(records
+        .filter_map(|(_, record)| {
+            Some((
+                record.get(code)?.trim().to_owned(),
+                record.get(name)?.trim().to_owned(),
+            ))
+        })
+        .collect())
+}
+
+/// The file formats airports can be loaded from
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AirportFormat {
+    /// The bincode file bundled with the app, read by [`airports_from_bytes`]
+    Binary,
+    /// A CSV file in the format of OurAirports' `airports.csv`, read by [`airports_from_csv`]
+    Csv,
+    /// A GeoJSON feature collection of points, read by [`airports_from_geojson`]
+    GeoJson,
+}
+
+impl AirportFormat {
+    /// Parses a format name as written on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "bin" | "binary" => Some(AirportFormat::Binary),
+            "csv" => Some(AirportFormat::Csv),
+            "geojson" | "json" => Some(AirportFormat::GeoJson),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format of `path` from its extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Self::from_name(path.extension()?.to_str()?)
+    }
+}
+
+/// An error that prevents an airport file from being loaded at all
+#[derive(Error, Debug)]
+pub enum AirportLoadError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("unknown file format, expected a .bin, .csv or .geojson file")]
+    UnknownFormat,
+    #[error("{0}")]
+    Binary(#[from] Box<bincode::ErrorKind>),
+    #[error("missing the `{0}` column")]
+    MissingColumn(&'static str),
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    #[error("expected a GeoJSON FeatureCollection")]
+    NotFeatureCollection,
+}
+
+/// A row of an airport file that was skipped because it is invalid
+#[derive(Clone, Debug, PartialEq)]
+pub struct RowError {
+    /// The line the row starts on for CSV files, or the index of the feature starting from one for
+    /// GeoJSON files
+    pub row: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for RowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "row {}: {}", self.row, self.message)
+    }
+}
+
+/// The airports read from a file, and the rows that had to be skipped
+#[derive(Debug, Default)]
+pub struct LoadedAirports {
+    pub airports: Vec<Airport>,
+    pub row_errors: Vec<RowError>,
+}
+
+/// The fields every airport row must have
+const REQUIRED_FIELDS: [&str; 5] = ["ident", "type", "name", "latitude_deg", "longitude_deg"];
+
+/// Loads the airports in the file at `path`, guessing the format from the file extension if
+/// `format` is `None`
+pub fn load_airports(
+    path: &Path,
+    format: Option<AirportFormat>,
+) -> Result<LoadedAirports, AirportLoadError> {
+    let format = format
+        .or_else(|| AirportFormat::from_path(path))
+        .ok_or(AirportLoadError::UnknownFormat)?;
+    match format {
+        AirportFormat::Binary => Ok(LoadedAirports {
+            airports: airports_from_bytes(&std::fs::read(path)?)?,
+            row_errors: Vec::new(),
+        }),
+        AirportFormat::Csv => airports_from_csv(&std::fs::read_to_string(path)?),
+        AirportFormat::GeoJson => airports_from_geojson(&std::fs::read_to_string(path)?),
+    }
+}
+
+/// Reads airports from a CSV file with a header row, using the column names of OurAirports'
+/// `airports.csv`.
+///
+/// Only `ident`, `type`, `name`, `latitude_deg` and `longitude_deg` are required. Rows of types
+/// which aren't an [`AirportCategory`] are left out, like in the bundled data
+pub fn airports_from_csv(text: &str) -> Result<LoadedAirports, AirportLoadError> {
+    let mut records = parse_csv(text).into_iter();
+    let header = match records.next() {
+        Some((_, header)) => header,
+        None => return Err(AirportLoadError::MissingColumn(REQUIRED_FIELDS[0])),
+    };
+    if let Some(missing) = REQUIRED_FIELDS
+        .iter()
+        .find(|&&name| !header.iter().any(|column| column.trim() == name))
+    {
+        return Err(AirportLoadError::MissingColumn(missing));
+    }
+
+    let mut loaded = LoadedAirports::default();
+    for (line, record) in records {
+        if record.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+        let field = |name: &str| {
+            let index = header.iter().position(|column| column.trim() == name)?;
+            record.get(index).map(String::as_str)
+        };
+        match airport_from_fields(field) {
+            Ok(Some(airport)) => loaded.airports.push(airport),
+            Ok(None) => {}
+            Err(message) => loaded.row_errors.push(RowError { row: line, message }),
+        }
+    }
+    Ok(loaded)
+}
+
+/// Reads airports from a GeoJSON `FeatureCollection` of `Point` features.
+///
+/// Each feature's properties use the same names as the columns read by [`airports_from_csv`],
+/// except that the location comes from the point instead of `latitude_deg` and `longitude_deg`
+pub fn airports_from_geojson(text: &str) -> Result<LoadedAirports, AirportLoadError> {
+    let json: serde_json::Value = serde_json::from_str(text)?;
+    if json["type"] != "FeatureCollection" {
+        return Err(AirportLoadError::NotFeatureCollection);
+    }
+    let features = json["features"]
+        .as_array()
+        .ok_or(AirportLoadError::NotFeatureCollection)?;
+
+    let mut loaded = LoadedAirports::default();
+    for (i, feature) in features.iter().enumerate() {
+        let row = i + 1;
+        let geometry = &feature["geometry"];
+        let coordinates = match geometry["coordinates"].as_array() {
+            Some(coordinates) if geometry["type"] == "Point" && coordinates.len() >= 2 => {
+                coordinates
+            }
+            _ => {
+                loaded.row_errors.push(RowError {
+                    row,
+                    message: "expected a Point geometry".to_owned(),
+                });
+                continue;
+            }
+        };
+
+        //Turn every property into text so that numbers and strings are parsed the same way as CSV
+        let mut fields: HashMap<&str, String> = HashMap::new();
+        if let Some(properties) = feature["properties"].as_object() {
+            for (name, value) in properties {
+                let value = match value {
+                    serde_json::Value::String(text) => text.clone(),
+                    serde_json::Value::Bool(true) => "yes".to_owned(),
+                    serde_json::Value::Bool(false) => "no".to_owned(),
+                    serde_json::Value::Null => String::new(),
+                    other => other.to_string(),
+                };
+                fields.insert(name.as_str(), value);
+            }
+        }
+        //GeoJSON positions are longitude first
+        fields.insert("longitude_deg", coordinates[0].to_string());
+        fields.insert("latitude_deg", coordinates[1].to_string());
+
+        match airport_from_fields(|name| fields.get(name).map(String::as_str)) {
+            Ok(Some(airport)) => loaded.airports.push(airport),
+            Ok(None) => {}
+            Err(message) => loaded.row_errors.push(RowError { row, message }),
+        }
+    }
+    Ok(loaded)
+}
+
+/// Builds an airport from the fields of one row, looked up by their OurAirports column names.
+///
+/// Returns `None` for airports of types that aren't shown on the map, or a message describing the
+/// first invalid field
+fn airport_from_fields<'a>(
+    field: impl Fn(&str) -> Option<&'a str>,
+) -> Result<Option<Airport>, String> {
+    let text = |name: &str| field(name).unwrap_or("").trim().to_owned();
+    let required = |name: &str| match text(name) {
+        value if value.is_empty() => Err(format!("missing `{}`", name)),
+        value => Ok(value),
+    };
+    let number = |name: &str, min: f32, max: f32| {
+        let value = required(name)?;
+        value
+            .parse::<f32>()
+            .ok()
+            .filter(|number| (min..=max).contains(number))
+            .ok_or_else(|| format!("invalid `{}` {:?}", name, value))
+    };
+
+    let ident = required("ident")?;
+    let airport_type = required("type")?;
+    let name = required("name")?;
+    let latitude = number("latitude_deg", -90.0, 90.0)?;
+    let longitude = number("longitude_deg", -180.0, 180.0)?;
+    let elevation = match text("elevation_ft") {
+        value if value.is_empty() => 0,
+        value => value
+            .parse::<f32>()
+            .map(|feet| feet.round() as i32)
+            .map_err(|_| format!("invalid `elevation_ft` {:?}", value))?,
+    };
+    let id = match text("id") {
+        value if value.is_empty() => 0,
+        value => value
+            .parse()
+            .map_err(|_| format!("invalid `id` {:?}", value))?,
+    };
+
+    let category = match AirportCategory::from_type(&airport_type) {
+        Some(category) => category,
+        None => return Ok(None),
+    };
+    Ok(Some(Airport {
+        id,
+        ident,
+        airport_type,
+        name,
+        latitude,
+        longitude,
+        elevation,
+        continent: text("continent"),
+        country_name: text("country_name"),
+        iso_country: text("iso_country"),
+        region_name: text("region_name"),
+        iso_region: text("iso_region"),
+        local_region: text("local_region"),
+        municipality: text("municipality"),
+        scheduled_service: text("scheduled_service").eq_ignore_ascii_case("yes"),
+        gps_code: text("gps_code"),
+        iata_code: text("iata_code"),
+        local_code: text("local_code"),
+        category: Some(category),
+    }))
+}
+
+/// Splits CSV text into records of fields, along with the line each record starts on.
+///
+/// Fields may be quoted, in which case they can contain commas, newlines and doubled quotes
+fn parse_csv(text: &str) -> Vec<(usize, Vec<String>)> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut record_line = 1;
+
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, in_quotes) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', _) => in_quotes = !in_quotes,
+            (',', false) => record.push(std::mem::take(&mut field)),
+            ('\r', false) => {}
+            ('\n', false) => {
+                record.push(std::mem::take(&mut field));
+                records.push((record_line, std::mem::take(&mut record)));
+                line += 1;
+                record_line = line;
+            }
+            (c, _) => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push((record_line, record));
+    }
+    records
+}
+
+/// The most airports [`AirportIndex::suggest`] returns for the search box
+pub const MAX_SUGGESTIONS: usize = 10;
+
+/// Allows looking up airports by their ICAO, IATA, GPS, or local codes, and searching them by code
+/// prefix or by name
+pub struct AirportIndex {
+    /// Maps upper case codes to indices in the airport list
+    codes: HashMap<String, usize>,
+    /// The entries of `codes` sorted by code, so that codes with the same prefix are next to each
+    /// other
+    sorted_codes: Vec<(String, usize)>,
+    /// The upper case name and municipality of each airport
+    names: Vec<String>,
+    categories: Vec<Option<AirportCategory>>,
+}
+
+impl AirportIndex {
+    /// Builds an index over `airports`. Indices returned by [`AirportIndex::find`] refer to this
+    /// slice
+    pub fn new(airports: &[Airport]) -> Self {
+        let mut codes = HashMap::with_capacity(airports.len() * 2);
+        for (i, airport) in airports.iter().enumerate() {
+            let airport_codes = [
+                &airport.ident,
+                &airport.gps_code,
+                &airport.iata_code,
+                &airport.local_code,
+            ];
+            for code in airport_codes {
+                let code = code.trim();
+                if !code.is_empty() {
+                    //Prefer the first airport with a given code, which matters for local codes
+                    codes.entry(code.to_uppercase()).or_insert(i);
+                }
+            }
+        }
+        let mut sorted_codes: Vec<_> = codes.iter().map(|(code, &i)| (code.clone(), i)).collect();
+        sorted_codes.sort_unstable();
+        let names = airports
+            .iter()
+            .map(|airport| format!("{} {}", airport.name, airport.municipality).to_uppercase())
+            .collect();
+        let categories = airports.iter().map(|airport| airport.category).collect();
+        Self {
+            codes,
+            sorted_codes,
+            names,
+            categories,
+        }
+    }
+
+    /// Finds the airport with `code`, ignoring case and surrounding whitespace
+    pub fn find(&self, code: &str) -> Option<usize> {
+        self.codes.get(&code.trim().to_uppercase()).copied()
+    }
+
+    /// Returns up to `limit` airports matching `query`, best first, ignoring case.
+    ///
+    /// An exact code comes first, then codes starting with `query`, then airports whose name or
+    /// city has a word starting with `query`, then ones containing it anywhere. Ties go to larger
+    /// airports
+    pub fn suggest(&self, query: &str, limit: usize) -> Vec<usize> {
+        let query = query.trim().to_uppercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        //The best rank each matching airport has reached, lower is better
+        let mut ranks: HashMap<usize, u8> = HashMap::new();
+        let mut add = |i: usize, rank: u8| {
+            let best = ranks.entry(i).or_insert(rank);
+            *best = (*best).min(rank);
+        };
+
+        let start = self
+            .sorted_codes
+            .partition_point(|(code, _)| code.as_str() < query.as_str());
+        for (code, i) in self.sorted_codes[start..]
+            .iter()
+            .take_while(|(code, _)| code.starts_with(&query))
+        {
+            add(*i, if *code == query { 0 } else { 1 });
+        }
+        for (i, name) in self.names.iter().enumerate() {
+            if let Some(position) = name.find(&query) {
+                let word_start = !name[..position].ends_with(char::is_alphanumeric);
+                add(i, if word_start { 2 } else { 3 });
+            }
+        }
+
+        let mut matches: Vec<_> = ranks.into_iter().collect();
+        matches.sort_unstable_by_key(|&(i, rank)| {
+            let category = self.categories[i];
+            (rank, category.is_none(), category, i)
+        });
+        matches.into_iter().take(limit).map(|(i, _)| i).collect()
+    }
+}
+
+/// The airports suggested below the search box while typing, and the one picked with the arrow
+/// keys
+#[derive(Debug, Default)]
+pub struct AirportSuggestions {
+    matches: Vec<usize>,
+    highlighted: Option<usize>,
+}
+
+impl AirportSuggestions {
+    /// Replaces the suggestions with the best matches for `query`
+    pub fn update(&mut self, index: &AirportIndex, query: &str) {
+        self.matches = index.suggest(query, MAX_SUGGESTIONS);
+        self.highlighted = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.matches.clear();
+        self.highlighted = None;
+    }
+
+    /// The suggested airports, as indices into the airport list
+    pub fn matches(&self) -> &[usize] {
+        &self.matches
+    }
+
+    /// Returns the row of the suggestion picked with the arrow keys
+    pub fn highlighted_row(&self) -> Option<usize> {
+        self.highlighted
+    }
+
+    /// Returns the airport picked with the arrow keys
+    pub fn highlighted(&self) -> Option<usize> {
+        self.highlighted.map(|row| self.matches[row])
+    }
+
+    /// Moves the highlight down one row, or up one row if `up` is set. Moving up from the first row
+    /// removes the highlight
+    pub fn move_highlight(&mut self, up: bool) {
+        let last = match self.matches.len().checked_sub(1) {
+            Some(last) => last,
+            None => return,
+        };
+        self.highlighted = match (self.highlighted, up) {
+            (None, false) => Some(0),
+            (None, true) | (Some(0), true) => None,
+            (Some(row), true) => Some(row - 1),
+            (Some(row), false) => Some((row + 1).min(last)),
+        };
+    }
+}
+
+/// The airports the user clicked on to draw a route between, stored as indices into the airport
+/// list
+#[derive(Debug, Default)]
+pub struct AirportRoute {
+    airports: Vec<usize>,
+}
+
+impl AirportRoute {
+    /// Adds an airport to the route. Selecting a third airport starts a new route from it
+    pub fn select(&mut self, airport: usize) {
+        if self.airports.len() >= 2 {
+            self.airports.clear();
+        }
+        self.airports.push(airport);
+    }
+
+    /// Returns the endpoints of the route, if both have been selected
+    pub fn endpoints(&self) -> Option<(usize, usize)> {
+        match self.airports[..] {
+            [a, b] => Some((a, b)),
+            _ => None,
+        }
+    }
+}
+
 /// Useful functions for rendering airports on the map
 pub mod airport_renderer {
-    use conrod_core::{Positionable, Sizeable, UiCell, Widget};
+    use conrod_core::{
+        widget::{Line, Text},
+        Colorable, Positionable, Sizeable, UiCell, Widget,
+    };
+    use glam::DVec2;
     use num::Float;
 
-    use crate::Airport;
+    use crate::{Airport, AirportRoute, AirportSettings};
+
+    /// The number of line segments used to draw a great circle route
+    const ROUTE_SEGMENTS: usize = 64;
+
+    /// The font size of airport code labels
+    const LABEL_FONT_SIZE: u32 = 11;
+
+    /// The space in pixels between an airport icon and its label
+    const LABEL_GAP: f64 = 2.0;
+
+    /// How close in pixels the cursor has to be to the middle of an airport to hover it, for icons
+    /// smaller than this
+    const MIN_HOVER_RADIUS: f64 = 8.0;
+
+    /// The state needed to render airports.
+    ///
+    /// Implemented as a struct to reduce the number of parameters passed to [`draw`]
+    pub struct AirportRendererState<'a> {
+        pub airports: &'a [Airport],
+        pub view: &'a crate::map::TileView,
+        pub ids: &'a mut crate::Ids,
+        pub image_id: crate::ImageId,
+        pub font: conrod_core::text::font::Id,
+        pub settings: &'a AirportSettings,
+        pub route: &'a mut AirportRoute,
+        /// The position of the cursor, in the same coordinates the airports are drawn in
+        pub cursor: Option<DVec2>,
+    }
 
     /// Draws all of the airports onto the map. Should be run before plane rendering, but after the
-    /// map tiles are rendered
-    pub fn draw(
-        airports: &[Airport],
-        view: &crate::map::TileView,
-        _display: &glium::Display,
-        ids: &mut crate::Ids,
-        image_id: crate::ImageId,
-        ui: &mut UiCell,
-    ) {
+    /// map tiles are rendered.
+    ///
+    /// Only airports in categories enabled in the settings are drawn, and only once zoomed in past
+    /// the category's minimum zoom. Labels are drawn beside the most important airports, on
+    /// whichever side doesn't overlap an icon or another label. Labels with no free side are
+    /// skipped.
+    ///
+    /// Clicking an airport adds it to the route, and the great circle route between the selected
+    /// airports is drawn.
+    ///
+    /// Returns the index of the airport under the cursor, if any
+    pub fn draw(state: AirportRendererState, ui: &mut UiCell) -> Option<usize> {
+        let AirportRendererState {
+            airports,
+            view,
+            ids,
+            image_id,
+            font,
+            settings,
+            route,
+            cursor,
+        } = state;
+
         let viewport = view.get_world_viewport(ui.win_w, ui.win_h);
 
         let zoom = view.get_zoom();
 
-        ids.airports
-            .resize(airports.len(), &mut ui.widget_id_generator());
-
-        let lat_top = crate::util::latitude_from_y(viewport.top_left.y.rem_euclid(1.0)) as f32;
+        let lat_top = crate::util::latitude_from_y(viewport.top_left.y.clamp(0.0, 1.0)) as f32;
         let lat_bottom =
-            crate::util::latitude_from_y(viewport.bottom_right.y.rem_euclid(1.0)) as f32;
+            crate::util::latitude_from_y(viewport.bottom_right.y.clamp(0.0, 1.0)) as f32;
         let long_left = crate::util::longitude_from_x(viewport.top_left.x.rem_euclid(1.0)) as f32;
         let long_right =
             crate::util::longitude_from_x(viewport.bottom_right.x.rem_euclid(1.0)) as f32;
 
-        for (i, airport) in airports.iter().enumerate() {
-            if (airport.latitude > lat_bottom && airport.latitude < lat_top)
-                && (airport.longitude > long_left && airport.longitude < long_right)
+        let mut visible: Vec<usize> = airports
+            .iter()
+            .enumerate()
+            .filter(|(_, airport)| {
+                airport
+                    .category
+                    .is_some_and(|c| settings.categories[c] && zoom >= c.min_zoom())
+            })
+            .filter(|(_, airport)| {
+                (airport.latitude > lat_bottom && airport.latitude < lat_top)
+                    && (airport.longitude > long_left && airport.longitude < long_right)
+            })
+            .map(|(i, _)| i)
+            .collect();
+        //Larger airports come first so that they win when labels overlap
+        visible.sort_by_key(|&i| airports[i].category);
+
+        ids.airports
+            .resize(visible.len(), &mut ui.widget_id_generator());
+
+        //The pixel position of each visible airport and the size of its icon
+        let mut positions = Vec::with_capacity(visible.len());
+        for (id_index, &i) in visible.iter().enumerate() {
+            let airport = &airports[i];
+            // Render airports
+            let world_x = crate::util::x_from_longitude(airport.longitude as f64);
+            let world_y = crate::util::y_from_latitude(airport.latitude as f64);
+
+            let pixel_x = crate::world_x_to_pixel_x(world_x, &viewport, ui.win_w);
+            let pixel_y = crate::world_y_to_pixel_y(world_y, &viewport, ui.win_h);
+
+            let size = 1.5.powf(zoom) / 100.0 * settings.icon_scale;
+            conrod_core::widget::Image::new(image_id.normal)
+                .x_y(pixel_x, pixel_y)
+                .w_h(size, size)
+                .set(ids.airports[id_index], ui);
+            positions.push((pixel_x, pixel_y, size));
+
+            if ui
+                .widget_input(ids.airports[id_index])
+                .clicks()
+                .left()
+                .next()
+                .is_some()
             {
-                // Render airports
-                let world_x = crate::util::x_from_longitude(airport.longitude as f64);
-                let world_y = crate::util::y_from_latitude(airport.latitude as f64);
-
-                let pixel_x = crate::world_x_to_pixel_x(world_x, &viewport, ui.win_w);
-                let pixel_y = crate::world_y_to_pixel_y(world_y, &viewport, ui.win_h);
-
-                let size = 1.5.powf(zoom) / 100.0;
-                conrod_core::widget::Image::new(image_id.normal)
-                    .x_y(pixel_x, pixel_y)
-                    .w_h(size, size)
-                    .set(ids.airports[i], ui);
+                route.select(i);
             }
         }
+        let hovered = cursor
+            .and_then(|cursor| airport_under_cursor(&positions, cursor))
+            .map(|index| visible[index]);
+
+        //========== Draw Labels ==========
+        let mut labels = Vec::new();
+        if zoom >= settings.labels.code_min_zoom {
+            let texts: Vec<String> = visible
+                .iter()
+                .map(|&i| {
+                    let airport = &airports[i];
+                    if zoom >= settings.labels.name_min_zoom {
+                        format!("{} {}", airport.code(), airport.name)
+                    } else {
+                        airport.code().to_owned()
+                    }
+                })
+                .collect();
+            let sizes: Vec<_> = texts
+                .iter()
+                .map(|text| estimate_label_size(text, LABEL_FONT_SIZE))
+                .collect();
+
+            let centers = place_labels(&positions, &sizes);
+            for (text, center) in texts.into_iter().zip(centers) {
+                if let Some([x, y]) = center {
+                    labels.push((text, x, y));
+                }
+            }
+        }
+
+        ids.airport_labels
+            .resize(labels.len(), &mut ui.widget_id_generator());
+        for (id_index, (text, x, y)) in labels.into_iter().enumerate() {
+            Text::new(&text)
+                .x_y(x, y)
+                .color(conrod_core::color::WHITE)
+                .font_size(LABEL_FONT_SIZE)
+                .font_id(font)
+                .set(ids.airport_labels[id_index], ui);
+        }
+
+        if let Some((a, b)) = route.endpoints() {
+            draw_route(&airports[a], &airports[b], &viewport, ids, ui);
+        } else {
+            ids.route_lines.resize(0, &mut ui.widget_id_generator());
+        }
+        hovered
+    }
+
+    /// Returns the index of the icon closest to `cursor`, if the cursor is over one.
+    ///
+    /// `icons` holds the center and size of each icon in pixels, like [`place_labels`]. Small icons
+    /// can be hovered from up to [`MIN_HOVER_RADIUS`] away so that they are easy to find
+    pub fn airport_under_cursor(icons: &[(f64, f64, f64)], cursor: DVec2) -> Option<usize> {
+        icons
+            .iter()
+            .enumerate()
+            .map(|(i, &(x, y, size))| (i, cursor.distance(DVec2::new(x, y)), size))
+            .filter(|&(_, distance, size)| distance <= (size / 2.0).max(MIN_HOVER_RADIUS))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _, _)| i)
+    }
+
+    /// Returns true if two `[left, bottom, right, top]` rectangles overlap
+    fn rects_overlap(a: &[f64; 4], b: &[f64; 4]) -> bool {
+        a[0] < b[2] && b[0] < a[2] && a[1] < b[3] && b[1] < a[3]
+    }
+
+    /// Returns the `[left, bottom, right, top]` rectangle of size `[width, height]` centered on
+    /// `x`, `y`
+    fn rect_around(x: f64, y: f64, [width, height]: [f64; 2]) -> [f64; 4] {
+        [
+            x - width / 2.0,
+            y - height / 2.0,
+            x + width / 2.0,
+            y + height / 2.0,
+        ]
+    }
+
+    /// Returns an estimate of the width and height in pixels of `text` drawn at `font_size`, with
+    /// a little padding. The real size is only known after the text is drawn
+    pub fn estimate_label_size(text: &str, font_size: u32) -> [f64; 2] {
+        let font_size = font_size as f64;
+        [
+            text.chars().count() as f64 * font_size * 0.6 + 4.0,
+            font_size + 4.0,
+        ]
+    }
+
+    /// Greedily picks where to draw the label of each icon.
+    ///
+    /// `icons` holds the center and size of each icon in pixels, most important first, and `sizes`
+    /// the width and height of each icon's label. Each label is tried below, right of, above, then
+    /// left of its icon, and placed on the first side that doesn't overlap any icon or earlier
+    /// label. Returns the center of each label, or `None` for labels that didn't fit.
+    ///
+    /// Icons past the end of `sizes` get no label, but labels still avoid them
+    pub fn place_labels(icons: &[(f64, f64, f64)], sizes: &[[f64; 2]]) -> Vec<Option<[f64; 2]>> {
+        let icon_rects: Vec<_> = icons
+            .iter()
+            .map(|&(x, y, size)| rect_around(x, y, [size, size]))
+            .collect();
+        //Rectangles of labels placed so far
+        let mut placed: Vec<[f64; 4]> = Vec::new();
+
+        icons
+            .iter()
+            .zip(sizes)
+            .map(|(&(x, y, size), &[width, height])| {
+                let dx = size / 2.0 + LABEL_GAP + width / 2.0;
+                let dy = size / 2.0 + LABEL_GAP + height / 2.0;
+                let candidates = [[x, y - dy], [x + dx, y], [x, y + dy], [x - dx, y]];
+
+                let [x, y] = candidates.into_iter().find(|&[x, y]| {
+                    let rect = rect_around(x, y, [width, height]);
+                    !icon_rects
+                        .iter()
+                        .chain(placed.iter())
+                        .any(|other| rects_overlap(&rect, other))
+                })?;
+                placed.push(rect_around(x, y, [width, height]));
+                Some([x, y])
+            })
+            .collect()
+    }
+
+    /// Draws the great circle route between two airports as a series of lines
+    fn draw_route(
+        a: &Airport,
+        b: &Airport,
+        viewport: &crate::map::WorldViewport,
+        ids: &mut crate::Ids,
+        ui: &mut UiCell,
+    ) {
+        let points = crate::util::great_circle_points(
+            (a.latitude as f64, a.longitude as f64),
+            (b.latitude as f64, b.longitude as f64),
+            ROUTE_SEGMENTS,
+        );
+        let pieces = crate::util::split_at_antimeridian(&points);
+
+        let line_count: usize = pieces.iter().map(|p| p.len().saturating_sub(1)).sum();
+        ids.route_lines
+            .resize(line_count, &mut ui.widget_id_generator());
+
+        let center_x = (viewport.top_left.x + viewport.bottom_right.x) / 2.0;
+        let mut id_counter = 0;
+        for piece in pieces {
+            //Shift the whole piece by the same amount so it stays connected, using the copy of the
+            //world closest to the center of the screen
+            let first_x = crate::util::x_from_longitude(piece[0].1);
+            let shift = (center_x - first_x).round();
+
+            let pixels: Vec<[f64; 2]> = piece
+                .iter()
+                .map(|&(lat, lng)| {
+                    let world_x = crate::util::x_from_longitude(lng) + shift;
+                    let world_y = crate::util::y_from_latitude(lat);
+                    [
+                        crate::world_x_to_pixel_x(world_x, viewport, ui.win_w),
+                        crate::world_y_to_pixel_y(world_y, viewport, ui.win_h),
+                    ]
+                })
+                .collect();
+
+            for pair in pixels.windows(2) {
+                Line::new(pair[0], pair[1])
+                    .x_y(0.0, 0.0)
+                    .color(conrod_core::color::LIGHT_BLUE)
+                    .thickness(2.0)
+                    .set(ids.route_lines[id_counter], ui);
+                id_counter += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::airport_renderer::{airport_under_cursor, place_labels};
+    use super::*;
+    use glam::DVec2;
+
+    #[test]
+    fn binary_round_trip() {
+        let sample = include_str!("../assets/data/sample_airports.csv");
+        let mut airports = airports_from_csv(sample).unwrap().airports;
+        add_region_names(
+            &mut airports,
+            "code,name\nUS,United States\nGB,United Kingdom",
+            "code,name\nUS-FL,Florida",
+        )
+        .unwrap();
+        assert_eq!(airports[0].country_name, "United States");
+        assert_eq!(airports[0].region_name, "Florida");
+        assert_eq!(airports[2].region_name, "");
+
+        let bytes = airports_to_bytes(&airports).unwrap();
+        assert_eq!(airports_from_bytes(&bytes).unwrap(), airports);
+        //The layout starts with the number of airports, then the first id
+        assert_eq!(bytes[..8], (airports.len() as u64).to_le_bytes());
+        assert_eq!(bytes[8..12], 3622u32.to_le_bytes());
+    }
+
+    #[test]
+    fn corrupt_binary_is_an_error() {
+        let sample = include_str!("../assets/data/sample_airports.csv");
+        let bytes = airports_to_bytes(&airports_from_csv(sample).unwrap().airports).unwrap();
+
+        for len in [0, 4, 8, 12, bytes.len() / 2, bytes.len() - 1] {
+            assert!(airports_from_bytes(&bytes[..len]).is_err(), "{} bytes", len);
+        }
+
+        //A huge airport count or string length must fail instead of allocating
+        let mut huge = bytes.clone();
+        huge[..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(airports_from_bytes(&huge).is_err());
+        let mut huge_string = bytes.clone();
+        huge_string[12..20].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+        assert!(airports_from_bytes(&huge_string).is_err());
+
+        let garbage: Vec<u8> = (0..4096u32).map(|i| (i * 7919 % 251) as u8).collect();
+        assert!(airports_from_bytes(&garbage).is_err());
+
+        //The bundled file still loads
+        let bundled = include_bytes!("../assets/data/airports.bin");
+        assert!(!airports_from_bytes(bundled).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_csv_and_geojson() {
+        let sample = include_str!("../assets/data/sample_airports.csv");
+        let loaded = airports_from_csv(sample).unwrap();
+        assert_eq!(loaded.row_errors, vec![]);
+        //The closed airfield isn't in any category
+        let idents: Vec<_> = loaded.airports.iter().map(|a| a.ident.as_str()).collect();
+        assert_eq!(idents, ["KDAB", "KMCO", "KATL", "00A", "EGLL"]);
+
+        let mco = &loaded.airports[1];
+        assert_eq!(mco.code(), "MCO");
+        assert_eq!(mco.category, Some(AirportCategory::Large));
+        assert_eq!(mco.elevation, 96);
+        assert!(mco.scheduled_service);
+        assert_eq!(loaded.airports[3].code(), "00A");
+
+        //Bad rows are reported with their line and skipped, quoted fields can span lines
+        let csv = "ident,type,name,latitude_deg,longitude_deg\n\
+                   KDAB,medium_airport,Daytona Beach,29.18,-81.06\n\
+                   KBAD,large_airport,,10,10\n\
+                   KNEW,small_airport,\"Two\nLines, \"\"Quoted\"\"\",95,0\n\
+                   KEND,small_airport,End,1,2";
+        let loaded = airports_from_csv(csv).unwrap();
+        assert_eq!(loaded.airports.len(), 2);
+        assert_eq!(loaded.airports[1].ident, "KEND");
+        assert_eq!(
+            loaded.row_errors,
+            vec![
+                RowError {
+                    row: 3,
+                    message: "missing `name`".to_owned()
+                },
+                RowError {
+                    row: 4,
+                    message: "invalid `latitude_deg` \"95\"".to_owned()
+                },
+            ]
+        );
+        assert!(matches!(
+            airports_from_csv("ident,name\nKDAB,Daytona"),
+            Err(AirportLoadError::MissingColumn("type"))
+        ));
+
+        let geojson = r#"{
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "geometry": { "type": "Point", "coordinates": [-81.06, 29.18] },
+                    "properties": { "ident": "KDAB", "type": "medium_airport",
+                        "name": "Daytona Beach", "elevation_ft": 34, "iata_code": "DAB" }
+                },
+                {
+                    "type": "Feature",
+                    "geometry": { "type": "LineString", "coordinates": [] },
+                    "properties": {}
+                }
+            ]
+        }"#;
+        let loaded = airports_from_geojson(geojson).unwrap();
+        assert_eq!(loaded.airports.len(), 1);
+        assert_eq!(loaded.airports[0].latitude, 29.18);
+        assert_eq!(loaded.airports[0].longitude, -81.06);
+        assert_eq!(loaded.airports[0].elevation, 34);
+        assert_eq!(loaded.row_errors[0].row, 2);
+        assert!(matches!(
+            airports_from_geojson("[]"),
+            Err(AirportLoadError::NotFeatureCollection)
+        ));
+
+        assert_eq!(
+            AirportFormat::from_path(Path::new("data/airports.geojson")),
+            Some(AirportFormat::GeoJson)
+        );
+        assert_eq!(AirportFormat::from_path(Path::new("airports")), None);
+    }
+
+    #[test]
+    fn suggestions_rank_codes_then_names() {
+        let sample = include_str!("../assets/data/sample_airports.csv");
+        let airports = airports_from_csv(sample).unwrap().airports;
+        let index = AirportIndex::new(&airports);
+
+        assert_eq!(index.suggest("mco", MAX_SUGGESTIONS), [1]);
+        //Every code starting with K, larger airports first
+        assert_eq!(index.suggest("k", MAX_SUGGESTIONS), [1, 2, 0, 3]);
+        assert_eq!(index.suggest("k", 2), [1, 2]);
+        //Names and cities match at the start of a word, or anywhere
+        assert_eq!(index.suggest("lon", MAX_SUGGESTIONS), [4]);
+        assert_eq!(index.suggest(" Ando", MAX_SUGGESTIONS), [1]);
+        assert_eq!(index.suggest("beach", MAX_SUGGESTIONS), [0]);
+        assert!(index.suggest("  ", MAX_SUGGESTIONS).is_empty());
+        assert!(index.suggest("zzz", MAX_SUGGESTIONS).is_empty());
+
+        let mut suggestions = AirportSuggestions::default();
+        suggestions.update(&index, "k");
+        assert_eq!(suggestions.highlighted(), None);
+        for _ in 0..6 {
+            suggestions.move_highlight(false);
+        }
+        assert_eq!(suggestions.highlighted(), Some(3));
+        suggestions.move_highlight(true);
+        assert_eq!(suggestions.highlighted_row(), Some(2));
+        suggestions.update(&index, "ka");
+        assert_eq!(suggestions.matches(), [2]);
+        assert_eq!(suggestions.highlighted(), None);
+    }
+
+    #[test]
+    fn labels_avoid_icons_and_each_other() {
+        let label = [30.0, 15.0];
+
+        //A lone icon is labeled below
+        assert_eq!(
+            place_labels(&[(0.0, 0.0, 10.0)], &[label]),
+            vec![Some([0.0, -14.5])]
+        );
+
+        //An icon right below the first pushes its label to the right
+        let icons = [(0.0, 0.0, 10.0), (0.0, -14.0, 10.0)];
+        let placed = place_labels(&icons, &[label, label]);
+        assert_eq!(placed[0], Some([22.0, 0.0]));
+        //The second icon still has room below
+        assert_eq!(placed[1], Some([0.0, -28.5]));
+
+        //Surrounded on every side, so the label is skipped
+        let icons = [
+            (0.0, 0.0, 10.0),
+            (0.0, -14.0, 10.0),
+            (0.0, 14.0, 10.0),
+            (20.0, 0.0, 10.0),
+            (-20.0, 0.0, 10.0),
+        ];
+        let placed = place_labels(&icons, &[label; 5]);
+        assert_eq!(placed[0], None);
+    }
+
+    #[test]
+    fn hover_picks_closest_airport() {
+        let icons = [(0.0, 0.0, 4.0), (10.0, 0.0, 40.0), (100.0, 100.0, 4.0)];
+        //Small icons can be hovered from a few pixels away
+        assert_eq!(airport_under_cursor(&icons, DVec2::new(-6.0, 0.0)), Some(0));
+        assert_eq!(airport_under_cursor(&icons, DVec2::new(6.0, 0.0)), Some(1));
+        //Large icons can be hovered anywhere on them
+        assert_eq!(airport_under_cursor(&icons, DVec2::new(28.0, 0.0)), Some(1));
+        assert_eq!(airport_under_cursor(&icons, DVec2::new(50.0, 50.0)), None);
+        assert_eq!(airport_under_cursor(&[], DVec2::ZERO), None);
     }
 }
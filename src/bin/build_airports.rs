@@ -0,0 +1,83 @@
+//! Regenerates `assets/data/airports.bin` from OurAirports' data, which can be downloaded from
+//! <https://ourairports.com/data/>.
+//!
+//! Usage: `build_airports <airports.csv> [--countries <countries.csv>] [--regions <regions.csv>]
+//! [--output <PATH>]`
+//!
+//! Country and region names are left empty unless `countries.csv` and `regions.csv` are given.
+//! See [`flight_tracking_erau_se300::airports_to_bytes`] for the layout of the output
+
+use std::path::PathBuf;
+
+use flight_tracking_erau_se300::{
+    add_region_names, airports_from_csv, airports_to_bytes, exit_with_usage,
+};
+
+const USAGE: &str = "\
+Usage: build_airports <airports.csv> [OPTIONS]
+
+Options:
+  --countries <PATH>   OurAirports' countries.csv, used to fill in country names
+  --regions <PATH>     OurAirports' regions.csv, used to fill in region names
+  --output <PATH>      Where to write the airports [default: assets/data/airports.bin]";
+
+/// A CSV file with the columns `add_region_names` needs, but no rows
+const NO_NAMES: &str = "code,name";
+
+fn main() {
+    let mut input = None;
+    let mut countries = None;
+    let mut regions = None;
+    let mut output = PathBuf::from("assets/data/airports.bin");
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| exit_with_usage(&arg, USAGE));
+        match arg.as_str() {
+            "--countries" => countries = Some(PathBuf::from(value())),
+            "--regions" => regions = Some(PathBuf::from(value())),
+            "--output" => output = PathBuf::from(value()),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if input.is_none() && !arg.starts_with('-') => input = Some(PathBuf::from(&arg)),
+            _ => exit_with_usage(&arg, USAGE),
+        }
+    }
+    let input = input.unwrap_or_else(|| exit_with_usage("<airports.csv>", USAGE));
+
+    let read = |path: &PathBuf| {
+        std::fs::read_to_string(path).unwrap_or_else(|err| {
+            println!("Failed to read {}: {}", path.display(), err);
+            std::process::exit(1);
+        })
+    };
+    let loaded = airports_from_csv(&read(&input)).unwrap_or_else(|err| {
+        println!("Failed to parse {}: {}", input.display(), err);
+        std::process::exit(1);
+    });
+    for err in &loaded.row_errors {
+        println!("Skipping airport in {} {}", input.display(), err);
+    }
+
+    let mut airports = loaded.airports;
+    let countries = countries.as_ref().map_or_else(|| NO_NAMES.to_owned(), read);
+    let regions = regions.as_ref().map_or_else(|| NO_NAMES.to_owned(), read);
+    if let Err(err) = add_region_names(&mut airports, &countries, &regions) {
+        println!("Failed to read country and region names: {}", err);
+        std::process::exit(1);
+    }
+
+    let bytes = airports_to_bytes(&airports).expect("Failed to serialize airports");
+    if let Err(err) = std::fs::write(&output, &bytes) {
+        println!("Failed to write {}: {}", output.display(), err);
+        std::process::exit(1);
+    }
+    println!(
+        "Wrote {} airports ({} bytes) to {}",
+        airports.len(),
+        bytes.len(),
+        output.display()
+    );
+}
@@ -0,0 +1,114 @@
+//! Measures how quickly the map works out which tiles to draw, without opening a window.
+//!
+//! Usage: `tile_bench [--passes <N>]`
+//!
+//! Sweeps a 1920x1080 view over a range of zoom levels and centers, running
+//! [`flight_tracking_erau_se300::layout_tiles`] on each and projecting every tile it returns back
+//! to world coordinates, like drawing a frame does. Some tiles are left out of the simulated
+//! cache so that the fallback to lower zoom levels is exercised. Build with `--release` for
+//! meaningful numbers
+
+use std::time::{Duration, Instant};
+
+use conrod_core::Rect;
+use flight_tracking_erau_se300::{
+    exit_with_usage, layout_tiles, pixel_x_to_world_x, pixel_y_to_world_y, TileId, TileView,
+};
+
+const USAGE: &str = "\
+Usage: tile_bench [OPTIONS]
+
+Options:
+  --passes <N>   How many times to repeat the sweep [default: 20]";
+
+const WINDOW_SIZE: [f64; 2] = [1920.0, 1080.0];
+const TILE_SIZE: u32 = 256;
+const MAX_ZOOM: u32 = 18;
+
+/// Tiles at or below this zoom are always in the simulated cache, like the first tiles loaded
+/// when the app starts
+const CACHED_ZOOM: u32 = 3;
+
+/// Returns true for about three quarters of the tiles above [`CACHED_ZOOM`], picked by a hash so
+/// that every pass sees the same tiles
+fn is_cached(tile: TileId) -> bool {
+    let hash = (tile.x as u64)
+        .wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        .wrapping_add((tile.y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F))
+        .wrapping_add(tile.zoom as u64);
+    tile.zoom <= CACHED_ZOOM || hash % 4 != 0
+}
+
+/// The views in one sweep: zoom levels from the whole world down to a city, each centered on a
+/// few places including either side of the antimeridian
+fn sweep() -> Vec<TileView> {
+    let centers = [
+        (29.19, -81.05),
+        (40.64, -73.78),
+        (51.47, -0.45),
+        (-33.94, 151.18),
+        (0.0, 179.9),
+        (0.0, -179.9),
+    ];
+    let mut views = Vec::new();
+    for step in 0..=32 {
+        let zoom = step as f64 * 0.5;
+        for &(lat, lng) in &centers {
+            views.push(TileView::new(lat, lng, zoom, WINDOW_SIZE[0]));
+        }
+    }
+    views
+}
+
+fn main() {
+    let mut passes = 20;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--passes" => {
+                passes = args
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .filter(|&passes| passes > 0)
+                    .unwrap_or_else(|| exit_with_usage(&arg, USAGE));
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => exit_with_usage(&arg, USAGE),
+        }
+    }
+
+    let bounds = Rect::from_xy_dim([0.0, 0.0], WINDOW_SIZE);
+    let views = sweep();
+    let mut layouts = 0u64;
+    let mut tiles = 0u64;
+    let mut checksum = 0.0;
+    let mut elapsed = Duration::ZERO;
+
+    for _ in 0..passes {
+        let start = Instant::now();
+        for view in &views {
+            let layout = layout_tiles(view, TILE_SIZE, MAX_ZOOM, bounds, is_cached);
+            let viewport = view.get_world_viewport(bounds.w(), bounds.h());
+            for layer in &layout.layers {
+                for &(x, y, _, _) in &layer.tiles {
+                    checksum += pixel_x_to_world_x(x, &viewport, bounds.w())
+                        + pixel_y_to_world_y(y, &viewport, bounds.h());
+                }
+            }
+            layouts += 1;
+            tiles += layout.tile_count() as u64;
+        }
+        elapsed += start.elapsed();
+    }
+
+    let secs = elapsed.as_secs_f64();
+    println!("{} layouts of {} tiles in {:.2?}", layouts, tiles, elapsed);
+    println!("  {:.0} layouts/sec", layouts as f64 / secs);
+    println!("  {:.0} tiles/sec", tiles as f64 / secs);
+    println!("  {:.1} ns per tile", secs * 1e9 / tiles as f64);
+    //Printed so that the projections can't be optimized away
+    println!("  checksum {:.3}", checksum);
+}
@@ -371,6 +371,30 @@ impl<'a, S> Labelable<'a> for CircularButton<'a, S> {
     }
 }
 
+/// The color of a toggle button's circle while it is turned on
+const TOGGLE_ON_COLOR: Color = conrod_core::color::LIGHT_BLUE;
+
+/// Like [`draw_circle_with_image`], but highlights the circle while `enabled` is true so the button
+/// shows the state it controls
+pub fn draw_toggle_with_image(
+    widget: widget::id::Id,
+    ui: &mut UiCell,
+    image_id: ImageId,
+    widget_x_position: f64,
+    widget_y_position: f64,
+    enabled: bool,
+) -> bool {
+    let button = CircularButton::image(image_id.normal)
+        .x(widget_x_position)
+        .y(widget_y_position)
+        .w_h(50.0, 50.0);
+    if enabled {
+        button.color(TOGGLE_ON_COLOR).set(widget, ui)
+    } else {
+        button.set(widget, ui)
+    }
+}
+
 ///Makes a Circle Widget with an ImageId given.
 ///Takes x and y to position Widget.
 pub fn draw_circle_with_image(
@@ -0,0 +1,79 @@
+//! The clock in the overlay, showing UTC and local time and how old the plane data is
+
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, FixedOffset, Utc};
+
+/// Plane data this many refresh intervals old is shown as stale, since at least two updates in a
+/// row were missed
+pub const STALE_INTERVALS: u32 = 3;
+
+/// Returns the two lines of the clock: the time in UTC, marked Zulu, then the time at `offset`
+/// from UTC and the offset
+pub fn clock_lines(now: DateTime<Utc>, offset: FixedOffset) -> [String; 2] {
+    let local = now.with_timezone(&offset);
+    [
+        now.format("%H:%M:%SZ  %Y-%m-%d").to_string(),
+        local.format("%H:%M:%S local (UTC%:z)").to_string(),
+    ]
+}
+
+/// Returns how old plane data timestamped `data_time` by OpenSky is at `now`, or `None` if there
+/// is no data yet. Timestamps from the future count as brand new
+pub fn data_age(data_time: Option<SystemTime>, now: SystemTime) -> Option<Duration> {
+    data_time.map(|time| now.duration_since(time).unwrap_or_default())
+}
+
+/// Describes `age` for the clock, such as "Data 3s old"
+pub fn data_age_text(age: Option<Duration>) -> String {
+    let secs = match age {
+        Some(age) => age.as_secs(),
+        None => return String::from("No plane data yet"),
+    };
+    match secs {
+        0..=59 => format!("Data {}s old", secs),
+        60..=3599 => format!("Data {}m {}s old", secs / 60, secs % 60),
+        _ => format!("Data {}h {}m old", secs / 3600, secs / 60 % 60),
+    }
+}
+
+/// Returns true if plane data `age` old means updates are no longer arriving every
+/// `refresh_interval`
+pub fn is_stale(age: Option<Duration>, refresh_interval: Duration) -> bool {
+    age.is_some_and(|age| age > refresh_interval * STALE_INTERVALS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn clock_and_data_age() {
+        let now = Utc.with_ymd_and_hms(2021, 11, 5, 2, 3, 4).unwrap();
+        let eastern = FixedOffset::west_opt(4 * 3600).unwrap();
+        assert_eq!(
+            clock_lines(now, eastern),
+            [
+                "02:03:04Z  2021-11-05".to_owned(),
+                "22:03:04 local (UTC-04:00)".to_owned()
+            ]
+        );
+
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let age = |secs| data_age(Some(now - Duration::from_secs(secs)), now);
+        assert_eq!(data_age_text(age(3)), "Data 3s old");
+        assert_eq!(data_age_text(age(125)), "Data 2m 5s old");
+        assert_eq!(data_age_text(age(7260)), "Data 2h 1m old");
+        assert_eq!(data_age_text(None), "No plane data yet");
+        assert_eq!(
+            data_age(Some(now + Duration::from_secs(5)), now),
+            Some(Duration::ZERO)
+        );
+
+        let interval = Duration::from_secs(10);
+        assert!(!is_stale(age(25), interval));
+        assert!(is_stale(age(31), interval));
+        assert!(!is_stale(None, interval));
+    }
+}
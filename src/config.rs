@@ -0,0 +1,1151 @@
+//! Startup options taken from the config file, environment variables and command line
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    AirportFormat, AirportLabelSettings, ClusterSettings, ColorRamp, IdleSettings, PlaneType,
+    PrefetchSettings, RangeRingSettings, TileKind, TileUrls, Units, VectorSettings,
+    DEFAULT_CACHE_MAX_MB, MAX_MARKER_SCALE, MIN_MARKER_SCALE,
+};
+
+/// Environment variable that overrides the initial window size, formatted as `<width>x<height>`
+const WINDOW_SIZE_VAR: &str = "FLIGHT_TRACKER_WINDOW_SIZE";
+/// Environment variable holding a path. When set, the app saves a screenshot there once the map
+/// and planes have loaded, then exits. Combine with [`WINDOW_SIZE_VAR`] to pick the image size
+const SCREENSHOT_VAR: &str = "FLIGHT_TRACKER_SCREENSHOT";
+/// Environment variable that sets how many seconds to wait between plane data requests
+const REFRESH_SECS_VAR: &str = "FLIGHT_TRACKER_REFRESH_SECS";
+/// Environment variable that sets how many MiB of map tiles are kept on disk
+const CACHE_MAX_MB_VAR: &str = "FLIGHT_TRACKER_CACHE_MAX_MB";
+
+const DEFAULT_WIDTH: u32 = 1280;
+const DEFAULT_HEIGHT: u32 = 720;
+const DEFAULT_LATITUDE: f64 = 29.18796;
+const DEFAULT_LONGITUDE: f64 = -81.04923;
+const DEFAULT_ZOOM: f64 = 8.0;
+/// Each ring of prefetched tiles costs more requests than the last, so keep it small
+const MAX_PREFETCH_RADIUS: u32 = 3;
+/// The width in pixels that [`StartView::zoom`] is measured across, half of a 1080p screen
+pub const ZOOM_REFERENCE_WIDTH: f64 = 1080.0 / 2.0;
+
+const USAGE: &str = "\
+Usage: flight_tracking_erau_se300 [OPTIONS]
+
+Options:
+  --lat <DEGREES>          Latitude of the center of the map
+  --lon <DEGREES>          Longitude of the center of the map
+  --zoom <LEVEL>           Initial zoom, where 1/2^LEVEL of the world spans 540 pixels
+  --width <PIXELS>         Initial width of the window
+  --height <PIXELS>        Initial height of the window
+  --base-layer <LAYER>     Imagery under the map, either satellite, street or vector
+  --weather-ramp <RAMP>    Colors of the weather layer, either classic, viridis or grayscale
+  --units <UNITS>          Units of distances, altitudes and speeds, either metric, imperial
+                           or nautical
+  --refresh-secs <SECS>    Seconds to wait between plane data requests
+  --screenshot <PATH>      Save a screenshot to PATH once everything loads, then exit
+  --airports <PATH>        Load airports from a .csv, .geojson or .bin file instead of the
+                           bundled ones
+  --airport-format <FMT>   Format of the airports file, either csv, geojson or bin. Guessed
+                           from the extension by default
+  --frame-history <FRAMES> Number of frames shown by the frame time graph in the debug menu
+  --cache-max-mb <MIB>     Most map tiles to keep on disk in MiB, shared between the map
+                           layers [default: 2432]
+  --plane-scale <SCALE>    Size of the plane icons, from 0.5 to 3 times the normal size
+  --airport-scale <SCALE>  Size of the airport icons, from 0.5 to 3 times the normal size
+  --idle-secs <SECS>       Tour the map after SECS seconds without input, for unattended
+                           displays. 0 turns the tour off
+  --offline <BOOL>         Start in offline mode, showing only cached map tiles and no new
+                           planes, either true or false
+  --detail-window <BOOL>   Open a second window showing the selected plane and the clock, for
+                           a second monitor, either true or false
+  -h, --help               Print this message
+
+Any of --lat, --lon or --zoom replace the view saved by the last run, and --plane-scale and
+--airport-scale replace the icon sizes saved by the last run. Defaults for these options
+can also be set in flight_tracking/config.toml inside the platform's config directory";
+
+/// An error caused by an invalid command line or config file
+#[derive(Error, Debug, PartialEq)]
+pub enum ConfigError {
+    #[error("help requested")]
+    HelpRequested,
+    #[error("unknown option `{0}`")]
+    UnknownFlag(String),
+    #[error("`{0}` requires a value")]
+    MissingValue(String),
+    #[error("invalid value `{value}` for `{option}`, expected {expected}")]
+    InvalidValue {
+        option: String,
+        value: String,
+        expected: &'static str,
+    },
+    #[error("{0}")]
+    Toml(#[from] toml::de::Error),
+    /// The config file couldn't be read or is invalid. Syntax errors include the line and column
+    #[error("{}: {message}", path.display())]
+    File { path: PathBuf, message: String },
+}
+
+/// Where the map is centered when the app starts
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StartView {
+    /// Latitude in degrees
+    pub latitude: f64,
+    /// Longitude in degrees
+    pub longitude: f64,
+    /// The zoom, where `1 / 2^zoom` of the world's width spans half of a 1080p screen
+    pub zoom: f64,
+}
+
+impl Default for StartView {
+    fn default() -> Self {
+        Self {
+            latitude: DEFAULT_LATITUDE,
+            longitude: DEFAULT_LONGITUDE,
+            zoom: DEFAULT_ZOOM,
+        }
+    }
+}
+
+impl StartView {
+    pub fn to_tile_view(&self) -> crate::map::TileView {
+        crate::map::TileViewBuilder::new()
+            .center(self.latitude, self.longitude)
+            .zoom_across(self.zoom, ZOOM_REFERENCE_WIDTH)
+            .build()
+    }
+}
+
+/// An airline that isn't in the built in table.
+///
+/// Added to the registry with [`crate::AirlineRegistry::register_dynamic`]
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AirlineMapping {
+    /// The three letter ICAO prefix of the airline's callsigns
+    pub prefix: String,
+    pub name: String,
+    #[serde(default = "default_plane_type")]
+    pub plane_type: PlaneType,
+}
+
+/// Overrides how the planes of an airline are drawn. Airlines are looked up by prefix after the
+/// `[[airlines]]` entries are registered, so this also works for airlines added there
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AirlineStyleMapping {
+    /// The three letter ICAO prefix of the airline's callsigns
+    pub prefix: String,
+    /// The tint of the airline's planes, formatted as `#rrggbb`
+    pub color: Option<String>,
+    /// A png drawn instead of the default airplane, pointing north. Only its shape is used, filled
+    /// with the airline's color
+    pub icon: Option<PathBuf>,
+}
+
+fn default_plane_type() -> PlaneType {
+    PlaneType::Commercial
+}
+
+/// Which optional parts of the UI are turned on at startup
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiToggles {
+    pub weather: bool,
+    pub airports: bool,
+    pub debug: bool,
+}
+
+impl Default for UiToggles {
+    fn default() -> Self {
+        Self {
+            weather: false,
+            airports: true,
+            debug: false,
+        }
+    }
+}
+
+/// The contents of the config file. Scalar options use the same names as the command line
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct FileConfig {
+    lat: Option<f64>,
+    lon: Option<f64>,
+    zoom: Option<f64>,
+    width: Option<u32>,
+    height: Option<u32>,
+    base_layer: Option<String>,
+    weather_ramp: Option<String>,
+    units: Option<String>,
+    refresh_secs: Option<f64>,
+    airports: Option<String>,
+    airport_format: Option<String>,
+    frame_history: Option<u32>,
+    cache_max_mb: Option<u32>,
+    plane_scale: Option<f64>,
+    airport_scale: Option<f64>,
+    offline: Option<bool>,
+    detail_window: Option<bool>,
+    tiles: Option<TileUrls>,
+    vector: Option<VectorSettings>,
+    prefetch: Option<PrefetchSettings>,
+    airlines: Vec<AirlineMapping>,
+    airline_styles: Vec<AirlineStyleMapping>,
+    clustering: Option<ClusterSettings>,
+    range_rings: Option<RangeRingSettings>,
+    idle: Option<IdleSettings>,
+    airport_labels: Option<AirportLabelSettings>,
+    ui: Option<UiToggles>,
+}
+
+/// Options that control how the app starts
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// The view to start with, set on the command line. When `None`, the view saved by the last
+    /// run is restored, falling back to `default_view`
+    pub start_view: Option<StartView>,
+    /// The view set in the config file, used when there is no view from the command line or the
+    /// last run. When `None`, [`StartView::default`] is used
+    pub default_view: Option<StartView>,
+    /// The initial size of the window in logical pixels
+    pub window_size: (u32, u32),
+    /// The imagery drawn under everything else
+    pub base_layer: TileKind,
+    /// The palette the weather layer is recolored with
+    pub weather_ramp: ColorRamp,
+    /// The units distances, altitudes and speeds are shown in at startup
+    pub units: Units,
+    /// How often to request planes, or `None` to use [`crate::DEFAULT_REFRESH_INTERVAL`]
+    pub refresh_interval: Option<Duration>,
+    /// Where to save a screenshot before exiting, once everything has loaded
+    pub screenshot_path: Option<PathBuf>,
+    /// A file to load airports from instead of the bundled data
+    pub airports_path: Option<PathBuf>,
+    /// The format of `airports_path`, or `None` to guess from its extension
+    pub airport_format: Option<AirportFormat>,
+    /// How far the map must be zoomed in before airports are labeled
+    pub airport_labels: AirportLabelSettings,
+    pub tile_urls: TileUrls,
+    /// Where the vector base layer is downloaded from, and how it is drawn
+    pub vector: VectorSettings,
+    /// Which map tiles are loaded before they are on screen
+    pub prefetch: PrefetchSettings,
+    /// Airlines recognized in addition to the built in ones
+    pub airlines: Vec<AirlineMapping>,
+    pub airline_styles: Vec<AirlineStyleMapping>,
+    pub clustering: ClusterSettings,
+    /// How far apart the range rings are drawn, and how many there are
+    pub range_rings: RangeRingSettings,
+    /// The number of frames shown by the frame time graph
+    pub frame_history: usize,
+    /// The most map tiles kept on disk in MiB, shared between the map layers
+    pub cache_max_mb: u32,
+    /// How much larger than normal plane icons are drawn, set on the command line. When `None`,
+    /// the size saved by the last run is restored, falling back to `default_plane_scale`
+    pub plane_scale: Option<f32>,
+    /// How much larger than normal airport icons are drawn, set on the command line. When
+    /// `None`, the size saved by the last run is restored, falling back to
+    /// `default_airport_scale`
+    pub airport_scale: Option<f32>,
+    /// The plane icon size set in the config file, used when there is no size from the command
+    /// line or the last run
+    pub default_plane_scale: Option<f32>,
+    /// The airport icon size set in the config file, used when there is no size from the command
+    /// line or the last run
+    pub default_airport_scale: Option<f32>,
+    /// Whether to start without making any network requests
+    pub offline: bool,
+    /// Whether to open a second window showing the selected plane and the clock
+    pub detail_window: bool,
+    /// When and where the camera tours the map while nobody is using the app
+    pub idle: IdleSettings,
+    pub ui: UiToggles,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            start_view: None,
+            default_view: None,
+            window_size: (DEFAULT_WIDTH, DEFAULT_HEIGHT),
+            base_layer: TileKind::Satellite,
+            weather_ramp: ColorRamp::Classic,
+            units: Units::Metric,
+            refresh_interval: None,
+            screenshot_path: None,
+            airports_path: None,
+            airport_format: None,
+            airport_labels: AirportLabelSettings::default(),
+            tile_urls: TileUrls::default(),
+            vector: VectorSettings::default(),
+            prefetch: PrefetchSettings::default(),
+            airlines: Vec::new(),
+            airline_styles: Vec::new(),
+            clustering: ClusterSettings::default(),
+            range_rings: RangeRingSettings::default(),
+            frame_history: crate::frame_graph::DEFAULT_FRAME_HISTORY,
+            cache_max_mb: DEFAULT_CACHE_MAX_MB,
+            plane_scale: None,
+            airport_scale: None,
+            default_plane_scale: None,
+            default_airport_scale: None,
+            offline: false,
+            detail_window: false,
+            idle: IdleSettings::default(),
+            ui: UiToggles::default(),
+        }
+    }
+}
+
+/// Returns the path of the config file, or `None` if the platform has no config directory
+pub fn config_path() -> Option<PathBuf> {
+    let mut path = dirs::config_dir()?;
+    path.push("flight_tracking");
+    path.push("config.toml");
+    Some(path)
+}
+
+/// Prints that `arg` was unexpected or missing its value, followed by `usage`, then exits. Used by
+/// the command line tools in `src/bin`
+pub fn exit_with_usage(arg: &str, usage: &str) -> ! {
+    println!(
+        "Error: unexpected or incomplete argument `{}`\n\n{}",
+        arg, usage
+    );
+    std::process::exit(2);
+}
+
+impl Config {
+    /// Reads the config file, then the environment variables, then the command line arguments of
+    /// this process. Later sources override earlier ones.
+    ///
+    /// Prints the error and exits if any of them are invalid, or prints the usage if `--help` is
+    /// passed
+    pub fn from_env_or_exit() -> Self {
+        let mut config = Self::default();
+        if let Some(path) = config_path() {
+            if let Err(err) = config.apply_file(&path) {
+                println!("Error in config file {}", err);
+                std::process::exit(2);
+            }
+        }
+        config.apply_env();
+        match config.apply_args(std::env::args().skip(1)) {
+            Ok(()) => config,
+            Err(ConfigError::HelpRequested) => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            Err(err) => {
+                println!("Error: {}\n\n{}", err, USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    /// Applies the config file at `path`. A missing file changes nothing
+    pub fn apply_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let file_error = |message: String| ConfigError::File {
+            path: path.to_owned(),
+            message,
+        };
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(file_error(err.to_string())),
+        };
+        self.apply_toml(&text)
+            .map_err(|err| file_error(err.to_string()))
+    }
+
+    /// Applies the contents of a config file
+    fn apply_toml(&mut self, text: &str) -> Result<(), ConfigError> {
+        let file: FileConfig = toml::from_str(text)?;
+
+        // The view in the file is only a default, so that it doesn't replace the view saved by
+        // the last run
+        let view = [("lat", file.lat), ("lon", file.lon), ("zoom", file.zoom)];
+        if view.iter().any(|(_, value)| value.is_some()) {
+            let start_view = self.start_view.take();
+            for (option, value) in view {
+                if let Some(value) = value {
+                    self.set_option(option, value.to_string())?;
+                }
+            }
+            self.default_view = self.start_view.take();
+            self.start_view = start_view;
+        }
+        // Like the view, icon sizes in the file are only defaults
+        let (plane_scale, airport_scale) = (self.plane_scale.take(), self.airport_scale.take());
+        let numbers = [
+            ("refresh-secs", file.refresh_secs),
+            ("plane-scale", file.plane_scale),
+            ("airport-scale", file.airport_scale),
+        ];
+        for (option, value) in numbers {
+            if let Some(value) = value {
+                self.set_option(option, value.to_string())?;
+            }
+        }
+        self.default_plane_scale = self.plane_scale.take().or(self.default_plane_scale);
+        self.default_airport_scale = self.airport_scale.take().or(self.default_airport_scale);
+        self.plane_scale = plane_scale;
+        self.airport_scale = airport_scale;
+        let integers = [
+            ("width", file.width),
+            ("height", file.height),
+            ("frame-history", file.frame_history),
+            ("cache-max-mb", file.cache_max_mb),
+        ];
+        for (option, value) in integers {
+            if let Some(value) = value {
+                self.set_option(option, value.to_string())?;
+            }
+        }
+        let flags = [
+            ("offline", file.offline),
+            ("detail-window", file.detail_window),
+        ];
+        for (option, value) in flags {
+            if let Some(value) = value {
+                self.set_option(option, value.to_string())?;
+            }
+        }
+        let text_options = [
+            ("base-layer", file.base_layer),
+            ("weather-ramp", file.weather_ramp),
+            ("units", file.units),
+            ("airports", file.airports),
+            ("airport-format", file.airport_format),
+        ];
+        for (option, value) in text_options {
+            if let Some(value) = value {
+                self.set_option(option, value)?;
+            }
+        }
+
+        if let Some(tile_urls) = file.tiles {
+            for url in tile_urls.all() {
+                if !["{z}", "{x}", "{y}"].iter().all(|part| url.contains(part)) {
+                    return Err(ConfigError::InvalidValue {
+                        option: "tiles".to_owned(),
+                        value: url.clone(),
+                        expected: "a url containing {z}, {x} and {y}",
+                    });
+                }
+            }
+            self.tile_urls = tile_urls;
+        }
+        if let Some(vector) = file.vector {
+            let urls = std::iter::once(("vector.url", &vector.url))
+                .chain(vector.mirrors.iter().map(|url| ("vector.mirrors", url)));
+            for (option, url) in urls {
+                if !["{z}", "{x}", "{y}"].iter().all(|part| url.contains(part)) {
+                    return Err(ConfigError::InvalidValue {
+                        option: option.to_owned(),
+                        value: url.clone(),
+                        expected: "a url containing {z}, {x} and {y}",
+                    });
+                }
+            }
+            if vector.max_zoom > crate::MAX_ZOOM_LEVEL {
+                return Err(ConfigError::InvalidValue {
+                    option: "vector.max-zoom".to_owned(),
+                    value: vector.max_zoom.to_string(),
+                    expected: "a zoom level between 0 and 20",
+                });
+            }
+            for (name, color) in vector.colors() {
+                if crate::parse_hex_color(color).is_none() {
+                    return Err(ConfigError::InvalidValue {
+                        option: format!("vector.{}", name),
+                        value: color.to_owned(),
+                        expected: "a color formatted as #rrggbb",
+                    });
+                }
+            }
+            let lines = [
+                ("coastline", &vector.coastline),
+                ("minor-road", &vector.minor_road),
+                ("major-road", &vector.major_road),
+            ];
+            for (name, style) in lines {
+                if !style.width.is_finite() || style.width <= 0.0 {
+                    return Err(ConfigError::InvalidValue {
+                        option: format!("vector.{}.width", name),
+                        value: style.width.to_string(),
+                        expected: "a positive number of pixels",
+                    });
+                }
+            }
+            self.vector = vector;
+        }
+        if let Some(prefetch) = file.prefetch {
+            if prefetch.radius > MAX_PREFETCH_RADIUS {
+                return Err(ConfigError::InvalidValue {
+                    option: "prefetch.radius".to_owned(),
+                    value: prefetch.radius.to_string(),
+                    expected: "a number of tiles between 0 and 3",
+                });
+            }
+            self.prefetch = prefetch;
+        }
+        let prefixes = file
+            .airlines
+            .iter()
+            .map(|airline| ("airlines.prefix", &airline.prefix))
+            .chain(
+                file.airline_styles
+                    .iter()
+                    .map(|style| ("airline-styles.prefix", &style.prefix)),
+            );
+        for (option, prefix) in prefixes {
+            if prefix.len() != 3 || !prefix.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err(ConfigError::InvalidValue {
+                    option: option.to_owned(),
+                    value: prefix.clone(),
+                    expected: "a three letter ICAO airline code",
+                });
+            }
+        }
+        for style in &file.airline_styles {
+            if let Some(color) = &style.color {
+                if crate::parse_hex_color(color).is_none() {
+                    return Err(ConfigError::InvalidValue {
+                        option: "airline-styles.color".to_owned(),
+                        value: color.clone(),
+                        expected: "a color formatted as #rrggbb",
+                    });
+                }
+            }
+        }
+        self.airlines.extend(file.airlines);
+        self.airline_styles.extend(file.airline_styles);
+        if let Some(clustering) = file.clustering {
+            if !clustering.distance.is_finite() || clustering.distance <= 0.0 {
+                return Err(ConfigError::InvalidValue {
+                    option: "clustering.distance".to_owned(),
+                    value: clustering.distance.to_string(),
+                    expected: "a positive number of pixels",
+                });
+            }
+            self.clustering = clustering;
+        }
+        if let Some(range_rings) = file.range_rings {
+            if !range_rings.spacing.is_finite() || range_rings.spacing <= 0.0 {
+                return Err(ConfigError::InvalidValue {
+                    option: "range-rings.spacing".to_owned(),
+                    value: range_rings.spacing.to_string(),
+                    expected: "a positive distance",
+                });
+            }
+            if !(1..=50).contains(&range_rings.count) {
+                return Err(ConfigError::InvalidValue {
+                    option: "range-rings.count".to_owned(),
+                    value: range_rings.count.to_string(),
+                    expected: "a number of rings between 1 and 50",
+                });
+            }
+            self.range_rings = range_rings;
+        }
+        if let Some(idle) = file.idle {
+            self.apply_idle(idle)?;
+        }
+        if let Some(airport_labels) = file.airport_labels {
+            let zooms = [
+                ("code-min-zoom", airport_labels.code_min_zoom),
+                ("name-min-zoom", airport_labels.name_min_zoom),
+            ];
+            for (name, zoom) in zooms {
+                if !zoom.is_finite() || zoom < 0.0 {
+                    return Err(ConfigError::InvalidValue {
+                        option: format!("airport-labels.{}", name),
+                        value: zoom.to_string(),
+                        expected: "a zoom level of 0 or more",
+                    });
+                }
+            }
+            self.airport_labels = airport_labels;
+        }
+        if let Some(ui) = file.ui {
+            self.ui = ui;
+        }
+        Ok(())
+    }
+
+    /// Checks and applies the `[idle]` table of a config file
+    fn apply_idle(&mut self, idle: IdleSettings) -> Result<(), ConfigError> {
+        let invalid = |option: &str, value: String, expected| ConfigError::InvalidValue {
+            option: format!("idle.{}", option),
+            value,
+            expected,
+        };
+        if !idle.after_secs.is_finite() || idle.after_secs < 0.0 {
+            let value = idle.after_secs.to_string();
+            return Err(invalid("after-secs", value, "a number of seconds, or 0"));
+        }
+        if !idle.lap_secs.is_finite() || idle.lap_secs <= 0.0 {
+            let value = idle.lap_secs.to_string();
+            return Err(invalid("lap-secs", value, "a positive number of seconds"));
+        }
+        if !idle.orbit_radius.is_finite() || idle.orbit_radius < 0.0 {
+            let value = idle.orbit_radius.to_string();
+            return Err(invalid("orbit-radius", value, "a distance in kilometers"));
+        }
+        let max_latitude = crate::util::latitude_from_y(0.0);
+        for &[lat, lng] in &idle.points {
+            if !(lat.abs() <= max_latitude && lng.abs() <= 180.0) {
+                let value = format!("[{}, {}]", lat, lng);
+                return Err(invalid("points", value, "[latitude, longitude] in degrees"));
+            }
+        }
+        if let Some(zoom) = idle.zoom {
+            if !(0.0..=crate::MAX_ZOOM_LEVEL as f64).contains(&zoom) {
+                let value = zoom.to_string();
+                return Err(invalid("zoom", value, "a zoom level between 0 and 20"));
+            }
+        }
+        self.idle = idle;
+        Ok(())
+    }
+
+    /// Applies the `FLIGHT_TRACKER_*` environment variables. Invalid values are ignored with a
+    /// warning
+    pub fn apply_env(&mut self) {
+        if let Ok(value) = std::env::var(WINDOW_SIZE_VAR) {
+            match parse_window_size(&value) {
+                Some(size) => self.window_size = size,
+                None => println!(
+                    "Ignoring invalid {} {:?}, expected <width>x<height>",
+                    WINDOW_SIZE_VAR, value
+                ),
+            }
+        }
+        if let Ok(value) = std::env::var(REFRESH_SECS_VAR) {
+            match parse_seconds(&value) {
+                Some(interval) => self.refresh_interval = Some(interval),
+                None => println!(
+                    "Ignoring invalid {} {:?}, expected a number of seconds",
+                    REFRESH_SECS_VAR, value
+                ),
+            }
+        }
+        if let Ok(value) = std::env::var(CACHE_MAX_MB_VAR) {
+            match parse_cache_size(&value) {
+                Some(mb) => self.cache_max_mb = mb,
+                None => println!(
+                    "Ignoring invalid {} {:?}, expected a positive number of MiB",
+                    CACHE_MAX_MB_VAR, value
+                ),
+            }
+        }
+        if let Some(path) = std::env::var_os(SCREENSHOT_VAR) {
+            self.screenshot_path = Some(PathBuf::from(path));
+        }
+    }
+
+    /// Applies command line arguments, not including the program name. Accepts both
+    /// `--flag value` and `--flag=value`
+    pub fn apply_args(
+        &mut self,
+        args: impl IntoIterator<Item = String>,
+    ) -> Result<(), ConfigError> {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_owned(), Some(value.to_owned()))
+                }
+                _ => (arg, None),
+            };
+            if flag == "-h" || flag == "--help" {
+                return Err(ConfigError::HelpRequested);
+            }
+
+            let option = match flag.strip_prefix("--") {
+                Some(option) if is_known_option(option) => option,
+                _ => return Err(ConfigError::UnknownFlag(flag)),
+            };
+            match inline_value.or_else(|| args.next()) {
+                Some(value) => self.set_option(option, value)?,
+                None => return Err(ConfigError::MissingValue(flag)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the option named `option`, using the command line names without the leading dashes
+    fn set_option(&mut self, option: &str, value: String) -> Result<(), ConfigError> {
+        let invalid = |expected| ConfigError::InvalidValue {
+            option: option.to_owned(),
+            value: value.clone(),
+            expected,
+        };
+
+        match option {
+            "lat" => {
+                let max_latitude = crate::util::latitude_from_y(0.0);
+                self.start_view_mut().latitude = value
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|lat: &f64| lat.abs() <= max_latitude)
+                    .ok_or_else(|| invalid("a latitude between -85 and 85 degrees"))?;
+            }
+            "lon" => {
+                self.start_view_mut().longitude = value
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|lng: &f64| lng.abs() <= 180.0)
+                    .ok_or_else(|| invalid("a longitude between -180 and 180 degrees"))?;
+            }
+            "zoom" => {
+                self.start_view_mut().zoom = value
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|zoom| (0.0..=crate::MAX_ZOOM_LEVEL as f64).contains(zoom))
+                    .ok_or_else(|| invalid("a zoom level between 0 and 20"))?;
+            }
+            "width" => {
+                self.window_size.0 =
+                    parse_pixels(&value).ok_or_else(|| invalid("a positive number of pixels"))?
+            }
+            "height" => {
+                self.window_size.1 =
+                    parse_pixels(&value).ok_or_else(|| invalid("a positive number of pixels"))?
+            }
+            "base-layer" => {
+                self.base_layer = match value.trim().to_ascii_lowercase().as_str() {
+                    "satellite" => TileKind::Satellite,
+                    "street" => TileKind::Street,
+                    "vector" => TileKind::Vector,
+                    _ => return Err(invalid("`satellite`, `street` or `vector`")),
+                };
+            }
+            "weather-ramp" => {
+                self.weather_ramp = ColorRamp::from_name(&value)
+                    .ok_or_else(|| invalid("`classic`, `viridis` or `grayscale`"))?;
+            }
+            "units" => {
+                self.units = Units::from_name(&value)
+                    .ok_or_else(|| invalid("`metric`, `imperial` or `nautical`"))?;
+            }
+            "refresh-secs" => {
+                self.refresh_interval = Some(
+                    parse_seconds(&value).ok_or_else(|| invalid("a positive number of seconds"))?,
+                );
+            }
+            "screenshot" => self.screenshot_path = Some(PathBuf::from(value)),
+            "airports" => self.airports_path = Some(PathBuf::from(value)),
+            "airport-format" => {
+                self.airport_format = Some(
+                    AirportFormat::from_name(&value)
+                        .ok_or_else(|| invalid("`csv`, `geojson` or `bin`"))?,
+                );
+            }
+            "frame-history" => {
+                self.frame_history = value
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|&frames| frames >= 2)
+                    .ok_or_else(|| invalid("a number of frames of at least 2"))?;
+            }
+            "cache-max-mb" => {
+                self.cache_max_mb =
+                    parse_cache_size(&value).ok_or_else(|| invalid("a positive number of MiB"))?;
+            }
+            "plane-scale" => {
+                self.plane_scale = Some(
+                    parse_marker_scale(&value).ok_or_else(|| invalid("a scale from 0.5 to 3"))?,
+                );
+            }
+            "airport-scale" => {
+                self.airport_scale = Some(
+                    parse_marker_scale(&value).ok_or_else(|| invalid("a scale from 0.5 to 3"))?,
+                );
+            }
+            "idle-secs" => {
+                self.idle.after_secs = value
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|secs: &f64| secs.is_finite() && *secs >= 0.0)
+                    .ok_or_else(|| invalid("a number of seconds, or 0"))?;
+            }
+            "offline" => {
+                self.offline = parse_bool(&value).ok_or_else(|| invalid("`true` or `false`"))?;
+            }
+            "detail-window" => {
+                self.detail_window =
+                    parse_bool(&value).ok_or_else(|| invalid("`true` or `false`"))?;
+            }
+            _ => unreachable!("unknown option {}", option),
+        }
+        Ok(())
+    }
+
+    /// Returns the start view, creating it from the config file's view or the defaults if no
+    /// view options were set yet
+    fn start_view_mut(&mut self) -> &mut StartView {
+        let default_view = self.default_view.unwrap_or_default();
+        self.start_view.get_or_insert(default_view)
+    }
+}
+
+fn is_known_option(option: &str) -> bool {
+    matches!(
+        option,
+        "lat"
+            | "lon"
+            | "zoom"
+            | "width"
+            | "height"
+            | "base-layer"
+            | "weather-ramp"
+            | "units"
+            | "refresh-secs"
+            | "screenshot"
+            | "airports"
+            | "airport-format"
+            | "frame-history"
+            | "cache-max-mb"
+            | "plane-scale"
+            | "airport-scale"
+            | "idle-secs"
+            | "offline"
+            | "detail-window"
+    )
+}
+
+/// Parses `true` or `false`, also accepting `on` and `off`
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "on" => Some(true),
+        "false" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_marker_scale(value: &str) -> Option<f32> {
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|scale| (MIN_MARKER_SCALE..=MAX_MARKER_SCALE).contains(scale))
+}
+
+fn parse_pixels(value: &str) -> Option<u32> {
+    value.trim().parse().ok().filter(|&pixels| pixels > 0)
+}
+
+fn parse_cache_size(value: &str) -> Option<u32> {
+    value.trim().parse().ok().filter(|&mb| mb > 0)
+}
+
+fn parse_seconds(value: &str) -> Option<Duration> {
+    match value.trim().parse::<f64>() {
+        Ok(secs) if secs.is_finite() && secs > 0.0 => Some(Duration::from_secs_f64(secs)),
+        _ => None,
+    }
+}
+
+/// Parses a size formatted as `<width>x<height>`
+fn parse_window_size(value: &str) -> Option<(u32, u32)> {
+    let (w, h) = value.split_once('x')?;
+    Some((parse_pixels(w)?, parse_pixels(h)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        config.apply_args(args.iter().map(|arg| arg.to_string()))?;
+        Ok(config)
+    }
+
+    #[test]
+    fn parse_args() {
+        assert_eq!(parse(&[]).unwrap(), Config::default());
+
+        let config = parse(&[
+            "--lat",
+            "40.6",
+            "--zoom=12",
+            "--width",
+            "800",
+            "--base-layer",
+            "Street",
+            "--airports=data/airports.txt",
+            "--airport-format",
+            "CSV",
+            "--offline=true",
+            "--detail-window",
+            "on",
+            "--plane-scale=1.5",
+            "--idle-secs",
+            "90",
+        ])
+        .unwrap();
+        let view = config.start_view.unwrap();
+        assert_eq!(view.latitude, 40.6);
+        assert_eq!(view.longitude, DEFAULT_LONGITUDE);
+        assert_eq!(view.zoom, 12.0);
+        assert_eq!(config.window_size, (800, DEFAULT_HEIGHT));
+        assert_eq!(config.base_layer, TileKind::Street);
+        assert_eq!(
+            config.airports_path,
+            Some(PathBuf::from("data/airports.txt"))
+        );
+        assert_eq!(config.airport_format, Some(AirportFormat::Csv));
+        assert!(config.offline);
+        assert!(config.detail_window);
+        assert_eq!(config.plane_scale, Some(1.5));
+        assert_eq!(config.airport_scale, None);
+        assert_eq!(config.idle.after_secs, 90.0);
+
+        assert_eq!(parse(&["--help"]), Err(ConfigError::HelpRequested));
+        assert_eq!(
+            parse(&["--serial"]),
+            Err(ConfigError::UnknownFlag("--serial".to_owned()))
+        );
+        assert_eq!(
+            parse(&["--lon"]),
+            Err(ConfigError::MissingValue("--lon".to_owned()))
+        );
+        assert!(matches!(
+            parse(&["--lat", "95"]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            parse(&["--height", "0"]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            parse(&["--offline", "yes please"]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            parse(&["--airport-scale", "10"]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            parse(&["--cache-max-mb", "-5"]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert_eq!(parse_cache_size("512"), Some(512));
+        assert_eq!(parse_cache_size("0"), None);
+        assert_eq!(parse_window_size("1920x1080"), Some((1920, 1080)));
+        assert_eq!(parse_window_size("1920"), None);
+    }
+
+    #[test]
+    fn file_view_is_only_a_default() {
+        let mut config = Config::default();
+        config.apply_toml("lat = 40.6\nzoom = 10").unwrap();
+        //The view saved by the last run is still restored
+        assert_eq!(config.start_view, None);
+        let view = config.default_view.unwrap();
+        assert_eq!(view.latitude, 40.6);
+        assert_eq!(view.longitude, DEFAULT_LONGITUDE);
+        assert_eq!(view.zoom, 10.0);
+
+        //Options on the command line replace it, keeping the file's values for the rest
+        config.apply_args(["--lon=-75".to_owned()]).unwrap();
+        let view = config.start_view.unwrap();
+        assert_eq!(view.latitude, 40.6);
+        assert_eq!(view.longitude, -75.0);
+        assert_eq!(config.default_view.unwrap().longitude, DEFAULT_LONGITUDE);
+    }
+
+    #[test]
+    fn file_then_args() {
+        let mut config = Config::default();
+        config
+            .apply_toml(
+                r##"
+                lat = 40.6
+                zoom = 10
+                base-layer = "street"
+                weather-ramp = "viridis"
+                units = "nautical"
+                offline = true
+                airport-scale = 2
+                cache-max-mb = 4096
+
+                [tiles]
+                street = "https://example.com/{z}/{x}/{y}.png"
+                street_mirrors = ["https://mirror.example.com/{z}/{x}/{y}.png"]
+
+                [vector]
+                url = "https://example.com/{z}/{x}/{y}.pbf"
+                water = "#0000ff"
+                major-road = { color = "#ff0000", width = 4 }
+
+                [[airlines]]
+                prefix = "JBU"
+                name = "JetBlue"
+
+                [[airline-styles]]
+                prefix = "JBU"
+                color = "#003876"
+
+                [clustering]
+                distance = 60
+
+                [range-rings]
+                spacing = 25
+
+                [prefetch]
+                next-zoom = true
+
+                [idle]
+                after-secs = 120
+                points = [[29.18, -81.05], [28.43, -81.31]]
+                zoom = 9
+
+                [airport-labels]
+                code-min-zoom = 17
+
+                [ui]
+                weather = true
+                "##,
+            )
+            .unwrap();
+        config
+            .apply_args([
+                "--zoom".to_owned(),
+                "5".to_owned(),
+                "--frame-history=600".to_owned(),
+                "--cache-max-mb=1024".to_owned(),
+            ])
+            .unwrap();
+
+        let view = config.start_view.unwrap();
+        assert_eq!(view.latitude, 40.6);
+        assert_eq!(view.zoom, 5.0);
+        assert_eq!(config.base_layer, TileKind::Street);
+        assert_eq!(
+            config.tile_urls.street,
+            "https://example.com/{z}/{x}/{y}.png"
+        );
+        assert_eq!(
+            config.tile_urls.dark_street,
+            TileUrls::default().dark_street
+        );
+        assert_eq!(config.tile_urls.street_mirrors.len(), 1);
+        assert!(config.tile_urls.dark_street_mirrors.is_empty());
+        assert_eq!(config.vector.url, "https://example.com/{z}/{x}/{y}.pbf");
+        assert_eq!(config.vector.water, "#0000ff");
+        assert_eq!(config.vector.major_road.width, 4.0);
+        assert_eq!(config.vector.land, VectorSettings::default().land);
+        assert_eq!(config.airlines[0].plane_type, PlaneType::Commercial);
+        assert_eq!(config.airline_styles[0].color.as_deref(), Some("#003876"));
+        assert_eq!(config.airline_styles[0].icon, None);
+        assert_eq!(config.frame_history, 600);
+        assert_eq!(config.cache_max_mb, 1024);
+        assert!(config.clustering.enabled);
+        assert_eq!(config.clustering.distance, 60.0);
+        assert_eq!(config.weather_ramp, ColorRamp::Viridis);
+        assert_eq!(config.units, Units::Nautical);
+        assert_eq!(config.range_rings.spacing, 25.0);
+        assert_eq!(config.range_rings.count, 5);
+        assert_eq!(config.prefetch.radius, 1);
+        assert!(config.prefetch.next_zoom);
+        assert!(config.ui.weather);
+        assert!(config.ui.airports);
+        assert!(config.offline);
+        assert_eq!(config.airport_scale, None);
+        assert_eq!(config.default_airport_scale, Some(2.0));
+        assert_eq!(config.idle.after_secs, 120.0);
+        assert_eq!(config.idle.points.len(), 2);
+        assert_eq!(config.idle.zoom, Some(9.0));
+        assert_eq!(config.idle.lap_secs, IdleSettings::default().lap_secs);
+        assert_eq!(config.airport_labels.code_min_zoom, 17.0);
+        assert_eq!(
+            config.airport_labels.name_min_zoom,
+            AirportLabelSettings::default().name_min_zoom
+        );
+
+        //Syntax errors point at the line they are on
+        let err = Config::default()
+            .apply_toml("lat = 1\nlon = \n")
+            .unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{}", err);
+        assert!(matches!(
+            Config::default().apply_toml("serial = \"/dev/ttyUSB0\""),
+            Err(ConfigError::Toml(_))
+        ));
+        assert!(matches!(
+            Config::default().apply_toml("lon = 200"),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            Config::default().apply_toml("[[airline-styles]]\nprefix = \"JBU\"\ncolor = \"blue\""),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            Config::default()
+                .apply_toml("[vector]\nminor-road = { color = \"#ffffff\", width = 0 }"),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            Config::default().apply_toml("[tiles]\nstreet_mirrors = [\"https://example.com\"]"),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            Config::default().apply_toml("[range-rings]\ncount = 0"),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            Config::default().apply_toml("[prefetch]\nradius = 4"),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            Config::default().apply_toml("[idle]\npoints = [[95, 0]]"),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            Config::default().apply_toml("[airport-labels]\nname-min-zoom = -1"),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            Config::default().apply_toml("cache-max-mb = 0"),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+
+        let missing = std::env::temp_dir().join("flight_tracking_missing_config.toml");
+        assert_eq!(Config::default().apply_file(&missing), Ok(()));
+    }
+
+    #[test]
+    fn env_overrides_file_and_args_override_env() {
+        //No other test sets this variable, so changing it doesn't race with them
+        let mut config = Config::default();
+        config.apply_toml("cache-max-mb = 4096").unwrap();
+        std::env::set_var(CACHE_MAX_MB_VAR, "2048");
+        config.apply_env();
+        assert_eq!(config.cache_max_mb, 2048);
+        config
+            .apply_args(["--cache-max-mb".to_owned(), "1024".to_owned()])
+            .unwrap();
+        assert_eq!(config.cache_max_mb, 1024);
+
+        //Invalid values from the environment are ignored
+        std::env::set_var(CACHE_MAX_MB_VAR, "0");
+        config.apply_env();
+        assert_eq!(config.cache_max_mb, 1024);
+        std::env::remove_var(CACHE_MAX_MB_VAR);
+    }
+}
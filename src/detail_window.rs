@@ -0,0 +1,214 @@
+//! A second window showing the selected plane and the clock, so that the map can fill one monitor
+//! while the details are on another
+
+use conrod_core::{text::Font, widget, widget_ids, Colorable, Positionable, Widget};
+use glium::glutin::event::{Event, WindowEvent};
+use glium::Surface;
+
+use crate::{Plane, Units};
+
+const WIDTH: f64 = 360.0;
+const HEIGHT: f64 = 300.0;
+const FONT_SIZE: u32 = 16;
+const LINE_HEIGHT: f64 = 24.0;
+const MARGIN: f64 = 16.0;
+
+widget_ids!(struct DetailIds {
+    lines[],
+});
+
+/// The second window, with its own conrod `Ui` drawing the lines it was last given
+pub struct DetailWindow {
+    display: glium::Display,
+    ui: conrod_core::Ui,
+    ids: DetailIds,
+    renderer: conrod_glium::Renderer,
+    /// Always empty, since the window only draws text
+    image_map: conrod_core::image::Map<glium::Texture2d>,
+    font: conrod_core::text::font::Id,
+    lines: Vec<String>,
+    closed: bool,
+}
+
+impl DetailWindow {
+    /// Opens the window. It can only be opened before the event loop starts running
+    pub fn new<T>(event_loop: &glium::glutin::event_loop::EventLoop<T>) -> Result<Self, String> {
+        let window = glium::glutin::window::WindowBuilder::new()
+            .with_title("Flight Tracker Details")
+            .with_inner_size(glium::glutin::dpi::LogicalSize::new(WIDTH, HEIGHT));
+        let context = glium::glutin::ContextBuilder::new().with_vsync(false);
+        let display =
+            glium::Display::new(window, context, event_loop).map_err(|err| err.to_string())?;
+
+        let mut ui = conrod_core::UiBuilder::new([WIDTH, HEIGHT]).build();
+        let ids = DetailIds::new(ui.widget_id_generator());
+        let b612_ttf = include_bytes!("../assets/fonts/B612Mono/B612Mono-Regular.ttf");
+        let font = ui
+            .fonts
+            .insert(Font::from_bytes(b612_ttf).expect("Failed to decode font"));
+        let renderer = conrod_glium::Renderer::new(&display).map_err(|err| err.to_string())?;
+
+        Ok(Self {
+            display,
+            ui,
+            ids,
+            renderer,
+            image_map: conrod_core::image::Map::new(),
+            font,
+            lines: Vec::new(),
+            closed: false,
+        })
+    }
+
+    fn window_id(&self) -> glium::glutin::window::WindowId {
+        self.display.gl_window().window().id()
+    }
+
+    /// Handles `event` if it is meant for this window, drawing the window if it needs it.
+    ///
+    /// Returns true if the event was for this window, in which case the main window should ignore
+    /// it
+    pub fn handle_event(&mut self, event: &Event<'_, ()>) -> bool {
+        match event {
+            Event::WindowEvent {
+                window_id,
+                event: window_event,
+            } if *window_id == self.window_id() => {
+                match window_event {
+                    WindowEvent::CloseRequested => self.closed = true,
+                    WindowEvent::Resized(size) => self.display.gl_window().resize(*size),
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        self.display.gl_window().resize(**new_inner_size)
+                    }
+                    _ => {}
+                }
+                let window = self.display.gl_window();
+                if let Some(event) = crate::support::convert_event(event, window.window()) {
+                    self.ui.handle_event(event);
+                }
+                true
+            }
+            Event::RedrawRequested(window_id) if *window_id == self.window_id() => {
+                self.draw();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true once the user has closed the window
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Shows `lines`, redrawing the window if they changed
+    pub fn update(&mut self, lines: Vec<String>) {
+        if lines != self.lines {
+            self.lines = lines;
+            self.display.gl_window().window().request_redraw();
+        }
+    }
+
+    fn draw(&mut self) {
+        {
+            let ui = &mut self.ui.set_widgets();
+            self.ids
+                .lines
+                .resize(self.lines.len(), &mut ui.widget_id_generator());
+            for (i, line) in self.lines.iter().enumerate() {
+                widget::Text::new(line)
+                    .top_left_with_margins(MARGIN + i as f64 * LINE_HEIGHT, MARGIN)
+                    .color(conrod_core::color::WHITE)
+                    .font_size(FONT_SIZE)
+                    .font_id(self.font)
+                    .set(self.ids.lines[i], ui);
+            }
+        }
+
+        let mut target = self.display.draw();
+        target.clear_color(0.05, 0.05, 0.08, 1.0);
+        self.renderer
+            .fill(&self.display, self.ui.draw(), &self.image_map);
+        self.renderer
+            .draw(&self.display, &mut target, &self.image_map)
+            .unwrap();
+        target.finish().unwrap();
+    }
+}
+
+/// Returns the lines describing `plane` in the detail window in `units`, or a hint to pick one
+pub fn plane_lines(plane: Option<&Plane>, units: Units) -> Vec<String> {
+    let plane = match plane {
+        Some(plane) => plane,
+        None => {
+            return vec![
+                String::from("No plane selected"),
+                String::from("Click a plane on the map"),
+            ]
+        }
+    };
+    let unknown = || String::from("Unknown");
+    vec![
+        format!("Callsign: {}", plane.label().unwrap_or("Unknown")),
+        format!("Airline: {}", plane.airline.to_str()),
+        format!("Type: {}", plane.plane_type.to_str()),
+        format!("Lat: {:.4}  Long: {:.4}", plane.latitude, plane.longitude),
+        format!(
+            "Heading: {}",
+            plane
+                .heading_degrees()
+                .map_or_else(unknown, |heading| format!("{:.0}°", heading))
+        ),
+        format!(
+            "Altitude: {}",
+            plane
+                .altitude()
+                .map_or_else(unknown, |meters| units.format_altitude(meters as f64))
+        ),
+        format!(
+            "Speed: {}",
+            plane
+                .velocity
+                .map_or_else(unknown, |speed| units.format_speed(speed as f64))
+        ),
+        format!(
+            "Vertical: {}",
+            plane
+                .vertical_rate
+                .map_or_else(unknown, |rate| units.format_vertical_rate(rate as f64))
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Airline, PlaneType};
+
+    #[test]
+    fn plane_lines_show_units_and_unknowns() {
+        assert_eq!(plane_lines(None, Units::Nautical)[0], "No plane selected");
+
+        let mut plane = Plane::new(
+            -81.05,
+            29.18,
+            Some(0.0),
+            String::from("AAL123  "),
+            Airline::Unknown,
+            PlaneType::Commercial,
+        );
+        plane.baro_altitude = Some(3048.0);
+        plane.velocity = Some(100.0);
+        let lines = plane_lines(Some(&plane), Units::Nautical);
+        assert_eq!(lines[0], "Callsign: AAL123");
+        assert_eq!(lines[3], "Lat: 29.1800  Long: -81.0500");
+        assert_eq!(lines[4], "Heading: 90°");
+        assert_eq!(lines[5], "Altitude: 10000 ft");
+        assert_eq!(lines[6], "Speed: 194 kt");
+        assert_eq!(lines[7], "Vertical: Unknown");
+
+        let lines = plane_lines(Some(&plane), Units::Metric);
+        assert_eq!(lines[5], "Altitude: 3048 m");
+        assert_eq!(lines[6], "Speed: 360 km/h");
+    }
+}
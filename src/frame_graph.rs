@@ -0,0 +1,153 @@
+//! A scrolling graph of recent frame times, shown with the debug menu so that hitches stand out as
+//! spikes
+
+use std::collections::VecDeque;
+
+use conrod_core::{
+    widget::{Line, Rectangle, Text},
+    Colorable, Positionable, UiCell, Widget,
+};
+
+/// The default number of frames shown by the graph
+pub const DEFAULT_FRAME_HISTORY: usize = 240;
+
+/// The size of the graph in logical pixels
+const GRAPH_SIZE: [f64; 2] = [240.0, 80.0];
+
+/// Frame times in milliseconds that get a reference line, for 60 and 30 FPS
+const REFERENCE_MS: [f64; 2] = [1000.0 / 60.0, 1000.0 / 30.0];
+
+/// The top of the graph is never lower than this, so that the reference lines are always shown
+const MIN_GRAPH_MS: f64 = 40.0;
+
+/// The most recent frame times in milliseconds. Once full, the oldest frame is dropped for each
+/// new one
+pub struct FrameTimeHistory {
+    capacity: usize,
+    times: VecDeque<f64>,
+}
+
+impl FrameTimeHistory {
+    /// Creates an empty history that keeps the last `capacity` frames
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(2);
+        Self {
+            capacity,
+            times: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn push(&mut self, frame_time_ms: f64) {
+        if self.times.len() == self.capacity {
+            self.times.pop_front();
+        }
+        self.times.push_back(frame_time_ms);
+    }
+
+    /// The frame time shown at the top of the graph: the slowest frame, or [`MIN_GRAPH_MS`] if
+    /// every frame was faster
+    pub fn graph_max_ms(&self) -> f64 {
+        self.times.iter().copied().fold(MIN_GRAPH_MS, f64::max)
+    }
+
+    /// Returns a point for each frame in a graph of `size`, measured from its bottom left corner.
+    ///
+    /// Frames are spread evenly over the width of a full history, so the newest frame is always
+    /// on the right edge and older frames scroll to the left
+    pub fn graph_points(&self, size: [f64; 2]) -> Vec<[f64; 2]> {
+        let [width, height] = size;
+        let max_ms = self.graph_max_ms();
+        let step = width / (self.capacity - 1) as f64;
+        let first_x = width - self.times.len().saturating_sub(1) as f64 * step;
+        self.times
+            .iter()
+            .enumerate()
+            .map(|(i, ms)| [first_x + i as f64 * step, ms / max_ms * height])
+            .collect()
+    }
+}
+
+/// Draws the graph of `history` at the top of the window, under the airport search box
+pub fn draw(
+    history: &FrameTimeHistory,
+    ui: &mut UiCell,
+    ids: &mut crate::Ids,
+    font: conrod_core::text::font::Id,
+) {
+    let [width, height] = GRAPH_SIZE;
+    let left = -width / 2.0;
+    let bottom = ui.win_h / 2.0 - 50.0 - height;
+    let max_ms = history.graph_max_ms();
+
+    Rectangle::fill(GRAPH_SIZE)
+        .x_y(0.0, bottom + height / 2.0)
+        .color(conrod_core::color::BLACK.alpha(0.6))
+        .set(ids.frame_graph_background, ui);
+
+    ids.frame_graph_reference_lines
+        .resize(REFERENCE_MS.len(), &mut ui.widget_id_generator());
+    ids.frame_graph_labels
+        .resize(REFERENCE_MS.len(), &mut ui.widget_id_generator());
+    for (i, ms) in REFERENCE_MS.iter().enumerate() {
+        let y = bottom + ms / max_ms * height;
+        Line::new([left, y], [left + width, y])
+            .x_y(0.0, 0.0)
+            .color(conrod_core::color::LIGHT_GRAY.alpha(0.5))
+            .thickness(1.0)
+            .set(ids.frame_graph_reference_lines[i], ui);
+        Text::new(&format!("{:.1}ms", ms))
+            .x_y(left + width + 22.0, y)
+            .color(conrod_core::color::WHITE)
+            .font_size(8)
+            .font_id(font)
+            .set(ids.frame_graph_labels[i], ui);
+    }
+
+    let points = history.graph_points(GRAPH_SIZE);
+    let segments = points.len().saturating_sub(1);
+    ids.frame_graph_lines
+        .resize(segments, &mut ui.widget_id_generator());
+    for (i, pair) in points.windows(2).enumerate() {
+        let start = [left + pair[0][0], bottom + pair[0][1]];
+        let end = [left + pair[1][0], bottom + pair[1][1]];
+        //Frames slower than 30 FPS are red
+        let color = if pair[1][1] > REFERENCE_MS[1] / max_ms * height {
+            conrod_core::color::LIGHT_RED
+        } else {
+            conrod_core::color::LIGHT_GREEN
+        };
+        Line::new(start, end)
+            .x_y(0.0, 0.0)
+            .color(color)
+            .thickness(1.0)
+            .set(ids.frame_graph_lines[i], ui);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_scrolls_and_scales() {
+        let mut history = FrameTimeHistory::new(5);
+        assert!(history.graph_points(GRAPH_SIZE).is_empty());
+        assert_eq!(history.graph_max_ms(), MIN_GRAPH_MS);
+
+        history.push(10.0);
+        history.push(20.0);
+        //The newest frame is on the right edge
+        let points = history.graph_points([100.0, 40.0]);
+        assert_eq!(points, vec![[75.0, 10.0], [100.0, 20.0]]);
+
+        for _ in 0..5 {
+            history.push(8.0);
+        }
+        history.push(80.0);
+        //Only the last five frames are kept, and the spike sets the scale
+        let points = history.graph_points([100.0, 40.0]);
+        assert_eq!(points.len(), 5);
+        assert_eq!(points[0], [0.0, 4.0]);
+        assert_eq!(points[4], [100.0, 40.0]);
+    }
+}
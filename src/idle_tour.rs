@@ -0,0 +1,192 @@
+//! An ambient mode for display walls: once nobody has touched the app for a while, the camera
+//! slowly tours a list of places or circles a point, until the next input
+
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+/// How the idle tour moves the camera, set in the `[idle]` table of the config file
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct IdleSettings {
+    /// Seconds without input before the tour starts. Zero turns the tour off
+    pub after_secs: f64,
+    /// Places visited in a loop, as `[latitude, longitude]`. With one place the camera circles
+    /// it, and with none it circles wherever the view was when the tour started
+    pub points: Vec<[f64; 2]>,
+    /// The radius in kilometers of the circle flown around a point
+    pub orbit_radius: f64,
+    /// Seconds to travel from one place to the next, or once around the circle
+    pub lap_secs: f64,
+    /// The zoom to tour at, on the same scale as `--zoom`, or `None` to keep the current zoom
+    pub zoom: Option<f64>,
+}
+
+impl Default for IdleSettings {
+    fn default() -> Self {
+        Self {
+            after_secs: 0.0,
+            points: Vec::new(),
+            orbit_radius: 20.0,
+            lap_secs: 60.0,
+            zoom: None,
+        }
+    }
+}
+
+impl IdleSettings {
+    /// Returns where the camera should be `elapsed_secs` into the tour, in degrees of latitude
+    /// and longitude. `origin` is circled if no places are configured.
+    ///
+    /// The camera eases in and out of each place, so that it lingers there before moving on
+    pub fn position(&self, origin: (f64, f64), elapsed_secs: f64) -> (f64, f64) {
+        let laps = elapsed_secs.max(0.0) / self.lap_secs;
+        if self.points.len() < 2 {
+            let center = self.points.first().map_or(origin, |&[lat, lng]| (lat, lng));
+            let bearing = laps.fract() * 360.0;
+            return crate::util::destination_point(
+                center.0,
+                center.1,
+                bearing,
+                self.orbit_radius * 1000.0,
+            );
+        }
+
+        let leg = laps.floor() as usize % self.points.len();
+        let [lat1, lng1] = self.points[leg];
+        let [lat2, lng2] = self.points[(leg + 1) % self.points.len()];
+        let t = laps.fract();
+        let eased = t * t * (3.0 - 2.0 * t);
+
+        //Move in a straight line on the map, the short way around the antimeridian
+        let x1 = crate::util::x_from_longitude(lng1);
+        let x2 = crate::util::x_from_longitude(lng2);
+        let x2 = x2 + (x1 - x2).round();
+        let y1 = crate::util::y_from_latitude(lat1);
+        let y2 = crate::util::y_from_latitude(lat2);
+        let x = crate::util::lerp(x1, x2, eased);
+        let y = crate::util::lerp(y1, y2, eased);
+        (
+            crate::util::latitude_from_y(y),
+            crate::util::longitude_from_x(x.rem_euclid(1.0)),
+        )
+    }
+}
+
+/// Starts the tour once there has been no input for [`IdleSettings::after_secs`], and stops it on
+/// the next input
+pub struct IdleTour {
+    pub settings: IdleSettings,
+    last_input: Instant,
+    /// When the tour started, and the center of the view at the time
+    touring: Option<(Instant, (f64, f64))>,
+}
+
+impl IdleTour {
+    pub fn new(settings: IdleSettings, now: Instant) -> Self {
+        Self {
+            settings,
+            last_input: now,
+            touring: None,
+        }
+    }
+
+    pub fn is_touring(&self) -> bool {
+        self.touring.is_some()
+    }
+
+    /// Records input from the user, which stops the tour. Returns true if the tour was running
+    pub fn input(&mut self, now: Instant) -> bool {
+        self.last_input = now;
+        self.touring.take().is_some()
+    }
+
+    /// Returns where the camera should be at `now`, or `None` if the tour isn't running.
+    ///
+    /// Starts the tour if it is turned on and there hasn't been any input for long enough.
+    /// `center` is the current center of the view, which is circled if no places are configured
+    pub fn update(&mut self, now: Instant, center: (f64, f64)) -> Option<(f64, f64)> {
+        if self.settings.after_secs <= 0.0 {
+            return None;
+        }
+        if self.touring.is_none() {
+            let idle = now.saturating_duration_since(self.last_input);
+            if idle < Duration::from_secs_f64(self.settings.after_secs) {
+                return None;
+            }
+            self.touring = Some((now, center));
+        }
+        let (start, origin) = self.touring?;
+        let elapsed = now.saturating_duration_since(start).as_secs_f64();
+        Some(self.settings.position(origin, elapsed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: (f64, f64), b: (f64, f64)) -> bool {
+        (a.0 - b.0).abs() < 1e-6 && (a.1 - b.1).abs() < 1e-6
+    }
+
+    #[test]
+    fn tours_places_in_a_loop() {
+        let settings = IdleSettings {
+            points: vec![[29.0, -81.0], [28.0, -82.0], [30.0, -80.0]],
+            lap_secs: 10.0,
+            ..IdleSettings::default()
+        };
+        let origin = (0.0, 0.0);
+        assert!(close(settings.position(origin, 0.0), (29.0, -81.0)));
+        assert!(close(settings.position(origin, 10.0), (28.0, -82.0)));
+        //After the last place the tour goes back to the first
+        let (lat, lng) = settings.position(origin, 25.0);
+        assert!((lng + 80.5).abs() < 1e-6);
+        assert!(lat > 29.0 && lat < 30.0);
+        assert!(close(settings.position(origin, 30.0), (29.0, -81.0)));
+
+        //Legs across the antimeridian go the short way
+        let settings = IdleSettings {
+            points: vec![[0.0, 170.0], [0.0, -170.0]],
+            lap_secs: 10.0,
+            ..IdleSettings::default()
+        };
+        assert!(settings.position(origin, 5.0).1.abs() > 170.0);
+    }
+
+    #[test]
+    fn circles_a_point_until_input() {
+        let settings = IdleSettings {
+            after_secs: 60.0,
+            orbit_radius: 10.0,
+            lap_secs: 40.0,
+            ..IdleSettings::default()
+        };
+        let center = (29.0, -81.0);
+        let start = Instant::now();
+        let mut tour = IdleTour::new(settings, start);
+        assert_eq!(tour.update(start + Duration::from_secs(59), center), None);
+
+        let touring = start + Duration::from_secs(60);
+        let north = tour.update(touring, center).unwrap();
+        assert!(north.0 > center.0);
+        assert!((north.1 - center.1).abs() < 1e-6);
+        //A quarter of a lap later the camera is east of where the tour started, even though the
+        //view moved
+        let east = tour
+            .update(touring + Duration::from_secs(10), north)
+            .unwrap();
+        assert!(east.1 > center.1);
+        let distance = crate::util::haversine_distance(center.0, center.1, east.0, east.1);
+        assert!((distance - 10_000.0).abs() < 1.0);
+
+        assert!(tour.input(touring + Duration::from_secs(11)));
+        assert!(!tour.is_touring());
+        assert_eq!(tour.update(touring + Duration::from_secs(12), center), None);
+        assert!(!tour.input(touring + Duration::from_secs(13)));
+
+        let mut off = IdleTour::new(IdleSettings::default(), start);
+        assert_eq!(off.update(start + Duration::from_secs(3600), center), None);
+    }
+}
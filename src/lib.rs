@@ -1,75 +1,225 @@
-use std::time::{Duration, Instant};
+use std::{
+    sync::{atomic::AtomicBool, Arc},
+    time::{Duration, Instant},
+};
 
 use conrod_core::{
-    text::Font, widget, widget_ids, Color, Colorable, Positionable, Sizeable, Widget,
+    text::Font, widget, widget_ids, Color, Colorable, Labelable, Positionable, Sizeable, Widget,
 };
 use glam::DVec2;
 use glium::Surface;
 
 mod airports;
 mod button_widget;
+mod clock;
+mod config;
+mod detail_window;
+mod frame_graph;
+mod idle_tour;
 mod loading_renderer;
 mod map;
 mod map_renderer;
+mod markers;
+mod measure;
+mod minimap;
+mod palette;
+mod persist;
 mod plane_renderer;
+mod quota;
+mod range_rings;
+mod region;
 mod request_plane;
+mod shortcuts;
 mod support;
+mod theme;
 mod tile;
+mod touch;
+mod track_recorder;
+mod track_up;
+mod trails;
 mod ui_filter;
 mod util;
+mod waypoints;
 
 pub use airports::*;
 pub use button_widget::*;
+pub use clock::*;
+pub use config::*;
+pub use detail_window::DetailWindow;
+pub use idle_tour::*;
 pub use loading_renderer::LoadingScreenRenderer;
 pub use map::*;
 pub use map_renderer::*;
+pub use markers::*;
+pub use measure::*;
+pub use minimap::Minimap;
+pub use palette::*;
+pub use persist::*;
 pub use plane_renderer::*;
+pub use quota::*;
+pub use range_rings::*;
+pub use region::*;
 pub use request_plane::*;
+pub use shortcuts::*;
 use statrs::statistics::OrderStatistics;
+pub use theme::*;
 pub use tile::*;
+pub use touch::*;
+pub use track_recorder::*;
+pub use track_up::RotatedCanvas;
+pub use trails::*;
 pub use ui_filter::*;
 pub use util::*;
-
-const WIDTH: u32 = 1280;
-const HEIGHT: u32 = 720;
+pub use waypoints::*;
+
+/// The number of rows in the column of pill buttons, including the weather controls that are
+/// only shown while the weather layer is on
+const BUTTON_ROWS: usize = 16;
+
+/// How often to wake up and check for new data when nothing on screen is changing
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// The number of frames of profiling samples used for the statistics in the debug menu
+const PROFILE_WINDOW_FRAMES: usize = 120;
+/// How opaque weather tiles are when the app starts
+const DEFAULT_WEATHER_OPACITY: f32 = 1.0;
+/// The zoom level used when jumping to an airport found with the search box
+const AIRPORT_SEARCH_ZOOM: f64 = 21.0;
+/// How long the message for a failed airport search stays on screen
+const AIRPORT_SEARCH_ERROR_TIME: Duration = Duration::from_secs(3);
+/// How long to wait on exit for background work, such as tile downloads being written to the disk
+/// cache, to finish
+const RUNTIME_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
 const MAX_ZOOM_LEVEL: u32 = 20;
 
 widget_ids!(pub struct Ids {
     debug_menu[],
+    frame_graph_background,
+    frame_graph_lines[],
+    frame_graph_reference_lines[],
+    frame_graph_labels[],
     text,
     viewport,
     map_images[],
-    satellite_tiles[],
+    base_tiles[],
+    vector_layers[],
     tiles[],
     weather_tiles[],
     weather_button,
+    weather_opacity_slider,
+    radar_loop_button,
+    weather_ramp_button,
     airplane_button,
     debug_button,
     airport_button,
     bench_button,
+    base_layer_button,
+    coord_format_button,
+    theme_button,
+    freeze_button,
+    measure_button,
+    units_button,
+    measure_markers[],
+    measure_line,
+    measure_text,
+    route_button,
+    route_mode_button,
+    range_rings_button,
+    range_ring_lines[],
+    range_ring_labels[],
+    range_ring_center,
+    waypoint_markers[],
+    waypoint_labels[],
+    waypoint_lines[],
+    waypoint_panel,
+    region_button,
+    region_clear_button,
+    marker_size_button,
+    marker_size_sliders[],
+    region_lines[],
+    region_corners[],
+    region_count,
+    track_up_button,
+    north_indicator_background,
+    north_indicator_needle,
+    north_indicator_tail,
+    north_indicator_label,
     latitude_lines[],
     latitude_text[],
     longitude_lines[],
     longitude_text[],
+    scale_bar,
+    scale_bar_background,
+    scale_bar_text,
     filer_button[],
+    airport_filter_buttons[],
+    altitude_filter_sliders[],
+    vertical_trend_buttons[],
+    unknown_altitude_button,
     airports[],
+    airport_labels[],
+    route_lines[],
+    airport_search,
+    airport_search_error,
+    airport_suggestions[],
+    offline_badge,
+    offline_badge_text,
+    quota_badge,
+    quota_badge_text,
+    track_badge,
+    track_badge_text,
+    clock_background,
+    clock_lines[],
+    help_background,
+    help_text,
+    minimap_background,
+    minimap_tiles[],
+    minimap_viewport,
+    minimap_border,
+    markers[],
+    marker_labels[],
     planes[],
     square,
     left_screen_details[],
+    altitude_legend_swatches[],
+    altitude_legend_labels[],
+    airline_legend_rows[],
+    airline_legend_swatches[],
+    plane_clusters[],
+    plane_cluster_labels[],
+    plane_labels[],
+    plane_coloring_button,
+    trails_button,
+    follow_button,
     hovering_plane_details[],
+    airport_tooltip,
+    airport_tooltip_lines[],
     loading_background,
+    loading_text,
 });
 
 use std::fmt::Write;
 pub use util::MAP_PERF_DATA;
 
-/// The app's "main" function. Our real main inside `main.rs` calls this function
+/// The app's "main" function. Our real main inside `main.rs` calls this function.
+///
+/// Reads the [`Config`] from the command line, exiting with a usage message if it is invalid
 pub fn run_app() {
+    run_app_with_markers(
+        Config::from_env_or_exit(),
+        Arc::new(parking_lot::Mutex::new(MarkerLayer::default())),
+    );
+}
+
+/// Runs the app with `config`, drawing the markers in `markers` on the map.
+///
+/// Keep a clone of `markers` to add and remove markers from another thread while the app runs
+pub fn run_app_with_markers(config: Config, markers: Arc<parking_lot::Mutex<MarkerLayer>>) {
     // Create our UI's event loop
     let event_loop = glium::glutin::event_loop::EventLoop::new();
+    let (width, height) = config.window_size;
     let window = glium::glutin::window::WindowBuilder::new()
         .with_title("Flight Tracker")
-        .with_inner_size(glium::glutin::dpi::LogicalSize::new(WIDTH, HEIGHT));
+        .with_inner_size(glium::glutin::dpi::LogicalSize::new(width, height));
 
     let context = glium::glutin::ContextBuilder::new()
         .with_vsync(false)
@@ -77,8 +227,28 @@ pub fn run_app() {
 
     let display = glium::Display::new(window, context, &event_loop).unwrap();
 
-    let mut map_ui = conrod_core::UiBuilder::new([WIDTH as f64, HEIGHT as f64]).build();
-    let mut overlay_ui = conrod_core::UiBuilder::new([WIDTH as f64, HEIGHT as f64]).build();
+    // Windows can only be opened before the event loop runs
+    let mut detail_panel = match config.detail_window {
+        true => match DetailWindow::new(&event_loop) {
+            Ok(window) => Some(window),
+            Err(err) => {
+                println!("Failed to open the detail window: {}", err);
+                None
+            }
+        },
+        false => None,
+    };
+
+    // The window manager may not honor the requested size, so use what we actually got. Later
+    // size changes reach the `Ui`s as resize events
+    let window_size: glium::glutin::dpi::LogicalSize<f64> = {
+        let gl_window = display.gl_window();
+        let window = gl_window.window();
+        window.inner_size().to_logical(window.scale_factor())
+    };
+    let ui_size = [window_size.width, window_size.height];
+    let mut map_ui = conrod_core::UiBuilder::new(ui_size).build();
+    let mut overlay_ui = conrod_core::UiBuilder::new(ui_size).build();
 
     // Generate our widget identifiers
     let mut map_ids = Ids::new(map_ui.widget_id_generator());
@@ -118,38 +288,184 @@ pub fn run_app() {
     let mut overlay_renderer = conrod_glium::Renderer::new(&display).unwrap();
     let mut plane_renderer = PlaneRenderer::new(&display);
     let mut loading_renderer = LoadingScreenRenderer::new(&display);
+    let mut rotated_canvas = RotatedCanvas::new(&display);
 
     let mut last_time = std::time::Instant::now();
     let mut frame_time_ms = 0.0;
 
     let runtime = tokio::runtime::Runtime::new().expect("Unable to create Tokio runtime!");
 
-    let mut pipelines = tile::pipelines(&runtime);
-    let mut plane_requester = PlaneRequester::new(&runtime);
+    // Shared by everything that makes network requests, and switched with the offline shortcut
+    let offline = Arc::new(AtomicBool::new(config.offline));
+    let weather_index = Arc::new(WeatherIndex::new());
+    let mut pipelines = tile::pipelines(
+        &runtime,
+        &config.tile_urls,
+        weather_index.clone(),
+        &offline,
+        config.cache_max_mb,
+    );
+    for pipeline in pipelines.values_mut() {
+        pipeline.set_prefetch(config.prefetch);
+    }
+    let mut vector_tiles =
+        tile::vector_pipeline(&runtime, &config.vector, &offline, config.cache_max_mb);
+    let mut radar_loop = RadarLoop::new(weather_index, offline.clone());
+    let mut minimap = Minimap::new(&pipelines, &runtime);
+    let mut minimap_enabled = true;
+    let mut legend_enabled = true;
+    let mut airlines = AirlineRegistry::default();
+    for airline in &config.airlines {
+        let shadowed =
+            airlines.register_dynamic(&airline.prefix, &airline.name, airline.plane_type);
+        if let Some((shadowed, _)) = shadowed {
+            println!(
+                "Warning: airline {} replaces {} for callsign prefix {}",
+                airline.name,
+                shadowed.to_str(),
+                airline.prefix
+            );
+        }
+    }
+    plane_renderer.apply_styles(&display, &config.airline_styles, &airlines);
+    plane_renderer.clustering = config.clustering;
+    let mut plane_requester = PlaneRequester::with_airlines(&runtime, airlines, offline);
+    if let Some(interval) = config.refresh_interval {
+        let interval = plane_requester.set_refresh_interval(interval);
+        println!("Requesting planes every {:?}", interval);
+    }
+    // Plane requests are paused while the window is in the background to save API quota, unless
+    // waiting for planes to load for a screenshot or the detail window is showing them
+    let mut window_focused = true;
+    let mut window_minimized = false;
+    // Taken when the event loop exits so that the runtime can be shut down cleanly
+    let mut runtime = Some(runtime);
+
+    // Where to save a screenshot after the next frame is drawn, set by F10
+    let mut screenshot_path: Option<std::path::PathBuf> = None;
+    // Where to save a screenshot before exiting, once everything has loaded
+    let mut exit_screenshot_path = config.screenshot_path;
 
     let airports_bin = include_bytes!("../assets/data/airports.bin");
-    let airports = airports_from_bytes(airports_bin).expect("Failed to load airports");
-
-    let mut viewer = map::TileView::new(29.18796, -81.04923, 8.0, 1080.0 / 2.0);
+    let bundled_airports = || match airports_from_bytes(airports_bin) {
+        Ok(airports) => airports,
+        Err(err) => {
+            // The map is still useful without airports, so carry on with the layer turned off
+            println!("Failed to load the bundled airports: {}", err);
+            Vec::new()
+        }
+    };
+    let airports = match &config.airports_path {
+        Some(path) => match load_airports(path, config.airport_format) {
+            Ok(loaded) => {
+                for err in &loaded.row_errors {
+                    println!("Skipping airport in {} {}", path.display(), err);
+                }
+                println!(
+                    "Loaded {} airports from {}",
+                    loaded.airports.len(),
+                    path.display()
+                );
+                loaded.airports
+            }
+            Err(err) => {
+                println!(
+                    "Failed to load airports from {}: {}. Using the bundled airports",
+                    path.display(),
+                    err
+                );
+                bundled_airports()
+            }
+        },
+        None => bundled_airports(),
+    };
+    let mut airport_route = AirportRoute::default();
+    let airport_index = AirportIndex::new(&airports);
+    let mut airport_search = String::new();
+    let mut airport_suggestions = AirportSuggestions::default();
+    // The message shown when a search fails, and when it was shown
+    let mut airport_search_error: Option<(String, Instant)> = None;
+
+    let saved_state = persist::load_state();
+    let mut viewer = match (&config.start_view, &saved_state) {
+        (Some(start_view), _) => start_view.to_tile_view(),
+        (None, Some(state)) => map::TileView::from_saved_state(&state.view),
+        (None, None) => config.default_view.unwrap_or_default().to_tile_view(),
+    };
+    let mut theme = saved_state
+        .as_ref()
+        .map(|state| state.theme)
+        .unwrap_or_default();
+    let mut palette = saved_state
+        .as_ref()
+        .map(|state| state.palette)
+        .unwrap_or_default();
+    plane_renderer.palette = palette;
+    // Icon sizes from the command line replace the ones saved by the last run, while the ones in
+    // the config file are only used until sizes have been saved
+    plane_renderer.marker_scale = config
+        .plane_scale
+        .or(saved_state.as_ref().map(|state| state.plane_scale))
+        .or(config.default_plane_scale)
+        .unwrap_or(1.0)
+        .clamp(MIN_MARKER_SCALE, MAX_MARKER_SCALE);
+    let airport_scale = config
+        .airport_scale
+        .or(saved_state.as_ref().map(|state| state.airport_scale))
+        .or(config.default_airport_scale)
+        .unwrap_or(1.0)
+        .clamp(MIN_MARKER_SCALE, MAX_MARKER_SCALE);
     let mut last_cursor_pos: Option<DVec2> = None;
     let mut left_pressed = false;
     // Set to true if last frame the mouse was clicked
     let mut left_last_pressed = false;
     // Set to true if the mouse was dragged (clicked and moved)
     let mut was_mouse_dragged = false;
+    let mut touch_tracker = TouchTracker::default();
 
-    let mut weather_enabled = false;
-    let mut debug_enabled = false;
+    let mut weather_enabled = config.ui.weather;
+    let mut weather_opacity = DEFAULT_WEATHER_OPACITY;
+    let mut weather_ramp = config.weather_ramp;
+    let mut base_layer = config.base_layer;
+    let mut coord_format = util::CoordFormat::Decimal;
+    let mut debug_enabled = config.ui.debug;
+    let mut help_enabled = false;
+    let mut modifiers = glium::glutin::event::ModifiersState::empty();
 
     let mut filter_enabled: bool = false;
-    let mut airport_enabled: bool = true;
+    let mut airport_enabled: bool = config.ui.airports && !airports.is_empty();
+    let mut airport_settings = AirportSettings {
+        labels: config.airport_labels,
+        icon_scale: airport_scale as f64,
+        ..AirportSettings::default()
+    };
+    // Whether the sliders for the plane and airport icon sizes are shown
+    let mut marker_size_enabled = false;
     let mut selected_airline = BasicAirline::All;
+    let mut altitude_filter = AltitudeFilter::default();
 
     let mut last_fps_print = Instant::now();
     let mut frame_counter = 0;
     let mut frame_times: Option<(Vec<f64>, Instant)> = None;
+    let mut frame_history = frame_graph::FrameTimeHistory::new(config.frame_history);
+    let mut trace_recorder = util::TraceRecorder::new();
+    let mut track_recorder = TrackRecorder::new();
+    let mut profile_history = util::ProfileHistory::new(PROFILE_WINDOW_FRAMES);
+
+    // Set when something changed that requires the UI to be rebuilt and redrawn
+    let mut ui_dirty = true;
+    // The planes drawn last frame, used to detect when new plane data arrives
+    let mut last_planes = plane_requester.planes_storage();
+    // The marker generation drawn last frame, used to detect when markers change
+    let mut last_marker_generation = None;
+    // The second shown by the clock last frame, so that the clock ticks and stale planes keep
+    // fading out while nothing else changes
+    let mut last_clock_second = None;
 
     let mut loading = true;
+    // Set once the first screen of map tiles has loaded, and never cleared
+    let mut map_loaded = false;
+    let mut tile_progress = TileProgress::default();
 
     overlay_ids
         .filer_button
@@ -157,45 +473,306 @@ pub fn run_app() {
 
     //Detects everytime the cursor is above a plane
     let mut selected_plane: Option<SelectedPlane> = None;
+    let mut plane_clusters: Vec<PlaneCluster> = Vec::new();
+    let mut plane_labels: Vec<PlaneLabel> = Vec::new();
     //Detects everytime a plane is clicked
     let mut clicked_plane: Option<SelectedPlane> = None;
     //Holds the plane size
     let mut olds_plane_size = 0.0;
     //Shows the clicked details when plane clicked
     let mut show_details = false;
+    // The callsign of the plane the camera keeps centered, if any
+    let mut followed_plane: Option<String> = None;
+    // Whether the map is turned so that the followed or clicked plane's track points up
+    let mut track_up = false;
+    // The units every distance, altitude and speed on screen is shown in
+    let mut units = config.units;
+
+    let mut measure = MeasureTool::default();
+    let mut measure_enabled = false;
+    let mut range_rings = RangeRingLayer::new(config.range_rings);
+    let mut route = WaypointRoute::default();
+    let mut route_enabled = false;
+    // The polygon planes are filtered by, and whether it is shown and can be edited
+    let mut region = RegionPolygon::default();
+    let mut region_enabled = false;
+    // The number of planes inside the region found while drawing the planes last frame
+    let mut region_count: Option<usize> = None;
+    // Whether any plane drawn last frame was fading out
+    let mut planes_fading = false;
+    // Set to true when the mouse was clicked without dragging, handled on the next frame
+    let mut mouse_clicked = false;
+    let mut idle_tour = IdleTour::new(config.idle.clone(), Instant::now());
 
     event_loop.run(move |event, _, control_flow| {
         use glium::glutin::event::{
-            ElementState, Event, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+            ElementState, Event, MouseButton, MouseScrollDelta, TouchPhase,
+            VirtualKeyCode, WindowEvent,
         };
 
+        // Events for the detail window are handled by it, and everything else is for the map
+        if let Some(detail) = &mut detail_panel {
+            if detail.handle_event(&event) {
+                if detail.is_closed() {
+                    detail_panel = None;
+                }
+                return;
+            }
+        }
+
         // Break from the loop upon `Escape` or closed window.
         if let Event::WindowEvent { event, .. } = &event {
+            // Any input may change what is on screen
+            ui_dirty = true;
+
+            // Any input hands the camera back from the idle tour
+            let input = matches!(
+                event,
+                WindowEvent::KeyboardInput { .. }
+                    | WindowEvent::ReceivedCharacter(_)
+                    | WindowEvent::MouseInput { .. }
+                    | WindowEvent::MouseWheel { .. }
+                    | WindowEvent::CursorMoved { .. }
+                    | WindowEvent::Touch(_)
+            );
+            if input && idle_tour.input(Instant::now()) {
+                // Stops the camera where it is
+                viewer.move_camera_pixels(DVec2::ZERO);
+                println!("Idle tour stopped");
+            }
+
             match event {
                 // Break from the loop upon `Escape`.
-                WindowEvent::CloseRequested
-                | WindowEvent::KeyboardInput {
+                WindowEvent::CloseRequested => {
+                    *control_flow = glium::glutin::event_loop::ControlFlow::Exit
+                }
+                WindowEvent::ModifiersChanged(state) => modifiers = *state,
+                WindowEvent::KeyboardInput {
                     input:
                         glium::glutin::event::KeyboardInput {
-                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            virtual_keycode: Some(key),
+                            state: ElementState::Pressed,
                             ..
                         },
                     ..
-                } => *control_flow = glium::glutin::event_loop::ControlFlow::Exit,
+                } => {
+                    // Letter shortcuts are ignored while typing in the search box
+                    let typing = overlay_ui
+                        .global_input()
+                        .current
+                        .widget_capturing_keyboard
+                        .is_some();
+                    // The arrow keys pick an airport from the search suggestions
+                    if typing && matches!(key, VirtualKeyCode::Up | VirtualKeyCode::Down) {
+                        airport_suggestions.move_highlight(*key == VirtualKeyCode::Up);
+                    }
+                    match shortcuts::action_for(*key, modifiers, typing) {
+                        Some(Action::Quit) => {
+                            *control_flow = glium::glutin::event_loop::ControlFlow::Exit
+                        }
+                        Some(Action::ToggleWeather) => weather_enabled = !weather_enabled,
+                        Some(Action::ToggleDebug) => debug_enabled = !debug_enabled,
+                        Some(Action::ToggleAirports) => airport_enabled = !airport_enabled,
+                        Some(Action::ToggleHelp) => help_enabled = !help_enabled,
+                        Some(Action::ToggleMinimap) => minimap_enabled = !minimap_enabled,
+                        Some(Action::ToggleLegend) => legend_enabled = !legend_enabled,
+                        Some(Action::ToggleClustering) => {
+                            plane_renderer.clustering.enabled = !plane_renderer.clustering.enabled
+                        }
+                        Some(Action::CyclePalette) => {
+                            palette = palette.next();
+                            plane_renderer.palette = palette;
+                            println!("Switched to the {} palette", palette.name());
+                        }
+                        Some(Action::ToggleOffline) => {
+                            let offline = !plane_requester.is_offline();
+                            plane_requester.set_offline(offline);
+                            if offline {
+                                println!("Offline mode on, showing only cached data");
+                            } else {
+                                // Tiles missing from the caches can be downloaded now
+                                for pipeline in pipelines.values_mut() {
+                                    pipeline.retry_unavailable();
+                                }
+                                minimap.retry_unavailable();
+                                vector_tiles.retry_unavailable();
+                                radar_loop.retry_unavailable();
+                                println!("Offline mode off");
+                            }
+                        }
+                        Some(Action::Screenshot) => {
+                            let secs = std::time::SystemTime::now()
+                                .duration_since(std::time::UNIX_EPOCH)
+                                .map(|d| d.as_secs())
+                                .unwrap_or(0);
+                            screenshot_path = Some(format!("screenshot-{}.png", secs).into());
+                        }
+                        Some(Action::ToggleFullscreen) => {
+                            use glium::glutin::window::Fullscreen;
+
+                            let gl_window = display.gl_window();
+                            let window = gl_window.window();
+                            let fullscreen = match window.fullscreen() {
+                                Some(_) => None,
+                                None => Some(Fullscreen::Borderless(window.current_monitor())),
+                            };
+                            window.set_fullscreen(fullscreen);
+                        }
+                        Some(Action::ToggleTrace) => {
+                            if trace_recorder.is_recording() {
+                                let secs = std::time::SystemTime::now()
+                                    .duration_since(std::time::UNIX_EPOCH)
+                                    .map(|d| d.as_secs())
+                                    .unwrap_or(0);
+                                let path = format!("trace-{}.json", secs);
+                                match trace_recorder.finish(std::path::Path::new(&path)) {
+                                    Ok(()) => println!("Wrote profiling trace to {}", path),
+                                    Err(err) => {
+                                        println!("Failed to write trace {}: {:?}", path, err)
+                                    }
+                                }
+                            } else {
+                                trace_recorder.start();
+                                println!("Recording profiling trace. Press F12 again to save it");
+                            }
+                        }
+                        Some(Action::ToggleTrackRecording) => {
+                            if track_recorder.is_recording() {
+                                track_recorder.stop();
+                                println!(
+                                    "Stopped recording {} with {} points. Press E to export it",
+                                    track_recorder.callsign(),
+                                    track_recorder.points().len()
+                                );
+                            } else {
+                                let plane = clicked_plane.as_ref().or(selected_plane.as_ref());
+                                match plane.filter(|selected| selected.plane.label().is_some()) {
+                                    Some(selected) => {
+                                        track_recorder.start(&selected.plane.callsign);
+                                        track_recorder.update(
+                                            &plane_requester.live_planes(),
+                                            std::time::SystemTime::now(),
+                                        );
+                                        println!(
+                                            "Recording the track of {}",
+                                            track_recorder.callsign()
+                                        );
+                                    }
+                                    None => println!(
+                                        "Click a plane with a callsign to record its track"
+                                    ),
+                                }
+                            }
+                            ui_dirty = true;
+                        }
+                        Some(Action::ToggleRegion) => {
+                            region_enabled = !region_enabled;
+                            region.end_drag();
+                            route_enabled = false;
+                            route.end_drag();
+                            measure_enabled = false;
+                            measure.clear();
+                        }
+                        Some(Action::ClearRegion) => region.clear(),
+                        Some(Action::ToggleTrackUp) => track_up = !track_up,
+                        Some(Action::ExportTrack) => {
+                            if track_recorder.points().is_empty() {
+                                println!("No track recorded yet. Press R to record one");
+                            } else {
+                                let secs = std::time::SystemTime::now()
+                                    .duration_since(std::time::UNIX_EPOCH)
+                                    .map(|d| d.as_secs())
+                                    .unwrap_or(0);
+                                for format in TrackFormat::ALL {
+                                    let path = format!(
+                                        "track-{}-{}.{}",
+                                        track_recorder.callsign(),
+                                        secs,
+                                        format.extension()
+                                    );
+                                    match track_recorder
+                                        .export(std::path::Path::new(&path), format)
+                                    {
+                                        Ok(()) => println!("Wrote track to {}", path),
+                                        Err(err) => {
+                                            println!("Failed to write track {}: {:?}", path, err)
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        None => {}
+                    }
+                }
+                WindowEvent::Resized(size) => {
+                    // Some platforms (notably Wayland) need the GL surface resized manually
+                    display.gl_window().resize(*size);
+                    // Minimizing is reported as resizing to zero on Windows
+                    window_minimized = size.width == 0 || size.height == 0;
+                    plane_requester.set_paused(
+                        (!window_focused || window_minimized)
+                            && exit_screenshot_path.is_none()
+                            && detail_panel.is_none(),
+                    );
+                }
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    // Moving to a monitor with a different DPI. Everything else reads the scale
+                    // factor from the window each frame, so only the GL surface needs updating
+                    display.gl_window().resize(**new_inner_size);
+                }
+                WindowEvent::Focused(focused) => {
+                    window_focused = *focused;
+                    plane_requester.set_paused(
+                        (!window_focused || window_minimized)
+                            && exit_screenshot_path.is_none()
+                            && detail_panel.is_none(),
+                    );
+                }
                 WindowEvent::MouseWheel { delta, .. } => {
                     let zoom_change = match delta {
                         MouseScrollDelta::LineDelta(_x, y) => *y as f64,
                         MouseScrollDelta::PixelDelta(data) => data.y / 100.0,
                     };
                     let zoom_change = (-zoom_change / 6.0).clamp(-0.5, 0.5);
-                    viewer.multiply_zoom(1.0 + zoom_change);
+                    match last_cursor_pos {
+                        Some(cursor) => {
+                            // The view works in logical pixels, but cursor events are physical.
+                            // The overlay is always the size of the window, unlike the map which
+                            // is drawn into a larger canvas while it is turned
+                            let scale_factor = display.gl_window().window().scale_factor();
+                            viewer.zoom_about_pixel(
+                                1.0 + zoom_change,
+                                cursor / scale_factor,
+                                overlay_ui.win_w,
+                                overlay_ui.win_h,
+                            );
+                        }
+                        None => viewer.multiply_zoom(1.0 + zoom_change),
+                    }
                 }
                 WindowEvent::CursorMoved { position, .. } => {
                     let position = DVec2::new(position.x, position.y);
-                    if let Some(last) = last_cursor_pos {
+                    if left_pressed && route.is_dragging() {
+                        let scale_factor = display.gl_window().window().scale_factor();
+                        let (lat, lng) = viewer.pixel_to_lat_long(
+                            position / scale_factor,
+                            overlay_ui.win_w,
+                            overlay_ui.win_h,
+                        );
+                        route.drag_to(lat, lng);
+                    } else if left_pressed && region.is_dragging() {
+                        let scale_factor = display.gl_window().window().scale_factor();
+                        let (lat, lng) = viewer.pixel_to_lat_long(
+                            position / scale_factor,
+                            overlay_ui.win_w,
+                            overlay_ui.win_h,
+                        );
+                        region.drag_to(lat, lng);
+                    } else if let Some(last) = last_cursor_pos {
                         let delta = (last - position).clamp_length_max(300.0);
                         if left_pressed {
                             viewer.move_camera_pixels(delta);
+                            followed_plane = None;
                         }
                     }
 
@@ -205,14 +782,92 @@ pub fn run_app() {
 
                     last_cursor_pos = Some(position);
                 }
+                WindowEvent::Touch(touch) => {
+                    let location = DVec2::new(touch.location.x, touch.location.y);
+                    if touch.phase == TouchPhase::Started {
+                        // Lets planes under the finger be found the same way as under the mouse
+                        last_cursor_pos = Some(location);
+                    }
+                    match touch_tracker.handle(touch.id, touch.phase, location) {
+                        Some(TouchGesture::Tap(location)) => {
+                            last_cursor_pos = Some(location);
+                            clicked_plane = selected_plane.clone();
+                        }
+                        Some(TouchGesture::Pinch { zoom, pan, center }) => {
+                            viewer.move_camera_pixels(pan);
+                            followed_plane = None;
+                            let scale_factor = display.gl_window().window().scale_factor();
+                            viewer.zoom_about_pixel(
+                                zoom,
+                                center / scale_factor,
+                                overlay_ui.win_w,
+                                overlay_ui.win_h,
+                            );
+                        }
+                        None => {}
+                    }
+                }
                 WindowEvent::MouseInput { button, state, .. } => {
+                    let waypoint_under_cursor = match (route_enabled, last_cursor_pos) {
+                        (true, Some(cursor)) => {
+                            let scale_factor = display.gl_window().window().scale_factor();
+                            route.hit_test(
+                                &viewer,
+                                cursor / scale_factor,
+                                overlay_ui.win_w,
+                                overlay_ui.win_h,
+                            )
+                        }
+                        _ => None,
+                    };
+                    let corner_under_cursor = match (region_enabled, last_cursor_pos) {
+                        (true, Some(cursor)) => {
+                            let scale_factor = display.gl_window().window().scale_factor();
+                            region.hit_test(
+                                &viewer,
+                                cursor / scale_factor,
+                                overlay_ui.win_w,
+                                overlay_ui.win_h,
+                            )
+                        }
+                        _ => None,
+                    };
+                    // Right clicking a waypoint or region corner deletes it
+                    if let (MouseButton::Right, ElementState::Pressed) = (button, state) {
+                        if let Some(i) = waypoint_under_cursor {
+                            route.remove(i);
+                        } else if let Some(i) = corner_under_cursor {
+                            region.remove(i);
+                        }
+                    }
                     if matches!(button, MouseButton::Left) {
                         left_pressed = matches!(state, ElementState::Pressed);
 
                         if left_pressed {
                             was_mouse_dragged = false;
-                        } else if !was_mouse_dragged && selected_plane.is_none() {
-                            clicked_plane = None;
+                            if let Some(i) = waypoint_under_cursor {
+                                route.start_drag(i);
+                            } else if let Some(i) = corner_under_cursor {
+                                region.start_drag(i);
+                            }
+                        } else if route.end_drag() {
+                            // Releasing a grabbed waypoint isn't a click, so no waypoint is added
+                        } else if region.end_drag() {
+                            // Clicking the first corner without moving it closes the polygon
+                            if !was_mouse_dragged && corner_under_cursor == Some(0) {
+                                region.close();
+                            }
+                        } else if !was_mouse_dragged {
+                            mouse_clicked = true;
+                            // Clicking a button, such as the follow button, keeps the selection
+                            let over_widget = overlay_ui
+                                .global_input()
+                                .current
+                                .widget_under_mouse
+                                .is_some();
+                            if selected_plane.is_none() && !over_widget {
+                                clicked_plane = None;
+                            }
                         }
                     }
                 }
@@ -228,15 +883,164 @@ pub fn run_app() {
 
         match &event {
             glium::glutin::event::Event::MainEventsCleared => {
+                //========== Update Detail Window ==========
+                if let Some(detail) = &mut detail_panel {
+                    let [utc, local] =
+                        clock::clock_lines(chrono::Utc::now(), *chrono::Local::now().offset());
+                    let age = clock::data_age(
+                        plane_requester.data_time(),
+                        plane_requester
+                            .frozen_at()
+                            .unwrap_or_else(std::time::SystemTime::now),
+                    );
+                    let mut age_text = clock::data_age_text(age);
+                    if plane_requester.is_frozen() {
+                        age_text.push_str(", paused");
+                    }
+                    let mut lines = vec![utc, local, age_text, String::new()];
+                    let plane = clicked_plane.as_ref().or(selected_plane.as_ref());
+                    lines.extend(detail_window::plane_lines(
+                        plane.map(|selected| &selected.plane),
+                        units,
+                    ));
+                    detail.update(lines);
+                }
+
+                //========== Update Idle Tour ==========
+                let was_touring = idle_tour.is_touring();
+                let view = viewer.to_saved_state();
+                if let Some((lat, lng)) =
+                    idle_tour.update(Instant::now(), (view.latitude, view.longitude))
+                {
+                    if !was_touring {
+                        println!("Nothing has happened for a while, starting the idle tour");
+                        followed_plane = None;
+                    }
+                    viewer.pan_to(lat, lng);
+                    if let Some(zoom) = idle_tour.settings.zoom {
+                        viewer.zoom_to_across(zoom, ZOOM_REFERENCE_WIDTH);
+                    }
+                }
+
+                //========== Check For Changes ==========
+                let planes = plane_requester.planes_storage();
+                if !Arc::ptr_eq(&planes, &last_planes) {
+                    if let Some(callsign) = &followed_plane {
+                        match find_plane(&planes, callsign) {
+                            Some(plane) => {
+                                viewer.pan_to(plane.latitude as f64, plane.longitude as f64)
+                            }
+                            None => {
+                                println!("{} is no longer in the feed, stopped following", callsign);
+                                followed_plane = None;
+                            }
+                        }
+                    }
+                    last_planes = planes;
+                    ui_dirty = true;
+                }
+                if track_recorder.is_recording() {
+                    let points = track_recorder.points().len();
+                    track_recorder
+                        .update(&plane_requester.live_planes(), std::time::SystemTime::now());
+                    if track_recorder.points().len() != points {
+                        ui_dirty = true;
+                    }
+                }
+                if weather_enabled && !plane_requester.is_frozen() {
+                    if let Some(runtime) = &runtime {
+                        if radar_loop.update(Instant::now(), runtime, &mut image_map) {
+                            ui_dirty = true;
+                        }
+                    }
+                }
+                let clock_second = chrono::Utc::now().timestamp();
+                if (!loading || planes_fading) && last_clock_second != Some(clock_second) {
+                    last_clock_second = Some(clock_second);
+                    ui_dirty = true;
+                }
+                let marker_generation = markers.lock().generation();
+                if last_marker_generation != Some(marker_generation) {
+                    last_marker_generation = Some(marker_generation);
+                    ui_dirty = true;
+                }
+                // Things that change on their own every frame
+                if viewer.is_animating()
+                    || loading
+                    || debug_enabled
+                    || frame_times.is_some()
+                    || trace_recorder.is_recording()
+                    || pipelines.values().any(|pipeline| pipeline.has_pending())
+                    || vector_tiles.has_pending()
+                    || (weather_enabled && radar_loop.has_pending())
+                    || (minimap_enabled && minimap.has_pending())
+                {
+                    ui_dirty = true;
+                }
+
+                let exiting = *control_flow == glium::glutin::event_loop::ControlFlow::Exit;
+                if !ui_dirty {
+                    // Nothing changed, so sleep for a bit instead of rebuilding the same frame.
+                    // Wake up periodically to check for new planes
+                    if !exiting {
+                        *control_flow = glium::glutin::event_loop::ControlFlow::WaitUntil(
+                            Instant::now() + IDLE_POLL_INTERVAL,
+                        );
+                    }
+                    return;
+                }
+                ui_dirty = false;
+                if !exiting {
+                    *control_flow = glium::glutin::event_loop::ControlFlow::Poll;
+                }
+
                 // This is only set to true for the exact *first* frame that the mouse is clicked
                 let left_just_pressed = left_pressed && !left_last_pressed;
                 left_last_pressed = left_pressed;
 
+                viewer.update(frame_time_ms / 1000.0);
+
+                //========== Update Track-up ==========
+                let heading = match track_up {
+                    true => followed_plane
+                        .as_deref()
+                        .or(clicked_plane
+                            .as_ref()
+                            .map(|clicked| clicked.plane.callsign.as_str()))
+                        .and_then(|callsign| find_plane(&last_planes, callsign))
+                        .and_then(|plane| plane.heading_degrees()),
+                    false => None,
+                };
+                viewer.set_rotation(heading.map_or(0.0, |heading| (heading as f64).to_radians()));
+                // A turned map is drawn into a canvas that covers the window at any angle
+                let scale_factor = display.gl_window().window().scale_factor();
+                let (canvas_width, canvas_height) =
+                    track_up::canvas_dimensions(&viewer, display.get_framebuffer_dimensions());
+                let canvas_size = [
+                    canvas_width as f64 / scale_factor,
+                    canvas_height as f64 / scale_factor,
+                ];
+                if [map_ui.win_w, map_ui.win_h] != canvas_size {
+                    map_ui.handle_event(conrod_core::event::Input::Resize(
+                        canvas_size[0],
+                        canvas_size[1],
+                    ));
+                }
+
                 let mut map_ui = map_ui.set_widgets();
                 let map_ui = &mut map_ui;
                 let mut overlay_ui = overlay_ui.set_widgets();
                 let overlay_ui = &mut overlay_ui;
 
+                // The cursor in the conrod coordinates of the overlay, centered with y up
+                let ui_cursor = last_cursor_pos.map(|pos| {
+                    let pos = pos / scale_factor;
+                    DVec2::new(pos.x - overlay_ui.win_w / 2.0, overlay_ui.win_h / 2.0 - pos.y)
+                });
+                // The same point on the map, which is turned with the view
+                let map_cursor = ui_cursor.map(|cursor| track_up::screen_point_to_map(&viewer, cursor));
+                let mut hovered_airport = None;
+
                 overlay_ids
                     .filer_button
                     .resize(6, &mut overlay_ui.widget_id_generator());
@@ -245,35 +1049,165 @@ pub fn run_app() {
                 {
                     let map_state = map_renderer::MapRendererState {
                         tile_cache: &mut pipelines,
+                        vector_tiles: &mut vector_tiles,
                         view: &viewer,
                         display: &display,
                         image_map: &mut image_map,
                         ids: &mut map_ids,
                         weather_enabled,
+                        weather_opacity,
+                        radar_loop: &mut radar_loop,
+                        weather_ramp,
+                        base_layer,
+                        coord_format,
+                        theme,
+                        grid_style: palette.grid_style(theme.grid_style()),
+                        scale_factor: display.gl_window().window().scale_factor(),
                     };
-                    map_renderer::draw(map_state, map_ui, b612_map);
+                    tile_progress = map_renderer::draw(map_state, map_ui, b612_map);
+                    if tile_progress.is_complete() {
+                        map_loaded = true;
+                    }
                 }
+                // Drawn over the map so that it stays in the corner of the window while the map
+                // is turned
+                map_renderer::draw_scale_bar(
+                    &viewer.get_world_viewport(overlay_ui.win_w, overlay_ui.win_h),
+                    overlay_ui,
+                    &mut overlay_ids,
+                    b612_overlay,
+                    theme,
+                    units,
+                );
 
-                //========== Draw Airports ==========
-                if airport_enabled {
-                    airports::airport_renderer::draw(
-                        &airports,
+                //========== Draw Minimap ==========
+                let mut clicked = std::mem::take(&mut mouse_clicked);
+                if minimap_enabled {
+                    if clicked {
+                        // Clicking the minimap recenters the main view there
+                        let xy = overlay_ui.global_input().current.mouse.xy;
+                        if let Some((lat, lng)) =
+                            minimap::lat_long_at(&viewer, xy, overlay_ui.win_w, overlay_ui.win_h)
+                        {
+                            viewer.pan_to(lat, lng);
+                            followed_plane = None;
+                            clicked = false;
+                        }
+                    }
+                    minimap.draw(
                         &viewer,
+                        theme.base_layer(base_layer),
                         &display,
+                        &mut image_map,
+                        &mut overlay_ids,
+                        overlay_ui,
+                    );
+                }
+
+                //========== Handle Range Ring Clicks ==========
+                if clicked && range_rings.is_placing() {
+                    let input = &overlay_ui.global_input().current;
+                    // Ignore clicks on buttons
+                    if input.widget_under_mouse.is_none() {
+                        let (lat, lng) = ui_point_to_lat_long(&viewer, input.mouse.xy, overlay_ui);
+                        range_rings.set_center(lat, lng);
+                        clicked = false;
+                    }
+                }
+
+                //========== Handle Measure, Route and Region Clicks ==========
+                if clicked && (measure_enabled || route_enabled || region_enabled) {
+                    let input = &overlay_ui.global_input().current;
+                    // Ignore clicks on buttons
+                    if input.widget_under_mouse.is_none() {
+                        let (lat, lng) = ui_point_to_lat_long(&viewer, input.mouse.xy, overlay_ui);
+                        if measure_enabled {
+                            measure.click(lat, lng);
+                        } else if route_enabled {
+                            route.push(lat, lng);
+                        } else {
+                            region.push(lat, lng);
+                        }
+                    }
+                }
+                if measure_enabled {
+                    measure.draw(&viewer, map_ui, &mut map_ids, b612_map, units);
+                }
+                range_rings.draw(&viewer, &mut map_ids, b612_map, units, map_ui);
+                if route_enabled {
+                    waypoints::waypoint_renderer::draw(
+                        &route,
+                        &viewer,
                         &mut map_ids,
-                        airport_id,
+                        b612_map,
+                        units,
                         map_ui,
                     );
                 }
+                if region_enabled {
+                    region::region_renderer::draw(
+                        &region,
+                        &viewer,
+                        &mut map_ids,
+                        b612_map,
+                        region_count,
+                        map_ui,
+                    );
+                }
+
+                //========== Draw Airports ==========
+                if airport_enabled {
+                    let airport_state = airports::airport_renderer::AirportRendererState {
+                        airports: &airports,
+                        view: &viewer,
+                        ids: &mut map_ids,
+                        image_id: airport_id,
+                        font: b612_map,
+                        settings: &airport_settings,
+                        route: &mut airport_route,
+                        cursor: map_cursor,
+                    };
+                    hovered_airport = airports::airport_renderer::draw(airport_state, map_ui);
+                }
+
+                //========== Draw Markers ==========
+                {
+                    let layer = markers.lock();
+                    let marker_state = markers::marker_renderer::MarkerRendererState {
+                        layer: &layer,
+                        view: &viewer,
+                        ids: &mut map_ids,
+                        airport_image: airport_id,
+                        font: b612_map,
+                    };
+                    markers::marker_renderer::draw(marker_state, map_ui);
+                }
+
+                plane_renderer::draw_callsigns(
+                    overlay_ui,
+                    &mut overlay_ids,
+                    b612_overlay,
+                    &plane_labels,
+                    &plane_clusters,
+                );
+                plane_renderer::draw_clusters(
+                    overlay_ui,
+                    &mut overlay_ids,
+                    b612_overlay,
+                    &plane_clusters,
+                );
 
                 //========== Draw Debug Data ==========
 
                 let perf_data = crate::take_profile_data();
+                trace_recorder.record(&perf_data);
+                profile_history.push_frame(&perf_data);
 
                 if debug_enabled {
                     let _scope_debug_view = crate::profile_scope("Render Debug Information");
-                    let mut perf_data: Vec<_> = perf_data.into_iter().collect();
-                    perf_data.sort_unstable_by(|a, b| a.0.cmp(b.0));
+                    let perf_data = profile_history.stats();
+
+                    frame_graph::draw(&frame_history, overlay_ui, &mut overlay_ids, b612_overlay);
 
                     //========== Draw Debug Text ==========
                     let map_data = {
@@ -281,7 +1215,10 @@ pub fn run_app() {
                         guard.snapshot()
                     };
 
-                    let debug_lines = 4 + map_data.backend_request_secs.len() + perf_data.len();
+                    let debug_lines = 8
+                        + map_data.backend_request_secs.len()
+                        + map_data.backend_request_stats.len()
+                        + perf_data.len();
 
                     let mut i = 0;
                     let mut buf: util::StringFormatter<512> = util::StringFormatter::new();
@@ -322,22 +1259,57 @@ pub fn run_app() {
                         map_data.tile_decode_time.as_secs_f64() * 1000.0,
                         map_data.tile_upload_time.as_secs_f64() * 1000.0
                     ));
+                    draw_text(format_args!(
+                        "Tile textures: {}, {:.1} MiB",
+                        map_data.tiles_on_gpu,
+                        map_data.tile_texture_bytes as f64 / (1024.0 * 1024.0)
+                    ));
+                    draw_text(format_args!(
+                        "Queue wait: {:.2}ms, Cancelled: {}",
+                        map_data.tile_queue_time.as_secs_f64() * 1000.0,
+                        map_data.cancelled_requests
+                    ));
+                    draw_text(format_args!(
+                        "Tile retries: {}, Failed: {}",
+                        map_data.tile_retries, map_data.failed_tiles
+                    ));
+                    let quota = plane_requester.quota();
+                    draw_text(format_args!(
+                        "OpenSky credits: {}/{}, Plane interval: {:?}",
+                        quota.remaining,
+                        quota.daily_credits,
+                        plane_requester.throttled_interval()
+                    ));
 
                     for (backend_name, time) in map_data.backend_request_secs {
                         draw_text(format_args!("  {} {:?}", backend_name, time,));
                     }
-                    for (name, data) in perf_data {
-                        let samples = data.get_samples();
-                        if samples.len() == 1 {
-                            draw_text(format_args!("{}: {:?}", name, samples[0]));
+                    for (backend_name, stats) in map_data.backend_request_stats {
+                        draw_text(format_args!(
+                            "  {} ok: {}, pending: {}, missing: {}, http: {}, decode: {}, timeout: {}, other: {}",
+                            backend_name,
+                            stats.succeeded,
+                            stats.pending,
+                            stats.not_found,
+                            stats.http_errors,
+                            stats.decode_errors,
+                            stats.timeouts,
+                            stats.other_errors,
+                        ));
+                    }
+                    for (name, stats) in perf_data {
+                        if stats.count == 1 {
+                            draw_text(format_args!("{}: {:?}", name, stats.max));
                         } else {
-                            let avg: Duration =
-                                samples.iter().sum::<Duration>() / samples.len() as u32;
                             draw_text(format_args!(
-                                "{}: {} times, {:?} avg",
+                                "{}: {} times, avg {:.2?}, p50 {:.2?}, p95 {:.2?}, p99 {:.2?}, max {:.2?}",
                                 name,
-                                samples.len(),
-                                avg
+                                stats.count,
+                                stats.average,
+                                stats.p50,
+                                stats.p95,
+                                stats.p99,
+                                stats.max,
                             ));
                         };
                     }
@@ -349,6 +1321,24 @@ pub fn run_app() {
 
                     let widget_x_position = (overlay_ui.win_w / 2.0) * 0.95 - 25.0;
                     let widget_y_position = (overlay_ui.win_h / 2.0) * 0.90;
+                    // The pill buttons are stacked below the round ones, wrapping into more columns
+                    // on short windows. Columns past the first start higher, but stay below the
+                    // filter buttons while they are shown and above the minimap
+                    let wrap_top = match filter_enabled {
+                        true => widget_y_position - 240.0,
+                        false => widget_y_position,
+                    };
+                    let bottom = match minimap_enabled {
+                        true => minimap::bounds(overlay_ui.win_w, overlay_ui.win_h).top(),
+                        false => -overlay_ui.win_h / 2.0,
+                    } + 20.0;
+                    let columns = ui_filter::ButtonColumns::new(
+                        BUTTON_ROWS,
+                        widget_x_position - 50.0,
+                        widget_y_position - 350.0,
+                        wrap_top,
+                        bottom,
+                    );
 
                     //========== Draw Airplane Filter Button ==========
                     if button_widget::draw_circle_with_image(
@@ -362,36 +1352,624 @@ pub fn run_app() {
                     }
 
                     //========== Draw weather Button ==========
-                    if button_widget::draw_circle_with_image(
+                    if button_widget::draw_toggle_with_image(
                         overlay_ids.weather_button,
                         overlay_ui,
                         weather_id,
                         widget_x_position,
                         widget_y_position - 70.0,
+                        weather_enabled,
                     ) {
                         weather_enabled = !weather_enabled;
                     }
                     //========== Draw Debug Button ==========
-                    if button_widget::draw_circle_with_image(
+                    if button_widget::draw_toggle_with_image(
                         overlay_ids.debug_button,
                         overlay_ui,
                         gear_id,
                         widget_x_position,
                         widget_y_position - 140.0,
+                        debug_enabled,
                     ) {
                         debug_enabled = !debug_enabled;
                     }
                     //========== Draw Airport Button ==========
-                    if button_widget::draw_circle_with_image(
+                    if button_widget::draw_toggle_with_image(
                         overlay_ids.airport_button,
                         overlay_ui,
                         airport_id,
                         widget_x_position,
                         widget_y_position - 210.0,
+                        airport_enabled,
                     ) {
                         airport_enabled = !airport_enabled;
                     }
+                    //========== Draw Base Layer Button ==========
+                    let [pill_x, pill_y] = columns.position(0);
+                    if ui_filter::draw(
+                        overlay_ids.base_layer_button,
+                        overlay_ui,
+                        format!("Map: {}", base_layer.name()),
+                        pill_x,
+                        pill_y,
+                    ) {
+                        base_layer = base_layer.next_base_layer();
+                    }
+                    //========== Draw Coordinate Format Button ==========
+                    let [pill_x, pill_y] = columns.position(3);
+                    if ui_filter::draw(
+                        overlay_ids.coord_format_button,
+                        overlay_ui,
+                        format!("Coords: {}", coord_format.name()),
+                        pill_x,
+                        pill_y,
+                    ) {
+                        coord_format = coord_format.toggle();
+                    }
+                    //========== Draw Plane Coloring Button ==========
+                    let [pill_x, pill_y] = columns.position(5);
+                    if ui_filter::draw(
+                        overlay_ids.plane_coloring_button,
+                        overlay_ui,
+                        format!("Color: {}", plane_renderer.coloring.name()),
+                        pill_x,
+                        pill_y,
+                    ) {
+                        plane_renderer.coloring = plane_renderer.coloring.toggle();
+                    }
+                    if plane_renderer.coloring == PlaneColoring::Altitude {
+                        plane_renderer::draw_altitude_legend(
+                            overlay_ui,
+                            &mut overlay_ids,
+                            b612_overlay,
+                            palette,
+                            units,
+                        );
+                    }
+                    if plane_renderer.coloring == PlaneColoring::Airline && legend_enabled {
+                        let rows = plane_renderer.styles.legend(selected_airline);
+                        if let Some(filter) = plane_renderer::draw_airline_legend(
+                            overlay_ui,
+                            &mut overlay_ids,
+                            b612_overlay,
+                            &rows,
+                            palette,
+                        ) {
+                            //Clicking the only airline shown goes back to showing everything
+                            selected_airline = if selected_airline == filter {
+                                BasicAirline::All
+                            } else {
+                                filter
+                            };
+                        }
+                    }
+
+                    //========== Draw Trails Button ==========
+                    let trail_points = plane_renderer.trails.max_points();
+                    let trails_label = if trail_points == 0 {
+                        String::from("Trails: Off")
+                    } else {
+                        format!("Trails: {}", trail_points)
+                    };
+                    let [pill_x, pill_y] = columns.position(6);
+                    if ui_filter::draw(
+                        overlay_ids.trails_button,
+                        overlay_ui,
+                        trails_label,
+                        pill_x,
+                        pill_y,
+                    ) {
+                        let index = TRAIL_LENGTHS
+                            .iter()
+                            .position(|&length| length == trail_points)
+                            .map_or(0, |i| i + 1);
+                        plane_renderer
+                            .trails
+                            .set_max_points(TRAIL_LENGTHS[index % TRAIL_LENGTHS.len()]);
+                    }
+
+                    //========== Draw Route Button ==========
+                    let route_label = if route_enabled {
+                        "Route: On"
+                    } else {
+                        "Route: Off"
+                    };
+                    let [pill_x, pill_y] = columns.position(7);
+                    if ui_filter::draw(
+                        overlay_ids.route_button,
+                        overlay_ui,
+                        String::from(route_label),
+                        pill_x,
+                        pill_y,
+                    ) {
+                        route_enabled = !route_enabled;
+                        route.end_drag();
+                        measure_enabled = false;
+                        measure.clear();
+                        region_enabled = false;
+                        region.end_drag();
+                    }
+                    if route_enabled
+                        && ui_filter::draw(
+                            overlay_ids.route_mode_button,
+                            overlay_ui,
+                            format!("Path: {}", route.mode().name()),
+                            columns.side_x(0),
+                            pill_y,
+                        )
+                    {
+                        route.set_mode(route.mode().next());
+                    }
+
+                    //========== Draw Marker Size Sliders ==========
+                    let [pill_x, pill_y] = columns.position(14);
+                    if ui_filter::draw(
+                        overlay_ids.marker_size_button,
+                        overlay_ui,
+                        String::from("Marker size"),
+                        pill_x,
+                        pill_y,
+                    ) {
+                        marker_size_enabled = !marker_size_enabled;
+                    }
+                    if marker_size_enabled {
+                        overlay_ids
+                            .marker_size_sliders
+                            .resize(2, &mut overlay_ui.widget_id_generator());
+                        let scales = [
+                            ("Planes", plane_renderer.marker_scale),
+                            ("Airports", airport_settings.icon_scale as f32),
+                        ];
+                        // Stacked beside the button so that both fit on narrow screens, going up
+                        // instead of down if the button is in the bottom row
+                        let step = match pill_y - ui_filter::ROW_SPACING < bottom {
+                            true => ui_filter::ROW_SPACING,
+                            false => -ui_filter::ROW_SPACING,
+                        };
+                        for (i, (name, scale)) in scales.into_iter().enumerate() {
+                            let label = format!("{}: {:.0}%", name, scale * 100.0);
+                            if let Some(scale) =
+                                widget::Slider::new(scale, MIN_MARKER_SCALE, MAX_MARKER_SCALE)
+                                    .x(columns.side_x(0))
+                                    .y(pill_y + step * i as f64)
+                                    .w_h(150.0, 30.0)
+                                    .label(label.as_str())
+                                    .label_font_size(10)
+                                    .set(overlay_ids.marker_size_sliders[i], overlay_ui)
+                            {
+                                // Round to the nearest 10% so the labels stay readable
+                                let scale = (scale * 10.0).round() / 10.0;
+                                if i == 0 {
+                                    plane_renderer.marker_scale = scale;
+                                } else {
+                                    airport_settings.icon_scale = scale as f64;
+                                }
+                            }
+                        }
+                    }
+
+                    //========== Draw Region Buttons ==========
+                    let region_label = if !region_enabled {
+                        "Region: Off"
+                    } else if region.is_closed() {
+                        "Region: On"
+                    } else {
+                        "Region: Draw"
+                    };
+                    let [pill_x, pill_y] = columns.position(13);
+                    if ui_filter::draw(
+                        overlay_ids.region_button,
+                        overlay_ui,
+                        String::from(region_label),
+                        pill_x,
+                        pill_y,
+                    ) {
+                        region_enabled = !region_enabled;
+                        region.end_drag();
+                        route_enabled = false;
+                        route.end_drag();
+                        measure_enabled = false;
+                        measure.clear();
+                    }
+                    if region_enabled
+                        && !region.points().is_empty()
+                        && ui_filter::draw(
+                            overlay_ids.region_clear_button,
+                            overlay_ui,
+                            String::from("Clear region"),
+                            columns.side_x(0),
+                            pill_y,
+                        )
+                    {
+                        region.clear();
+                    }
+
+                    //========== Draw Range Rings Button ==========
+                    let rings_label = if range_rings.is_placing() {
+                        "Rings: Click map"
+                    } else if range_rings.is_enabled() {
+                        "Rings: On"
+                    } else {
+                        "Rings: Off"
+                    };
+                    let [pill_x, pill_y] = columns.position(10);
+                    if ui_filter::draw(
+                        overlay_ids.range_rings_button,
+                        overlay_ui,
+                        String::from(rings_label),
+                        pill_x,
+                        pill_y,
+                    ) {
+                        if range_rings.is_enabled() {
+                            range_rings.disable();
+                        } else {
+                            // Start at the center of the screen until a spot is clicked
+                            let viewport =
+                                viewer.get_world_viewport(overlay_ui.win_w, overlay_ui.win_h);
+                            range_rings.enable_at(
+                                pixel_y_to_latitude(0.0, &viewport, overlay_ui.win_h),
+                                pixel_x_to_longitude(0.0, &viewport, overlay_ui.win_w),
+                            );
+                        }
+                    }
+
+                    //========== Draw Theme Button ==========
+                    let [pill_x, pill_y] = columns.position(4);
+                    if ui_filter::draw(
+                        overlay_ids.theme_button,
+                        overlay_ui,
+                        format!("Theme: {}", theme.name()),
+                        pill_x,
+                        pill_y,
+                    ) {
+                        theme = theme.toggle();
+                    }
+
+                    //========== Draw Freeze Button ==========
+                    // Freezing holds the planes and the radar animation still while the map can
+                    // still be moved around
+                    let freeze_label = match plane_requester.is_frozen() {
+                        true => "Updates: Paused",
+                        false => "Updates: Live",
+                    };
+                    let [pill_x, pill_y] = columns.position(12);
+                    if ui_filter::draw(
+                        overlay_ids.freeze_button,
+                        overlay_ui,
+                        String::from(freeze_label),
+                        pill_x,
+                        pill_y,
+                    ) {
+                        plane_requester.set_frozen(!plane_requester.is_frozen());
+                    }
+
+                    //========== Draw Track-up Button ==========
+                    // Track-up turns the map by the track of the followed plane, or the clicked one
+                    // when none is followed, and stays north-up until there is one
+                    let track_up_label = match track_up {
+                        true => "Up: Track",
+                        false => "Up: North",
+                    };
+                    let [pill_x, pill_y] = columns.position(15);
+                    if ui_filter::draw(
+                        overlay_ids.track_up_button,
+                        overlay_ui,
+                        String::from(track_up_label),
+                        pill_x,
+                        pill_y,
+                    ) {
+                        track_up = !track_up;
+                    }
+                    if track_up {
+                        track_up::draw_north_indicator(
+                            &viewer,
+                            DVec2::new(widget_x_position - 80.0, widget_y_position),
+                            overlay_ui,
+                            &mut overlay_ids,
+                            b612_overlay,
+                        );
+                    }
+
+                    //========== Draw Airport Search ==========
+                    let search_y = overlay_ui.win_h / 2.0 - 24.0;
+                    // The airport picked from the search box this frame
+                    let mut found_airport = None;
+                    for event in widget::TextBox::new(&airport_search)
+                        .x_y(0.0, search_y)
+                        .w_h(200.0, 28.0)
+                        .font_size(14)
+                        .left_justify()
+                        .set(overlay_ids.airport_search, overlay_ui)
+                    {
+                        match event {
+                            widget::text_box::Event::Update(text) => {
+                                airport_search = text;
+                                airport_suggestions.update(&airport_index, &airport_search);
+                            }
+                            widget::text_box::Event::Enter => {
+                                // An exact code wins over the first suggestion, unless a
+                                // suggestion was picked with the arrow keys
+                                found_airport = airport_suggestions
+                                    .highlighted()
+                                    .or_else(|| airport_index.find(&airport_search))
+                                    .or_else(|| airport_suggestions.matches().first().copied());
+                                if found_airport.is_none() {
+                                    let message = format!(
+                                        "No airport matching \"{}\"",
+                                        airport_search.trim()
+                                    );
+                                    airport_search_error = Some((message, Instant::now()));
+                                }
+                            }
+                        }
+                    }
+
+                    //========== Draw Airport Suggestions ==========
+                    let suggestions = airport_suggestions.matches();
+                    overlay_ids
+                        .airport_suggestions
+                        .resize(suggestions.len(), &mut overlay_ui.widget_id_generator());
+                    for (row, &i) in suggestions.iter().enumerate() {
+                        let airport = &airports[i];
+                        let label = format!(
+                            "{}  {}  ({:.2}, {:.2})",
+                            airport.code(),
+                            airport.name,
+                            airport.latitude,
+                            airport.longitude
+                        );
+                        let color = match airport_suggestions.highlighted_row() == Some(row) {
+                            true => conrod_core::color::LIGHT_BLUE.alpha(0.9),
+                            false => conrod_core::Color::Rgba(0.0, 0.0, 0.0, 0.8),
+                        };
+                        if widget::Button::new()
+                            .w_h(400.0, 22.0)
+                            .x_y(0.0, search_y - 25.0 - row as f64 * 22.0)
+                            .color(color)
+                            .border(0.0)
+                            .label(&label)
+                            .label_color(conrod_core::color::WHITE)
+                            .label_font_size(11)
+                            .label_font_id(b612_overlay)
+                            .set(overlay_ids.airport_suggestions[row], overlay_ui)
+                            .was_clicked()
+                        {
+                            found_airport = Some(i);
+                        }
+                    }
+
+                    if let Some(i) = found_airport {
+                        let airport = &airports[i];
+                        viewer.set_center(airport.latitude as f64, airport.longitude as f64);
+                        viewer.zoom_to(AIRPORT_SEARCH_ZOOM);
+                        if range_rings.is_enabled() {
+                            range_rings
+                                .set_center(airport.latitude as f64, airport.longitude as f64);
+                        }
+                        airport_search = airport.code().to_owned();
+                        airport_suggestions.clear();
+                        airport_search_error = None;
+                    }
+                    if let Some((message, shown)) = &airport_search_error {
+                        if shown.elapsed() < AIRPORT_SEARCH_ERROR_TIME {
+                            widget::Text::new(message)
+                                .x_y(0.0, search_y - 26.0)
+                                .color(conrod_core::color::LIGHT_RED)
+                                .font_size(12)
+                                .font_id(b612_overlay)
+                                .set(overlay_ids.airport_search_error, overlay_ui);
+                        } else {
+                            airport_search_error = None;
+                        }
+                    }
+
+                    //========== Draw Offline Badge ==========
+                    if plane_requester.is_offline() {
+                        widget::Rectangle::fill([90.0, 24.0])
+                            .x_y(0.0, search_y - 52.0)
+                            .color(conrod_core::color::DARK_RED.alpha(0.85))
+                            .set(overlay_ids.offline_badge, overlay_ui);
+                        widget::Text::new("OFFLINE")
+                            .middle_of(overlay_ids.offline_badge)
+                            .color(conrod_core::color::WHITE)
+                            .font_size(12)
+                            .font_id(b612_overlay)
+                            .set(overlay_ids.offline_badge_text, overlay_ui);
+                    }
+
+                    //========== Draw Plane Quota Warning ==========
+                    if plane_requester.quota().exhausted {
+                        let y = match plane_requester.is_offline() {
+                            true => search_y - 80.0,
+                            false => search_y - 52.0,
+                        };
+                        widget::Rectangle::fill([220.0, 24.0])
+                            .x_y(0.0, y)
+                            .color(conrod_core::color::DARK_ORANGE.alpha(0.85))
+                            .set(overlay_ids.quota_badge, overlay_ui);
+                        widget::Text::new("PLANE QUOTA USED UP")
+                            .middle_of(overlay_ids.quota_badge)
+                            .color(conrod_core::color::WHITE)
+                            .font_size(12)
+                            .font_id(b612_overlay)
+                            .set(overlay_ids.quota_badge_text, overlay_ui);
+                    }
+
+                    //========== Draw Track Recording Badge ==========
+                    if track_recorder.is_recording() {
+                        let badges_above = plane_requester.is_offline() as u32
+                            + plane_requester.quota().exhausted as u32;
+                        widget::Rectangle::fill([220.0, 24.0])
+                            .x_y(0.0, search_y - 52.0 - 28.0 * badges_above as f64)
+                            .color(conrod_core::color::DARK_RED.alpha(0.85))
+                            .set(overlay_ids.track_badge, overlay_ui);
+                        widget::Text::new(&format!(
+                            "REC {} ({} points)",
+                            track_recorder.callsign(),
+                            track_recorder.points().len()
+                        ))
+                        .middle_of(overlay_ids.track_badge)
+                        .color(conrod_core::color::WHITE)
+                        .font_size(12)
+                        .font_id(b612_overlay)
+                        .set(overlay_ids.track_badge_text, overlay_ui);
+                    }
+
+                    //========== Draw Clock ==========
+                    let age = clock::data_age(
+                        plane_requester.data_time(),
+                        plane_requester
+                            .frozen_at()
+                            .unwrap_or_else(std::time::SystemTime::now),
+                    );
+                    let mut age_text = clock::data_age_text(age);
+                    if plane_requester.is_frozen() {
+                        age_text.push_str(", paused");
+                    }
+                    let [utc, local] =
+                        clock::clock_lines(chrono::Utc::now(), *chrono::Local::now().offset());
+                    let stale = clock::is_stale(age, plane_requester.throttled_interval());
+                    let lines = [
+                        (utc, conrod_core::color::WHITE),
+                        (local, conrod_core::color::WHITE),
+                        match stale {
+                            true => (age_text, conrod_core::color::LIGHT_ORANGE),
+                            false => (age_text, conrod_core::color::WHITE),
+                        },
+                    ];
+                    overlay_ids
+                        .clock_lines
+                        .resize(lines.len(), &mut overlay_ui.widget_id_generator());
+                    let clock_y = -overlay_ui.win_h / 2.0 + 30.0;
+                    widget::Rectangle::fill([220.0, 48.0])
+                        .x_y(0.0, clock_y)
+                        .color(conrod_core::color::BLACK.alpha(0.6))
+                        .set(overlay_ids.clock_background, overlay_ui);
+                    for (i, (text, color)) in lines.iter().enumerate() {
+                        widget::Text::new(text)
+                            .x_y(0.0, clock_y + 14.0 - i as f64 * 14.0)
+                            .color(*color)
+                            .font_size(11)
+                            .font_id(b612_overlay)
+                            .set(overlay_ids.clock_lines[i], overlay_ui);
+                    }
+
+                    //========== Draw Keyboard Shortcut Help ==========
+                    if help_enabled {
+                        let text = shortcuts::help_text();
+                        let lines = text.lines().count() as f64;
+                        widget::Rectangle::fill([480.0, 40.0 + lines * 20.0])
+                            .x_y(0.0, 0.0)
+                            .color(conrod_core::color::BLACK.alpha(0.8))
+                            .set(overlay_ids.help_background, overlay_ui);
+                        widget::Text::new(&text)
+                            .middle_of(overlay_ids.help_background)
+                            .color(conrod_core::color::WHITE)
+                            .font_size(14)
+                            .font_id(b612_overlay)
+                            .set(overlay_ids.help_text, overlay_ui);
+                    }
+
+                    //========== Draw Measure Buttons ==========
+                    let measure_label = if measure_enabled {
+                        "Measure: On"
+                    } else {
+                        "Measure: Off"
+                    };
+                    let [pill_x, pill_y] = columns.position(1);
+                    if ui_filter::draw(
+                        overlay_ids.measure_button,
+                        overlay_ui,
+                        String::from(measure_label),
+                        pill_x,
+                        pill_y,
+                    ) {
+                        measure_enabled = !measure_enabled;
+                        region_enabled = false;
+                        region.end_drag();
+                        measure.clear();
+                        // Clicks on the map can only go to one tool at a time
+                        route_enabled = false;
+                    }
+                    let [pill_x, pill_y] = columns.position(2);
+                    if ui_filter::draw(
+                        overlay_ids.units_button,
+                        overlay_ui,
+                        format!("Units: {}", units.name()),
+                        pill_x,
+                        pill_y,
+                    ) {
+                        units = units.next();
+                    }
+                    if weather_enabled {
+                        let label = format!("Weather: {:.0}%", weather_opacity * 100.0);
+                        let [pill_x, pill_y] = columns.position(8);
+                        if let Some(opacity) = widget::Slider::new(weather_opacity, 0.0, 1.0)
+                            .x(pill_x)
+                            .y(pill_y)
+                            .w_h(150.0, 30.0)
+                            .label(label.as_str())
+                            .label_font_size(10)
+                            .set(overlay_ids.weather_opacity_slider, overlay_ui)
+                        {
+                            weather_opacity = opacity;
+                        }
+
+                        let time = match radar_loop.displayed_time() {
+                            Some(time) => tile::format_utc_time(time),
+                            None => String::from("Latest"),
+                        };
+                        let state = if radar_loop.is_playing() && !plane_requester.is_frozen() {
+                            "Playing"
+                        } else {
+                            "Paused"
+                        };
+                        let [pill_x, pill_y] = columns.position(9);
+                        if ui_filter::draw(
+                            overlay_ids.radar_loop_button,
+                            overlay_ui,
+                            format!("Radar: {} ({})", time, state),
+                            pill_x,
+                            pill_y,
+                        ) {
+                            radar_loop.set_playing(!radar_loop.is_playing());
+                        }
+
+                        let [pill_x, pill_y] = columns.position(11);
+                        if ui_filter::draw(
+                            overlay_ids.weather_ramp_button,
+                            overlay_ui,
+                            format!("Colors: {}", weather_ramp.name()),
+                            pill_x,
+                            pill_y,
+                        ) {
+                            weather_ramp = weather_ramp.next();
+                        }
+                    }
                     //========== Filtering buttons enabling/disabling ==========
+                    if filter_enabled && airport_enabled {
+                        //========== Draw Airport Category Filters ==========
+                        overlay_ids.airport_filter_buttons.resize(
+                            airport_settings.categories.len(),
+                            &mut overlay_ui.widget_id_generator(),
+                        );
+                        for (i, (category, enabled)) in airport_settings.categories.iter_mut().enumerate() {
+                            let label = format!(
+                                "{}: {}",
+                                category.name(),
+                                if *enabled { "On" } else { "Off" }
+                            );
+                            if ui_filter::draw(
+                                overlay_ids.airport_filter_buttons[i],
+                                overlay_ui,
+                                label,
+                                widget_x_position - 290.0,
+                                widget_y_position - i as f64 * 40.0,
+                            ) {
+                                *enabled = !*enabled;
+                            }
+                        }
+                    }
                     if filter_enabled {
                         //========== Draw American Airlines Filter ==========
                         if ui_filter::draw(
@@ -453,6 +2031,74 @@ pub fn run_app() {
                         ) {
                             selected_airline = BasicAirline::All
                         }
+
+                        //========== Draw Altitude Filters ==========
+                        overlay_ids
+                            .altitude_filter_sliders
+                            .resize(2, &mut overlay_ui.widget_id_generator());
+                        let bounds = [
+                            ("Min", altitude_filter.min_feet),
+                            ("Max", altitude_filter.max_feet),
+                        ];
+                        for (i, (name, feet)) in bounds.into_iter().enumerate() {
+                            let label = if i == 1 && feet >= FILTER_MAX_ALTITUDE_FEET {
+                                format!("{}: Any", name)
+                            } else {
+                                let meters = feet_to_meters(feet as f64);
+                                format!("{}: {}", name, units.format_altitude(meters))
+                            };
+                            if let Some(feet) =
+                                widget::Slider::new(feet, 0.0, FILTER_MAX_ALTITUDE_FEET)
+                                    .x(widget_x_position - 450.0)
+                                    .y(widget_y_position - i as f64 * 40.0)
+                                    .w_h(150.0, 30.0)
+                                    .label(label.as_str())
+                                    .label_font_size(10)
+                                    .set(overlay_ids.altitude_filter_sliders[i], overlay_ui)
+                            {
+                                // Round to the nearest 500 feet so the labels stay readable
+                                let feet = (feet / 500.0).round() * 500.0;
+                                if i == 0 {
+                                    altitude_filter.min_feet = feet;
+                                    altitude_filter.max_feet = altitude_filter.max_feet.max(feet);
+                                } else {
+                                    altitude_filter.max_feet = feet;
+                                    altitude_filter.min_feet = altitude_filter.min_feet.min(feet);
+                                }
+                            }
+                        }
+
+                        overlay_ids.vertical_trend_buttons.resize(
+                            altitude_filter.trends.len(),
+                            &mut overlay_ui.widget_id_generator(),
+                        );
+                        for (i, (trend, shown)) in altitude_filter.trends.iter_mut().enumerate() {
+                            let label =
+                                format!("{}: {}", trend.name(), if *shown { "On" } else { "Off" });
+                            if ui_filter::draw(
+                                overlay_ids.vertical_trend_buttons[i],
+                                overlay_ui,
+                                label,
+                                widget_x_position - 450.0,
+                                widget_y_position - 80.0 - i as f64 * 40.0,
+                            ) {
+                                *shown = !*shown;
+                            }
+                        }
+
+                        let unknown_label = format!(
+                            "Unknown: {}",
+                            if altitude_filter.show_unknown { "On" } else { "Off" }
+                        );
+                        if ui_filter::draw(
+                            overlay_ids.unknown_altitude_button,
+                            overlay_ui,
+                            unknown_label,
+                            widget_x_position - 450.0,
+                            widget_y_position - 200.0,
+                        ) {
+                            altitude_filter.show_unknown = !altitude_filter.show_unknown;
+                        }
                     }
 
                     if button_widget::draw_circle_with_image(
@@ -488,6 +2134,19 @@ pub fn run_app() {
                         .color(Color::Rgba(0.2, 0.2, 0.2, 1.0))
                         .top_left()
                         .set(overlay_ids.loading_background, overlay_ui);
+
+                    let status = if map_loaded {
+                        String::from("Loading planes...")
+                    } else {
+                        format!("Loading map {}%", tile_progress.percent())
+                    };
+                    // Just below the spinning logo
+                    widget::Text::new(&status)
+                        .x_y(0.0, -overlay_ui.win_h / 6.0 - 30.0)
+                        .color(conrod_core::color::WHITE)
+                        .font_size(16)
+                        .font_id(b612_overlay)
+                        .set(overlay_ids.loading_text, overlay_ui);
                 }
 
                 frame_counter += 1;
@@ -512,7 +2171,7 @@ pub fn run_app() {
                     let plane_type = hover_plane.plane.plane_type;
 
                     //Where to draw the detail lines
-                    let detail_lines = 5;
+                    let detail_lines = 6;
                     let mut i = 0;
                     let mut buf: util::StringFormatter<512> = util::StringFormatter::new();
                     overlay_ids
@@ -556,6 +2215,64 @@ pub fn run_app() {
                     draw_text(format_args!("CallSign: {}", plane.callsign));
                     draw_text(format_args!("Lat: {}", plane.latitude));
                     draw_text(format_args!("Long: {}", plane.longitude));
+                    match plane.heading_degrees() {
+                        Some(heading) => draw_text(format_args!("Heading: {:.0}°", heading)),
+                        None => draw_text(format_args!("Heading: Unknown")),
+                    }
+                }
+
+                //Only one tooltip is shown at a time, and planes are drawn over airports
+                let hovered_airport = match selected_plane {
+                    Some(_) => None,
+                    None => hovered_airport.zip(ui_cursor),
+                };
+                if let Some((i, cursor)) = hovered_airport {
+                    let airport = &airports[i];
+                    let code = match airport.code() {
+                        code if code == airport.ident => code.to_owned(),
+                        code => format!("{} ({})", code, airport.ident),
+                    };
+                    let lines = [
+                        airport.name.clone(),
+                        format!("Code: {}", code),
+                        format!(
+                            "Elevation: {}",
+                            units.format_altitude(feet_to_meters(airport.elevation as f64))
+                        ),
+                    ];
+                    overlay_ids
+                        .airport_tooltip_lines
+                        .resize(lines.len(), &mut overlay_ui.widget_id_generator());
+
+                    //Estimate the text width, since the real size is only known after it is drawn
+                    let longest = lines.iter().map(|line| line.chars().count()).max();
+                    let width = longest.unwrap_or(0) as f64 * 10.0 * 0.6 + 12.0;
+                    let height = lines.len() as f64 * 12.0 + 8.0;
+                    //Keep the panel beside the cursor, flipping to the left near the right edge
+                    let mut center = cursor + DVec2::new(16.0 + width / 2.0, -height / 2.0);
+                    if center.x + width / 2.0 > overlay_ui.win_w / 2.0 {
+                        center.x = cursor.x - 16.0 - width / 2.0;
+                    }
+                    center.y = center
+                        .y
+                        .max(height / 2.0 - overlay_ui.win_h / 2.0);
+
+                    widget::Rectangle::fill([width, height])
+                        .x_y(center.x, center.y)
+                        .color(conrod_core::color::BLACK.alpha(0.75))
+                        .set(overlay_ids.airport_tooltip, overlay_ui);
+                    for (i, line) in lines.iter().enumerate() {
+                        widget::Text::new(line)
+                            .top_left_with_margins_on(
+                                overlay_ids.airport_tooltip,
+                                4.0 + i as f64 * 12.0,
+                                6.0,
+                            )
+                            .color(conrod_core::color::WHITE)
+                            .font_size(10)
+                            .font_id(b612_overlay)
+                            .set(overlay_ids.airport_tooltip_lines[i], overlay_ui);
+                    }
                 }
 
                 if show_details {
@@ -566,7 +2283,7 @@ pub fn run_app() {
                         let plane_type = clicked_plane.plane.plane_type;
 
                         //Where to draw the detail lines
-                        let detail_lines = 5;
+                        let detail_lines = 6;
                         let mut i = 0;
                         let mut buf: util::StringFormatter<512> = util::StringFormatter::new();
                         overlay_ids
@@ -607,6 +2324,32 @@ pub fn run_app() {
                         draw_text(format_args!("CallSign: {}", plane.callsign));
                         draw_text(format_args!("Lat: {}", plane.latitude));
                         draw_text(format_args!("Long: {}", plane.longitude));
+                        match plane.heading_degrees() {
+                            Some(heading) => draw_text(format_args!("Heading: {:.0}°", heading)),
+                            None => draw_text(format_args!("Heading: Unknown")),
+                        }
+
+                        //========== Draw Follow Button ==========
+                        let following = followed_plane.as_deref() == Some(plane.callsign.as_str());
+                        let follow_label = if following {
+                            "Follow: On"
+                        } else {
+                            "Follow: Off"
+                        };
+                        if ui_filter::draw(
+                            overlay_ids.follow_button,
+                            overlay_ui,
+                            String::from(follow_label),
+                            -overlay_ui.win_w / 2.0 + 85.0,
+                            -(detail_lines as f64) * 20.0 - 10.0,
+                        ) {
+                            if following {
+                                followed_plane = None;
+                            } else {
+                                followed_plane = Some(plane.callsign.clone());
+                                viewer.pan_to(plane.latitude as f64, plane.longitude as f64);
+                            }
+                        }
                     }
                 }
 
@@ -616,6 +2359,7 @@ pub fn run_app() {
                 if let Some((vec, _)) = &mut frame_times {
                     vec.push(frame_time_ms);
                 }
+                frame_history.push(frame_time_ms);
                 last_time = now;
 
                 display.gl_window().window().request_redraw();
@@ -625,27 +2369,73 @@ pub fn run_app() {
                 let map_primitives = map_ui.draw();
 
                 let mut target = display.draw();
-                target.clear_color(0.21, 0.32, 0.4, 1.0);
-
-                map_renderer.fill(&display, map_primitives, &image_map);
-                map_renderer
-                    .draw(&display, &mut target, &image_map)
-                    .unwrap();
-
-                //=========Draw Planes============
-
-                let plane_data = plane_renderer.draw(
-                    &display,
-                    &mut target,
-                    &mut plane_requester,
-                    &viewer,
-                    selected_airline,
-                    &mut clicked_plane,
-                    last_cursor_pos,
-                );
+                let (red, green, blue, alpha) = theme.clear_color();
+                target.clear_color(red, green, blue, alpha);
+
+                //=========Draw Map and Planes============
+
+                // A turned map is drawn unrotated into a canvas, which is then turned onto the
+                // window. Otherwise the canvas is the window
+                let screen_dimensions = target.get_dimensions();
+                let canvas_dimensions = track_up::canvas_dimensions(&viewer, screen_dimensions);
+                let canvas_display = track_up::CanvasDisplay {
+                    display: &display,
+                    dimensions: canvas_dimensions,
+                };
+                map_renderer.fill(&canvas_display, map_primitives, &image_map);
+                // The cursor over the canvas, in physical pixels from its top left
+                let canvas_cursor = last_cursor_pos.map(|pos| {
+                    let screen = DVec2::new(screen_dimensions.0 as f64, screen_dimensions.1 as f64);
+                    let canvas = DVec2::new(canvas_dimensions.0 as f64, canvas_dimensions.1 as f64);
+                    viewer.screen_offset_to_map(pos - screen / 2.0) + canvas / 2.0
+                });
+                let shown_region = Some(&region).filter(|_| region_enabled);
+
+                // Draws the map and then the planes onto `$surface`, which is either the window
+                // or a canvas. A macro since closures can't be generic over the surface
+                macro_rules! draw_map_and_planes {
+                    ($surface:expr) => {{
+                        map_renderer
+                            .draw(&display, $surface, &image_map)
+                            .unwrap();
+                        plane_renderer.draw(
+                            &display,
+                            $surface,
+                            &mut plane_requester,
+                            &viewer,
+                            selected_airline,
+                            &altitude_filter,
+                            shown_region,
+                            &mut clicked_plane,
+                            canvas_cursor,
+                        )
+                    }};
+                }
 
-                loading = !plane_data.planes_loaded;
+                let mut plane_data = match viewer.rotation() == 0.0 {
+                    true => draw_map_and_planes!(&mut target),
+                    false => rotated_canvas.draw(
+                        &display,
+                        &mut target,
+                        &viewer,
+                        canvas_dimensions,
+                        |canvas| {
+                            canvas.clear_color(red, green, blue, alpha);
+                            draw_map_and_planes!(canvas)
+                        },
+                    ),
+                };
+                // Labels and plane details are drawn upright in the overlay
+                plane_data.rotate_to_screen(&viewer);
+
+                // No planes are coming while offline, so don't wait for them
+                let planes_loaded = plane_data.planes_loaded || plane_requester.is_offline();
+                loading = !planes_loaded || !map_loaded;
                 selected_plane = plane_data.plane_selection;
+                plane_clusters = plane_data.clusters;
+                plane_labels = plane_data.labels;
+                region_count = plane_data.region_count;
+                planes_fading = plane_data.fading;
 
                 //=========Draw Overlay===========
 
@@ -662,12 +2452,127 @@ pub fn run_app() {
                 }
 
                 target.finish().unwrap();
+
+                //=========Save Screenshots===========
+                let mut screenshots: Vec<std::path::PathBuf> =
+                    screenshot_path.take().into_iter().collect();
+                let exit_after_screenshot = !loading && exit_screenshot_path.is_some();
+                if exit_after_screenshot {
+                    screenshots.extend(exit_screenshot_path.take());
+                }
+                for path in screenshots {
+                    // Draws the same frame again, this time into a texture that can be read back
+                    save_screenshot(&display, &path, |surface| {
+                        surface.clear_color(red, green, blue, alpha);
+                        match viewer.rotation() == 0.0 {
+                            true => {
+                                draw_map_and_planes!(surface);
+                            }
+                            false => {
+                                rotated_canvas.draw(
+                                    &display,
+                                    surface,
+                                    &viewer,
+                                    canvas_dimensions,
+                                    |canvas| {
+                                        canvas.clear_color(red, green, blue, alpha);
+                                        draw_map_and_planes!(canvas);
+                                    },
+                                );
+                            }
+                        }
+                        overlay_renderer
+                            .draw(&display, surface, &image_map)
+                            .unwrap();
+                        if loading {
+                            loading_renderer.draw(&display, surface, frame_time_ms);
+                        }
+                    });
+                }
+                if exit_after_screenshot {
+                    *control_flow = glium::glutin::event_loop::ControlFlow::Exit;
+                }
+            }
+            glium::glutin::event::Event::LoopDestroyed => {
+                persist::save_state(&SavedState {
+                    view: viewer.to_saved_state(),
+                    theme,
+                    palette,
+                    plane_scale: plane_renderer.marker_scale,
+                    airport_scale: airport_settings.icon_scale as f32,
+                });
+
+                if let Some(runtime) = runtime.take() {
+                    plane_requester.shutdown(&runtime);
+                    // Gives in progress disk cache writes a chance to finish
+                    runtime.shutdown_timeout(RUNTIME_SHUTDOWN_TIMEOUT);
+                }
             }
             _ => {}
         }
     })
 }
 
+/// Returns the latitude and longitude under `xy`, in the conrod coordinates of `ui`, which covers
+/// the window
+fn ui_point_to_lat_long(view: &TileView, xy: [f64; 2], ui: &conrod_core::UiCell<'_>) -> (f64, f64) {
+    //The view measures from the top left of the window with y down
+    let pixel = DVec2::new(xy[0] + ui.win_w / 2.0, ui.win_h / 2.0 - xy[1]);
+    view.pixel_to_lat_long(pixel, ui.win_w, ui.win_h)
+}
+
+/// Calls `draw` to draw a frame into an offscreen texture the size of the window, then saves it
+/// as a PNG at `path`.
+///
+/// The window's own buffers aren't read, since their contents are undefined after they are
+/// swapped on many drivers
+fn save_screenshot(
+    display: &glium::Display,
+    path: &std::path::Path,
+    draw: impl FnOnce(&mut glium::framebuffer::SimpleFrameBuffer),
+) {
+    let (width, height) = display.get_framebuffer_dimensions();
+    let texture = match glium::texture::Texture2d::empty_with_format(
+        display,
+        glium::texture::UncompressedFloatFormat::U8U8U8U8,
+        glium::texture::MipmapsOption::NoMipmap,
+        width,
+        height,
+    ) {
+        Ok(texture) => texture,
+        Err(err) => {
+            println!("Failed to create texture for screenshot: {:?}", err);
+            return;
+        }
+    };
+    match glium::framebuffer::SimpleFrameBuffer::new(display, &texture) {
+        Ok(mut surface) => draw(&mut surface),
+        Err(err) => {
+            println!("Failed to create framebuffer for screenshot: {:?}", err);
+            return;
+        }
+    }
+
+    let raw: glium::texture::RawImage2d<u8> = texture.read();
+    let mut image = match image::RgbaImage::from_raw(raw.width, raw.height, raw.data.into_owned()) {
+        Some(image) => image,
+        None => {
+            println!("Frame read for screenshot has the wrong size");
+            return;
+        }
+    };
+    // Blending also blends the alpha channel, which the window ignores but a PNG wouldn't
+    for pixel in image.pixels_mut() {
+        pixel[3] = u8::MAX;
+    }
+    // OpenGL stores rows from the bottom up
+    let image = image::imageops::flip_vertical(&image);
+    match image.save(path) {
+        Ok(()) => println!("Saved screenshot to {}", path.display()),
+        Err(err) => println!("Failed to save screenshot to {}: {:?}", path.display(), err),
+    }
+}
+
 // Function to return the Id for images
 // Must convert image path to bytes
 fn return_image_essentials(
@@ -95,11 +95,11 @@ impl<'a> LoadingScreenRenderer<'a> {
         }
     }
 
-    /// Draw the rotating logo on the OpenGL Frame that is provided
-    pub fn draw(
+    /// Draw the rotating logo on the OpenGL surface that is provided
+    pub fn draw<S: Surface>(
         &mut self,
         display: &glium::Display,
-        target: &mut glium::Frame,
+        target: &mut S,
         frame_time_ms: f64,
     ) {
         // Here we collect the dynamic numbers for rendering our OpenGL planes
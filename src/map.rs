@@ -1,5 +1,6 @@
 use glam::DVec2;
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use std::convert::TryInto;
 use std::ops::Range;
 
@@ -32,6 +33,32 @@ pub struct WorldViewport {
     pub bottom_right: DVec2,
 }
 
+/// The parts of a [`TileView`] that are remembered between runs
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedView {
+    /// The latitude of the center of the view in degrees
+    pub latitude: f64,
+    /// The longitude of the center of the view in degrees
+    pub longitude: f64,
+    /// The zoom as returned by [`TileView::get_zoom`]
+    pub zoom: f64,
+}
+
+impl SavedView {
+    /// Returns true if this view describes a location that can actually be displayed.
+    ///
+    /// Used to reject saved state that was edited by hand or written by a buggy version
+    pub fn is_valid(&self) -> bool {
+        let max_latitude = crate::util::latitude_from_y(0.0);
+        self.latitude.is_finite()
+            && self.longitude.is_finite()
+            && self.zoom.is_finite()
+            && self.latitude.abs() <= max_latitude
+            && self.longitude.abs() <= 180.0
+            && (0.0..=MAX_ZOOM_LEVEL as f64 + 10.0).contains(&self.zoom)
+    }
+}
+
 pub struct TileView {
     /// The center of the view [0..1] for both x and y
     ///
@@ -47,15 +74,186 @@ pub struct TileView {
     /// the window can be resized and the center will stay in the center, and the zoom level will
     /// remain the same
     pixel_size: f64,
+
+    /// The pixel size `pixel_size` is animating towards.
+    ///
+    /// Zooming sets this value, and [`TileView::update`] eases `pixel_size` towards it each frame
+    target_pixel_size: f64,
+
+    /// The offset in pixels from the center of the window to the point which should stay fixed
+    /// while the zoom animates, or `None` to zoom about the center
+    zoom_anchor: Option<DVec2>,
+
+    /// The center the view is panning towards, set by [`TileView::pan_to`]
+    target_center: Option<DVec2>,
+
+    /// The smallest `pixel_size` zooming in can reach
+    min_pixel_size: f64,
+
+    /// The largest `pixel_size` zooming out can reach
+    max_pixel_size: f64,
+
+    /// The bearing in radians that points up the screen. Zero is north-up.
+    ///
+    /// The map is always drawn unrotated and then turned by this much, so only the functions
+    /// taking on screen pixels account for it
+    rotation: f64,
+}
+
+/// Configures and creates a [`TileView`].
+///
+/// Every setting is optional. Without any, the view shows the whole world with the default zoom
+/// limits
+#[derive(Clone, Debug)]
+pub struct TileViewBuilder {
+    latitude: f64,
+    longitude: f64,
+    pixel_size: f64,
+    scale_factor: f64,
+    min_pixel_size: f64,
+    max_pixel_size: f64,
 }
 
+impl TileViewBuilder {
+    /// Starts with the whole world in view, centered on the equator and prime meridian, and the
+    /// default zoom limits
+    pub fn new() -> Self {
+        Self {
+            latitude: 0.0,
+            longitude: 0.0,
+            pixel_size: MAX_PIXEL_SIZE,
+            scale_factor: 1.0,
+            min_pixel_size: MIN_PIXEL_SIZE,
+            max_pixel_size: MAX_PIXEL_SIZE,
+        }
+    }
+
+    /// Sets the center of the view in degrees. Latitudes past the edge of the mercator projection
+    /// (about 85 degrees) are clamped
+    pub fn center(mut self, latitude: f64, longitude: f64) -> Self {
+        self.latitude = latitude;
+        self.longitude = longitude;
+        self
+    }
+
+    /// Sets the zoom using the scale of [`TileView::get_zoom`], where each pixel covers
+    /// `1 / 2^zoom` of the world's width. A zoom of 17 shows a city on a typical window
+    pub fn zoom(mut self, zoom: f64) -> Self {
+        self.pixel_size = pixel_size_from_raw_zoom(zoom);
+        self
+    }
+
+    /// Sets the zoom so that `1 / 2^zoom` of the world's width spans `window_width` pixels.
+    ///
+    /// This is the scale used by [`TileView::new`], which is convenient when the window size is
+    /// known, but note that it differs from [`TileView::get_zoom`]
+    pub fn zoom_across(mut self, zoom: f64, window_width: f64) -> Self {
+        self.pixel_size = pixel_size_from_zoom(zoom, window_width);
+        self
+    }
+
+    /// The ratio of physical to logical pixels of the window.
+    ///
+    /// The view works in logical pixels, while the zoom set with [`TileViewBuilder::zoom`] or
+    /// [`TileViewBuilder::zoom_across`] is taken to be in physical pixels, so that tiles appear at
+    /// the same sharpness on high DPI screens. Defaults to one
+    pub fn scale_factor(mut self, scale_factor: f64) -> Self {
+        self.scale_factor = scale_factor;
+        self
+    }
+
+    /// The deepest zoom, using the scale of [`TileView::get_zoom`], that the user can zoom in to
+    pub fn max_zoom(mut self, zoom: f64) -> Self {
+        self.min_pixel_size = pixel_size_from_raw_zoom(zoom);
+        self
+    }
+
+    /// The furthest zoom, using the scale of [`TileView::get_zoom`], that the user can zoom out to
+    pub fn min_zoom(mut self, zoom: f64) -> Self {
+        self.max_pixel_size = pixel_size_from_raw_zoom(zoom);
+        self
+    }
+
+    /// Creates the view. The zoom is clamped between the minimum and maximum zoom, which are
+    /// swapped if the minimum is deeper than the maximum
+    pub fn build(self) -> TileView {
+        let x = crate::util::x_from_longitude(self.longitude);
+        let y = crate::util::y_from_latitude(self.latitude).clamp(0.0, 1.0);
+        //`clamp` panics if the limits are reversed
+        let min_pixel_size = self.min_pixel_size.min(self.max_pixel_size);
+        let max_pixel_size = self.min_pixel_size.max(self.max_pixel_size);
+        let pixel_size =
+            (self.pixel_size * self.scale_factor).clamp(min_pixel_size, max_pixel_size);
+        TileView {
+            center: DVec2::new(x, y),
+            pixel_size,
+            target_pixel_size: pixel_size,
+            zoom_anchor: None,
+            target_center: None,
+            min_pixel_size,
+            max_pixel_size,
+            rotation: 0.0,
+        }
+    }
+}
+
+impl Default for TileViewBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How quickly zoom animations approach their target. Larger is faster.
+///
+/// After `1 / ZOOM_SPEED` seconds, about 63% of the remaining zoom distance has been covered
+const ZOOM_SPEED: f64 = 15.0;
+
+/// How quickly pan animations approach their target. Larger is faster
+const PAN_SPEED: f64 = 4.0;
+
+/// Make sure the entire world cannot be smaller that 100 pixels across
+const MAX_PIXEL_SIZE: f64 = 1.0 / 100.0;
+
+/// Prevent the user from scrolling to where tile coordinates are less that 2^-28.
+/// On a normal screen this means you can zoom into about zoom level 20
+const MIN_PIXEL_SIZE: f64 = 1.064 / (1u64 << 28) as f64;
+
 impl TileView {
+    /// Creates a view centered on `latitude` and `longitude` where `1 / 2^zoom` of the world's
+    /// width spans `window_width` pixels.
+    ///
+    /// See [`TileViewBuilder`] for more options
     pub fn new(latitude: f64, longitude: f64, zoom: f64, window_width: f64) -> Self {
-        let x = crate::util::x_from_longitude(longitude);
-        let y = crate::util::y_from_latitude(latitude);
+        TileViewBuilder::new()
+            .center(latitude, longitude)
+            .zoom_across(zoom, window_width)
+            .build()
+    }
+
+    /// Creates a view from a state previously returned by [`TileView::to_saved_state`]
+    pub fn from_saved_state(state: &SavedView) -> Self {
+        let pixel_size = pixel_size_from_raw_zoom(state.zoom);
         Self {
-            center: DVec2::new(x, y),
-            pixel_size: pixel_size_from_zoom(zoom, window_width),
+            center: DVec2::new(
+                crate::util::x_from_longitude(state.longitude),
+                crate::util::y_from_latitude(state.latitude),
+            ),
+            pixel_size,
+            target_pixel_size: pixel_size,
+            zoom_anchor: None,
+            target_center: None,
+            min_pixel_size: MIN_PIXEL_SIZE,
+            max_pixel_size: MAX_PIXEL_SIZE,
+            rotation: 0.0,
+        }
+    }
+
+    /// Returns the center and zoom of this view so that it can be restored on the next run
+    pub fn to_saved_state(&self) -> SavedView {
+        SavedView {
+            latitude: crate::util::latitude_from_y(self.center.y),
+            longitude: crate::util::longitude_from_x(self.center.x.rem_euclid(1.0)),
+            zoom: self.get_zoom(),
         }
     }
 
@@ -88,30 +286,241 @@ impl TileView {
     pub fn set_zoom(&mut self, zoom: f64, window_width: f64) {
         let new_pixel_size = pixel_size_from_zoom(zoom, window_width);
         self.pixel_size = new_pixel_size;
+        self.target_pixel_size = new_pixel_size;
     }
     ///Returns the zoom level of the current tile positioned.
     pub fn get_zoom(&self) -> f64 {
         zoom_from_pixel_size(self.pixel_size)
     }
+
+    /// Returns a still, north-up view with the same center as this one, zoomed out by `levels`
+    /// zoom levels.
+    ///
+    /// The zoom is limited to the furthest this view can zoom out
+    pub fn zoomed_out(&self, levels: f64) -> TileView {
+        let pixel_size = (self.pixel_size * 2f64.powf(levels)).min(self.max_pixel_size);
+        TileView {
+            center: self.center,
+            pixel_size,
+            target_pixel_size: pixel_size,
+            zoom_anchor: None,
+            target_center: None,
+            min_pixel_size: self.min_pixel_size,
+            max_pixel_size: self.max_pixel_size,
+            rotation: 0.0,
+        }
+    }
+
+    /// Zooms by `multiplier`, where values less than one zoom in and values greater than one zoom
+    /// out.
+    ///
+    /// The zoom is animated over the next few calls to [`TileView::update`]
     pub fn multiply_zoom(&mut self, multiplier: f64) {
-        let new_pixel_size = self.pixel_size * multiplier;
-        //Make sure the entire world cannot be smaller that 100 pixels across
-        if new_pixel_size < (1.0 / 100.0) {
-            //Prevent the user from scrolling to where tile coordinates are less that 2^-28
-            //On a normal screen this means you can zoom into about zoom level 20
-            let min_size: f64 = 1.064 / 2.0f64.powi(28);
-            if new_pixel_size > min_size {
-                self.pixel_size = new_pixel_size;
+        let new_pixel_size = self.target_pixel_size * multiplier;
+        if new_pixel_size < self.max_pixel_size && new_pixel_size > self.min_pixel_size {
+            self.target_pixel_size = new_pixel_size;
+            self.zoom_anchor = None;
+        }
+    }
+
+    /// Zooms by `multiplier` like [`TileView::multiply_zoom`], but keeps the world location under
+    /// `pixel` fixed on screen instead of the center.
+    ///
+    /// `pixel` is measured from the top left of a window of size `screen_width` by
+    /// `screen_height`, with y increasing downwards
+    pub fn zoom_about_pixel(
+        &mut self,
+        multiplier: f64,
+        pixel: DVec2,
+        screen_width: f64,
+        screen_height: f64,
+    ) {
+        self.multiply_zoom(multiplier);
+        if self.is_animating() {
+            let offset = pixel - DVec2::new(screen_width, screen_height) / 2.0;
+            self.zoom_anchor = Some(self.screen_offset_to_map(offset));
+        }
+    }
+
+    /// Returns the world location under `pixel`, which is measured from the top left of a window
+    /// of size `screen_width` by `screen_height`, with y increasing downwards
+    pub fn pixel_to_world(&self, pixel: DVec2, screen_width: f64, screen_height: f64) -> DVec2 {
+        let offset = pixel - DVec2::new(screen_width, screen_height) / 2.0;
+        self.center + self.screen_offset_to_map(offset) * self.pixel_size
+    }
+
+    /// The inverse of [`TileView::pixel_to_world`]
+    pub fn world_to_pixel(&self, world: DVec2, screen_width: f64, screen_height: f64) -> DVec2 {
+        let offset = (world - self.center) / self.pixel_size;
+        self.map_offset_to_screen(offset) + DVec2::new(screen_width, screen_height) / 2.0
+    }
+
+    /// Returns the bearing in radians that points up the screen
+    pub fn rotation(&self) -> f64 {
+        self.rotation
+    }
+
+    /// Turns the screen so that the bearing `rotation`, in radians, points up. Zero is north-up
+    pub fn set_rotation(&mut self, rotation: f64) {
+        self.rotation = rotation.rem_euclid(std::f64::consts::TAU);
+    }
+
+    /// Converts `offset` in pixels from the center of the screen to the offset from the center of
+    /// the unrotated map under it. Both have y increasing downwards
+    pub fn screen_offset_to_map(&self, offset: DVec2) -> DVec2 {
+        crate::util::rotate(offset, self.rotation)
+    }
+
+    /// The inverse of [`TileView::screen_offset_to_map`]
+    pub fn map_offset_to_screen(&self, offset: DVec2) -> DVec2 {
+        crate::util::rotate(offset, -self.rotation)
+    }
+
+    /// Returns the latitude and longitude in degrees under `pixel`, using the same coordinates as
+    /// [`TileView::pixel_to_world`]
+    pub fn pixel_to_lat_long(
+        &self,
+        pixel: DVec2,
+        screen_width: f64,
+        screen_height: f64,
+    ) -> (f64, f64) {
+        let world = self.pixel_to_world(pixel, screen_width, screen_height);
+        (
+            crate::util::latitude_from_y(world.y.clamp(0.0, 1.0)),
+            crate::util::longitude_from_x(world.x.rem_euclid(1.0)),
+        )
+    }
+
+    /// The inverse of [`TileView::pixel_to_lat_long`]. Returns the pixel that `latitude` and
+    /// `longitude` in degrees are drawn at, using the same coordinates.
+    ///
+    /// The world repeats horizontally, so the copy of the point closest to the center of the
+    /// screen is used. The pixel may be off screen
+    pub fn lat_long_to_pixel(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        screen_width: f64,
+        screen_height: f64,
+    ) -> DVec2 {
+        let x = crate::util::x_from_longitude(longitude);
+        let world = DVec2::new(
+            x + (self.center.x - x).round(),
+            crate::util::y_from_latitude(latitude),
+        );
+        self.world_to_pixel(world, screen_width, screen_height)
+    }
+
+    /// Advances any pan and zoom animations by `dt_seconds`. Should be called once per frame
+    pub fn update(&mut self, dt_seconds: f64) {
+        self.update_pan(dt_seconds);
+        self.update_zoom(dt_seconds);
+        //Zooming about a point near the edge of the world can push the center past it
+        self.clamp_center();
+    }
+
+    fn update_pan(&mut self, dt_seconds: f64) {
+        let target = match self.target_center {
+            Some(target) => target,
+            None => return,
+        };
+        let t = 1.0 - f64::exp(-dt_seconds.max(0.0) * PAN_SPEED);
+        let next = self.center.lerp(target, t);
+
+        //Snap once the remaining distance is less than a pixel
+        if (next - target).length() < self.pixel_size {
+            self.center = target;
+            self.target_center = None;
+        } else {
+            self.center = next;
+        }
+    }
+
+    fn update_zoom(&mut self, dt_seconds: f64) {
+        if self.pixel_size == self.target_pixel_size {
+            return;
+        }
+        //Interpolate in log space so that zooming in and out feel the same
+        let current = self.pixel_size.ln();
+        let target = self.target_pixel_size.ln();
+        let t = 1.0 - f64::exp(-dt_seconds.max(0.0) * ZOOM_SPEED);
+        let next = crate::util::lerp(current, target, t);
+
+        let old_pixel_size = self.pixel_size;
+        //Snap once we are close enough that the difference is invisible
+        if (next - target).abs() < 0.001 {
+            self.pixel_size = self.target_pixel_size;
+        } else {
+            self.pixel_size = next.exp();
+        }
+
+        if let Some(anchor) = self.zoom_anchor {
+            //Move the center so that the world location under the anchor doesn't move
+            self.center += anchor * (old_pixel_size - self.pixel_size);
+            if !self.is_animating() {
+                self.zoom_anchor = None;
             }
         }
     }
 
+    /// Centers the view on `latitude` and `longitude` (in degrees), keeping the current zoom
+    pub fn set_center(&mut self, latitude: f64, longitude: f64) {
+        self.center = DVec2::new(
+            crate::util::x_from_longitude(longitude),
+            crate::util::y_from_latitude(latitude),
+        );
+        self.target_center = None;
+        self.clamp_center();
+    }
+
+    /// Like [`TileView::set_center`], but the camera eases towards the new center over the next
+    /// few calls to [`TileView::update`]
+    pub fn pan_to(&mut self, latitude: f64, longitude: f64) {
+        let mut target = DVec2::new(
+            crate::util::x_from_longitude(longitude),
+            crate::util::y_from_latitude(latitude),
+        );
+        //Pan to the copy of the world closest to the camera instead of going the long way around
+        target.x += (self.center.x - target.x).round();
+        target.y = target.y.clamp(0.0, 1.0);
+        self.target_center = Some(target);
+    }
+
+    /// Animates the zoom towards `zoom`, which uses the same scale as [`TileView::get_zoom`]
+    pub fn zoom_to(&mut self, zoom: f64) {
+        self.target_pixel_size =
+            pixel_size_from_raw_zoom(zoom).clamp(self.min_pixel_size, self.max_pixel_size);
+        self.zoom_anchor = None;
+    }
+
+    /// Animates the zoom towards where `1 / 2^zoom` of the world's width spans `window_width`
+    /// pixels, like [`TileViewBuilder::zoom_across`]
+    pub fn zoom_to_across(&mut self, zoom: f64, window_width: f64) {
+        self.target_pixel_size = pixel_size_from_zoom(zoom, window_width)
+            .clamp(self.min_pixel_size, self.max_pixel_size);
+        self.zoom_anchor = None;
+    }
+
+    /// Returns true if the zoom or center is still animating towards its target
+    pub fn is_animating(&self) -> bool {
+        self.pixel_size != self.target_pixel_size || self.target_center.is_some()
+    }
+
     /// Moves the camera for this map view based on `direction`.
     ///
-    /// The units are current screen pixels based on the current zoom level.
+    /// The units are current screen pixels based on the current zoom level and rotation.
     /// Visually this will move the camera the same amount regardless of the zoom.
+    /// Cancels any pan started by [`TileView::pan_to`]
     pub fn move_camera_pixels(&mut self, direction: DVec2) {
-        self.center += direction * self.pixel_size;
+        self.center += self.screen_offset_to_map(direction) * self.pixel_size;
+        self.target_center = None;
+        self.clamp_center();
+    }
+
+    /// Keeps the center between the top and bottom of the mercator projection (about 85 degrees
+    /// north and south), where latitudes and tiles exist
+    fn clamp_center(&mut self) {
+        self.center.y = self.center.y.clamp(0.0, 1.0);
     }
 
     pub fn get_world_viewport(&self, screen_width: f64, screen_height: f64) -> WorldViewport {
@@ -131,15 +540,22 @@ impl TileView {
         }
     }
 
+    /// Returns an iterator over the tiles covering the screen, at the zoom level that best matches
+    /// the view but no deeper than `max_zoom`.
+    ///
+    /// Tile x indices wrap modulo the number of tiles in a row, so a view across the antimeridian
+    /// yields the last tiles in a row followed by the first ones, while the on screen positions
+    /// derived from [`TileViewIterator::tile_offset`] keep increasing to the right
     pub fn tile_iter(
         &self,
         tile_size: u32,
+        max_zoom: u32,
         screen_width: f64,
         screen_height: f64,
     ) -> TileViewIterator {
-        //Tile zoom maxes out at 20.
-        //TODO: Make this configurable in case tile providers have different maxes
-        let tile_zoom = self.tile_zoom_level(tile_size).min(MAX_ZOOM_LEVEL);
+        //Past `max_zoom` the tile provider has no more detail, so keep using the deepest tiles and
+        //let `tile_size` grow to stretch them across the screen
+        let tile_zoom = self.tile_zoom_level(tile_size).min(max_zoom);
         let max_tile = 2u32.pow(tile_zoom) as f64;
 
         //Tile size is the size of a tile in pixels based on the current zoom level
@@ -161,29 +577,24 @@ impl TileView {
         let top_left_world = viewport.top_left;
         let bottom_right_world = viewport.bottom_right;
 
-        let top_left = DVec2::new(
-            top_left_world.x.rem_euclid(1.0),
-            top_left_world.y.rem_euclid(1.0),
-        );
-
-        let dest_max = DVec2::new(max_tile, max_tile);
-
         //Next map world coordinates to tile coordinates (0..1) to (0..max_tile)
-        let top_left_tiles = top_left * dest_max;
+        //Columns wrap around the antimeridian, but rows end at the top and bottom of the mercator
+        //projection, so y is left unwrapped
+        let top_left_tiles = DVec2::new(
+            top_left_world.x.rem_euclid(1.0) * max_tile,
+            top_left_world.y * max_tile,
+        );
 
         //Floor and ceil to render all tiles that are even partially visible
-        let first_offset = DVec2::new(top_left_tiles.x % 1.0, top_left_tiles.y % 1.0);
-
-        let first_x = (top_left_tiles.x - first_offset.x) as u32;
-        let first_y = (top_left_tiles.y - first_offset.y) as u32;
+        let first_x = top_left_tiles.x.floor();
+        //Skip rows above the top of the world instead of drawing tiles from the bottom there
+        let first_y = top_left_tiles.y.floor().clamp(0.0, max_tile);
+        let first_offset = top_left_tiles - DVec2::new(first_x, first_y);
 
-        let (tiles_wide, tiles_high) = {
-            let diff = bottom_right_world - top_left_world;
-            (
-                (diff.x * max_tile).ceil() as u32 + 1,
-                (diff.y * max_tile).ceil() as u32 + 2,
-            )
-        };
+        let tiles_wide = ((bottom_right_world.x - top_left_world.x) * max_tile).ceil() as u32 + 1;
+        let last_y = ((bottom_right_world.y * max_tile).ceil() + 1.0).clamp(first_y, max_tile);
+        let tiles_high = (last_y - first_y) as u32;
+        let (first_x, first_y) = (first_x as u32, first_y as u32);
 
         // We have all the values to make the iterator
         TileViewIterator {
@@ -217,6 +628,11 @@ fn zoom_from_pixel_size(pixel_size: f64) -> f64 {
     f64::log2(1.0 / pixel_size)
 }
 
+/// The inverse of [`zoom_from_pixel_size`]
+fn pixel_size_from_raw_zoom(zoom: f64) -> f64 {
+    1.0 / 2f64.powf(zoom)
+}
+
 /// Walks the positions of all the tiles currently in view, returning their coordinates for
 /// rendering
 #[derive(Clone, Debug)]
@@ -268,9 +684,12 @@ mod tests {
     }
 
     fn are_tiles_visible(data: IsSameTiles) {
-        let real_iter = data
-            .view
-            .tile_iter(data.tile_size, data.screen_width, data.screen_height);
+        let real_iter = data.view.tile_iter(
+            data.tile_size,
+            MAX_ZOOM_LEVEL,
+            data.screen_width,
+            data.screen_height,
+        );
 
         let real: Vec<TileCoordinate> = real_iter.collect();
         let max_tile = 2u32.pow(data.view.tile_zoom_level(data.tile_size));
@@ -385,6 +804,331 @@ mod tests {
         });
     }
 
+    #[test]
+    fn smooth_zoom_converges() {
+        let mut view = TileView::new(0.0, 0.0, 4.0, 1000.0);
+        let start = view.get_zoom();
+        view.multiply_zoom(0.5);
+
+        //Nothing changes until the view is updated
+        assert_eq!(view.get_zoom(), start);
+        assert!(view.is_animating());
+
+        view.update(1.0 / 60.0);
+        let partial = view.get_zoom();
+        assert!(partial > start && partial < start + 1.0);
+
+        for _ in 0..120 {
+            view.update(1.0 / 60.0);
+        }
+        assert!(!view.is_animating());
+        assert!((view.get_zoom() - (start + 1.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn zoom_about_pixel_keeps_cursor_fixed() {
+        let (width, height) = (1280.0, 720.0);
+        let cursor = DVec2::new(1000.0, 150.0);
+        let mut view = TileView::new(29.18796, -81.04923, 8.0, width);
+        let before = view.pixel_to_world(cursor, width, height);
+        let start_center = view.center;
+
+        view.zoom_about_pixel(0.5, cursor, width, height);
+        //Check part way through the animation as well as at the end
+        view.update(1.0 / 60.0);
+        assert!((view.pixel_to_world(cursor, width, height) - before).length() < 1e-12);
+        for _ in 0..120 {
+            view.update(1.0 / 60.0);
+        }
+        assert!(!view.is_animating());
+        assert!((view.pixel_to_world(cursor, width, height) - before).length() < 1e-12);
+
+        //Zooming in moved the center towards the cursor, which is up and to the right
+        assert!(view.center.x > start_center.x);
+        assert!(view.center.y < start_center.y);
+    }
+
+    #[test]
+    fn builder() {
+        let (width, height) = (1000.0, 500.0);
+        let view = TileViewBuilder::new()
+            .center(0.0, 0.0)
+            .zoom(10.0)
+            .max_zoom(12.0)
+            .build();
+        assert_eq!(view.get_zoom(), 10.0);
+
+        //Each pixel covers 2^-10 of the world
+        let viewport = view.get_world_viewport(width, height);
+        let half = DVec2::new(width, height) / 2.0 / 1024.0;
+        assert!((viewport.top_left - (DVec2::splat(0.5) - half)).length() < 1e-12);
+        assert!((viewport.bottom_right - (DVec2::splat(0.5) + half)).length() < 1e-12);
+
+        //Zooming stops at the maximum
+        let mut view = view;
+        view.zoom_to(30.0);
+        for _ in 0..120 {
+            view.update(1.0 / 60.0);
+        }
+        assert_eq!(view.get_zoom(), 12.0);
+
+        //A scale factor of two makes each logical pixel cover twice as much of the world
+        let high_dpi = TileViewBuilder::new().zoom(10.0).scale_factor(2.0).build();
+        assert_eq!(high_dpi.get_zoom(), 9.0);
+
+        //`new` is the same as `zoom_across`
+        let view = TileView::new(29.18796, -81.04923, 8.0, 1080.0 / 2.0);
+        let built = TileViewBuilder::new()
+            .center(29.18796, -81.04923)
+            .zoom_across(8.0, 1080.0 / 2.0)
+            .build();
+        assert_eq!(view.to_saved_state(), built.to_saved_state());
+    }
+
+    #[test]
+    fn builder_swaps_reversed_zoom_limits() {
+        let mut view = TileViewBuilder::new()
+            .zoom(15.0)
+            .min_zoom(12.0)
+            .max_zoom(4.0)
+            .build();
+        assert_eq!(view.get_zoom(), 12.0);
+
+        //Zooming still works between the limits, and stops at them
+        view.zoom_to(30.0);
+        for _ in 0..120 {
+            view.update(1.0 / 60.0);
+        }
+        assert_eq!(view.get_zoom(), 12.0);
+        view.zoom_to(0.0);
+        for _ in 0..120 {
+            view.update(1.0 / 60.0);
+        }
+        assert_eq!(view.get_zoom(), 4.0);
+    }
+
+    #[test]
+    fn pixel_world_inverse() {
+        let (width, height) = (1280.0, 720.0);
+        let view = TileView::new(-33.9, 151.2, 12.0, width);
+        let pixel = DVec2::new(100.0, 650.0);
+        let world = view.pixel_to_world(pixel, width, height);
+        assert!((view.world_to_pixel(world, width, height) - pixel).length() < 1e-6);
+
+        let (lat, lng) = view.pixel_to_lat_long(DVec2::new(width, height) / 2.0, width, height);
+        assert!((lat - -33.9).abs() < 1e-9);
+        assert!((lng - 151.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn lat_long_pixel_round_trip() {
+        let (width, height) = (1280.0, 720.0);
+        let views = [
+            TileView::new(29.18796, -81.04923, 8.0, width),
+            TileView::new(-33.9, 151.2, 3.0, width),
+            TileView::new(0.0, 179.9, 6.0, width),
+        ];
+        for view in &views {
+            for pixel in [
+                DVec2::new(0.0, 0.0),
+                DVec2::new(640.0, 360.0),
+                DVec2::new(1279.0, 17.5),
+            ] {
+                let (lat, lng) = view.pixel_to_lat_long(pixel, width, height);
+                let back = view.lat_long_to_pixel(lat, lng, width, height);
+                assert!(
+                    (back - pixel).length() < 1e-6,
+                    "{:?} became {:?}",
+                    pixel,
+                    back
+                );
+            }
+        }
+
+        //Points across the antimeridian are drawn next to the center, not a world away
+        let pixel = views[2].lat_long_to_pixel(0.0, -179.9, width, height);
+        assert!(pixel.x > width / 2.0 && pixel.x < width);
+        assert!((pixel.y - height / 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rotated_view() {
+        let (width, height) = (1280.0, 720.0);
+        let center = DVec2::new(width, height) / 2.0;
+        let mut view = TileView::new(29.18796, -81.04923, 8.0, width);
+        let north = view.pixel_to_world(center - DVec2::new(0.0, 100.0), width, height);
+
+        //Facing east, what was north of the center is now to its left
+        view.set_rotation(90f64.to_radians());
+        let left = view.pixel_to_world(center - DVec2::new(100.0, 0.0), width, height);
+        assert!((left - north).length() < 1e-12);
+        let pixel = DVec2::new(100.0, 650.0);
+        let world = view.pixel_to_world(pixel, width, height);
+        assert!((view.world_to_pixel(world, width, height) - pixel).length() < 1e-6);
+
+        //Dragging the map up moves the camera east
+        let start = view.center;
+        view.move_camera_pixels(DVec2::new(0.0, -10.0));
+        assert!(view.center.x > start.x);
+        assert!((view.center.y - start.y).abs() < 1e-12);
+
+        //Zooming keeps the cursor fixed on the turned screen too
+        let cursor = DVec2::new(1000.0, 150.0);
+        let before = view.pixel_to_world(cursor, width, height);
+        view.zoom_about_pixel(0.5, cursor, width, height);
+        for _ in 0..120 {
+            view.update(1.0 / 60.0);
+        }
+        assert!((view.pixel_to_world(cursor, width, height) - before).length() < 1e-12);
+
+        //The minimap stays north-up
+        assert_eq!(view.zoomed_out(4.0).rotation(), 0.0);
+    }
+
+    #[test]
+    fn pan_to_eases_and_wraps() {
+        let mut view = TileView::new(0.0, 179.0, 8.0, 1000.0);
+        view.pan_to(10.0, -179.0);
+        assert!(view.is_animating());
+
+        view.update(1.0 / 60.0);
+        let partial = view.to_saved_state();
+        assert!(partial.latitude > 0.0 && partial.latitude < 10.0);
+        //Crossing the antimeridian is shorter than going back around the world
+        assert!(partial.longitude > 179.0 || partial.longitude < -179.0);
+
+        for _ in 0..600 {
+            view.update(1.0 / 60.0);
+        }
+        assert!(!view.is_animating());
+        let state = view.to_saved_state();
+        assert!((state.latitude - 10.0).abs() < 1e-3);
+        assert!((state.longitude - -179.0).abs() < 1e-3);
+
+        //Dragging the map cancels the pan
+        view.pan_to(0.0, 0.0);
+        view.move_camera_pixels(DVec2::new(1.0, 0.0));
+        assert!(!view.is_animating());
+    }
+
+    #[test]
+    fn set_center_and_zoom() {
+        let mut view = TileView::new(0.0, 0.0, 4.0, 1000.0);
+        view.set_center(33.9425, -118.408);
+        view.zoom_to(12.0);
+        for _ in 0..120 {
+            view.update(1.0 / 60.0);
+        }
+
+        let state = view.to_saved_state();
+        assert!((state.latitude - 33.9425).abs() < 1e-9);
+        assert!((state.longitude - -118.408).abs() < 1e-9);
+        assert!((view.get_zoom() - 12.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn saved_state_round_trip() {
+        let view = TileView::new(29.18796, -81.04923, 8.0, 540.0);
+        let saved = view.to_saved_state();
+        assert!(saved.is_valid());
+
+        let restored = TileView::from_saved_state(&saved);
+        assert!((restored.center - view.center).length() < 1e-9);
+        assert!((restored.get_zoom() - view.get_zoom()).abs() < 1e-9);
+
+        let mut corrupt = saved;
+        corrupt.latitude = f64::NAN;
+        assert!(!corrupt.is_valid());
+        corrupt.latitude = 91.0;
+        assert!(!corrupt.is_valid());
+    }
+
+    #[test]
+    fn tile_iter_wraps_at_antimeridian() {
+        let (width, height) = (1280.0, 720.0);
+        for zoom in [3.0, 8.0, 14.0, 19.0] {
+            for longitude in [-179.99999, 180.0, 179.99999] {
+                let mut view = TileView::new(0.0, longitude, zoom, width);
+                view.set_zoom(zoom, width);
+                let it = view.tile_iter(256, MAX_ZOOM_LEVEL, width, height);
+                let max_tile = it.max_tile;
+                let tiles_high = it.tiles_vertically as usize;
+
+                //Tiles are emitted column by column, so every `tiles_high`th tile starts a column
+                let columns: Vec<u32> = it.step_by(tiles_high).map(|(x, _)| x).collect();
+                assert!(columns.iter().all(|&x| x < max_tile));
+                for pair in columns.windows(2) {
+                    assert_eq!(pair[1], (pair[0] + 1) % max_tile, "zoom {}", zoom);
+                }
+                //The view straddles the date line, so both edges of the world are visible
+                assert!(columns.contains(&0), "zoom {} lng {}", zoom, longitude);
+                assert!(
+                    columns.contains(&(max_tile - 1)),
+                    "zoom {} lng {}",
+                    zoom,
+                    longitude
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn dragging_past_the_poles_stays_valid() {
+        let (width, height) = (1280.0, 720.0);
+        for zoom in [1.0, 6.0, 15.0] {
+            let mut view = TileView::new(80.0, 0.0, zoom, width);
+            view.set_zoom(zoom, width);
+            for direction in [-1.0, 1.0] {
+                for _ in 0..100 {
+                    //Each drag moves the camera a fiftieth of the world
+                    let drag = 0.02 / view.pixel_size;
+                    view.move_camera_pixels(DVec2::new(37.0, direction * drag));
+                    view.update(1.0 / 60.0);
+
+                    let saved = view.to_saved_state();
+                    assert!(saved.is_valid(), "{:?} at zoom {}", saved, zoom);
+
+                    let mut it = view.tile_iter(256, MAX_ZOOM_LEVEL, width, height);
+                    let max_tile = it.max_tile;
+                    assert!(it.all(|(x, y)| x < max_tile && y < max_tile));
+                }
+                //The camera stops at the edge of the world instead of running off it
+                let expected = if direction < 0.0 { 0.0 } else { 1.0 };
+                assert_eq!(view.center.y, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn tile_iter_skips_rows_outside_the_world() {
+        let (width, height) = (1000.0, 1000.0);
+        //Zoomed out so far that the whole world is a small square in the middle of the screen
+        let mut view = TileView::new(0.0, 0.0, 0.0, width);
+        view.set_zoom(0.0, width);
+        view.pixel_size = 1.0 / 250.0;
+        let it = view.tile_iter(256, MAX_ZOOM_LEVEL, width, height);
+        assert_eq!(it.tile_zoom, 0);
+        assert_eq!(it.tiles_vertically, 1);
+        //The single row starts at the top of the world, 375 pixels below the top of the screen
+        assert!((it.tile_offset.y - (-375.0 - 250.0)).abs() < 1e-6);
+        assert!(it.clone().all(|(_, y)| y == 0));
+    }
+
+    #[test]
+    fn tile_iter_stretches_past_max_zoom() {
+        let window_width = 1000.0;
+        let mut view = TileView::new(0.0, 0.0, 0.0, window_width);
+        view.set_zoom(19.0, window_width);
+        assert!(view.tile_zoom_level(256) > 17);
+
+        let it = view.tile_iter(256, 17, window_width, window_width);
+        assert_eq!(it.tile_zoom, 17);
+        assert_eq!(it.max_tile, 2u32.pow(17));
+        //Each tile now covers more than its own pixel count on screen
+        assert!(it.tile_size.x > 256.0);
+        assert!(it.clone().all(|(x, y)| x < it.max_tile && y < it.max_tile));
+    }
+
     #[test]
     fn tile_view_high_res() {
         let window_width = 1000.0;
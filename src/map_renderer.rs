@@ -1,6 +1,10 @@
 use conrod_core::{
-    widget::{id::List, Image, Line, Text},
-    Colorable, Positionable, Sizeable, UiCell, Widget,
+    widget::{
+        id::List,
+        triangles::{ColoredPoint, Triangle},
+        Image, Line, Text, Triangles,
+    },
+    Colorable, Positionable, Rect, Sizeable, UiCell, Widget,
 };
 use glam::DVec2;
 
@@ -40,6 +44,62 @@ pub fn world_y_to_pixel_y(
     )
 }
 
+/// The inverse of [`world_x_to_pixel_x`]. Converts an x pixel location in the conrad coordinate
+/// system to a x world location
+pub fn pixel_x_to_world_x(
+    pixel_x: f64,
+    viewport: &crate::map::WorldViewport,
+    window_width: f64,
+) -> f64 {
+    let half_width = window_width / 2.0;
+    crate::util::map(
+        -half_width,
+        half_width,
+        pixel_x,
+        viewport.top_left.x,
+        viewport.bottom_right.x,
+    )
+}
+
+/// The inverse of [`world_y_to_pixel_y`]. Converts a y pixel location in the conrad coordinate
+/// system to a y world location
+pub fn pixel_y_to_world_y(
+    pixel_y: f64,
+    viewport: &crate::map::WorldViewport,
+    window_height: f64,
+) -> f64 {
+    let half_height = window_height / 2.0;
+    crate::util::map(
+        -half_height,
+        half_height,
+        pixel_y,
+        viewport.bottom_right.y,
+        viewport.top_left.y,
+    )
+}
+
+/// Returns the latitude in degrees under the y pixel location `pixel_y` in the conrad coordinate
+/// system. Locations past the top or bottom of the world are clamped to the edge of the map
+pub fn pixel_y_to_latitude(
+    pixel_y: f64,
+    viewport: &crate::map::WorldViewport,
+    window_height: f64,
+) -> f64 {
+    let world_y = pixel_y_to_world_y(pixel_y, viewport, window_height);
+    crate::util::latitude_from_y(world_y.clamp(0.0, 1.0))
+}
+
+/// Returns the longitude in degrees under the x pixel location `pixel_x` in the conrad coordinate
+/// system, wrapped into the range [-180, 180)
+pub fn pixel_x_to_longitude(
+    pixel_x: f64,
+    viewport: &crate::map::WorldViewport,
+    window_width: f64,
+) -> f64 {
+    let world_x = pixel_x_to_world_x(pixel_x, viewport, window_width);
+    crate::util::longitude_from_x(world_x.rem_euclid(1.0))
+}
+
 /// Returns how many degrees should between lines given the viewport range (in world coordinates), and the size
 /// of the window, either width or height, depending on which dimension these lines are for
 fn line_distance_for_viewport_degrees(world_range: f64, dimension_size: f64) -> f64 {
@@ -75,26 +135,112 @@ fn line_distance_for_viewport_degrees(world_range: f64, dimension_size: f64) ->
     }
 }
 
-fn world_width_from_longitude(lng: f64) -> f64 {
-    // The world is 360 degrees around, and in world coordinates, 1.0 units around
-    lng / 360.0
+/// Returns the world x location and label longitude of each line of longitude between `left_x`
+/// and `right_x`, spaced `line_distance` degrees apart.
+///
+/// World x coordinates outside of 0..1 are copies of the world wrapped around horizontally, so
+/// the returned longitudes are wrapped into -180..=180 to match the copy of the world they fall in
+fn longitude_lines(left_x: f64, right_x: f64, line_distance: f64) -> Vec<(f64, f64)> {
+    //Work in unwrapped degrees where world x 0 is -180 and world x 1 is 180, so that indices
+    //keep increasing across the antimeridian
+    let to_degrees = |x: f64| x * 360.0 - 180.0;
+    let first = (to_degrees(left_x) / line_distance).ceil() as i64;
+    let last = (to_degrees(right_x) / line_distance).floor() as i64;
+
+    (first..=last)
+        .map(|i| {
+            let lng = i as f64 * line_distance;
+            let world_x = (lng + 180.0) / 360.0;
+            (world_x, crate::util::wrap_longitude(lng))
+        })
+        .collect()
+}
+
+/// How the lines of latitude and longitude and their labels are drawn
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GridStyle {
+    /// The color of the lines. The alpha of this color is ignored in favor of `line_alpha`
+    pub line_color: conrod_core::Color,
+    pub line_alpha: f32,
+    pub line_thickness: f64,
+    pub label_color: conrod_core::Color,
+}
+
+impl Default for GridStyle {
+    fn default() -> Self {
+        Self {
+            line_color: conrod_core::color::BLACK,
+            line_alpha: 0.4,
+            line_thickness: 1.5,
+            label_color: conrod_core::color::WHITE,
+        }
+    }
+}
+
+/// How many of the tiles needed to cover the window have finished loading
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TileProgress {
+    /// The number of tiles which loaded or failed to load
+    pub resolved: usize,
+    pub total: usize,
+}
+
+impl TileProgress {
+    /// Returns true once every tile is resolved. Tiles which fail count as resolved, so that a
+    /// missing tile can't keep this false forever
+    pub fn is_complete(&self) -> bool {
+        self.total > 0 && self.resolved == self.total
+    }
+
+    /// The percentage of tiles resolved, from 0 to 100
+    pub fn percent(&self) -> usize {
+        if self.total == 0 {
+            0
+        } else {
+            self.resolved * 100 / self.total
+        }
+    }
 }
 
 /// The state needed to render the map.
 ///
 /// Implemented as a struct to reduce the number of parameters passed to the map_render function
-pub struct MapRendererState<'a, 'b, 'c, 'd, 'e> {
+pub struct MapRendererState<'a, 'b, 'c, 'd, 'e, 'f, 'g> {
     pub tile_cache: &'a mut tile::PipelineMap,
+    /// Draws the base layer instead of `tile_cache` when `base_layer` is [`TileKind::Vector`]
+    pub vector_tiles: &'g mut tile::VectorPipeline,
     pub view: &'b crate::map::TileView,
     pub display: &'c glium::Display,
     pub image_map: &'d mut conrod_core::image::Map<glium::Texture2d>,
     pub ids: &'e mut crate::Ids,
     pub weather_enabled: bool,
+    /// How opaque the weather tiles are, from 0 (invisible) to 1 (hides the base layer)
+    pub weather_opacity: f32,
+    /// Picks which radar frame the weather layer shows
+    pub radar_loop: &'f mut tile::RadarLoop,
+    /// The palette the weather tiles are recolored with
+    pub weather_ramp: tile::ColorRamp,
+    /// The imagery drawn underneath everything else. Must be one of [`TileKind::BASE_LAYERS`]
+    pub base_layer: TileKind,
+    /// How the latitude and longitude line labels are written
+    pub coord_format: crate::util::CoordFormat,
+    /// The colors used for the map
+    pub theme: crate::Theme,
+    /// How the lines of latitude and longitude are drawn
+    pub grid_style: GridStyle,
+    /// The ratio of physical to logical pixels of the window
+    pub scale_factor: f64,
 }
 
-/// Draws the satellite tiles, weather tiles (if enabled), latitude lines, and longitude lines,
-/// using the `view` inside `state`
-pub fn draw(state: MapRendererState, ui: &mut UiCell<'_>, font: conrod_core::text::font::Id) {
+/// Draws the base layer tiles, weather tiles (if enabled), latitude lines, and longitude lines,
+/// using the `view` inside `state`.
+///
+/// Returns how much of the base layer in view has loaded
+pub fn draw(
+    state: MapRendererState,
+    ui: &mut UiCell<'_>,
+    font: conrod_core::text::font::Id,
+) -> TileProgress {
     let _scope = crate::profile_scope("map_renderer::draw");
     //Or value is okay here because `tile_size()` only returns `None` if no tiles are cached, which
     //only happens the first few frames, therefore this value doesn't need to be accurate
@@ -103,92 +249,446 @@ pub fn draw(state: MapRendererState, ui: &mut UiCell<'_>, font: conrod_core::tex
     let display = state.display;
     let image_map = state.image_map;
     let ids = state.ids;
+    let base_layer = state.theme.base_layer(state.base_layer);
 
     let viewport = state.view.get_world_viewport(ui.win_w, ui.win_h);
 
-    let mut cache_it = tile_cache.values_mut();
-    let satellite = cache_it.next().unwrap();
-    let weather = cache_it.next().unwrap();
-
     {
-        let _p = crate::profile_scope("Satellite Tile Cache Update");
-        satellite.update(&viewport, display, image_map);
+        let _p = crate::profile_scope("Base Tile Cache Update");
+        if base_layer == TileKind::Vector {
+            state.vector_tiles.update();
+        } else {
+            tile_cache[base_layer].update(&viewport, display, image_map);
+        }
     }
 
     {
         let _p = crate::profile_scope("Weather Tile Cache Update");
 
         if state.weather_enabled {
-            weather.update(&viewport, display, image_map);
+            tile_cache[TileKind::Weather].set_color_ramp(state.weather_ramp, image_map);
+            state
+                .radar_loop
+                .set_color_ramp(state.weather_ramp, image_map);
+
+            let window = Rect::from_xy_dim([0.0, 0.0], [ui.win_w, ui.win_h]);
+            let mut load = |pipeline: &mut TilePipeline| {
+                pipeline.update(&viewport, display, image_map);
+                request_tiles(pipeline, view, window, state.scale_factor)
+            };
+            if state.radar_loop.displayed_time().is_none() {
+                load(&mut tile_cache[TileKind::Weather]);
+            }
+            //Loads every radar frame while the loop plays, so that frames are ready when shown
+            state.radar_loop.prefetch(load);
         }
     }
 
-    render_tile_set(satellite, view, &mut ids.satellite_tiles, ui);
+    let progress = if base_layer == TileKind::Vector {
+        render_vector_tile_set(state.vector_tiles, view, &mut ids.vector_layers, ui)
+    } else {
+        render_tile_set(
+            &mut tile_cache[base_layer],
+            view,
+            &mut ids.base_tiles,
+            ui,
+            state.scale_factor,
+            1.0,
+        )
+    };
     if state.weather_enabled {
-        render_tile_set(weather, view, &mut ids.weather_tiles, ui);
+        let weather = match state.radar_loop.displayed_pipeline() {
+            Some(frame) => frame,
+            None => &mut tile_cache[TileKind::Weather],
+        };
+        render_tile_set(
+            weather,
+            view,
+            &mut ids.weather_tiles,
+            ui,
+            state.scale_factor,
+            state.weather_opacity,
+        );
     }
 
     // Draw the latitude and longitude lines
-    draw_lat_long(&viewport, ui, ids, font);
+    draw_lat_long(
+        &viewport,
+        ui,
+        ids,
+        font,
+        state.coord_format,
+        &state.grid_style,
+    );
+
+    progress
 }
 
-/// Renders a tile set from a provided tile pipeline
+/// Renders a tile set from a provided tile pipeline.
+///
+/// Returns how many of the tiles at the current zoom level have loaded. Lower resolution tiles
+/// drawn in place of missing ones are not counted.
+///
+/// When zoomed in past [`TilePipeline::max_zoom`], the deepest available tiles are stretched to
+/// cover the screen.
+///
+/// `scale_factor` is the ratio of physical to logical pixels. Tiles are picked so that each tile
+/// pixel covers at most one physical pixel, keeping them sharp on high DPI screens.
+///
+/// `opacity` ranges from 0 to 1, and is used to blend overlays with the layers underneath
 pub fn render_tile_set(
     pipeline: &mut TilePipeline,
     view: &crate::map::TileView,
     ids: &mut List,
     ui: &mut UiCell<'_>,
-) {
+    scale_factor: f64,
+    opacity: f32,
+) -> TileProgress {
+    let window = Rect::from_xy_dim([0.0, 0.0], [ui.win_w, ui.win_h]);
+    let (progress, zoom) = render_tiles(pipeline, view, ids, ui, window, scale_factor, opacity);
+    {
+        let mut guard = crate::MAP_PERF_DATA.lock();
+        guard.tiles_rendered = progress.total;
+        guard.zoom = zoom;
+    }
+    progress
+}
+
+/// Renders a tile set like [`render_tile_set`], but into `bounds` instead of the whole window.
+///
+/// `view` is positioned as if `bounds` were the window, and tiles are cropped so that nothing is
+/// drawn outside of `bounds`
+pub fn render_tile_set_in(
+    pipeline: &mut TilePipeline,
+    view: &crate::map::TileView,
+    ids: &mut List,
+    ui: &mut UiCell<'_>,
+    bounds: Rect,
+    scale_factor: f64,
+    opacity: f32,
+) -> TileProgress {
+    render_tiles(pipeline, view, ids, ui, bounds, scale_factor, opacity).0
+}
+
+/// Requests the tiles of `pipeline` that [`render_tile_set`] would draw in `bounds` without
+/// drawing them, returning true if every tile in view has loaded
+fn request_tiles(
+    pipeline: &mut TilePipeline,
+    view: &crate::map::TileView,
+    bounds: Rect,
+    scale_factor: f64,
+) -> bool {
     let tile_size = pipeline.tile_size().unwrap();
+    let logical_tile_size = ((tile_size as f64 / scale_factor).round() as u32).max(1);
+    let max_zoom = pipeline.max_zoom();
+    let layout = layout_tiles(view, logical_tile_size, max_zoom, bounds, |tile| {
+        pipeline.get_tile(tile).is_some()
+    });
+    layout
+        .visible
+        .iter()
+        .all(|&tile| pipeline.is_resolved(tile))
+}
 
-    let it = view.tile_iter(tile_size, ui.win_w, ui.win_h);
-    let mut size = it.tile_size;
-    let offset = it.tile_offset;
-    let mut zoom_level = it.tile_zoom;
-    let half_width = ui.win_w / 2.0;
-    let half_height = ui.win_h / 2.0;
+/// Draws the tiles of `pipeline` covering `bounds`, returning the progress and the zoom level of
+/// the tiles in view
+fn render_tiles(
+    pipeline: &mut TilePipeline,
+    view: &crate::map::TileView,
+    ids: &mut List,
+    ui: &mut UiCell<'_>,
+    bounds: Rect,
+    scale_factor: f64,
+    opacity: f32,
+) -> (TileProgress, u32) {
+    let tile_size = pipeline.tile_size().unwrap();
+    // The view measures in logical pixels, so a tile shown at its native resolution is smaller
+    // than `tile_size` logical pixels on a high DPI screen
+    let logical_tile_size = ((tile_size as f64 / scale_factor).round() as u32).max(1);
 
-    let tiles_vertically = it.tiles_vertically;
+    let scope_render_tiles = crate::profile_scope("Render Tiles");
 
-    let tiles: Vec<_> = it.collect();
+    let max_zoom = pipeline.max_zoom();
+    let layout = layout_tiles(view, logical_tile_size, max_zoom, bounds, |tile| {
+        pipeline.get_tile(tile).is_some()
+    });
+
+    // We now need to account for more tiles than we currently expect to display
+    ids.resize(layout.tile_count(), &mut ui.widget_id_generator());
+
+    let mut id_counter = 0;
+
+    for draw_layer in &layout.layers {
+        let size = draw_layer.size;
+        let zoom_level = draw_layer.zoom_level;
+
+        for &(x, y, tile_x, tile_y) in &draw_layer.tiles {
+            let tile_id = TileId::new(tile_x, tile_y, zoom_level);
+
+            if let Some(tile) = pipeline.get_tile(tile_id) {
+                let area = Rect::from_xy_dim([x, y], [size.x, size.y]);
+                if let Some((visible, source)) = crop_tile(area, bounds, tile_size as f64) {
+                    let mut image = Image::new(tile)
+                        .xy(visible.xy())
+                        .wh(visible.dim())
+                        .color(Some(conrod_core::color::WHITE.alpha(opacity)));
+                    if let Some(source) = source {
+                        image = image.source_rectangle(source);
+                    }
+                    image.set(ids[id_counter], ui);
+                }
+
+                id_counter += 1;
+            }
+        }
+    }
+
+    scope_render_tiles.end();
+
+    let progress = TileProgress {
+        resolved: layout
+            .visible
+            .iter()
+            .filter(|&&tile| pipeline.is_resolved(tile))
+            .count(),
+        total: layout.visible.len(),
+    };
+    (progress, layout.visible_zoom)
+}
+
+/// Draws the vector base layer from `pipeline`, like [`render_tile_set`] does for raster tiles.
+///
+/// Each zoom level is drawn as a single widget holding every triangle of its tiles. Lines are
+/// widened on screen, so they keep the same width at every zoom
+pub fn render_vector_tile_set(
+    pipeline: &mut VectorPipeline,
+    view: &crate::map::TileView,
+    ids: &mut List,
+    ui: &mut UiCell<'_>,
+) -> TileProgress {
+    let _scope = crate::profile_scope("Render Vector Tiles");
+    let window = Rect::from_xy_dim([0.0, 0.0], [ui.win_w, ui.win_h]);
+    let max_zoom = pipeline.max_zoom();
+    let layout = layout_tiles(view, VECTOR_TILE_SIZE, max_zoom, window, |tile| {
+        pipeline.get_tile(tile).is_some()
+    });
+
+    ids.resize(layout.layers.len(), &mut ui.widget_id_generator());
+    for (i, layer) in layout.layers.iter().enumerate() {
+        let triangles = vector_layer_triangles(pipeline, layer);
+        if !triangles.is_empty() {
+            Triangles::multi_color(triangles)
+                .with_bounding_rect(window)
+                .set(ids[i], ui);
+        }
+    }
+
+    let progress = TileProgress {
+        resolved: layout
+            .visible
+            .iter()
+            .filter(|&&tile| pipeline.is_resolved(tile))
+            .count(),
+        total: layout.visible.len(),
+    };
     {
         let mut guard = crate::MAP_PERF_DATA.lock();
-        guard.tiles_rendered = tiles.len();
-        guard.zoom = zoom_level;
+        guard.tiles_rendered = progress.total;
+        guard.zoom = layout.visible_zoom;
     }
+    progress
+}
+
+/// Returns the triangles of every loaded tile in `layer`, in conrod's coordinates.
+///
+/// Each kind of shape is drawn for all of the tiles before the next, so that shapes which spill
+/// past the edge of their tile aren't covered by the land of the tile next to it
+fn vector_layer_triangles(
+    pipeline: &VectorPipeline,
+    layer: &RenderLayer,
+) -> Vec<Triangle<ColoredPoint>> {
+    let palette = pipeline.palette();
+    let tiles: Vec<(Rect, &TileGeometry)> = layer
+        .tiles
+        .iter()
+        .filter_map(|&(x, y, tile_x, tile_y)| {
+            let geometry = pipeline.geometry(TileId::new(tile_x, tile_y, layer.zoom_level))?;
+            let area = Rect::from_xy_dim([x, y], [layer.size.x, layer.size.y]);
+            Some((area, geometry))
+        })
+        .collect();
+    let to_screen = |area: &Rect, point: DVec2| {
+        DVec2::new(
+            area.left() + point.x * area.w(),
+            area.top() - point.y * area.h(),
+        )
+    };
+
+    let mut triangles = Vec::new();
+    let mut push = |color: conrod_core::color::Rgba, corners: [DVec2; 3]| {
+        triangles.push(Triangle(corners.map(|p| ([p.x, p.y], color))));
+    };
+    // Land is the background, so that this layer hides the lower resolution layers behind it
+    for (area, _) in &tiles {
+        let corners = [
+            DVec2::new(area.left(), area.top()),
+            DVec2::new(area.right(), area.top()),
+            DVec2::new(area.right(), area.bottom()),
+            DVec2::new(area.left(), area.bottom()),
+        ];
+        push(palette.land, [corners[0], corners[1], corners[2]]);
+        push(palette.land, [corners[0], corners[2], corners[3]]);
+    }
+    for (area, geometry) in &tiles {
+        for triangle in &geometry.water {
+            push(palette.water, triangle.map(|p| to_screen(area, p)));
+        }
+    }
+    for (area, geometry) in &tiles {
+        for triangle in &geometry.islands {
+            push(palette.land, triangle.map(|p| to_screen(area, p)));
+        }
+    }
+
+    let mut line = Vec::new();
+    for (kind, &(color, width)) in palette.lines.iter() {
+        for (area, geometry) in &tiles {
+            for points in &geometry.lines[kind] {
+                let points: Vec<DVec2> = points.iter().map(|&p| to_screen(area, p)).collect();
+                line.clear();
+                line_triangles(&points, width, &mut line);
+                for &triangle in &line {
+                    push(color, triangle);
+                }
+            }
+        }
+    }
+    triangles
+}
+
+/// The tiles to draw for a view, grouped by zoom level
+pub struct TileLayout {
+    /// Ordered back to front, so the lowest resolution fallbacks come first and the tiles at
+    /// `visible_zoom` come last
+    pub layers: Vec<RenderLayer>,
+    /// Every tile in view at `visible_zoom`, whether or not it is available
+    pub visible: Vec<TileId>,
+    pub visible_zoom: u32,
+}
+
+impl TileLayout {
+    /// The number of tiles drawn across every layer
+    pub fn tile_count(&self) -> usize {
+        self.layers.iter().map(|layer| layer.tiles.len()).sum()
+    }
+}
+
+/// Works out where to draw each tile covering `bounds`, using conrod's coordinates.
+///
+/// `tile_size` is the size of a tile in logical pixels. Tiles that `is_available` returns false
+/// for are replaced as described in [`fallback_layers`]. This is the geometry behind
+/// [`render_tile_set`], kept separate from the GPU so that it can be tested and benchmarked
+/// without a window
+pub fn layout_tiles(
+    view: &crate::map::TileView,
+    tile_size: u32,
+    max_zoom: u32,
+    bounds: Rect,
+    is_available: impl FnMut(TileId) -> bool,
+) -> TileLayout {
+    let it = view.tile_iter(tile_size, max_zoom, bounds.w(), bounds.h());
+    let size = it.tile_size;
+    let offset = it.tile_offset;
+    let zoom_level = it.tile_zoom;
+    let visible_zoom = zoom_level;
+    let left = bounds.left();
+    let top = bounds.top();
+
+    let tiles_vertically = it.tiles_vertically;
+
+    let tiles: Vec<_> = it.collect();
 
     // The conrod coordinate system places 0, 0 in the center of the window. Up is the positive y
     // axis, and right is the positive x axis.
     // The units are in terms of screen pixels, so on a window with a size of 1000x500 the point
     // (500, 250) would be the top right corner
-    let scope_render_tiles = crate::profile_scope("Render Tiles");
-
-    let mut draw_layers = Vec::new();
-    let mut missing = RenderLayer::new(size, zoom_level);
+    let mut visible = RenderLayer::new(size, zoom_level);
 
     // Iteratre through each initial tile
     for (i, tile) in tiles.iter().enumerate() {
         let tile_x = i / tiles_vertically as usize;
         let tile_y = i % tiles_vertically as usize;
 
-        let x = offset.x + tile_x as f64 * size.x - half_width + size.x / 2.0;
-        let y = offset.y - (tile_y as f64 * size.y) + half_height + size.y / 2.0;
+        let x = offset.x + tile_x as f64 * size.x + left + size.x / 2.0;
+        let y = offset.y - (tile_y as f64 * size.y) + top + size.y / 2.0;
+
+        visible.tiles.push((x, y, tile.0, tile.1));
+    }
+
+    TileLayout {
+        layers: fallback_layers(visible, is_available),
+        visible: tiles
+            .iter()
+            .map(|tile| TileId::new(tile.0, tile.1, visible_zoom))
+            .collect(),
+        visible_zoom,
+    }
+}
 
-        // Set each one as missing so that we can just use the later loop for everything
-        missing.tiles.push((x, y, tile.0, tile.1));
+/// Clips a tile drawn over `area` to `bounds`.
+///
+/// Returns `None` if the tile is entirely outside of `bounds`. Otherwise returns the part of
+/// `area` inside `bounds`, and the matching part of the tile's texture in pixels if the tile had
+/// to be cropped. Textures are stored bottom row first, so the source rectangle is measured up
+/// from the bottom of the texture like conrod's y axis
+fn crop_tile(area: Rect, bounds: Rect, texture_size: f64) -> Option<(Rect, Option<Rect>)> {
+    let visible = area.overlap(bounds)?;
+    if visible == area {
+        return Some((area, None));
     }
+    let to_texture = |[x, y]: [f64; 2]| {
+        [
+            (x - area.left()) / area.w() * texture_size,
+            (y - area.bottom()) / area.h() * texture_size,
+        ]
+    };
+    let source = Rect::from_corners(
+        to_texture(visible.bottom_left()),
+        to_texture(visible.top_right()),
+    );
+    Some((visible, Some(source)))
+}
+
+/// Returns the layers to draw, back to front, so that every tile in `visible` is covered by
+/// itself or by its closest available ancestor.
+///
+/// Tiles that `is_available` returns false for are replaced by their parent, walking up the zoom
+/// levels until every tile is covered or zoom 0 is reached. Tiles that are still missing at zoom
+/// 0 are left blank. Each layer is one zoom level lower than the one after it, so its tiles are
+/// twice as large, even if it is empty
+pub fn fallback_layers(
+    visible: RenderLayer,
+    mut is_available: impl FnMut(TileId) -> bool,
+) -> Vec<RenderLayer> {
+    let mut size = visible.size;
+    let mut zoom_level = visible.zoom_level;
+    let mut missing = visible;
+    let mut draw_layers = Vec::new();
 
-    while !missing.tiles.is_empty() && zoom_level > 0 {
+    // Walk up the zoom levels, replacing each missing tile with its parent until every tile is
+    // covered or we run out of levels. Zoom 0 is included so that a fully zoomed out view still
+    // draws something
+    while !missing.tiles.is_empty() {
         let mut newest_layer = RenderLayer::new(size, zoom_level);
-        let mut new_missing = RenderLayer::new(size * 2.0, zoom_level - 1);
+        let mut new_missing = RenderLayer::new(size * 2.0, zoom_level.saturating_sub(1));
 
         for (x, y, tile_x, tile_y) in missing.tiles {
             let tile_id = TileId::new(tile_x, tile_y, missing.zoom_level);
 
-            if pipeline.get_tile(tile_id).is_some() {
+            if is_available(tile_id) {
                 let data = (x, y, tile_x, tile_y);
                 newest_layer.tiles.push(data);
-            } else {
+            } else if zoom_level > 0 {
                 // If the tile isn't present, add the one that should replace it
                 let inner_offset_x = tile_x % 2;
                 let inner_offset_y = tile_y % 2;
@@ -198,60 +698,41 @@ pub fn render_tile_set(
                 let x = x - inner_offset_x as f64 * size.x + size.x / 2.0;
                 let y = y + inner_offset_y as f64 * size.y - size.y / 2.0;
 
-                let data = (x, y, tile_x, tile_y);
-
-                new_missing.tiles.push(data);
+                // Up to four missing siblings share a parent, so only draw it once per position
+                let duplicate = new_missing.tiles.iter().any(|&(other_x, other_y, ox, oy)| {
+                    (ox, oy) == (tile_x, tile_y)
+                        && (other_x - x).abs() < 1.0
+                        && (other_y - y).abs() < 1.0
+                });
+                if !duplicate {
+                    new_missing.tiles.push((x, y, tile_x, tile_y));
+                }
             }
         }
 
+        draw_layers.push(newest_layer);
+
+        if zoom_level == 0 {
+            break;
+        }
         zoom_level -= 1;
         size *= 2.0;
 
-        draw_layers.push(newest_layer);
-
         missing = new_missing;
     }
 
-    // We now need to account for more tiles than we currently expect to display
-    let mut tile_count = 0;
-
-    for draw_layer in draw_layers.iter() {
-        tile_count += draw_layer.tiles.len();
-    }
-
-    // Now we resize
-    ids.resize(tile_count, &mut ui.widget_id_generator());
-
     // Otherwise this would draw all of the lower-res images on top of the regular res ones instead
     // of behind like we want
     draw_layers.reverse();
-
-    let mut id_counter = 0;
-
-    for draw_layer in draw_layers {
-        let size = draw_layer.size;
-        let zoom_level = draw_layer.zoom_level;
-
-        for (x, y, tile_x, tile_y) in draw_layer.tiles {
-            let tile_id = TileId::new(tile_x, tile_y, zoom_level);
-
-            if let Some(tile) = pipeline.get_tile(tile_id) {
-                Image::new(tile)
-                    .x_y(x, y)
-                    .w_h(size.x, size.y)
-                    .set(ids[id_counter], ui);
-
-                id_counter += 1;
-            }
-        }
-    }
-
-    scope_render_tiles.end();
+    draw_layers
 }
 
-struct RenderLayer {
+/// The tiles of one zoom level in a [`TileLayout`]
+pub struct RenderLayer {
+    /// The size of each tile in pixels
     pub size: DVec2,
     pub zoom_level: u32,
+    /// The center of each tile in conrod's coordinates, followed by the tile's x and y
     pub tiles: Vec<(f64, f64, u32, u32)>,
 }
 
@@ -265,20 +746,25 @@ impl RenderLayer {
     }
 }
 
-/// Draws the lines of latitude and longitude onto the map
+/// Draws the lines of latitude and longitude onto the map, labeled using `coord_format` and
+/// drawn using `style`
 pub fn draw_lat_long(
     viewport: &crate::map::WorldViewport,
     ui: &mut UiCell<'_>,
     ids: &mut crate::Ids,
     font: conrod_core::text::font::Id,
+    coord_format: crate::util::CoordFormat,
+    style: &GridStyle,
 ) {
+    let line_color = style.line_color.alpha(style.line_alpha);
+
     let scope_render_latitude = crate::profile_scope("Render Latitude");
     //Lines of latitude
     let lat_line_distance =
         line_distance_for_viewport_degrees(viewport.bottom_right.y - viewport.top_left.y, ui.win_h);
 
-    let lat_top = crate::util::latitude_from_y(viewport.top_left.y.rem_euclid(1.0));
-    let lat_bottom = crate::util::latitude_from_y(viewport.bottom_right.y.rem_euclid(1.0));
+    let lat_top = crate::util::latitude_from_y(viewport.top_left.y.clamp(0.0, 1.0));
+    let lat_bottom = crate::util::latitude_from_y(viewport.bottom_right.y.clamp(0.0, 1.0));
     let lat_start = crate::util::modulo_ceil(lat_top, lat_line_distance);
 
     let lat_lines = ((lat_top - lat_bottom) / lat_line_distance + 1.0).ceil() as usize;
@@ -295,8 +781,6 @@ pub fn draw_lat_long(
         0usize
     };
 
-    const LINE_ALPHA: f32 = 0.4;
-
     //Latitude decreases as world y increases
     for i in 0..lat_lines {
         let lat = lat_start - i as f64 * lat_line_distance;
@@ -307,19 +791,15 @@ pub fn draw_lat_long(
         Line::new([-half_width, y_pixel], [half_width, y_pixel])
             //Why does this call need to happen?
             .x_y(0.0, 0.0)
-            .color(conrod_core::color::BLACK.alpha(LINE_ALPHA))
-            .thickness(1.5)
+            .color(line_color)
+            .thickness(style.line_thickness)
             .set(ids.latitude_lines[i], ui);
 
-        let text = if lat >= 0.0 {
-            format!("{:.1$}°N", lat, precision)
-        } else {
-            format!("{:.1$}°S", -lat, precision)
-        };
+        let text = crate::util::format_latitude(lat, precision, coord_format);
         Text::new(text.as_str())
             .top_right()
             .y(y_pixel)
-            .color(conrod_core::color::WHITE)
+            .color(style.label_color)
             .font_size(12)
             .font_id(font)
             .set(ids.latitude_text[i], ui);
@@ -331,15 +811,12 @@ pub fn draw_lat_long(
     let lng_line_distance =
         line_distance_for_viewport_degrees(viewport.bottom_right.x - viewport.top_left.x, ui.win_w);
 
-    let line_distance_world = world_width_from_longitude(lng_line_distance);
-    let lng_start = crate::util::modulo_ceil(
-        crate::util::longitude_from_x(viewport.top_left.x.rem_euclid(1.0)),
+    let lines = longitude_lines(
+        viewport.top_left.x,
+        viewport.bottom_right.x,
         lng_line_distance,
     );
-    let x_start = crate::util::modulo_ceil(viewport.top_left.x, line_distance_world);
-
-    let lng_lines = ((viewport.bottom_right.x - viewport.top_left.x) / line_distance_world + 1.0)
-        .ceil() as usize;
+    let lng_lines = lines.len();
 
     ids.longitude_lines
         .resize(lng_lines, &mut ui.widget_id_generator());
@@ -354,27 +831,21 @@ pub fn draw_lat_long(
     };
 
     //Longitude increases as world x increases
-    for i in 0..lng_lines {
-        let lng = lng_start + i as f64 * lng_line_distance;
-        let world_x = x_start + i as f64 * line_distance_world;
+    for (i, (world_x, lng)) in lines.into_iter().enumerate() {
         let x_pixel = world_x_to_pixel_x(world_x, viewport, ui.win_w);
 
         let half_height = ui.win_h / 2.0;
         Line::new([x_pixel, -half_height], [x_pixel, half_height])
             .x_y(0.0, 0.0)
-            .color(conrod_core::color::BLACK.alpha(LINE_ALPHA))
-            .thickness(1.5)
+            .color(line_color)
+            .thickness(style.line_thickness)
             .set(ids.longitude_lines[i], ui);
 
-        let text = if lng >= 0.0 {
-            format!("{:.1$}°E", lng, precision)
-        } else {
-            format!("{:.1$}°W", -lng, precision)
-        };
+        let text = crate::util::format_longitude(lng, precision, coord_format);
         Text::new(text.as_str())
             .bottom_right()
             .x(x_pixel)
-            .color(conrod_core::color::WHITE)
+            .color(style.label_color)
             .font_size(12)
             .font_id(font)
             .set(ids.longitude_text[i], ui);
@@ -382,3 +853,348 @@ pub fn draw_lat_long(
 
     scope_render_longitude.end();
 }
+
+/// The longest the scale bar is allowed to be in pixels. The actual bar is shorter so that it can
+/// represent a round distance
+const MAX_SCALE_BAR_PIXELS: f64 = 150.0;
+
+/// Draws a scale bar in the bottom left corner showing how far a round distance is on the ground
+/// at the center of the viewport
+pub fn draw_scale_bar(
+    viewport: &crate::map::WorldViewport,
+    ui: &mut UiCell<'_>,
+    ids: &mut crate::Ids,
+    font: conrod_core::text::font::Id,
+    theme: crate::Theme,
+    units: crate::Units,
+) {
+    let _scope = crate::profile_scope("Render Scale Bar");
+
+    // Mercator distorts distances depending on latitude, so measure at the center of the screen
+    let center_y = (viewport.top_left.y + viewport.bottom_right.y) / 2.0;
+    let center_lat = crate::util::latitude_from_y(center_y.clamp(0.0, 1.0));
+
+    let world_per_pixel = (viewport.bottom_right.x - viewport.top_left.x) / ui.win_w;
+    let meters_per_pixel = world_per_pixel * crate::util::meters_per_world_x(center_lat);
+
+    let (bar_meters, text) = units.round_distance(MAX_SCALE_BAR_PIXELS * meters_per_pixel);
+    if bar_meters <= 0.0 {
+        return;
+    }
+    let bar_pixels = bar_meters / meters_per_pixel;
+
+    // Leave room below for the longitude labels
+    let left = -ui.win_w / 2.0 + 12.0;
+    let y = -ui.win_h / 2.0 + 30.0;
+    let right = left + bar_pixels;
+
+    Line::new([left, y], [right, y])
+        .x_y(0.0, 0.0)
+        .color(conrod_core::color::BLACK.alpha(0.6))
+        .thickness(6.0)
+        .set(ids.scale_bar_background, ui);
+
+    Line::new([left, y], [right, y])
+        .x_y(0.0, 0.0)
+        .color(theme.label_color())
+        .thickness(3.0)
+        .set(ids.scale_bar, ui);
+
+    Text::new(text.as_str())
+        .x_y(left + bar_pixels / 2.0, y + 12.0)
+        .color(theme.label_color())
+        .font_size(12)
+        .font_id(font)
+        .set(ids.scale_bar_text, ui);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::ish;
+
+    /// Checks that the edges and center of the viewport project onto the edges and center of the
+    /// window
+    fn check_projection(view: &crate::map::TileView, win_w: f64, win_h: f64) {
+        let viewport = view.get_world_viewport(win_w, win_h);
+        let center = (viewport.top_left + viewport.bottom_right) / 2.0;
+
+        ish(
+            world_x_to_pixel_x(viewport.top_left.x, &viewport, win_w),
+            -win_w / 2.0,
+        );
+        ish(
+            world_x_to_pixel_x(viewport.bottom_right.x, &viewport, win_w),
+            win_w / 2.0,
+        );
+        ish(world_x_to_pixel_x(center.x, &viewport, win_w), 0.0);
+
+        ish(
+            world_y_to_pixel_y(viewport.top_left.y, &viewport, win_h),
+            win_h / 2.0,
+        );
+        ish(
+            world_y_to_pixel_y(viewport.bottom_right.y, &viewport, win_h),
+            -win_h / 2.0,
+        );
+        ish(world_y_to_pixel_y(center.y, &viewport, win_h), 0.0);
+    }
+
+    /// Checks that longitude lines are evenly spaced in world space, and labeled with wrapped
+    /// longitudes which increase by `distance` each line, except across the antimeridian
+    fn check_longitude_lines(lines: &[(f64, f64)], distance: f64) {
+        for pair in lines.windows(2) {
+            let ((x1, lng1), (x2, lng2)) = (pair[0], pair[1]);
+            ish(x2 - x1, distance / 360.0);
+
+            let step = (lng2 - lng1).rem_euclid(360.0);
+            ish(step, distance);
+        }
+        for &(world_x, lng) in lines {
+            assert!(lng > -180.0 && lng <= 180.0, "{} is not wrapped", lng);
+            ish(crate::util::wrap_longitude(world_x * 360.0 - 180.0), lng);
+        }
+    }
+
+    #[test]
+    fn longitude_lines_normal() {
+        //Daytona Beach
+        let lines = longitude_lines(0.27, 0.28, 1.0);
+        let lngs: Vec<_> = lines.iter().map(|l| l.1.round() as i32).collect();
+        assert_eq!(lngs, vec![-82, -81, -80]);
+        check_longitude_lines(&lines, 1.0);
+    }
+
+    #[test]
+    fn longitude_lines_across_antimeridian() {
+        //Centered on the antimeridian, in the copy of the world to the right
+        let lines = longitude_lines(0.99, 1.01, 1.0);
+        let lngs: Vec<_> = lines.iter().map(|l| l.1.round() as i32).collect();
+        assert_eq!(lngs, vec![177, 178, 179, 180, -179, -178, -177]);
+        check_longitude_lines(&lines, 1.0);
+
+        //Centered on the antimeridian, in the copy of the world to the left
+        let lines = longitude_lines(-0.01, 0.01, 1.0);
+        let lngs: Vec<_> = lines.iter().map(|l| l.1.round() as i32).collect();
+        assert_eq!(lngs, vec![177, 178, 179, 180, -179, -178, -177]);
+        check_longitude_lines(&lines, 1.0);
+
+        //Zoomed out far enough to see the whole world more than once
+        let lines = longitude_lines(-0.6, 1.6, 45.0);
+        assert!(lines.iter().all(|l| l.1 > -180.0 && l.1 <= 180.0));
+        check_longitude_lines(&lines, 45.0);
+    }
+
+    /// Views and window sizes covering zoomed in and out, high latitudes, and both sides of the
+    /// antimeridian
+    fn round_trip_cases() -> Vec<(crate::map::TileView, f64, f64)> {
+        let places = [
+            (29.18796, -81.04923),
+            (0.0, 0.0),
+            (-33.9, 151.2),
+            (70.0, 179.9),
+            (-70.0, -179.9),
+        ];
+        let zooms = [1.0, 8.0, 15.0];
+        let windows = [(1280.0, 720.0), (500.0, 1000.0), (1.0, 1.0)];
+
+        let mut cases = Vec::new();
+        for &(lat, lng) in &places {
+            for &zoom in &zooms {
+                for &(w, h) in &windows {
+                    cases.push((crate::map::TileView::new(lat, lng, zoom, w), w, h));
+                }
+            }
+        }
+        cases
+    }
+
+    #[test]
+    fn pixel_world_round_trip() {
+        for (view, win_w, win_h) in round_trip_cases() {
+            let viewport = view.get_world_viewport(win_w, win_h);
+            for &fraction in &[-0.5, -0.25, 0.0, 0.1, 0.5] {
+                let (x, y) = (fraction * win_w, fraction * win_h);
+                let world_x = pixel_x_to_world_x(x, &viewport, win_w);
+                let world_y = pixel_y_to_world_y(y, &viewport, win_h);
+                ish(world_x_to_pixel_x(world_x, &viewport, win_w), x);
+                ish(world_y_to_pixel_y(world_y, &viewport, win_h), y);
+            }
+
+            //And the other way around
+            let center = (viewport.top_left + viewport.bottom_right) / 2.0;
+            let size = viewport.bottom_right - viewport.top_left;
+            for &fraction in &[-0.5, -0.3, 0.0, 0.2, 0.5] {
+                let world = center + size * fraction;
+                let x = world_x_to_pixel_x(world.x, &viewport, win_w);
+                let y = world_y_to_pixel_y(world.y, &viewport, win_h);
+                ish(pixel_x_to_world_x(x, &viewport, win_w), world.x);
+                ish(pixel_y_to_world_y(y, &viewport, win_h), world.y);
+            }
+        }
+    }
+
+    #[test]
+    fn pixel_lat_long_round_trip() {
+        for (view, win_w, win_h) in round_trip_cases() {
+            let viewport = view.get_world_viewport(win_w, win_h);
+            let center = (viewport.top_left + viewport.bottom_right) / 2.0;
+            let size = viewport.bottom_right - viewport.top_left;
+            for &fraction in &[-0.4, 0.0, 0.3] {
+                let world = center + size * fraction;
+                let lat = crate::util::latitude_from_y(world.y.clamp(0.0, 1.0));
+                let lng = crate::util::longitude_from_x(world.x.rem_euclid(1.0));
+
+                let x = world_x_to_pixel_x(crate::util::x_from_longitude(lng), &viewport, win_w);
+                let y = world_y_to_pixel_y(crate::util::y_from_latitude(lat), &viewport, win_h);
+                if (0.0..=1.0).contains(&world.y) {
+                    ish(pixel_y_to_latitude(y, &viewport, win_h), lat);
+                }
+                // The same longitude appears once per copy of the world, so only compare the
+                // wrapped values
+                let round_trip = pixel_x_to_longitude(x, &viewport, win_w);
+                ish(
+                    crate::util::wrap_longitude(round_trip),
+                    crate::util::wrap_longitude(lng),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn projection_follows_resize() {
+        let view = crate::map::TileView::new(29.18796, -81.04923, 8.0, 1280.0);
+        let mut ui = conrod_core::UiBuilder::new([1280.0, 720.0]).build();
+        check_projection(&view, ui.win_w, ui.win_h);
+
+        // A point 100 pixels right of center before the resize
+        let viewport = view.get_world_viewport(ui.win_w, ui.win_h);
+        let center_x = (viewport.top_left.x + viewport.bottom_right.x) / 2.0;
+        let world_x = center_x + 100.0 * (viewport.bottom_right.x - viewport.top_left.x) / ui.win_w;
+
+        ui.handle_event(conrod_core::event::Input::Resize(1920.0, 1080.0));
+        assert_eq!([ui.win_w, ui.win_h], [1920.0, 1080.0]);
+        check_projection(&view, ui.win_w, ui.win_h);
+
+        // Resizing shows more of the map rather than stretching it, so the point stays 100 pixels
+        // from the center
+        let viewport = view.get_world_viewport(ui.win_w, ui.win_h);
+        ish(world_x_to_pixel_x(world_x, &viewport, ui.win_w), 100.0);
+    }
+
+    #[test]
+    fn crop_tile_to_bounds() {
+        let bounds = Rect::from_corners([0.0, 0.0], [100.0, 100.0]);
+
+        //Fully inside, so the whole texture is used
+        let inside = Rect::from_corners([10.0, 10.0], [60.0, 60.0]);
+        assert_eq!(crop_tile(inside, bounds, 256.0), Some((inside, None)));
+
+        //Fully outside
+        let outside = Rect::from_corners([120.0, 0.0], [170.0, 50.0]);
+        assert_eq!(crop_tile(outside, bounds, 256.0), None);
+
+        //The top right quarter hangs outside of the bounds
+        let overhanging = Rect::from_corners([50.0, 50.0], [150.0, 150.0]);
+        let (visible, source) = crop_tile(overhanging, bounds, 256.0).unwrap();
+        assert_eq!(visible, Rect::from_corners([50.0, 50.0], [100.0, 100.0]));
+        let source = source.unwrap();
+        ish(source.left(), 0.0);
+        ish(source.right(), 128.0);
+        ish(source.bottom(), 0.0);
+        ish(source.top(), 128.0);
+    }
+
+    /// The four children of tile (1, 0) at zoom 1, as a 200 pixel square centered on the origin
+    fn four_siblings() -> RenderLayer {
+        let mut layer = RenderLayer::new(DVec2::new(100.0, 100.0), 2);
+        layer.tiles = vec![
+            (-50.0, 50.0, 2, 0),
+            (-50.0, -50.0, 2, 1),
+            (50.0, 50.0, 3, 0),
+            (50.0, -50.0, 3, 1),
+        ];
+        layer
+    }
+
+    #[test]
+    fn fallback_all_present() {
+        let layers = fallback_layers(four_siblings(), |_| true);
+        assert_eq!(layers.len(), 1);
+        assert_eq!(layers[0].zoom_level, 2);
+        assert_eq!(layers[0].size, DVec2::new(100.0, 100.0));
+        assert_eq!(layers[0].tiles, four_siblings().tiles);
+    }
+
+    #[test]
+    fn fallback_all_missing() {
+        //Only the whole world is available, so it is drawn once behind two empty layers
+        let layers = fallback_layers(four_siblings(), |tile| tile.zoom == 0);
+        let zooms: Vec<_> = layers.iter().map(|layer| layer.zoom_level).collect();
+        assert_eq!(zooms, vec![0, 1, 2]);
+        assert!(layers[1].tiles.is_empty());
+        assert!(layers[2].tiles.is_empty());
+        assert_eq!(layers[0].size, DVec2::new(400.0, 400.0));
+        assert_eq!(layers[0].tiles, vec![(-100.0, -100.0, 0, 0)]);
+
+        //Nothing is available, so every level is tried and left blank
+        let layers = fallback_layers(four_siblings(), |_| false);
+        assert_eq!(layers.len(), 3);
+        assert!(layers.iter().all(|layer| layer.tiles.is_empty()));
+    }
+
+    #[test]
+    fn fallback_mixed() {
+        //One child is available, and the shared parent covers the other three
+        let layers = fallback_layers(four_siblings(), |tile| {
+            tile == TileId::new(2, 0, 2) || tile == TileId::new(1, 0, 1)
+        });
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers[0].zoom_level, 1);
+        assert_eq!(layers[0].size, DVec2::new(200.0, 200.0));
+        assert_eq!(layers[0].tiles, vec![(0.0, 0.0, 1, 0)]);
+        assert_eq!(layers[1].zoom_level, 2);
+        assert_eq!(layers[1].tiles, vec![(-50.0, 50.0, 2, 0)]);
+    }
+
+    #[test]
+    fn layout_falls_back_to_parents() {
+        let bounds = Rect::from_xy_dim([0.0, 0.0], [1000.0, 800.0]);
+        let view = crate::map::TileView::new(29.2, -81.0, 8.0, bounds.w());
+
+        //Everything is available, so only the visible zoom level is drawn
+        let layout = layout_tiles(&view, 256, 20, bounds, |_| true);
+        assert_eq!(layout.layers.len(), 1);
+        assert_eq!(layout.layers[0].zoom_level, layout.visible_zoom);
+        assert_eq!(layout.tile_count(), layout.visible.len());
+        assert!(layout.visible.len() >= 12);
+
+        //Only the parents of the visible tiles are available. Each parent is drawn once, behind
+        //where the visible tiles would be, and covers the children it replaces
+        let zoom = layout.visible_zoom;
+        let fallback = layout_tiles(&view, 256, 20, bounds, |tile| tile.zoom == zoom - 1);
+        assert_eq!(fallback.layers.len(), 2);
+        assert!(fallback.layers[1].tiles.is_empty());
+        let parents = &fallback.layers[0];
+        assert_eq!(parents.zoom_level, zoom - 1);
+        let mut ids: Vec<(u32, u32)> = parents.tiles.iter().map(|t| (t.2, t.3)).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), parents.tiles.len());
+        for child in &layout.layers[0].tiles {
+            let parent = parents
+                .tiles
+                .iter()
+                .find(|p| (p.2, p.3) == (child.2 / 2, child.3 / 2))
+                .unwrap();
+            let area = Rect::from_xy_dim([parent.0, parent.1], [parents.size.x, parents.size.y]);
+            assert!(area.is_over([child.0, child.1]));
+        }
+
+        //Nothing is available, so every level down to zero is tried
+        let empty = layout_tiles(&view, 256, 20, bounds, |_| false);
+        assert_eq!(empty.layers.len(), zoom as usize + 1);
+        assert_eq!(empty.tile_count(), 0);
+        assert_eq!(empty.visible, layout.visible);
+    }
+}
@@ -0,0 +1,228 @@
+//! User defined pins drawn on top of the map, such as points of interest or waypoints
+
+use conrod_core::Color;
+
+/// How a [`Marker`] is drawn
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MarkerIcon {
+    /// A filled circle of the given color
+    Pin(Color),
+    /// The same icon used for airports
+    Airport,
+}
+
+impl Default for MarkerIcon {
+    fn default() -> Self {
+        MarkerIcon::Pin(conrod_core::color::RED)
+    }
+}
+
+/// A single pin on the map
+#[derive(Clone, Debug, PartialEq)]
+pub struct Marker {
+    /// Latitude in degrees
+    pub latitude: f64,
+    /// Longitude in degrees
+    pub longitude: f64,
+    /// Text drawn below the icon. Empty labels are not drawn
+    pub label: String,
+    pub icon: MarkerIcon,
+}
+
+impl Marker {
+    /// Creates a marker with the default icon
+    pub fn new(latitude: f64, longitude: f64, label: impl Into<String>) -> Self {
+        Self {
+            latitude,
+            longitude,
+            label: label.into(),
+            icon: MarkerIcon::default(),
+        }
+    }
+}
+
+/// Identifies a marker added to a [`MarkerLayer`]. Ids are never reused by the same layer
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct MarkerId(u64);
+
+/// A set of markers drawn on the map each frame.
+///
+/// [`crate::run_app_with_markers`] takes a shared handle to one of these, so markers can be added
+/// and removed from other threads while the app is running
+#[derive(Debug, Default)]
+pub struct MarkerLayer {
+    markers: Vec<(MarkerId, Marker)>,
+    next_id: u64,
+    /// Incremented whenever the markers change, so the app knows to redraw
+    generation: u64,
+}
+
+impl MarkerLayer {
+    /// Adds `marker` to the layer, returning an id that can be used to remove it later
+    pub fn add_marker(&mut self, marker: Marker) -> MarkerId {
+        let id = MarkerId(self.next_id);
+        self.next_id += 1;
+        self.markers.push((id, marker));
+        self.generation += 1;
+        id
+    }
+
+    /// Removes the marker with `id`, returning it if it was still in the layer
+    pub fn remove_marker(&mut self, id: MarkerId) -> Option<Marker> {
+        let index = self.markers.iter().position(|(other, _)| *other == id)?;
+        self.generation += 1;
+        Some(self.markers.remove(index).1)
+    }
+
+    /// Removes all markers
+    pub fn clear(&mut self) {
+        self.markers.clear();
+        self.generation += 1;
+    }
+
+    /// Returns the marker with `id`, if it is in the layer
+    pub fn get(&self, id: MarkerId) -> Option<&Marker> {
+        self.iter()
+            .find(|(other, _)| *other == id)
+            .map(|(_, marker)| marker)
+    }
+
+    /// Iterates over the markers in the order they were added
+    pub fn iter(&self) -> impl Iterator<Item = (MarkerId, &Marker)> {
+        self.markers.iter().map(|(id, marker)| (*id, marker))
+    }
+
+    pub fn len(&self) -> usize {
+        self.markers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.markers.is_empty()
+    }
+
+    /// A counter that changes every time markers are added or removed
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+/// Useful functions for rendering markers on the map
+pub mod marker_renderer {
+    use conrod_core::{
+        widget::{Circle, Image, Text},
+        Colorable, Positionable, Sizeable, UiCell, Widget,
+    };
+
+    use super::{MarkerIcon, MarkerLayer};
+
+    /// The diameter of marker icons in pixels
+    const ICON_SIZE: f64 = 16.0;
+
+    /// The font size of marker labels
+    const LABEL_FONT_SIZE: u32 = 12;
+
+    /// The state needed to render markers.
+    ///
+    /// Implemented as a struct to reduce the number of parameters passed to [`draw`]
+    pub struct MarkerRendererState<'a> {
+        pub layer: &'a MarkerLayer,
+        pub view: &'a crate::map::TileView,
+        pub ids: &'a mut crate::Ids,
+        /// Used for [`MarkerIcon::Airport`]
+        pub airport_image: crate::ImageId,
+        pub font: conrod_core::text::font::Id,
+    }
+
+    /// Draws every marker in the layer that is on screen. Should be run after the map tiles are
+    /// rendered
+    pub fn draw(state: MarkerRendererState, ui: &mut UiCell) {
+        let MarkerRendererState {
+            layer,
+            view,
+            ids,
+            airport_image,
+            font,
+        } = state;
+
+        let viewport = view.get_world_viewport(ui.win_w, ui.win_h);
+        let center_x = (viewport.top_left.x + viewport.bottom_right.x) / 2.0;
+        let half_w = ui.win_w / 2.0 + ICON_SIZE;
+        let half_h = ui.win_h / 2.0 + ICON_SIZE;
+
+        let visible: Vec<_> = layer
+            .iter()
+            .map(|(_, marker)| {
+                let world_x = crate::util::x_from_longitude(marker.longitude);
+                //Use the copy of the marker closest to the center of the screen
+                let world_x = world_x + (center_x - world_x).round();
+                let world_y = crate::util::y_from_latitude(marker.latitude);
+                let x = crate::world_x_to_pixel_x(world_x, &viewport, ui.win_w);
+                let y = crate::world_y_to_pixel_y(world_y, &viewport, ui.win_h);
+                (marker, x, y)
+            })
+            .filter(|(_, x, y)| x.abs() < half_w && y.abs() < half_h)
+            .collect();
+
+        ids.markers
+            .resize(visible.len(), &mut ui.widget_id_generator());
+        let label_count = visible
+            .iter()
+            .filter(|(marker, _, _)| !marker.label.is_empty())
+            .count();
+        ids.marker_labels
+            .resize(label_count, &mut ui.widget_id_generator());
+
+        let mut label_index = 0;
+        for (i, (marker, x, y)) in visible.into_iter().enumerate() {
+            match marker.icon {
+                MarkerIcon::Pin(color) => Circle::fill(ICON_SIZE / 2.0)
+                    .x_y(x, y)
+                    .color(color)
+                    .set(ids.markers[i], ui),
+                MarkerIcon::Airport => Image::new(airport_image.normal)
+                    .x_y(x, y)
+                    .w_h(ICON_SIZE, ICON_SIZE)
+                    .set(ids.markers[i], ui),
+            }
+
+            if !marker.label.is_empty() {
+                Text::new(marker.label.as_str())
+                    .x_y(x, y - ICON_SIZE / 2.0 - LABEL_FONT_SIZE as f64)
+                    .color(conrod_core::color::WHITE)
+                    .font_size(LABEL_FONT_SIZE)
+                    .font_id(font)
+                    .set(ids.marker_labels[label_index], ui);
+                label_index += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_remove_clear() {
+        let mut layer = MarkerLayer::default();
+        let a = layer.add_marker(Marker::new(29.0, -81.0, "Home"));
+        let b = layer.add_marker(Marker::new(40.6, -73.8, ""));
+        assert_ne!(a, b);
+        assert_eq!(layer.len(), 2);
+        assert_eq!(layer.get(a).unwrap().label, "Home");
+
+        let generation = layer.generation();
+        assert_eq!(layer.remove_marker(a).unwrap().label, "Home");
+        assert!(layer.remove_marker(a).is_none());
+        assert!(layer.generation() > generation);
+        assert_eq!(layer.iter().map(|(id, _)| id).collect::<Vec<_>>(), vec![b]);
+
+        //Ids are not reused after removal
+        let c = layer.add_marker(Marker::new(0.0, 0.0, "C"));
+        assert_ne!(c, a);
+
+        layer.clear();
+        assert!(layer.is_empty());
+        assert!(layer.get(c).is_none());
+    }
+}
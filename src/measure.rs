@@ -0,0 +1,98 @@
+//! A tool for measuring the great circle distance and bearing between two points on the map
+
+use conrod_core::{
+    widget::{Circle, Line, Text},
+    Colorable, Positionable, UiCell, Widget,
+};
+
+/// The points placed by the user, in degrees of latitude and longitude
+#[derive(Debug, Default)]
+pub struct MeasureTool {
+    start: Option<(f64, f64)>,
+    end: Option<(f64, f64)>,
+}
+
+impl MeasureTool {
+    /// Places the next point. Once both points are placed, the next click clears them
+    pub fn click(&mut self, lat: f64, lng: f64) {
+        match (self.start, self.end) {
+            (None, _) => self.start = Some((lat, lng)),
+            (Some(_), None) => self.end = Some((lat, lng)),
+            (Some(_), Some(_)) => self.clear(),
+        }
+    }
+
+    /// Removes both points
+    pub fn clear(&mut self) {
+        self.start = None;
+        self.end = None;
+    }
+
+    /// Returns the distance in meters and initial bearing in degrees from the first point to the
+    /// second, if both have been placed
+    pub fn measurement(&self) -> Option<(f64, f64)> {
+        let ((lat1, lng1), (lat2, lng2)) = (self.start?, self.end?);
+        Some((
+            crate::util::haversine_distance(lat1, lng1, lat2, lng2),
+            crate::util::initial_bearing(lat1, lng1, lat2, lng2),
+        ))
+    }
+
+    /// Draws the placed points, the line between them, and the distance and bearing label
+    pub fn draw(
+        &self,
+        view: &crate::map::TileView,
+        ui: &mut UiCell<'_>,
+        ids: &mut crate::Ids,
+        font: conrod_core::text::font::Id,
+        units: crate::Units,
+    ) {
+        let viewport = view.get_world_viewport(ui.win_w, ui.win_h);
+        let center_x = (viewport.top_left.x + viewport.bottom_right.x) / 2.0;
+
+        let to_pixel = |(lat, lng): (f64, f64)| {
+            let world_x = crate::util::x_from_longitude(lng);
+            //Use the copy of the point closest to the center of the screen
+            let world_x = world_x + (center_x - world_x).round();
+            let world_y = crate::util::y_from_latitude(lat);
+            [
+                crate::world_x_to_pixel_x(world_x, &viewport, ui.win_w),
+                crate::world_y_to_pixel_y(world_y, &viewport, ui.win_h),
+            ]
+        };
+
+        let points: Vec<_> = [self.start, self.end]
+            .into_iter()
+            .flatten()
+            .map(to_pixel)
+            .collect();
+
+        ids.measure_markers
+            .resize(points.len(), &mut ui.widget_id_generator());
+
+        if let [start, end] = points[..] {
+            Line::new(start, end)
+                .x_y(0.0, 0.0)
+                .color(conrod_core::color::YELLOW)
+                .thickness(2.0)
+                .set(ids.measure_line, ui);
+        }
+
+        for (i, point) in points.iter().enumerate() {
+            Circle::fill(5.0)
+                .x_y(point[0], point[1])
+                .color(conrod_core::color::YELLOW)
+                .set(ids.measure_markers[i], ui);
+        }
+
+        if let (Some((meters, bearing)), [_, end]) = (self.measurement(), &points[..]) {
+            let text = format!("{}, {:.0}°", units.format_distance(meters, 2), bearing);
+            Text::new(text.as_str())
+                .x_y(end[0], end[1] + 16.0)
+                .color(conrod_core::color::WHITE)
+                .font_size(14)
+                .font_id(font)
+                .set(ids.measure_text, ui);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! A small inset map in the bottom right corner showing a wider area around the main view
+
+use conrod_core::{widget::Rectangle, Colorable, Positionable, Rect, UiCell, Widget};
+use enum_map::enum_map;
+use glam::DVec2;
+use tokio::runtime::Runtime;
+
+use crate::map::TileView;
+use crate::tile::{PipelineMap, TileKind};
+
+/// The size of the minimap in logical pixels
+pub const MINIMAP_SIZE: [f64; 2] = [240.0, 160.0];
+
+/// How many zoom levels further out the minimap is than the main view
+pub const MINIMAP_ZOOM_OUT: f64 = 4.0;
+
+/// The distance in pixels between the minimap and the right edge of the window. Leaves room for
+/// the latitude labels
+const RIGHT_MARGIN: f64 = 48.0;
+
+/// The distance in pixels between the minimap and the bottom edge of the window. Leaves room for
+/// the longitude labels
+const BOTTOM_MARGIN: f64 = 24.0;
+
+/// The minimap only shows a handful of tiles, so it needs far fewer textures than the main view
+const MINIMAP_MAX_TEXTURES: usize = 64;
+
+/// Draws a zoomed out copy of the base layer with an outline around the area shown by the main
+/// view.
+///
+/// The minimap downloads tiles through the same backends as the main view, but keeps its own
+/// textures so that drawing it doesn't evict the main view's tiles
+pub struct Minimap {
+    pipelines: PipelineMap,
+}
+
+impl Minimap {
+    /// Creates a minimap which shares the backends of `main`
+    pub fn new(main: &PipelineMap, runtime: &Runtime) -> Self {
+        Self {
+            pipelines: enum_map! {
+                kind => {
+                    let mut pipeline = main[kind].share_backends(runtime);
+                    pipeline.set_max_textures(MINIMAP_MAX_TEXTURES);
+                    pipeline
+                }
+            },
+        }
+    }
+
+    /// Returns true if the minimap is waiting for tiles
+    pub fn has_pending(&self) -> bool {
+        self.pipelines
+            .values()
+            .any(|pipeline| pipeline.has_pending())
+    }
+
+    /// Requests the tiles that weren't available again, like [`crate::TilePipeline::retry_unavailable`]
+    pub fn retry_unavailable(&mut self) {
+        for pipeline in self.pipelines.values_mut() {
+            pipeline.retry_unavailable();
+        }
+    }
+
+    /// Draws the minimap for the main view `view` using the imagery of `base_layer`
+    pub fn draw(
+        &mut self,
+        view: &TileView,
+        base_layer: TileKind,
+        display: &glium::Display,
+        image_map: &mut conrod_core::image::Map<glium::Texture2d>,
+        ids: &mut crate::Ids,
+        ui: &mut UiCell,
+    ) {
+        let _scope = crate::profile_scope("Render Minimap");
+        let bounds = bounds(ui.win_w, ui.win_h);
+        let minimap_view = view.zoomed_out(MINIMAP_ZOOM_OUT);
+
+        let pipeline = &mut self.pipelines[base_layer];
+        let viewport = minimap_view.get_world_viewport(bounds.w(), bounds.h());
+        pipeline.update(&viewport, display, image_map);
+
+        Rectangle::fill(MINIMAP_SIZE)
+            .xy(bounds.xy())
+            .color(conrod_core::color::BLACK)
+            .set(ids.minimap_background, ui);
+
+        if pipeline.tile_size().is_some() {
+            let scale_factor = display.gl_window().window().scale_factor();
+            crate::render_tile_set_in(
+                pipeline,
+                &minimap_view,
+                &mut ids.minimap_tiles,
+                ui,
+                bounds,
+                scale_factor,
+                1.0,
+            );
+        }
+
+        let outline = viewport_outline(view, &minimap_view, ui.win_w, ui.win_h);
+        Rectangle::outline(outline)
+            .xy(bounds.xy())
+            .color(conrod_core::color::LIGHT_ORANGE)
+            .set(ids.minimap_viewport, ui);
+
+        Rectangle::outline(MINIMAP_SIZE)
+            .xy(bounds.xy())
+            .color(conrod_core::color::WHITE)
+            .set(ids.minimap_border, ui);
+    }
+}
+
+/// Returns where the minimap is drawn in a window of size `win_w` by `win_h`, using conrod's
+/// coordinates
+pub fn bounds(win_w: f64, win_h: f64) -> Rect {
+    let [w, h] = MINIMAP_SIZE;
+    Rect::from_xy_dim(
+        [
+            win_w / 2.0 - RIGHT_MARGIN - w / 2.0,
+            -win_h / 2.0 + BOTTOM_MARGIN + h / 2.0,
+        ],
+        MINIMAP_SIZE,
+    )
+}
+
+/// Returns the latitude and longitude in degrees shown by the minimap at `xy`, or `None` if `xy`
+/// is outside of the minimap.
+///
+/// `view` is the main view, and `xy` uses conrod's coordinates in a window of size `win_w` by
+/// `win_h`
+pub fn lat_long_at(view: &TileView, xy: [f64; 2], win_w: f64, win_h: f64) -> Option<(f64, f64)> {
+    let bounds = bounds(win_w, win_h);
+    if !bounds.is_over(xy) {
+        return None;
+    }
+    //Measure from the top left of the minimap with y increasing downwards, like the view expects
+    let pixel = DVec2::new(xy[0] - bounds.left(), bounds.top() - xy[1]);
+    let minimap_view = view.zoomed_out(MINIMAP_ZOOM_OUT);
+    Some(minimap_view.pixel_to_lat_long(pixel, bounds.w(), bounds.h()))
+}
+
+/// Returns the size in pixels of the area shown by the main `view` when drawn on the minimap,
+/// limited to the size of the minimap
+fn viewport_outline(view: &TileView, minimap_view: &TileView, win_w: f64, win_h: f64) -> [f64; 2] {
+    let [w, h] = MINIMAP_SIZE;
+    let main = view.get_world_viewport(win_w, win_h);
+    let minimap = minimap_view.get_world_viewport(w, h);
+    let scale = w / (minimap.bottom_right.x - minimap.top_left.x);
+    [
+        ((main.bottom_right.x - main.top_left.x) * scale).min(w),
+        ((main.bottom_right.y - main.top_left.y) * scale).min(h),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outline_and_clicks() {
+        let (win_w, win_h) = (1600.0, 900.0);
+        let view = TileView::new(29.2, -81.0, 8.0, win_w);
+        let minimap_view = view.zoomed_out(MINIMAP_ZOOM_OUT);
+        assert!((minimap_view.get_zoom() - (view.get_zoom() - MINIMAP_ZOOM_OUT)).abs() < 1e-9);
+
+        //Four levels out, so the main view is a sixteenth of its size on the minimap
+        let [w, h] = viewport_outline(&view, &minimap_view, win_w, win_h);
+        assert!((w - win_w / 16.0).abs() < 1e-6);
+        assert!((h - win_h / 16.0).abs() < 1e-6);
+
+        //The center of the minimap is the center of the main view
+        let bounds = bounds(win_w, win_h);
+        let (lat, lng) = lat_long_at(&view, bounds.xy(), win_w, win_h).unwrap();
+        assert!((lat - 29.2).abs() < 1e-6);
+        assert!((lng + 81.0).abs() < 1e-6);
+
+        //Clicking right of center moves east, and clicking above it moves north
+        let (lat, lng) =
+            lat_long_at(&view, [bounds.x() + 50.0, bounds.y() + 20.0], win_w, win_h).unwrap();
+        assert!(lng > -81.0);
+        assert!(lat > 29.2);
+
+        assert_eq!(lat_long_at(&view, [0.0, 0.0], win_w, win_h), None);
+
+        //Zoomed all the way out, the outline is limited to the minimap
+        let world = TileView::new(0.0, 0.0, -4.0, win_w);
+        let world_minimap = world.zoomed_out(MINIMAP_ZOOM_OUT);
+        assert_eq!(
+            viewport_outline(&world, &world_minimap, win_w, win_h),
+            MINIMAP_SIZE
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! Palettes that keep the map readable for colorblind users.
+//!
+//! Colors that are drawn to tell things apart, such as airline and altitude colors, should be
+//! picked through a [`Palette`] instead of being used directly
+
+use conrod_core::Color;
+use serde::{Deserialize, Serialize};
+
+use crate::{sample_ramp, GridStyle, MAX_RAMP_ALTITUDE, UNKNOWN_ALTITUDE_COLOR};
+
+/// The kind of color vision the map's colors are adjusted for
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Palette {
+    /// Colors are drawn as they are
+    #[default]
+    Normal,
+    /// Reduced sensitivity to green
+    Deuteranopia,
+    /// Reduced sensitivity to red
+    Protanopia,
+    /// Reduced sensitivity to blue
+    Tritanopia,
+}
+
+/// Converts linear rgb to the LMS color space, which models the cones of the eye
+const RGB_TO_LMS: [[f32; 3]; 3] = [
+    [17.8824, 43.5161, 4.11935],
+    [3.45565, 27.1554, 3.86714],
+    [0.0299566, 0.184309, 1.46709],
+];
+
+const LMS_TO_RGB: [[f32; 3]; 3] = [
+    [0.080_944_45, -0.130_504_41, 0.116_721_07],
+    [-0.010_248_534, 0.054_019_33, -0.113_614_71],
+    [-0.000_365_296_94, -0.004_121_614_7, 0.693_511_4],
+];
+
+/// Dark to light so that altitudes can be told apart by brightness alone. Safe for red and green
+/// colorblindness
+const VIRIDIS_RAMP: [[f32; 3]; 5] = [
+    [0.267, 0.005, 0.329],
+    [0.231, 0.322, 0.545],
+    [0.129, 0.569, 0.549],
+    [0.369, 0.788, 0.384],
+    [0.992, 0.906, 0.145],
+];
+
+/// Dark red to pale pink, which avoids the blues and yellows that are confused with tritanopia
+const RED_RAMP: [[f32; 3]; 4] = [
+    [0.45, 0.0, 0.05],
+    [0.8, 0.1, 0.15],
+    [0.98, 0.45, 0.5],
+    [1.0, 0.85, 0.88],
+];
+
+fn multiply(matrix: &[[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+    matrix.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+impl Palette {
+    /// Every palette, in the order the palette shortcut cycles through them
+    pub const ALL: [Palette; 4] = [
+        Palette::Normal,
+        Palette::Deuteranopia,
+        Palette::Protanopia,
+        Palette::Tritanopia,
+    ];
+
+    /// Returns the palette after this one, wrapping around to the first
+    pub fn next(self) -> Palette {
+        let index = Self::ALL
+            .iter()
+            .position(|&palette| palette == self)
+            .unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// A short human readable name for this palette
+    pub fn name(self) -> &'static str {
+        match self {
+            Palette::Normal => "Normal",
+            Palette::Deuteranopia => "Deuteranopia",
+            Palette::Protanopia => "Protanopia",
+            Palette::Tritanopia => "Tritanopia",
+        }
+    }
+
+    /// Returns how `rgb` looks to someone with this kind of color vision
+    pub fn simulate(self, rgb: [f32; 3]) -> [f32; 3] {
+        let [l, m, s] = multiply(&RGB_TO_LMS, rgb);
+        let lms = match self {
+            Palette::Normal => return rgb,
+            Palette::Deuteranopia => [l, 0.494207 * l + 1.24827 * s, s],
+            Palette::Protanopia => [2.02344 * m - 2.52581 * s, m, s],
+            Palette::Tritanopia => [l, m, -0.395913 * l + 0.801109 * m],
+        };
+        multiply(&LMS_TO_RGB, lms)
+    }
+
+    /// Shifts the parts of `rgb` that this kind of color vision can't see into colors it can, so
+    /// that colors which would look the same stay apart. Grays are left alone
+    pub fn remap(self, rgb: [f32; 3]) -> [f32; 3] {
+        if self == Palette::Normal {
+            return rgb;
+        }
+        let simulated = self.simulate(rgb);
+        let [er, eg, eb] = [0, 1, 2].map(|i| rgb[i] - simulated[i]);
+        let shift = [0.0, 0.7 * er + eg, 0.7 * er + eb];
+        [0, 1, 2].map(|i| (rgb[i] + shift[i]).clamp(0.0, 1.0))
+    }
+
+    /// Like [`Palette::remap`], for conrod colors. Transparency is kept
+    pub fn color(self, color: Color) -> Color {
+        let rgba = color.to_rgb();
+        let [r, g, b] = self.remap([rgba.0, rgba.1, rgba.2]);
+        Color::Rgba(r, g, b, rgba.3)
+    }
+
+    /// Returns the color of a plane at `altitude` meters, or a neutral gray if the altitude is
+    /// unknown.
+    ///
+    /// Colorblind palettes use a ramp that gets steadily brighter with altitude instead of
+    /// [`crate::altitude_color`]'s rainbow
+    pub fn altitude_color(self, altitude: Option<f32>) -> [f32; 3] {
+        let stops: &[[f32; 3]] = match self {
+            Palette::Normal => return crate::altitude_color(altitude),
+            Palette::Deuteranopia | Palette::Protanopia => &VIRIDIS_RAMP,
+            Palette::Tritanopia => &RED_RAMP,
+        };
+        match altitude {
+            Some(altitude) => sample_ramp(stops, altitude / MAX_RAMP_ALTITUDE),
+            None => UNKNOWN_ALTITUDE_COLOR,
+        }
+    }
+
+    /// Returns `style` with its colors remapped for this palette
+    pub fn grid_style(self, style: GridStyle) -> GridStyle {
+        GridStyle {
+            line_color: self.color(style.line_color),
+            label_color: self.color(style.label_color),
+            ..style
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+        (0..3).map(|i| (a[i] - b[i]).powi(2)).sum::<f32>().sqrt()
+    }
+
+    #[test]
+    fn remapped_colors_stay_apart() {
+        let red = [0.9, 0.1, 0.1];
+        let green = [0.1, 0.6, 0.1];
+        for palette in [Palette::Deuteranopia, Palette::Protanopia] {
+            let before = distance(palette.simulate(red), palette.simulate(green));
+            let after = distance(
+                palette.simulate(palette.remap(red)),
+                palette.simulate(palette.remap(green)),
+            );
+            assert!(after > before, "{:?}: {} <= {}", palette, after, before);
+        }
+
+        for palette in Palette::ALL {
+            let gray = palette.remap([0.5, 0.5, 0.5]);
+            assert!(distance(gray, [0.5, 0.5, 0.5]) < 0.02, "{:?}", palette);
+        }
+        assert_eq!(Palette::Normal.remap(red), red);
+        assert_eq!(Palette::Tritanopia.next(), Palette::Normal);
+    }
+
+    #[test]
+    fn colorblind_altitude_ramps_get_brighter() {
+        let brightness = |[r, g, b]: [f32; 3]| 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        for palette in [Palette::Deuteranopia, Palette::Tritanopia] {
+            let colors: Vec<_> = (0..=10)
+                .map(|i| palette.altitude_color(Some(i as f32 * MAX_RAMP_ALTITUDE / 10.0)))
+                .collect();
+            assert!(colors
+                .windows(2)
+                .all(|pair| brightness(pair[0]) < brightness(pair[1])));
+            assert_eq!(
+                palette.altitude_color(Some(2.0 * MAX_RAMP_ALTITUDE)),
+                *colors.last().unwrap()
+            );
+            assert_eq!(palette.altitude_color(None), UNKNOWN_ALTITUDE_COLOR);
+        }
+        assert_eq!(
+            Palette::Normal.altitude_color(Some(0.0)),
+            crate::altitude_color(Some(0.0))
+        );
+    }
+}
@@ -0,0 +1,81 @@
+//! Saving and loading state that should survive between runs of the app.
+//!
+//! State is stored as TOML in the platform's config directory. Loading never fails loudly: a
+//! missing or unreadable file simply means the app starts with its defaults.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Palette, SavedView, Theme};
+
+/// Everything that is remembered between runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedState {
+    pub view: SavedView,
+    /// Missing from state saved by older versions
+    #[serde(default)]
+    pub theme: Theme,
+    #[serde(default)]
+    pub palette: Palette,
+    /// How much larger than normal plane icons are drawn
+    #[serde(default = "default_marker_scale")]
+    pub plane_scale: f32,
+    /// How much larger than normal airport icons are drawn
+    #[serde(default = "default_marker_scale")]
+    pub airport_scale: f32,
+}
+
+fn default_marker_scale() -> f32 {
+    1.0
+}
+
+/// Returns the path of the file state is saved to, or `None` if the platform has no config
+/// directory
+fn state_path() -> Option<PathBuf> {
+    let mut path = dirs::config_dir()?;
+    path.push("flight_tracking");
+    path.push("state.toml");
+    Some(path)
+}
+
+/// Loads the state saved by the last run.
+///
+/// Returns `None` if there is no saved state, or if it is corrupt
+pub fn load_state() -> Option<SavedState> {
+    let path = state_path()?;
+    let text = std::fs::read_to_string(&path).ok()?;
+    match toml::from_str::<SavedState>(&text) {
+        Ok(state) if state.view.is_valid() => Some(state),
+        Ok(_) => {
+            println!("Ignoring invalid view in {}", path.display());
+            None
+        }
+        Err(err) => {
+            println!("Failed to parse saved state {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+/// Saves `state` so that it can be restored by [`load_state`] on the next run
+pub fn save_state(state: &SavedState) {
+    let path = match state_path() {
+        Some(path) => path,
+        None => return,
+    };
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+
+    let text = match toml::to_string(state) {
+        Ok(text) => text,
+        Err(err) => {
+            println!("Failed to serialize saved state: {}", err);
+            return;
+        }
+    };
+    if let Err(err) = std::fs::write(&path, text) {
+        println!("Failed to save state to {}: {:?}", path.display(), err);
+    }
+}
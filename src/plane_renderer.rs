@@ -1,13 +1,328 @@
-use std::io::Cursor;
+use std::{
+    io::Cursor,
+    ops::Range,
+    time::{Duration, SystemTime},
+};
 
+use conrod_core::{
+    widget::{self, Rectangle, Text},
+    Borderable, Colorable, Labelable, Positionable, Sizeable, UiCell, Widget,
+};
 use enum_map::{enum_map, Enum, EnumMap};
 use glam::DVec2;
 use glium::{
     implement_vertex, index::NoIndices, texture::SrgbTexture2d, uniform, DrawParameters, Program,
     Surface,
 };
+use serde::Deserialize;
+
+use crate::{
+    airports::airport_renderer::{estimate_label_size, place_labels},
+    util, world_x_to_pixel_x, world_y_to_pixel_y, AirlineRegistry, AirlineStyleMapping, Palette,
+    Plane, PlaneRequester, RegionPolygon, TrailStore,
+};
+
+/// The smallest distance in pixels the cursor can be from a plane for it to be selected.
+///
+/// Planes grow as the map is zoomed in, so the real radius is the larger of this and the plane's
+/// size
+const MIN_SELECT_RADIUS_PIXELS: f64 = 8.0;
+
+/// Planes at or above this altitude in meters (about 40,000 ft) get the last color of the
+/// altitude ramp
+pub const MAX_RAMP_ALTITUDE: f32 = 12_192.0;
+
+/// The hue in degrees of the highest altitude, violet. The lowest altitude is red, at 0 degrees
+const MAX_RAMP_HUE: f32 = 270.0;
+
+/// The color used for planes with no known altitude
+pub const UNKNOWN_ALTITUDE_COLOR: [f32; 3] = [0.6, 0.6, 0.6];
+
+/// The opacity of the newest segment of a trail. Older segments fade out towards zero
+const TRAIL_ALPHA: f32 = 0.8;
+
+/// Planes OpenSky hasn't heard from for this long start fading out, since they are probably no
+/// longer where they are drawn
+pub const STALE_PLANE_AGE: Duration = Duration::from_secs(30);
+
+/// Planes OpenSky hasn't heard from for this long are hidden instead of being drawn where they
+/// were last seen
+pub const HIDDEN_PLANE_AGE: Duration = Duration::from_secs(300);
+
+/// The opacity of a plane just before it is hidden
+const MIN_STALE_ALPHA: f32 = 0.2;
+
+/// The smallest and largest sizes plane and airport icons can be scaled to, relative to their
+/// normal size. Very different screen densities need quite different sizes to stay readable
+pub const MIN_MARKER_SCALE: f32 = 0.5;
+pub const MAX_MARKER_SCALE: f32 = 3.0;
+
+/// How much planes outside of a closed [`crate::RegionPolygon`] are faded, so that the planes
+/// inside it stand out
+const OUTSIDE_REGION_ALPHA: f32 = 0.25;
+
+/// Returns the opacity to draw a plane with when OpenSky last heard from it `age` ago, or `None`
+/// if it is too old to draw at all.
+///
+/// Planes are opaque until [`STALE_PLANE_AGE`], then fade out steadily until they are hidden at
+/// [`HIDDEN_PLANE_AGE`]
+pub fn staleness_alpha(age: Duration) -> Option<f32> {
+    if age >= HIDDEN_PLANE_AGE {
+        return None;
+    }
+    let fade = age.saturating_sub(STALE_PLANE_AGE).as_secs_f32()
+        / (HIDDEN_PLANE_AGE - STALE_PLANE_AGE).as_secs_f32();
+    Some(1.0 - fade * (1.0 - MIN_STALE_ALPHA))
+}
+
+/// How planes are colored
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PlaneColoring {
+    /// Each airline has its own color
+    Airline,
+    /// Planes are shaded from red near the ground to violet at cruising altitude
+    Altitude,
+}
 
-use crate::{map, util, world_x_to_pixel_x, world_y_to_pixel_y, Plane, PlaneRequester};
+impl PlaneColoring {
+    /// Returns the other coloring
+    pub fn toggle(self) -> Self {
+        match self {
+            PlaneColoring::Airline => PlaneColoring::Altitude,
+            PlaneColoring::Altitude => PlaneColoring::Airline,
+        }
+    }
+
+    /// A short human readable name for this coloring
+    pub fn name(self) -> &'static str {
+        match self {
+            PlaneColoring::Airline => "Airline",
+            PlaneColoring::Altitude => "Altitude",
+        }
+    }
+}
+
+/// Converts a hue in degrees to a fully saturated and bright rgb color
+fn hue_to_rgb(hue: f32) -> [f32; 3] {
+    let h = hue.rem_euclid(360.0) / 60.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    match h as u32 {
+        0 => [1.0, x, 0.0],
+        1 => [x, 1.0, 0.0],
+        2 => [0.0, 1.0, x],
+        3 => [0.0, x, 1.0],
+        4 => [x, 0.0, 1.0],
+        _ => [1.0, 0.0, x],
+    }
+}
+
+/// Returns the color of a plane at `altitude` meters on a ramp from red on the ground to violet at
+/// cruising altitude, or a neutral gray if the altitude is unknown
+pub fn altitude_color(altitude: Option<f32>) -> [f32; 3] {
+    match altitude {
+        Some(altitude) => {
+            let t = (altitude / MAX_RAMP_ALTITUDE).clamp(0.0, 1.0);
+            hue_to_rgb(t * MAX_RAMP_HUE)
+        }
+        None => UNKNOWN_ALTITUDE_COLOR,
+    }
+}
+
+/// The altitudes labeled on the altitude legend in feet, from top to bottom
+const LEGEND_ALTITUDES_FEET: [f64; 5] = [40_000.0, 30_000.0, 20_000.0, 10_000.0, 0.0];
+
+/// The altitudes labeled on the altitude legend in meters, used when altitudes are shown in
+/// meters so that the labels stay round
+const LEGEND_ALTITUDES_METERS: [f64; 5] = [12_000.0, 9_000.0, 6_000.0, 3_000.0, 0.0];
+
+/// Draws a legend for [`Palette::altitude_color`] in the bottom right corner of the window
+pub fn draw_altitude_legend(
+    ui: &mut UiCell<'_>,
+    ids: &mut crate::Ids,
+    font: conrod_core::text::font::Id,
+    palette: Palette,
+    units: crate::Units,
+) {
+    const SWATCH_SIZE: f64 = 14.0;
+    const ROW_HEIGHT: f64 = 18.0;
+
+    let altitudes = match units {
+        crate::Units::Metric => LEGEND_ALTITUDES_METERS,
+        crate::Units::Imperial | crate::Units::Nautical => LEGEND_ALTITUDES_FEET,
+    };
+    let count = altitudes.len();
+    ids.altitude_legend_swatches
+        .resize(count, &mut ui.widget_id_generator());
+    ids.altitude_legend_labels
+        .resize(count, &mut ui.widget_id_generator());
+
+    let x = ui.win_w / 2.0 - 90.0;
+    let bottom = -ui.win_h / 2.0 + 20.0;
+    for (i, &altitude) in altitudes.iter().enumerate() {
+        let y = bottom + (count - 1 - i) as f64 * ROW_HEIGHT;
+        let meters = units.altitude_to_meters(altitude);
+        let [r, g, b] = palette.altitude_color(Some(meters as f32));
+        Rectangle::fill([SWATCH_SIZE, SWATCH_SIZE])
+            .x_y(x, y)
+            .color(conrod_core::Color::Rgba(r, g, b, 1.0))
+            .set(ids.altitude_legend_swatches[i], ui);
+
+        let text = units.format_altitude(meters);
+        Text::new(text.as_str())
+            .x_y(x + 45.0, y)
+            .color(conrod_core::color::WHITE)
+            .font_size(11)
+            .font_id(font)
+            .set(ids.altitude_legend_labels[i], ui);
+    }
+}
+
+/// Draws a legend of [`AirlineStyles::legend`] to the left of the minimap, with colors remapped
+/// by `palette`. Rows hidden by the filter are grayed out.
+///
+/// Returns the filter of the row that was clicked, if any
+pub fn draw_airline_legend(
+    ui: &mut UiCell<'_>,
+    ids: &mut crate::Ids,
+    font: conrod_core::text::font::Id,
+    rows: &[LegendRow],
+    palette: Palette,
+) -> Option<BasicAirline> {
+    const ROW_WIDTH: f64 = 170.0;
+    const ROW_HEIGHT: f64 = 20.0;
+    const SWATCH_SIZE: f64 = 12.0;
+
+    ids.airline_legend_rows
+        .resize(rows.len(), &mut ui.widget_id_generator());
+    ids.airline_legend_swatches
+        .resize(rows.len(), &mut ui.widget_id_generator());
+
+    let minimap = crate::minimap::bounds(ui.win_w, ui.win_h);
+    let x = minimap.left() - 12.0 - ROW_WIDTH / 2.0;
+    let mut clicked = None;
+    for (i, row) in rows.iter().enumerate() {
+        let y = minimap.bottom() + (rows.len() - 1 - i) as f64 * ROW_HEIGHT + ROW_HEIGHT / 2.0;
+        let (label_color, alpha) = if row.shown {
+            (conrod_core::color::WHITE, 1.0)
+        } else {
+            (conrod_core::color::GRAY, 0.3)
+        };
+        if widget::Button::new()
+            .w_h(ROW_WIDTH, ROW_HEIGHT)
+            .x_y(x, y)
+            .color(conrod_core::Color::Rgba(0.0, 0.0, 0.0, 0.6))
+            .border(0.0)
+            .label(&row.name)
+            .label_color(label_color)
+            .label_font_size(11)
+            .label_font_id(font)
+            .set(ids.airline_legend_rows[i], ui)
+            .was_clicked()
+        {
+            clicked = Some(row.filter);
+        }
+
+        let [r, g, b] = palette.remap(row.color);
+        widget::BorderedRectangle::new([SWATCH_SIZE, SWATCH_SIZE])
+            .x_y(x - ROW_WIDTH / 2.0 + ROW_HEIGHT / 2.0, y)
+            .color(conrod_core::Color::Rgba(r, g, b, alpha))
+            .border(1.0)
+            .border_color(label_color)
+            .set(ids.airline_legend_swatches[i], ui);
+    }
+    clicked
+}
+
+/// The top of the altitude filter's range in feet. A maximum set here also shows planes above it
+pub const FILTER_MAX_ALTITUDE_FEET: f32 = 45_000.0;
+
+/// Vertical rates closer to zero than this, in meters per second (about 200 ft/min), count as level
+const LEVEL_VERTICAL_RATE: f32 = 1.0;
+
+/// Whether a plane is going up, down, or holding its altitude
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Enum)]
+pub enum VerticalTrend {
+    Climbing,
+    Level,
+    Descending,
+}
+
+impl VerticalTrend {
+    /// Classifies a vertical rate in meters per second
+    pub fn from_rate(vertical_rate: f32) -> Self {
+        if vertical_rate >= LEVEL_VERTICAL_RATE {
+            VerticalTrend::Climbing
+        } else if vertical_rate <= -LEVEL_VERTICAL_RATE {
+            VerticalTrend::Descending
+        } else {
+            VerticalTrend::Level
+        }
+    }
+
+    /// A human readable name for this trend
+    pub fn name(self) -> &'static str {
+        match self {
+            VerticalTrend::Climbing => "Climbing",
+            VerticalTrend::Level => "Level",
+            VerticalTrend::Descending => "Descending",
+        }
+    }
+}
+
+/// Hides planes outside of an altitude band or that aren't moving vertically the way the user
+/// wants
+#[derive(Clone, Debug, PartialEq)]
+pub struct AltitudeFilter {
+    /// The lowest altitude shown in feet. Zero also shows planes reporting negative altitudes
+    pub min_feet: f32,
+    /// The highest altitude shown in feet. [`FILTER_MAX_ALTITUDE_FEET`] shows everything above
+    /// the minimum
+    pub max_feet: f32,
+    /// Which vertical trends are shown
+    pub trends: EnumMap<VerticalTrend, bool>,
+    /// Whether planes with an unknown altitude are shown, along with planes missing the vertical
+    /// rate needed by the filter
+    pub show_unknown: bool,
+}
+
+impl Default for AltitudeFilter {
+    fn default() -> Self {
+        Self {
+            min_feet: 0.0,
+            max_feet: FILTER_MAX_ALTITUDE_FEET,
+            trends: enum_map! { _ => true },
+            show_unknown: true,
+        }
+    }
+}
+
+impl AltitudeFilter {
+    /// Returns true if `plane` passes this filter
+    pub fn matches(&self, plane: &Plane) -> bool {
+        match plane.altitude() {
+            Some(meters) => {
+                let feet = util::meters_to_feet(meters as f64) as f32;
+                if (self.min_feet > 0.0 && feet < self.min_feet)
+                    || (self.max_feet < FILTER_MAX_ALTITUDE_FEET && feet > self.max_feet)
+                {
+                    return false;
+                }
+            }
+            None if !self.show_unknown => return false,
+            None => {}
+        }
+
+        let trend_limited = self.trends.values().any(|shown| !shown);
+        if trend_limited {
+            match plane.vertical_rate {
+                Some(rate) => self.trends[VerticalTrend::from_rate(rate)],
+                None => self.show_unknown,
+            }
+        } else {
+            true
+        }
+    }
+}
 
 ///Normal body of plane we select
 #[derive(Clone)]
@@ -31,9 +346,195 @@ impl SelectedPlane {
 pub struct LoadingStruct {
     pub planes_loaded: bool,
     pub plane_selection: Option<SelectedPlane>,
+    /// Groups of planes drawn as a single bubble instead of individually
+    pub clusters: Vec<PlaneCluster>,
+    /// The planes drawn on their own, to label with their callsigns. Empty while zoomed out
+    /// further than [`CALLSIGN_LABEL_MIN_ZOOM`]
+    pub labels: Vec<PlaneLabel>,
+    /// The number of planes shown inside the region polygon, on screen or not, or `None` if no
+    /// closed region was given
+    pub region_count: Option<usize>,
+    /// True if any plane drawn, or any trail, is fading out because OpenSky hasn't heard from it
+    /// in a while. The planes need redrawing as time passes even if no new data arrives
+    pub fading: bool,
+}
+
+impl LoadingStruct {
+    /// Moves the positions found while drawing the planes onto the unrotated map to where they
+    /// are shown on the screen, which is turned by the rotation of `view`
+    pub fn rotate_to_screen(&mut self, view: &crate::TileView) {
+        let to_screen = |position: DVec2| crate::track_up::map_point_to_screen(view, position);
+        if let Some(selection) = &mut self.plane_selection {
+            selection.location = to_screen(selection.location);
+        }
+        for cluster in &mut self.clusters {
+            cluster.position = to_screen(cluster.position);
+        }
+        for label in &mut self.labels {
+            label.position = to_screen(label.position);
+        }
+    }
+}
+
+/// Planes are labeled with their callsigns once [`crate::TileView::get_zoom`] reaches this value
+pub const CALLSIGN_LABEL_MIN_ZOOM: f64 = 15.0;
+
+/// The font size of callsign labels
+const CALLSIGN_FONT_SIZE: u32 = 10;
+
+/// A plane drawn on its own, which may be labeled with its callsign
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlaneLabel {
+    /// The callsign to show, or `None` if the plane has none. Planes without one are still kept
+    /// so that other labels don't cover them
+    pub callsign: Option<String>,
+    /// The location of the plane in conrod's coordinates
+    pub position: DVec2,
+    /// The size of the plane's icon in pixels
+    pub size: f64,
+}
+
+/// Picks where to draw callsign labels so that they don't overlap each other, planes or cluster
+/// bubbles, the same way airport labels are placed.
+///
+/// Returns each callsign that fit and the center of its label
+pub fn place_callsigns<'a>(
+    labels: &'a [PlaneLabel],
+    clusters: &[PlaneCluster],
+) -> Vec<(&'a str, DVec2)> {
+    let (named, unnamed): (Vec<_>, Vec<_>) =
+        labels.iter().partition(|label| label.callsign.is_some());
+    //Planes with a callsign come first, since only the icons with a size get a label
+    let icons: Vec<_> = named
+        .iter()
+        .chain(&unnamed)
+        .map(|label| (label.position.x, label.position.y, label.size))
+        .chain(clusters.iter().map(|cluster| {
+            let size = cluster_radius(cluster.count) * 2.0;
+            (cluster.position.x, cluster.position.y, size)
+        }))
+        .collect();
+    let callsigns: Vec<&str> = named
+        .iter()
+        .filter_map(|label| label.callsign.as_deref())
+        .collect();
+    let sizes: Vec<_> = callsigns
+        .iter()
+        .map(|callsign| estimate_label_size(callsign, CALLSIGN_FONT_SIZE))
+        .collect();
+
+    callsigns
+        .into_iter()
+        .zip(place_labels(&icons, &sizes))
+        .filter_map(|(callsign, center)| center.map(|[x, y]| (callsign, DVec2::new(x, y))))
+        .collect()
+}
+
+/// Draws the callsign of each plane in `labels` that has room for one
+pub fn draw_callsigns(
+    ui: &mut UiCell<'_>,
+    ids: &mut crate::Ids,
+    font: conrod_core::text::font::Id,
+    labels: &[PlaneLabel],
+    clusters: &[PlaneCluster],
+) {
+    let placed = place_callsigns(labels, clusters);
+    ids.plane_labels
+        .resize(placed.len(), &mut ui.widget_id_generator());
+    for (i, (callsign, center)) in placed.into_iter().enumerate() {
+        Text::new(callsign)
+            .x_y(center.x, center.y)
+            .color(conrod_core::color::WHITE)
+            .font_size(CALLSIGN_FONT_SIZE)
+            .font_id(font)
+            .set(ids.plane_labels[i], ui);
+    }
+}
+
+/// Controls how planes that are close together on screen are grouped into clusters
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClusterSettings {
+    pub enabled: bool,
+    /// The width in pixels of the grid cells that planes are grouped by. Planes in the same cell
+    /// are drawn as one cluster
+    pub distance: f64,
+}
+
+impl Default for ClusterSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            distance: 40.0,
+        }
+    }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Enum)]
+/// A group of planes drawn as one bubble showing how many planes it holds
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlaneCluster {
+    /// The average position of the planes in conrod's coordinates
+    pub position: DVec2,
+    pub count: usize,
+}
+
+/// Groups `positions` that fall in the same cell of a square grid with cells `cell_size` wide.
+///
+/// Returns the indices of the positions in each group with more than one position, ordered by
+/// their first position. Positions alone in their cell aren't part of any group
+pub fn cluster_positions(positions: &[DVec2], cell_size: f64) -> Vec<Vec<usize>> {
+    let mut cells: std::collections::HashMap<(i64, i64), usize> = Default::default();
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (i, position) in positions.iter().enumerate() {
+        let cell = (*position / cell_size).floor();
+        let group = *cells
+            .entry((cell.x as i64, cell.y as i64))
+            .or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+        groups[group].push(i);
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+/// The radius in pixels of the bubble of a cluster of two planes. Bigger clusters get bigger
+/// bubbles
+const CLUSTER_RADIUS: f64 = 10.0;
+
+/// Returns the radius in pixels of the bubble of a cluster of `count` planes
+fn cluster_radius(count: usize) -> f64 {
+    CLUSTER_RADIUS + 2.0 * (count as f64).log2()
+}
+
+/// Draws a bubble with the number of planes for each cluster
+pub fn draw_clusters(
+    ui: &mut UiCell<'_>,
+    ids: &mut crate::Ids,
+    font: conrod_core::text::font::Id,
+    clusters: &[PlaneCluster],
+) {
+    ids.plane_clusters
+        .resize(clusters.len(), &mut ui.widget_id_generator());
+    ids.plane_cluster_labels
+        .resize(clusters.len(), &mut ui.widget_id_generator());
+    for (i, cluster) in clusters.iter().enumerate() {
+        widget::Circle::fill(cluster_radius(cluster.count))
+            .x_y(cluster.position.x, cluster.position.y)
+            .color(conrod_core::Color::Rgba(0.1, 0.1, 0.1, 0.8))
+            .set(ids.plane_clusters[i], ui);
+        Text::new(&cluster.count.to_string())
+            .x_y(cluster.position.x, cluster.position.y)
+            .color(conrod_core::color::WHITE)
+            .font_size(11)
+            .font_id(font)
+            .set(ids.plane_cluster_labels[i], ui);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Enum, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum PlaneType {
     Commercial,
     Trainer,
@@ -69,7 +570,7 @@ pub struct DynamicAirline {
 
 /// Describes a few specific airlines, and also the selections of All or Other which the user can
 /// filter by
-#[derive(Copy, Clone, PartialEq, Eq, Enum)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Enum)]
 pub enum BasicAirline {
     American,
     Spirit,
@@ -110,6 +611,160 @@ impl From<BasicAirline> for Airline {
     }
 }
 
+/// How the planes of one airline are drawn
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AirlineStyle {
+    /// The tint used when planes are colored by airline
+    pub color: [f32; 3],
+    /// The index of the airline's icon in [`PlaneRenderer::icons`], or `None` for the default
+    /// airplane
+    pub icon: Option<usize>,
+}
+
+impl AirlineStyle {
+    pub const fn color(color: [f32; 3]) -> Self {
+        Self { color, icon: None }
+    }
+}
+
+/// The style of every airline. Airlines without a style of their own, including
+/// [`Airline::Unknown`], use the style of [`BasicAirline::Other`]
+#[derive(Clone)]
+pub struct AirlineStyles {
+    basic: EnumMap<BasicAirline, AirlineStyle>,
+    /// Styles of dynamic airlines, in the order they were set
+    dynamic: Vec<(DynamicAirline, AirlineStyle)>,
+}
+
+impl Default for AirlineStyles {
+    fn default() -> Self {
+        Self {
+            basic: enum_map! {
+                BasicAirline::American => AirlineStyle::color([3.0 / 255.0, 5.0 / 255.0, 135.0 / 255.0]),
+                BasicAirline::Spirit => AirlineStyle::color([1.0, 1.0, 0.0]),
+                BasicAirline::United => AirlineStyle::color([146.0 / 255.0, 182.0 / 255.0, 240.0 / 255.0]),
+                BasicAirline::Southwest => AirlineStyle::color([229.0 / 255.0, 29.0 / 255.0, 35.0 / 255.0]),
+                _ => AirlineStyle::color([0.0, 0.0, 0.0])
+            },
+            dynamic: Vec::new(),
+        }
+    }
+}
+
+impl AirlineStyles {
+    /// Returns the style planes of `airline` are drawn with
+    pub fn style(&self, airline: &Airline) -> AirlineStyle {
+        match airline {
+            Airline::Basic(basic) => self.basic[*basic],
+            Airline::Dynamic(dynamic) => self
+                .dynamic
+                .iter()
+                .find(|(other, _)| other.callsign == dynamic.callsign)
+                .map_or(self.basic[BasicAirline::Other], |(_, style)| *style),
+            Airline::Unknown => self.basic[BasicAirline::Other],
+        }
+    }
+
+    /// Replaces the style of `airline`. Setting the style of [`Airline::Unknown`] changes the
+    /// fallback used by every airline without a style
+    pub fn set(&mut self, airline: &Airline, style: AirlineStyle) {
+        match airline {
+            Airline::Basic(basic) => self.basic[*basic] = style,
+            Airline::Dynamic(dynamic) => {
+                match self
+                    .dynamic
+                    .iter_mut()
+                    .find(|(other, _)| other.callsign == dynamic.callsign)
+                {
+                    Some((_, existing)) => *existing = style,
+                    None => self.dynamic.push((dynamic.clone(), style)),
+                }
+            }
+            Airline::Unknown => self.basic[BasicAirline::Other] = style,
+        }
+    }
+}
+
+impl AirlineStyles {
+    /// Returns the rows of the airline legend: every airline with a filter button, then the
+    /// dynamic airlines with a style of their own, then everything else.
+    ///
+    /// Rows are marked hidden when `selected_airline` filters them out
+    pub fn legend(&self, selected_airline: BasicAirline) -> Vec<LegendRow> {
+        let shows = |filter: BasicAirline| {
+            selected_airline == BasicAirline::All || selected_airline == filter
+        };
+        let basic = [
+            BasicAirline::American,
+            BasicAirline::Spirit,
+            BasicAirline::Southwest,
+            BasicAirline::United,
+            BasicAirline::Delta,
+        ];
+        let mut rows: Vec<LegendRow> = basic
+            .into_iter()
+            .map(|airline| LegendRow {
+                name: airline.to_str().to_owned(),
+                color: self.basic[airline].color,
+                filter: airline,
+                shown: shows(airline),
+            })
+            .collect();
+        for (airline, style) in &self.dynamic {
+            rows.push(LegendRow {
+                name: airline.name.clone(),
+                color: style.color,
+                filter: BasicAirline::Other,
+                shown: shows(BasicAirline::Other),
+            });
+        }
+        rows.push(LegendRow {
+            name: String::from("Other Airlines"),
+            color: self.basic[BasicAirline::Other].color,
+            filter: BasicAirline::Other,
+            shown: shows(BasicAirline::Other),
+        });
+        rows
+    }
+}
+
+/// One row of the airline legend
+#[derive(Clone, Debug, PartialEq)]
+pub struct LegendRow {
+    pub name: String,
+    pub color: [f32; 3],
+    /// The filter that shows this airline's planes
+    pub filter: BasicAirline,
+    /// False if the current filter hides this airline
+    pub shown: bool,
+}
+
+/// Parses a color written as `#rrggbb`
+pub fn parse_hex_color(text: &str) -> Option<[f32; 3]> {
+    let hex = text.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16)
+            .ok()
+            .map(|value| value as f32 / 255.0)
+    };
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Decodes a png image into a texture that can be drawn by the plane shader
+fn load_plane_texture(
+    display: &glium::Display,
+    bytes: &[u8],
+) -> Result<SrgbTexture2d, image::ImageError> {
+    let image = image::load(Cursor::new(bytes), image::ImageFormat::Png)?.to_rgba8();
+    let image_dimensions = image.dimensions();
+    let image =
+        glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
+    Ok(SrgbTexture2d::new(display, image).unwrap())
+}
+
 #[derive(Copy, Clone)]
 pub struct Vertex {
     pub position: [f32; 2],
@@ -117,18 +772,50 @@ pub struct Vertex {
     pub offset: [f32; 2],
     pub tex_coords: [f32; 2],
     pub color: [f32; 3],
+    /// Below 1.0 for planes that haven't been heard from in a while, see [`staleness_alpha`]
+    pub alpha: f32,
+    /// 1.0 if this plane has no known heading and should be drawn as a dot, 0.0 otherwise
+    pub no_heading: f32,
 }
 
-implement_vertex!(Vertex, position, angle, offset, tex_coords, color);
+implement_vertex!(Vertex, position, angle, offset, tex_coords, color, alpha, no_heading);
+
+/// A point on the line drawn behind a plane
+#[derive(Copy, Clone)]
+pub struct TrailVertex {
+    /// The location in the OpenGL coordinate system
+    pub position: [f32; 2],
+    pub color: [f32; 4],
+}
+
+implement_vertex!(TrailVertex, position, color);
 
 /// This struct renders the planes that are requested by the API and displays them using custom OpenGL
 pub struct PlaneRenderer<'a> {
     pub program: Program,
     pub draw_parameters: DrawParameters<'a>,
     pub vertices: Vec<Vertex>,
+    /// Runs of [`Self::vertices`] drawn with the same icon
+    pub batches: Vec<(Option<usize>, Range<usize>)>,
+    /// The default airplane icon
     pub texture: SrgbTexture2d,
+    /// Icons added with [`Self::add_icon`], used by airlines whose [`AirlineStyle`] has one
+    pub icons: Vec<SrgbTexture2d>,
     pub indices: NoIndices,
-    pub color_map: EnumMap<BasicAirline, [f32; 3]>,
+    pub styles: AirlineStyles,
+    /// Whether planes are colored by airline or altitude
+    pub coloring: PlaneColoring,
+    /// Adjusts plane colors for colorblind users
+    pub palette: Palette,
+    pub clustering: ClusterSettings,
+    /// How much larger than normal planes are drawn, independent of the zoom, between
+    /// [`MIN_MARKER_SCALE`] and [`MAX_MARKER_SCALE`]
+    pub marker_scale: f32,
+    /// Where each plane has been. Trails are hidden while the trail length is zero
+    pub trails: TrailStore,
+    pub trail_program: Program,
+    pub trail_draw_parameters: DrawParameters<'a>,
+    pub trail_vertices: Vec<TrailVertex>,
 }
 
 impl<'a> PlaneRenderer<'a> {
@@ -142,20 +829,26 @@ impl<'a> PlaneRenderer<'a> {
             in vec2 offset;
             in vec2 tex_coords;
             in vec3 color;
+            in float alpha;
+            in float no_heading;
 
             out vec2 v_tex_coords;
             out vec3 v_color;
+            out float v_alpha;
+            out float v_no_heading;
 
             uniform mat4 matrix;
-            uniform float dpi_factor;
 
             void main() {
                 v_tex_coords = tex_coords;
                 v_color = color;
+                v_alpha = alpha;
+                v_no_heading = no_heading;
                 vec2 pos = position;
                 vec2 new_position = vec2(pos.x * cos(angle) - pos.y * sin(angle), pos.x * sin(angle) + pos.y * cos(angle));
                 vec4 scaled = matrix * vec4(new_position, 0.0, 1.0);
-                vec4 with_offset = vec4(offset * dpi_factor, 0.0, 0.0) + scaled;
+                // The offset is already in normalized device coordinates
+                vec4 with_offset = vec4(offset, 0.0, 0.0) + scaled;
                 gl_Position = with_offset;
             }
         "#;
@@ -165,13 +858,21 @@ impl<'a> PlaneRenderer<'a> {
 
             in vec2 v_tex_coords;
             in vec3 v_color;
+            in float v_alpha;
+            in float v_no_heading;
             out vec4 color;
 
             uniform sampler2D tex;
 
             void main() {
-                float tex_alpha = texture(tex, v_tex_coords).a;
-                color = vec4(v_color, tex_alpha);
+                float tex_alpha;
+                if (v_no_heading > 0.5) {
+                    // Planes without a heading are drawn as a circle so they don't point north
+                    tex_alpha = distance(v_tex_coords, vec2(0.5, 0.5)) < 0.25 ? 1.0 : 0.0;
+                } else {
+                    tex_alpha = texture(tex, v_tex_coords).a;
+                }
+                color = vec4(v_color, tex_alpha * v_alpha);
             }
         "#;
 
@@ -179,55 +880,138 @@ impl<'a> PlaneRenderer<'a> {
             glium::Program::from_source(display, vertex_shader_src, fragment_shader_src, None)
                 .unwrap();
 
-        let image = image::load(
-            Cursor::new(&include_bytes!("../assets/images/airplane-image.png")),
-            image::ImageFormat::Png,
+        let texture = load_plane_texture(
+            display,
+            include_bytes!("../assets/images/airplane-image.png"),
         )
-        .unwrap()
-        .to_rgba8();
+        .unwrap();
 
-        let image_dimensions = image.dimensions();
+        let trail_vertex_shader_src = r#"
+            #version 140
 
-        let image =
-            glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
+            in vec2 position;
+            in vec4 color;
 
-        let texture = glium::texture::SrgbTexture2d::new(display, image).unwrap();
+            out vec4 v_color;
 
-        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+            void main() {
+                v_color = color;
+                gl_Position = vec4(position, 0.0, 1.0);
+            }
+        "#;
 
-        let draw_parameters = glium::draw_parameters::DrawParameters {
+        let trail_fragment_shader_src = r#"
+            #version 140
+
+            in vec4 v_color;
+            out vec4 color;
+
+            void main() {
+                color = v_color;
+            }
+        "#;
+
+        let trail_program = glium::Program::from_source(
+            display,
+            trail_vertex_shader_src,
+            trail_fragment_shader_src,
+            None,
+        )
+        .unwrap();
+
+        let trail_draw_parameters = glium::draw_parameters::DrawParameters {
             blend: glium::draw_parameters::Blend::alpha_blending(),
+            line_width: Some(2.0),
             ..glium::draw_parameters::DrawParameters::default()
         };
 
-        let color_map = enum_map! {
-            BasicAirline::American => [3.0 / 255.0, 5.0 / 255.0, 135.0 / 255.0],
-            BasicAirline::Spirit => [1.0, 1.0, 0.0],
-            BasicAirline::United => [146.0 / 255.0, 182.0 / 255.0, 240.0 / 255.0],
-            BasicAirline::Southwest => [229.0 / 255.0, 29.0 / 255.0, 35.0 / 255.0],
-            _ => [0.0, 0.0, 0.0]
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+
+        let draw_parameters = glium::draw_parameters::DrawParameters {
+            blend: glium::draw_parameters::Blend::alpha_blending(),
+            ..glium::draw_parameters::DrawParameters::default()
         };
 
         Self {
             program,
             draw_parameters,
             vertices: Vec::new(),
+            batches: Vec::new(),
             texture,
+            icons: Vec::new(),
             indices,
-            color_map,
+            styles: AirlineStyles::default(),
+            coloring: PlaneColoring::Airline,
+            palette: Palette::Normal,
+            clustering: ClusterSettings::default(),
+            marker_scale: 1.0,
+            trails: TrailStore::default(),
+            trail_program,
+            trail_draw_parameters,
+            trail_vertices: Vec::new(),
         }
     }
 
-    /// Draw the planes on the OpenGL Frame that is provided
-    pub fn draw(
+    /// Decodes a png icon and returns its index for [`AirlineStyle::icon`]
+    pub fn add_icon(
         &mut self,
         display: &glium::Display,
-        target: &mut glium::Frame,
+        bytes: &[u8],
+    ) -> Result<usize, image::ImageError> {
+        self.icons.push(load_plane_texture(display, bytes)?);
+        Ok(self.icons.len() - 1)
+    }
+
+    /// Applies the airline styles from the config file, loading their icons.
+    ///
+    /// Prefixes that `registry` doesn't know and icons that can't be loaded are skipped with a
+    /// warning
+    pub fn apply_styles(
+        &mut self,
+        display: &glium::Display,
+        mappings: &[AirlineStyleMapping],
+        registry: &AirlineRegistry,
+    ) {
+        for mapping in mappings {
+            let airline = match registry.airline_for_prefix(&mapping.prefix) {
+                Some(airline) => airline.clone(),
+                None => {
+                    println!(
+                        "No airline uses the prefix {}, ignoring its style",
+                        mapping.prefix
+                    );
+                    continue;
+                }
+            };
+            let mut style = self.styles.style(&airline);
+            if let Some(color) = mapping.color.as_deref().and_then(parse_hex_color) {
+                style.color = color;
+            }
+            if let Some(path) = &mapping.icon {
+                let icon = std::fs::read(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|bytes| self.add_icon(display, &bytes).map_err(|e| e.to_string()));
+                match icon {
+                    Ok(icon) => style.icon = Some(icon),
+                    Err(e) => println!("Failed to load icon {}: {}", path.display(), e),
+                }
+            }
+            self.styles.set(&airline, style);
+        }
+    }
+
+    /// Draw the planes on the OpenGL surface that is provided
+    pub fn draw<S: Surface>(
+        &mut self,
+        display: &glium::Display,
+        target: &mut S,
         plane_requester: &mut PlaneRequester,
         view: &crate::TileView,
         selected_airline: BasicAirline,
+        altitude_filter: &AltitudeFilter,
+        region: Option<&RegionPolygon>,
         clicked_plane: &mut Option<SelectedPlane>,
-        mut last_cursor_pos: Option<DVec2>,
+        last_cursor_pos: Option<DVec2>,
     ) -> LoadingStruct {
         // Here we collect the dynamic numbers for rendering our OpenGL planes
         let (width, height) = target.get_dimensions();
@@ -240,114 +1024,211 @@ impl<'a> PlaneRenderer<'a> {
 
         let planes_loaded = !airlines.is_empty();
 
+        // The map works in logical pixels, while the frame is measured in physical pixels
+        let logical_width = width as f64 / dpi_factor as f64;
+        let logical_height = height as f64 / dpi_factor as f64;
+
         // Viewport of the world
-        let viewport = view.get_world_viewport(width as f64, height as f64);
-        let lat_top = crate::util::latitude_from_y(viewport.top_left.y.rem_euclid(1.0)) as f32;
+        let viewport = view.get_world_viewport(logical_width, logical_height);
+        let lat_top = crate::util::latitude_from_y(viewport.top_left.y.clamp(0.0, 1.0)) as f32;
         let lat_bottom =
-            crate::util::latitude_from_y(viewport.bottom_right.y.rem_euclid(1.0)) as f32;
+            crate::util::latitude_from_y(viewport.bottom_right.y.clamp(0.0, 1.0)) as f32;
         let long_left = crate::util::longitude_from_x(viewport.top_left.x.rem_euclid(1.0)) as f32;
         let long_right =
             crate::util::longitude_from_x(viewport.bottom_right.x.rem_euclid(1.0)) as f32;
         let zoom = view.get_zoom() as f32;
 
-        let size_of_plane = 1.5_f32.powf(zoom) / 30.0;
-        if let Some(pos) = last_cursor_pos {
-            let cursor_x = map(0.0, width as f64, pos.x, -1.0, 1.0) / dpi_factor as f64;
-            let cursor_y = map(0.0, height as f64, pos.y, 1.0, -1.0) / dpi_factor as f64;
+        let size_of_plane = 1.5_f32.powf(zoom) / 30.0 * self.marker_scale;
 
-            last_cursor_pos = Some(DVec2::new(cursor_x, cursor_y));
-        }
-
-        let mut selected_plane = None;
-        let closest_x = 0.01;
-        let closest_y = 0.01;
-
-        //Margin error to compare the distance of planes
-        let margin_error_distance = 0.00001;
+        // Convert the cursor from window pixels to the same conrod coordinates `pixel_x` and
+        // `pixel_y` use for planes below, so that hit testing happens in screen space
+        let cursor = last_cursor_pos.map(|pos| {
+            DVec2::new(
+                (pos.x - width as f64 / 2.0) / dpi_factor as f64,
+                (height as f64 / 2.0 - pos.y) / dpi_factor as f64,
+            )
+        });
+        let select_radius = (size_of_plane as f64 / 2.0).max(MIN_SELECT_RADIUS_PIXELS);
 
         self.vertices.clear();
-
-        let mut plane_position: DVec2 = DVec2::new(0.0, 0.0);
-
-        // We iterate through all the planes and generated their OpenGL vertices
+        self.batches.clear();
+        self.trail_vertices.clear();
+        self.trails.update(&airlines);
+
+        // Grid cells are measured from the world's origin so that clusters don't change while
+        // panning
+        let pixels_per_world = logical_width / (viewport.bottom_right.x - viewport.top_left.x);
+
+        // OpenSky timestamps are in seconds since the unix epoch. Time stands still while frozen
+        // so that planes don't fade out
+        let now = plane_requester
+            .frozen_at()
+            .unwrap_or_else(SystemTime::now)
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+
+        // Planes outside of a closed region are faded, and the ones inside are counted
+        let region_corners = region
+            .filter(|region| region.is_closed())
+            .map(RegionPolygon::world_corners);
+        let mut region_count = region_corners.as_ref().map(|_| 0);
+        let mut fading = false;
+
+        // First find where every plane on screen is, so they can be clustered
+        let mut visible: Vec<VisiblePlane> = Vec::new();
         for plane in airlines.iter() {
             let airline = &plane.airline;
-            let color = match airline {
+            let shown = match airline {
                 Airline::Basic(airline) => {
-                    if airline == &selected_airline || selected_airline == BasicAirline::All {
-                        Some(self.color_map[airline.clone()])
-                    } else {
-                        None
-                    }
+                    airline == &selected_airline || selected_airline == BasicAirline::All
                 }
+                //Only show dynamic airlines when showing all or other planes
                 _ => {
-                    if selected_airline == BasicAirline::All
-                        || selected_airline == BasicAirline::Other
-                    {
-                        Some([0.0, 0.0, 0.0])
-                    } else {
-                        None
-                    }
-                } //Only show dynamic airlines when showing all planes
+                    selected_airline == BasicAirline::All || selected_airline == BasicAirline::Other
+                }
             };
 
-            if let Some(color) = color {
+            if shown {
+                let style = self.styles.style(airline);
                 for plane in plane.planes.iter() {
+                    if !altitude_filter.matches(plane) {
+                        continue;
+                    }
+                    // Planes that haven't been heard from in a while fade out, then disappear
+                    let age = plane.last_contact.map_or(Duration::ZERO, |last_contact| {
+                        now.saturating_sub(Duration::from_secs(last_contact))
+                    });
+                    let mut alpha = match staleness_alpha(age) {
+                        Some(alpha) => alpha,
+                        None => continue,
+                    };
+                    fading |= age > STALE_PLANE_AGE;
+                    let world_x = util::x_from_longitude(plane.longitude as f64);
+                    let world_y = util::y_from_latitude(plane.latitude as f64);
+                    if let (Some(corners), Some(count)) = (&region_corners, &mut region_count) {
+                        if RegionPolygon::corners_contain(corners, world_x, world_y) {
+                            *count += 1;
+                        } else {
+                            alpha *= OUTSIDE_REGION_ALPHA;
+                        }
+                    }
+                    let color = match self.coloring {
+                        PlaneColoring::Airline => self.palette.remap(style.color),
+                        PlaneColoring::Altitude => self.palette.altitude_color(plane.altitude()),
+                    };
+
+                    // Trails are drawn even if the plane itself is off screen
+                    if let Some(trail) = self.trails.trail(&plane.callsign) {
+                        push_trail(&mut self.trail_vertices, trail, color, alpha, &viewport);
+                    }
+
                     if (plane.latitude > lat_bottom && plane.latitude < lat_top)
                         && (plane.longitude > long_left && plane.longitude < long_right)
                     {
                         // Translates real world coordinates to window coordinates.
-                        let world_x = util::x_from_longitude(plane.longitude as f64);
-                        let world_y = util::y_from_latitude(plane.latitude as f64);
-
                         let offset_x = world_x_to_window_x(world_x, &viewport);
                         let offset_y = world_y_to_window_y(world_y, &viewport);
 
-                        let pixel_x = world_x_to_pixel_x(world_x, &viewport, width as f64);
-                        let pixel_y = world_y_to_pixel_y(world_y, &viewport, height as f64);
-
-                        let color = if let Some(last_cursor_pos) = last_cursor_pos {
-                            if (offset_x - last_cursor_pos.x as f32).abs() < closest_x
-                                && (offset_y - last_cursor_pos.y as f32).abs() < closest_y
-                            {
-                                //Gets the plane position as a DVec2
-                                plane_position = DVec2::new(pixel_x, pixel_y);
-
-                                selected_plane = Some(plane.clone());
-
-                                // Draw it as white
-                                [1.0, 1.0, 1.0]
-                            } else {
-                                color
-                            }
-                        } else {
-                            color
-                        };
+                        let pixel_x = world_x_to_pixel_x(world_x, &viewport, logical_width);
+                        let pixel_y = world_y_to_pixel_y(world_y, &viewport, logical_height);
+                        let pixel = DVec2::new(pixel_x, pixel_y);
 
-                        //Show details about already clicked planes
+                        //Keep the clicked plane up to date as new data arrives
                         if let Some(clicked_plane) = clicked_plane {
-                            if clicked_plane.plane.callsign == plane.callsign
-                                && (clicked_plane.plane.latitude - plane.latitude).abs()
-                                    > margin_error_distance
-                                && (clicked_plane.plane.longitude - plane.longitude).abs()
-                                    > margin_error_distance
-                            {
-                                //Updates the new plane data.
+                            if clicked_plane.plane.callsign == plane.callsign {
                                 clicked_plane.plane = plane.clone();
+                                clicked_plane.location = pixel;
                             }
                         }
 
-                        let offset = [offset_x, offset_y];
+                        visible.push(VisiblePlane {
+                            plane,
+                            offset: [offset_x, offset_y],
+                            pixel,
+                            grid: DVec2::new(world_x, world_y) * pixels_per_world,
+                            color,
+                            alpha,
+                            icon: style.icon,
+                        });
+                    }
+                }
+            }
+        }
 
-                        // Generate the vertices
-                        let plane = plane_shape(plane.track, offset, color);
+        let mut clustered = vec![false; visible.len()];
+        let mut clusters = Vec::new();
+        if self.clustering.enabled {
+            let grid: Vec<DVec2> = visible.iter().map(|visible| visible.grid).collect();
+            for group in cluster_positions(&grid, self.clustering.distance) {
+                let total: DVec2 = group.iter().map(|&i| visible[i].pixel).sum();
+                for &i in &group {
+                    clustered[i] = true;
+                }
+                clusters.push(PlaneCluster {
+                    position: total / group.len() as f64,
+                    count: group.len(),
+                });
+            }
+        }
 
-                        for vertex in plane {
-                            self.vertices.push(vertex);
-                        }
-                    }
+        // The closest plane under the cursor: (distance, index of first vertex, plane, position)
+        let mut hovered: Option<(f64, usize, &Plane, DVec2)> = None;
+        let show_labels = view.get_zoom() >= CALLSIGN_LABEL_MIN_ZOOM;
+        let mut labels = Vec::new();
+
+        // Then generate the OpenGL vertices of the planes that aren't in a cluster
+        for (shown, _) in visible
+            .iter()
+            .zip(clustered)
+            .filter(|(_, clustered)| !clustered)
+        {
+            if let Some(cursor) = cursor {
+                let distance = cursor.distance(shown.pixel);
+                let closer = match &hovered {
+                    Some((closest, ..)) => distance < *closest,
+                    None => true,
+                };
+                if distance < select_radius && closer {
+                    hovered = Some((distance, self.vertices.len(), shown.plane, shown.pixel));
                 }
             }
+
+            if show_labels {
+                labels.push(PlaneLabel {
+                    callsign: shown.plane.label().map(str::to_owned),
+                    position: shown.pixel,
+                    size: size_of_plane as f64,
+                });
+            }
+
+            let first_vertex = self.vertices.len();
+            let plane = plane_shape(shown.plane.track, shown.offset, shown.color, shown.alpha);
+            self.vertices.extend(plane);
+            push_batch(
+                &mut self.batches,
+                shown.icon,
+                first_vertex..self.vertices.len(),
+            );
+        }
+
+        // Draw the plane under the cursor as white
+        if let Some((_, first_vertex, _, _)) = hovered {
+            for vertex in &mut self.vertices[first_vertex..first_vertex + 6] {
+                vertex.color = [1.0, 1.0, 1.0];
+            }
+        }
+
+        if !self.trail_vertices.is_empty() {
+            let trail_buffer = glium::VertexBuffer::new(display, &self.trail_vertices).unwrap();
+            target
+                .draw(
+                    &trail_buffer,
+                    glium::index::NoIndices(glium::index::PrimitiveType::LinesList),
+                    &self.trail_program,
+                    &glium::uniforms::EmptyUniforms,
+                    &self.trail_draw_parameters,
+                )
+                .unwrap();
         }
 
         let vertex_buffer = glium::VertexBuffer::new(display, &self.vertices).unwrap();
@@ -359,30 +1240,106 @@ impl<'a> PlaneRenderer<'a> {
             cgmath::Matrix4::from_nonuniform_scale(aspect_ratio * scale_factor, scale_factor, 1.0)
                 .into();
 
-        let uniforms = uniform! {
-            matrix: matrix,
-            tex: &self.texture,
-            dpi_factor: dpi_factor
-        };
+        for (icon, range) in &self.batches {
+            let texture = icon.map_or(&self.texture, |icon| &self.icons[icon]);
+            let uniforms = uniform! {
+                matrix: matrix,
+                tex: texture,
+            };
 
-        target
-            .draw(
-                &vertex_buffer,
-                &self.indices,
-                &self.program,
-                &uniforms,
-                &self.draw_parameters,
-            )
-            .unwrap();
+            target
+                .draw(
+                    vertex_buffer.slice(range.clone()).unwrap(),
+                    &self.indices,
+                    &self.program,
+                    &uniforms,
+                    &self.draw_parameters,
+                )
+                .unwrap();
+        }
 
         LoadingStruct {
             planes_loaded,
-            plane_selection: selected_plane
-                .map(|plane| SelectedPlane::new(plane, plane_position, size_of_plane)),
+            plane_selection: hovered.map(|(_, _, plane, location)| {
+                SelectedPlane::new(plane.clone(), location, size_of_plane)
+            }),
+            clusters,
+            labels,
+            region_count,
+            fading,
         }
     }
 }
 
+/// A plane inside of the viewport that passed the filters
+struct VisiblePlane<'a> {
+    plane: &'a Plane,
+    /// The location in the OpenGL coordinate system
+    offset: [f32; 2],
+    /// The location in conrod's coordinates
+    pixel: DVec2,
+    /// The location in pixels from the world's origin, used to find clusters
+    grid: DVec2,
+    color: [f32; 3],
+    /// See [`staleness_alpha`]
+    alpha: f32,
+    icon: Option<usize>,
+}
+
+/// Adds the vertices in `range` to the batches drawn with `icon`, extending the last batch if it
+/// uses the same icon so that airlines sharing an icon are drawn together
+fn push_batch(
+    batches: &mut Vec<(Option<usize>, Range<usize>)>,
+    icon: Option<usize>,
+    range: Range<usize>,
+) {
+    if range.is_empty() {
+        return;
+    }
+    match batches.last_mut() {
+        Some((last_icon, last)) if *last_icon == icon && last.end == range.start => {
+            last.end = range.end
+        }
+        _ => batches.push((icon, range)),
+    }
+}
+
+/// Adds a line segment to `vertices` for each pair of neighboring positions in `trail`, fading
+/// from transparent at the oldest position to [`TRAIL_ALPHA`] times `alpha` at the newest.
+///
+/// Positions more than 180 degrees of longitude apart crossed the antimeridian, so no segment is
+/// drawn between them rather than one across the whole world
+fn push_trail(
+    vertices: &mut Vec<TrailVertex>,
+    trail: &std::collections::VecDeque<[f32; 2]>,
+    color: [f32; 3],
+    alpha: f32,
+    viewport: &crate::map::WorldViewport,
+) {
+    let [r, g, b] = color;
+    let segments = trail.len().saturating_sub(1);
+    let vertex = |i: usize| {
+        let [longitude, latitude] = trail[i];
+        let world_x = util::x_from_longitude(longitude as f64);
+        let world_y = util::y_from_latitude(latitude as f64);
+        let alpha = TRAIL_ALPHA * alpha * i as f32 / segments as f32;
+        TrailVertex {
+            position: [
+                world_x_to_window_x(world_x, viewport),
+                world_y_to_window_y(world_y, viewport),
+            ],
+            color: [r, g, b, alpha],
+        }
+    };
+    for i in 0..segments {
+        if (trail[i + 1][0] - trail[i][0]).abs() > 180.0 {
+            continue;
+        }
+        vertices.push(vertex(i));
+        vertices.push(vertex(i + 1));
+    }
+}
+
 /// Projects a x world location combined with a viewport to determine the x location in the OpenGL
 /// coordinate system
 pub fn world_x_to_window_x(world_x: f64, viewport: &crate::map::WorldViewport) -> f32 {
@@ -408,13 +1365,24 @@ pub fn world_y_to_window_y(world_y: f64, viewport: &crate::map::WorldViewport) -
 }
 
 /// Generates a set of vertices that describe a single plane that must be drawn
-pub fn plane_shape(angle: f32, offset: [f32; 2], color: [f32; 3]) -> [Vertex; 6] {
+///
+/// Planes are rotated to face `angle`. If the angle is unknown, a dot is drawn instead
+pub fn plane_shape(
+    angle: Option<f32>,
+    offset: [f32; 2],
+    color: [f32; 3],
+    alpha: f32,
+) -> [Vertex; 6] {
+    let no_heading = if angle.is_some() { 0.0 } else { 1.0 };
+    let angle = angle.unwrap_or(0.0);
     let vertex1 = Vertex {
         position: [-1.0, 1.0],
         angle,
         offset,
         tex_coords: [0.0, 1.0],
         color,
+        alpha,
+        no_heading,
     };
     let vertex2 = Vertex {
         position: [1.0, 1.0],
@@ -422,6 +1390,8 @@ pub fn plane_shape(angle: f32, offset: [f32; 2], color: [f32; 3]) -> [Vertex; 6]
         offset,
         tex_coords: [1.0, 1.0],
         color,
+        alpha,
+        no_heading,
     };
     let vertex3 = Vertex {
         position: [1.0, -1.0],
@@ -429,6 +1399,8 @@ pub fn plane_shape(angle: f32, offset: [f32; 2], color: [f32; 3]) -> [Vertex; 6]
         offset,
         tex_coords: [1.0, 0.0],
         color,
+        alpha,
+        no_heading,
     };
     let vertex4 = Vertex {
         position: [-1.0, -1.0],
@@ -436,7 +1408,248 @@ pub fn plane_shape(angle: f32, offset: [f32; 2], color: [f32; 3]) -> [Vertex; 6]
         offset,
         tex_coords: [0.0, 0.0],
         color,
+        alpha,
+        no_heading,
     };
 
     [vertex1, vertex2, vertex3, vertex4, vertex3, vertex1]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn altitude_ramp() {
+        assert_eq!(altitude_color(Some(0.0)), [1.0, 0.0, 0.0]);
+        //Below ground and above the top of the ramp are clamped
+        assert_eq!(altitude_color(Some(-50.0)), [1.0, 0.0, 0.0]);
+        assert_eq!(altitude_color(Some(MAX_RAMP_ALTITUDE)), [0.5, 0.0, 1.0]);
+        assert_eq!(altitude_color(Some(20_000.0)), [0.5, 0.0, 1.0]);
+        assert_eq!(altitude_color(None), UNKNOWN_ALTITUDE_COLOR);
+
+        //Halfway up is between green and cyan
+        let [r, g, b] = altitude_color(Some(MAX_RAMP_ALTITUDE / 2.0));
+        assert_eq!(r, 0.0);
+        assert_eq!(g, 1.0);
+        assert!(b > 0.0 && b < 1.0);
+    }
+
+    #[test]
+    fn plane_offsets_ignore_the_scale_factor() {
+        //The viewport is measured in logical pixels, so a plane in the middle of a high DPI
+        //window is drawn in the middle of the screen without scaling its offset
+        let (physical_width, physical_height) = (2560.0, 1440.0);
+        let scale_factor = 2.0;
+        let view = crate::map::TileViewBuilder::new()
+            .center(29.18796, -81.04923)
+            .zoom(12.0)
+            .scale_factor(scale_factor)
+            .build();
+        let viewport = view.get_world_viewport(
+            physical_width / scale_factor,
+            physical_height / scale_factor,
+        );
+        let center = (viewport.top_left + viewport.bottom_right) / 2.0;
+        assert!(world_x_to_window_x(center.x, &viewport).abs() < 1e-6);
+        assert!(world_y_to_window_y(center.y, &viewport).abs() < 1e-6);
+        //The corners stay on the edges of the screen
+        assert!((world_x_to_window_x(viewport.bottom_right.x, &viewport) - 1.0).abs() < 1e-6);
+        assert!((world_y_to_window_y(viewport.top_left.y, &viewport) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn trails_split_at_the_antimeridian() {
+        let viewport = crate::map::WorldViewport {
+            top_left: DVec2::ZERO,
+            bottom_right: DVec2::ONE,
+        };
+        //Flies west from Alaska across the antimeridian to Russia, then on
+        let trail: std::collections::VecDeque<[f32; 2]> =
+            [[-178.0, 60.0], [-179.5, 60.0], [179.5, 60.0], [178.0, 60.0]]
+                .into_iter()
+                .collect();
+        let mut vertices = Vec::new();
+        push_trail(&mut vertices, &trail, [1.0; 3], 1.0, &viewport);
+
+        //The segment across the antimeridian is left out
+        assert_eq!(vertices.len(), 4);
+        for segment in vertices.chunks(2) {
+            assert!((segment[0].position[0] - segment[1].position[0]).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn stale_planes_fade_then_hide() {
+        assert_eq!(staleness_alpha(Duration::ZERO), Some(1.0));
+        assert_eq!(staleness_alpha(STALE_PLANE_AGE), Some(1.0));
+        let halfway = (STALE_PLANE_AGE + HIDDEN_PLANE_AGE) / 2;
+        let alpha = staleness_alpha(halfway).unwrap();
+        assert!(
+            (alpha - (1.0 + MIN_STALE_ALPHA) / 2.0).abs() < 1e-6,
+            "{}",
+            alpha
+        );
+        let almost = HIDDEN_PLANE_AGE - Duration::from_millis(1);
+        assert!(staleness_alpha(almost).unwrap() >= MIN_STALE_ALPHA);
+        assert_eq!(staleness_alpha(HIDDEN_PLANE_AGE), None);
+    }
+
+    #[test]
+    fn airline_styles() {
+        assert_eq!(parse_hex_color("#ff0080"), Some([1.0, 0.0, 128.0 / 255.0]));
+        assert_eq!(parse_hex_color("ff0080"), None);
+        assert_eq!(parse_hex_color("#ff00"), None);
+        assert_eq!(parse_hex_color("#gg0000"), None);
+
+        let mut styles = AirlineStyles::default();
+        let jetblue = Airline::Dynamic(DynamicAirline {
+            callsign: "JBU".to_owned(),
+            name: "JetBlue".to_owned(),
+        });
+        let other = styles.style(&Airline::Unknown);
+        assert_eq!(styles.style(&jetblue), other);
+        assert_ne!(styles.style(&BasicAirline::Spirit.into()), other);
+
+        let style = AirlineStyle {
+            color: [0.0, 0.2, 0.5],
+            icon: Some(0),
+        };
+        styles.set(&jetblue, style);
+        assert_eq!(styles.style(&jetblue), style);
+        assert_eq!(styles.style(&Airline::Unknown), other);
+
+        //Neighboring runs with the same icon are drawn together
+        let mut batches = Vec::new();
+        push_batch(&mut batches, None, 0..6);
+        push_batch(&mut batches, None, 6..12);
+        push_batch(&mut batches, Some(0), 12..12);
+        push_batch(&mut batches, Some(0), 12..18);
+        push_batch(&mut batches, None, 18..24);
+        assert_eq!(
+            batches,
+            vec![(None, 0..12), (Some(0), 12..18), (None, 18..24)]
+        );
+    }
+
+    #[test]
+    fn legend_follows_filter() {
+        let mut styles = AirlineStyles::default();
+        let names = |rows: &[LegendRow]| -> Vec<String> {
+            rows.iter().map(|row| row.name.clone()).collect()
+        };
+        let rows = styles.legend(BasicAirline::All);
+        assert_eq!(rows.len(), 6);
+        assert!(rows.iter().all(|row| row.shown));
+        assert_eq!(
+            rows[0].color,
+            styles.style(&BasicAirline::American.into()).color
+        );
+        assert_eq!(names(&rows).last().unwrap(), "Other Airlines");
+
+        let jetblue = Airline::Dynamic(DynamicAirline {
+            callsign: "JBU".to_owned(),
+            name: "JetBlue".to_owned(),
+        });
+        styles.set(&jetblue, AirlineStyle::color([0.0, 0.2, 0.5]));
+        let rows = styles.legend(BasicAirline::Spirit);
+        assert_eq!(names(&rows)[5], "JetBlue");
+        assert_eq!(rows[5].filter, BasicAirline::Other);
+        let shown: Vec<bool> = rows.iter().map(|row| row.shown).collect();
+        assert_eq!(shown, [false, true, false, false, false, false, false]);
+
+        let rows = styles.legend(BasicAirline::Other);
+        assert!(rows[5].shown && rows[6].shown);
+        assert!(!rows[0].shown);
+    }
+
+    #[test]
+    fn clusters_share_a_cell() {
+        let positions = [
+            DVec2::new(5.0, 5.0),
+            DVec2::new(100.0, 100.0),
+            DVec2::new(35.0, 20.0),
+            DVec2::new(45.0, 5.0),
+            DVec2::new(-5.0, 5.0),
+            DVec2::new(39.0, 39.0),
+        ];
+        assert_eq!(cluster_positions(&positions, 40.0), vec![vec![0, 2, 5]]);
+        //Zooming in spreads the planes out, so the cluster breaks up
+        let zoomed: Vec<DVec2> = positions.iter().map(|p| *p * 4.0).collect();
+        assert!(cluster_positions(&zoomed, 40.0).is_empty());
+        //Cells are big enough for everything when zoomed far out
+        let groups = cluster_positions(&positions, 1000.0);
+        assert_eq!(groups, vec![vec![0, 1, 2, 3, 5]]);
+    }
+
+    #[test]
+    fn altitude_filter() {
+        let plane = |feet: Option<f32>, vertical_rate: Option<f32>| {
+            let mut plane = Plane::new(
+                0.0,
+                0.0,
+                None,
+                String::new(),
+                Airline::Unknown,
+                PlaneType::Unknown,
+            );
+            plane.baro_altitude = feet.map(|feet| util::feet_to_meters(feet as f64) as f32);
+            plane.vertical_rate = vertical_rate;
+            plane
+        };
+
+        //The default filter shows everything
+        let mut filter = AltitudeFilter::default();
+        assert!(filter.matches(&plane(None, None)));
+        assert!(filter.matches(&plane(Some(-100.0), Some(-20.0))));
+        assert!(filter.matches(&plane(Some(60_000.0), None)));
+        //Even without a band, unknown altitudes can be hidden
+        filter.show_unknown = false;
+        assert!(!filter.matches(&plane(None, None)));
+        assert!(!filter.matches(&plane(None, Some(5.0))));
+        assert!(filter.matches(&plane(Some(60_000.0), None)));
+        assert!(filter.matches(&plane(Some(-100.0), Some(-20.0))));
+        filter.show_unknown = true;
+
+        filter.min_feet = 10_000.0;
+        filter.max_feet = 20_000.0;
+        assert!(!filter.matches(&plane(Some(5_000.0), None)));
+        assert!(filter.matches(&plane(Some(15_000.0), None)));
+        assert!(!filter.matches(&plane(Some(25_000.0), None)));
+        assert!(filter.matches(&plane(None, None)));
+        filter.show_unknown = false;
+        assert!(!filter.matches(&plane(None, None)));
+
+        let mut filter = AltitudeFilter::default();
+        filter.trends[VerticalTrend::Level] = false;
+        assert!(filter.matches(&plane(Some(3_000.0), Some(5.0))));
+        assert!(!filter.matches(&plane(Some(3_000.0), Some(0.5))));
+        assert!(filter.matches(&plane(Some(3_000.0), Some(-5.0))));
+        filter.show_unknown = false;
+        assert!(!filter.matches(&plane(Some(3_000.0), None)));
+    }
+
+    #[test]
+    fn callsigns_avoid_planes_and_clusters() {
+        let label = |callsign: Option<&str>, y: f64| PlaneLabel {
+            callsign: callsign.map(str::to_owned),
+            position: DVec2::new(0.0, y),
+            size: 10.0,
+        };
+        //The plane without a callsign right below pushes the label to the right
+        let labels = [label(None, -20.0), label(Some("AAL1"), 0.0)];
+        assert_eq!(
+            place_callsigns(&labels, &[]),
+            [("AAL1", DVec2::new(21.0, 0.0))]
+        );
+
+        let cluster = PlaneCluster {
+            position: DVec2::new(30.0, 0.0),
+            count: 2,
+        };
+        assert_eq!(
+            place_callsigns(&labels, &[cluster]),
+            [("AAL1", DVec2::new(0.0, 14.0))]
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Keeps plane requests within the daily credits OpenSky gives anonymous users.
+//!
+//! The OpenSky client doesn't expose the rate limit headers, so credits are counted locally and
+//! the server running out is noticed from its `429 Too Many Requests` responses
+
+use std::time::{Duration, Instant};
+
+/// Credits OpenSky gives anonymous users per day
+pub const ANONYMOUS_DAILY_CREDITS: u32 = 400;
+
+/// Credits a request for every plane in the world costs
+pub const CREDITS_PER_REQUEST: u32 = 4;
+
+/// How long credits last before OpenSky replenishes them
+pub const QUOTA_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long to wait between requests once the credits have run out. Requests still go out this
+/// often to notice when the credits are replenished
+pub const EXHAUSTED_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Requests are spread over the rest of the window once fewer than this fraction of the credits
+/// are left
+const LOW_QUOTA_FRACTION: f64 = 0.25;
+
+/// How many credits are left, for showing to the user
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct QuotaStatus {
+    pub remaining: u32,
+    pub daily_credits: u32,
+    /// True once the credits have run out, either by our count or because OpenSky said so
+    pub exhausted: bool,
+}
+
+/// Counts the credits spent on plane requests and decides how long to wait between requests so
+/// that they last until they are replenished
+#[derive(Debug)]
+pub struct QuotaTracker {
+    daily_credits: u32,
+    /// When the first request counted against the current credits was made
+    window_start: Option<Instant>,
+    used: u32,
+    /// Set when OpenSky refuses a request for using too many credits
+    refused: bool,
+}
+
+impl QuotaTracker {
+    pub fn new(daily_credits: u32) -> Self {
+        Self {
+            daily_credits,
+            window_start: None,
+            used: 0,
+            refused: false,
+        }
+    }
+
+    /// Forgets the credits spent once they have been replenished
+    fn roll_window(&mut self, now: Instant) {
+        if let Some(start) = self.window_start {
+            if now.saturating_duration_since(start) >= QUOTA_WINDOW {
+                self.window_start = None;
+                self.used = 0;
+                self.refused = false;
+            }
+        }
+    }
+
+    /// Records a request that OpenSky answered
+    pub fn record_request(&mut self, now: Instant) {
+        self.roll_window(now);
+        self.window_start.get_or_insert(now);
+        self.used = self.used.saturating_add(CREDITS_PER_REQUEST);
+        self.refused = false;
+    }
+
+    /// Records OpenSky refusing a request because the credits have run out. Returns true if they
+    /// weren't already known to have run out
+    pub fn record_refused(&mut self, now: Instant) -> bool {
+        self.roll_window(now);
+        self.window_start.get_or_insert(now);
+        !std::mem::replace(&mut self.refused, true)
+    }
+
+    pub fn status(&mut self, now: Instant) -> QuotaStatus {
+        self.roll_window(now);
+        let remaining = match self.refused {
+            true => 0,
+            false => self.daily_credits.saturating_sub(self.used),
+        };
+        QuotaStatus {
+            remaining,
+            daily_credits: self.daily_credits,
+            exhausted: remaining < CREDITS_PER_REQUEST,
+        }
+    }
+
+    /// Returns how long to wait after a request before making the next one, given the interval
+    /// the user asked for.
+    ///
+    /// That is `interval` while plenty of credits are left. Once they run low the remaining
+    /// requests are spread out until the credits are replenished, and once they run out requests
+    /// are only made every [`EXHAUSTED_INTERVAL`]
+    pub fn interval(&mut self, interval: Duration, now: Instant) -> Duration {
+        let status = self.status(now);
+        let until_replenished = match self.window_start {
+            Some(start) => (start + QUOTA_WINDOW).saturating_duration_since(now),
+            None => QUOTA_WINDOW,
+        };
+        let requests_left = status.remaining / CREDITS_PER_REQUEST;
+        let throttled = if requests_left == 0 {
+            EXHAUSTED_INTERVAL.min(until_replenished)
+        } else if (status.remaining as f64) < self.daily_credits as f64 * LOW_QUOTA_FRACTION {
+            until_replenished / requests_left
+        } else {
+            Duration::ZERO
+        };
+        throttled.max(interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_slow_down_as_credits_run_low() {
+        let base = Duration::from_secs(10);
+        let start = Instant::now();
+        let mut quota = QuotaTracker::new(ANONYMOUS_DAILY_CREDITS);
+        assert_eq!(quota.interval(base, start), base);
+
+        let mut now = start;
+        let mut requests = 0;
+        while now < start + QUOTA_WINDOW {
+            assert!(!quota.status(now).exhausted);
+            quota.record_request(now);
+            requests += 1;
+            now += quota.interval(base, now);
+        }
+        //The last requests are spread out, so the credits last until they are replenished. The
+        //very last one is due just as they are
+        assert!(requests >= ANONYMOUS_DAILY_CREDITS / CREDITS_PER_REQUEST - 1);
+
+        let mut quota = QuotaTracker::new(ANONYMOUS_DAILY_CREDITS);
+        for _ in 0..ANONYMOUS_DAILY_CREDITS / CREDITS_PER_REQUEST {
+            quota.record_request(start);
+        }
+        assert_eq!(quota.status(start).remaining, 0);
+        assert_eq!(quota.interval(base, start), EXHAUSTED_INTERVAL);
+
+        let replenished = start + QUOTA_WINDOW;
+        assert_eq!(quota.status(replenished).remaining, ANONYMOUS_DAILY_CREDITS);
+        assert_eq!(quota.interval(base, replenished), base);
+    }
+
+    #[test]
+    fn refusal_backs_off_until_a_request_succeeds() {
+        let base = Duration::from_secs(10);
+        let now = Instant::now();
+        let mut quota = QuotaTracker::new(ANONYMOUS_DAILY_CREDITS);
+        assert!(quota.record_refused(now));
+        assert!(!quota.record_refused(now));
+        assert!(quota.status(now).exhausted);
+        assert_eq!(quota.interval(base, now), EXHAUSTED_INTERVAL);
+
+        let later = now + EXHAUSTED_INTERVAL;
+        quota.record_request(later);
+        assert_eq!(
+            quota.status(later).remaining,
+            ANONYMOUS_DAILY_CREDITS - CREDITS_PER_REQUEST
+        );
+        assert_eq!(quota.interval(base, later), base);
+    }
+}
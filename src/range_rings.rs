@@ -0,0 +1,189 @@
+//! Concentric rings at fixed distances around a point, such as an airport, for judging how far
+//! away planes are
+
+use conrod_core::{
+    widget::{Circle, Line, Text},
+    Colorable, Positionable, UiCell, Widget,
+};
+use serde::Deserialize;
+
+use crate::Units;
+
+/// The number of line segments used to draw each ring
+const RING_SEGMENTS: usize = 96;
+
+const RING_COLOR: conrod_core::Color = conrod_core::color::LIGHT_BLUE;
+
+/// How far apart the rings are and how many are drawn
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RangeRingSettings {
+    /// The distance between rings in the unit picked with the units button
+    pub spacing: f64,
+    pub count: u32,
+}
+
+impl Default for RangeRingSettings {
+    fn default() -> Self {
+        Self {
+            spacing: 10.0,
+            count: 5,
+        }
+    }
+}
+
+/// The center of the rings, in degrees of latitude and longitude
+#[derive(Debug, Default)]
+pub struct RangeRingLayer {
+    pub settings: RangeRingSettings,
+    center: Option<(f64, f64)>,
+    /// True while waiting for a click on the map to place the center
+    placing: bool,
+}
+
+impl RangeRingLayer {
+    pub fn new(settings: RangeRingSettings) -> Self {
+        Self {
+            settings,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.center.is_some()
+    }
+
+    /// Returns true if the next click on the map moves the center
+    pub fn is_placing(&self) -> bool {
+        self.placing
+    }
+
+    /// Shows the rings around `(lat, lng)` until the user clicks somewhere else on the map
+    pub fn enable_at(&mut self, lat: f64, lng: f64) {
+        self.center = Some((lat, lng));
+        self.placing = true;
+    }
+
+    /// Moves the center of the rings to `(lat, lng)`
+    pub fn set_center(&mut self, lat: f64, lng: f64) {
+        self.center = Some((lat, lng));
+        self.placing = false;
+    }
+
+    pub fn disable(&mut self) {
+        self.center = None;
+        self.placing = false;
+    }
+
+    /// Returns the radius in meters of each ring, smallest first
+    pub fn radii_meters(&self, units: Units) -> Vec<f64> {
+        let spacing = units.distance_to_meters(self.settings.spacing);
+        (1..=self.settings.count)
+            .map(|i| i as f64 * spacing)
+            .collect()
+    }
+
+    /// Draws each ring as a true circle on the ground, labeled with its distance at the top
+    pub fn draw(
+        &self,
+        view: &crate::map::TileView,
+        ids: &mut crate::Ids,
+        font: conrod_core::text::font::Id,
+        units: Units,
+        ui: &mut UiCell,
+    ) {
+        let center = match self.center {
+            Some(center) => center,
+            None => return,
+        };
+        let viewport = view.get_world_viewport(ui.win_w, ui.win_h);
+        let center_x = (viewport.top_left.x + viewport.bottom_right.x) / 2.0;
+        let to_pixel = |(lat, lng): (f64, f64), shift: f64| {
+            let world_x = crate::util::x_from_longitude(lng) + shift;
+            let world_y = crate::util::y_from_latitude(lat);
+            [
+                crate::world_x_to_pixel_x(world_x, &viewport, ui.win_w),
+                crate::world_y_to_pixel_y(world_y, &viewport, ui.win_h),
+            ]
+        };
+        //Use the copy of a point closest to the center of the screen
+        let nearest_shift = |lng: f64| {
+            let world_x = crate::util::x_from_longitude(lng);
+            (center_x - world_x).round()
+        };
+
+        let radii = self.radii_meters(units);
+        let mut lines = Vec::new();
+        let mut labels = Vec::new();
+        for (i, &radius) in radii.iter().enumerate() {
+            let points = crate::util::geodesic_circle(center, radius, RING_SEGMENTS);
+            for piece in crate::util::split_at_antimeridian(&points) {
+                //Shift the whole piece by the same amount so it stays connected
+                let shift = nearest_shift(piece[0].1);
+                let pixels: Vec<[f64; 2]> = piece.iter().map(|&p| to_pixel(p, shift)).collect();
+                lines.extend(pixels.windows(2).map(|pair| (pair[0], pair[1])));
+            }
+            let distance = self.settings.spacing * (i + 1) as f64;
+            labels.push((
+                format!("{} {}", distance, units.distance_suffix()),
+                to_pixel(points[0], nearest_shift(points[0].1)),
+            ));
+        }
+
+        ids.range_ring_lines
+            .resize(lines.len(), &mut ui.widget_id_generator());
+        for (i, (start, end)) in lines.into_iter().enumerate() {
+            Line::new(start, end)
+                .x_y(0.0, 0.0)
+                .color(RING_COLOR)
+                .thickness(1.5)
+                .set(ids.range_ring_lines[i], ui);
+        }
+
+        ids.range_ring_labels
+            .resize(labels.len(), &mut ui.widget_id_generator());
+        for (i, (text, [x, y])) in labels.iter().enumerate() {
+            Text::new(text)
+                .x_y(*x, *y + 10.0)
+                .color(RING_COLOR)
+                .font_size(12)
+                .font_id(font)
+                .set(ids.range_ring_labels[i], ui);
+        }
+
+        let [x, y] = to_pixel(center, nearest_shift(center.1));
+        Circle::fill(4.0)
+            .x_y(x, y)
+            .color(RING_COLOR)
+            .set(ids.range_ring_center, ui);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rings_follow_settings_and_unit() {
+        let mut rings = RangeRingLayer::new(RangeRingSettings {
+            spacing: 10.0,
+            count: 3,
+        });
+        assert!(!rings.is_enabled());
+
+        rings.enable_at(29.2, -81.0);
+        assert!(rings.is_enabled() && rings.is_placing());
+        rings.set_center(28.4, -81.3);
+        assert!(rings.is_enabled() && !rings.is_placing());
+
+        let radii = rings.radii_meters(Units::Nautical);
+        assert_eq!(radii.len(), 3);
+        assert!((radii[0] - 18_520.0).abs() < 1e-6);
+        assert!((radii[2] - 55_560.0).abs() < 1e-6);
+        let radii = rings.radii_meters(Units::Metric);
+        assert!((radii[1] - 20_000.0).abs() < 1e-6);
+
+        rings.disable();
+        assert!(!rings.is_enabled() && !rings.is_placing());
+    }
+}
@@ -0,0 +1,282 @@
+//! A polygon drawn around an area of the map, such as an airspace sector, so that the planes
+//! inside it stand out and can be counted
+
+use glam::DVec2;
+
+/// The corners of a polygon in degrees of latitude and longitude, whether it has been closed, and
+/// the corner being dragged, if any.
+///
+/// Corners are joined by straight lines on the map, so the polygon covers the same area at every
+/// zoom level
+#[derive(Debug, Default)]
+pub struct RegionPolygon {
+    points: Vec<(f64, f64)>,
+    closed: bool,
+    dragging: Option<usize>,
+}
+
+impl RegionPolygon {
+    /// The fewest corners a closed polygon can have
+    pub const MIN_CORNERS: usize = 3;
+
+    /// Adds a corner after the last one. Does nothing once the polygon is closed
+    pub fn push(&mut self, lat: f64, lng: f64) {
+        if !self.closed {
+            self.points.push((lat, lng));
+        }
+    }
+
+    /// Joins the last corner to the first, so that planes are filtered by the polygon. Returns
+    /// false if there aren't enough corners yet
+    pub fn close(&mut self) -> bool {
+        self.closed = self.points.len() >= Self::MIN_CORNERS;
+        self.closed
+    }
+
+    /// Returns true once the polygon has been closed and planes are filtered by it
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Removes the corner at `index`, returning it if it existed. The polygon is opened again if
+    /// too few corners are left
+    pub fn remove(&mut self, index: usize) -> Option<(f64, f64)> {
+        if index >= self.points.len() {
+            return None;
+        }
+        self.dragging = None;
+        let point = self.points.remove(index);
+        if self.points.len() < Self::MIN_CORNERS {
+            self.closed = false;
+        }
+        Some(point)
+    }
+
+    /// Removes every corner, so that a new polygon can be drawn
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.closed = false;
+        self.dragging = None;
+    }
+
+    pub fn points(&self) -> &[(f64, f64)] {
+        &self.points
+    }
+
+    /// Starts dragging the corner at `index`. Returns false if there is no such corner
+    pub fn start_drag(&mut self, index: usize) -> bool {
+        if index < self.points.len() {
+            self.dragging = Some(index);
+        }
+        self.dragging.is_some()
+    }
+
+    /// Moves the corner being dragged to `lat`, `lng`
+    pub fn drag_to(&mut self, lat: f64, lng: f64) {
+        if let Some(index) = self.dragging {
+            self.points[index] = (lat, lng);
+        }
+    }
+
+    /// Stops dragging, returning true if a corner was being dragged
+    pub fn end_drag(&mut self) -> bool {
+        self.dragging.take().is_some()
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.dragging.is_some()
+    }
+
+    /// Returns the index of the corner closest to `pixel`, if any are close enough to grab.
+    ///
+    /// `pixel` is measured from the top left of the window in logical pixels, like
+    /// [`crate::TileView::pixel_to_world`]
+    pub fn hit_test(
+        &self,
+        view: &crate::map::TileView,
+        pixel: DVec2,
+        screen_width: f64,
+        screen_height: f64,
+    ) -> Option<usize> {
+        crate::hit_test_points(&self.points, view, pixel, screen_width, screen_height)
+    }
+
+    /// Returns the corners in world coordinates. Each corner uses the copy of the world closest
+    /// to the previous one, so that polygons crossing the antimeridian stay in one piece
+    pub fn world_corners(&self) -> Vec<(f64, f64)> {
+        let mut previous_x: Option<f64> = None;
+        self.points
+            .iter()
+            .map(|&(lat, lng)| {
+                let mut world_x = crate::util::x_from_longitude(lng);
+                if let Some(previous_x) = previous_x {
+                    world_x += (previous_x - world_x).round();
+                }
+                previous_x = Some(world_x);
+                (world_x, crate::util::y_from_latitude(lat))
+            })
+            .collect()
+    }
+
+    /// Returns true if the world coordinates `world_x`, `world_y` are inside the polygon with
+    /// `corners` from [`RegionPolygon::world_corners`], checking the copy of the point closest to
+    /// the polygon
+    pub fn corners_contain(corners: &[(f64, f64)], world_x: f64, world_y: f64) -> bool {
+        if corners.is_empty() {
+            return false;
+        }
+        let center_x = corners.iter().map(|corner| corner.0).sum::<f64>() / corners.len() as f64;
+        let world_x = world_x + (center_x - world_x).round();
+        crate::util::point_in_polygon((world_x, world_y), corners)
+    }
+
+    /// Returns true if the polygon is closed and `lat`, `lng` is inside it
+    pub fn contains(&self, lat: f64, lng: f64) -> bool {
+        self.closed
+            && Self::corners_contain(
+                &self.world_corners(),
+                crate::util::x_from_longitude(lng),
+                crate::util::y_from_latitude(lat),
+            )
+    }
+}
+
+/// Useful functions for rendering a [`RegionPolygon`] on the map
+pub mod region_renderer {
+    use conrod_core::{
+        widget::{Circle, Line, Text},
+        Colorable, Positionable, UiCell, Widget,
+    };
+
+    use super::RegionPolygon;
+
+    const REGION_COLOR: conrod_core::Color = conrod_core::color::LIGHT_BLUE;
+
+    /// Draws the edges and corners of `region`, and once it is closed, `count` planes inside it
+    /// above its highest corner. The first corner is drawn larger while the polygon is open, as
+    /// clicking it closes the polygon
+    pub fn draw(
+        region: &RegionPolygon,
+        view: &crate::map::TileView,
+        ids: &mut crate::Ids,
+        font: conrod_core::text::font::Id,
+        count: Option<usize>,
+        ui: &mut UiCell,
+    ) {
+        let viewport = view.get_world_viewport(ui.win_w, ui.win_h);
+        let center_x = (viewport.top_left.x + viewport.bottom_right.x) / 2.0;
+        let corners = region.world_corners();
+
+        //Shift the whole polygon by the same amount so it stays connected, using the copy of the
+        //world closest to the center of the screen
+        let shift = match corners.first() {
+            Some(first) => (center_x - first.0).round(),
+            None => 0.0,
+        };
+        let pixels: Vec<[f64; 2]> = corners
+            .iter()
+            .map(|&(world_x, world_y)| {
+                [
+                    crate::world_x_to_pixel_x(world_x + shift, &viewport, ui.win_w),
+                    crate::world_y_to_pixel_y(world_y, &viewport, ui.win_h),
+                ]
+            })
+            .collect();
+
+        //========== Draw Edges ==========
+        let mut edges: Vec<([f64; 2], [f64; 2])> =
+            pixels.windows(2).map(|pair| (pair[0], pair[1])).collect();
+        if region.is_closed() {
+            edges.push((pixels[pixels.len() - 1], pixels[0]));
+        }
+        ids.region_lines
+            .resize(edges.len(), &mut ui.widget_id_generator());
+        for (i, (start, end)) in edges.into_iter().enumerate() {
+            Line::new(start, end)
+                .x_y(0.0, 0.0)
+                .color(REGION_COLOR)
+                .thickness(2.0)
+                .set(ids.region_lines[i], ui);
+        }
+
+        //========== Draw Corners ==========
+        ids.region_corners
+            .resize(pixels.len(), &mut ui.widget_id_generator());
+        for (i, &[x, y]) in pixels.iter().enumerate() {
+            let closes =
+                i == 0 && !region.is_closed() && pixels.len() >= RegionPolygon::MIN_CORNERS;
+            Circle::fill(if closes { 8.0 } else { 5.0 })
+                .x_y(x, y)
+                .color(REGION_COLOR)
+                .set(ids.region_corners[i], ui);
+        }
+
+        //========== Draw Plane Count ==========
+        let top = pixels.iter().max_by(|a, b| a[1].total_cmp(&b[1]));
+        if let (true, Some(count), Some(&[x, y])) = (region.is_closed(), count, top) {
+            let text = match count {
+                1 => String::from("1 plane"),
+                count => format!("{} planes", count),
+            };
+            Text::new(&text)
+                .x_y(x, y + 16.0)
+                .color(conrod_core::color::WHITE)
+                .font_size(14)
+                .font_id(font)
+                .set(ids.region_count, ui);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closing_editing_and_containment() {
+        let mut region = RegionPolygon::default();
+        region.push(28.0, -82.0);
+        region.push(28.0, -80.0);
+        assert!(!region.close());
+        region.push(30.0, -80.0);
+        region.push(30.0, -82.0);
+        assert!(!region.contains(29.0, -81.0));
+
+        assert!(region.close());
+        region.push(0.0, 0.0);
+        assert_eq!(region.points().len(), 4);
+        assert!(region.contains(29.0, -81.0));
+        assert!(!region.contains(31.0, -81.0));
+        //Planes on another copy of the world are still found
+        assert!(RegionPolygon::corners_contain(
+            &region.world_corners(),
+            crate::util::x_from_longitude(-81.0) + 1.0,
+            crate::util::y_from_latitude(29.0)
+        ));
+
+        assert!(region.start_drag(2));
+        region.drag_to(32.0, -80.0);
+        assert!(region.end_drag());
+        assert!(region.contains(31.0, -80.5));
+
+        assert_eq!(region.remove(0), Some((28.0, -82.0)));
+        assert!(region.is_closed());
+        region.remove(0);
+        assert!(!region.is_closed());
+        region.clear();
+        assert!(region.points().is_empty());
+    }
+
+    #[test]
+    fn polygons_across_the_antimeridian() {
+        let mut region = RegionPolygon::default();
+        region.push(-10.0, 170.0);
+        region.push(-10.0, -170.0);
+        region.push(10.0, -170.0);
+        region.push(10.0, 170.0);
+        assert!(region.close());
+        assert!(region.contains(0.0, 179.0));
+        assert!(region.contains(0.0, -179.0));
+        assert!(!region.contains(0.0, 0.0));
+    }
+}
@@ -1,30 +1,56 @@
-use std::sync::{Arc, Mutex};
-use tokio::{runtime::Runtime, time::Instant};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::SystemTime;
+use tokio::{
+    runtime::Runtime,
+    sync::Notify,
+    task::JoinHandle,
+    time::{Duration, Instant},
+};
 
 use opensky_api::errors::Error;
 
-use crate::{Airline, BasicAirline, DynamicAirline, PlaneType};
+use crate::{
+    Airline, BasicAirline, DynamicAirline, PlaneType, QuotaStatus, QuotaTracker,
+    ANONYMOUS_DAILY_CREDITS,
+};
+
+/// The callsign stored for planes OpenSky didn't send one for
+pub const UNKNOWN_CALLSIGN: &str = "Unknown";
 
 /// The body of a Plane
 ///
-/// Right Now we only care about Long and Lat;
-/// It will maybe be bigger depending on things we may like
-/// The planes to do.
+/// Holds the parts of the OpenSky state vector we use. Fields OpenSky may not know are `None`
 #[derive(Clone)]
 pub struct Plane {
     pub longitude: f32,
     pub latitude: f32,
-    pub track: f32,
+    /// The heading of the plane in radians, or `None` if OpenSky doesn't know it
+    pub track: Option<f32>,
     pub airline: Airline,
     pub plane_type: PlaneType,
     pub callsign: String,
+    /// Speed over ground in meters per second
+    pub velocity: Option<f32>,
+    /// Rate of climb in meters per second. Negative when descending
+    pub vertical_rate: Option<f32>,
+    /// Barometric altitude in meters
+    pub baro_altitude: Option<f32>,
+    /// Geometric (GPS) altitude in meters
+    pub geo_altitude: Option<f32>,
+    pub on_ground: bool,
+    /// When OpenSky last heard from the plane, in seconds since the unix epoch
+    pub last_contact: Option<u64>,
 }
 impl Plane {
-    ///Constructor on to make a new Plane
+    ///Constructor on to make a new Plane.
+    ///
+    /// The velocity, altitude and last contact fields are left unknown
     pub fn new(
         longitude: f32,
         latitude: f32,
-        track: f32,
+        track: Option<f32>,
         callsign: String,
         airline: Airline,
         plane_type: PlaneType,
@@ -36,8 +62,37 @@ impl Plane {
             airline,
             plane_type,
             callsign,
+            velocity: None,
+            vertical_rate: None,
+            baro_altitude: None,
+            geo_altitude: None,
+            on_ground: false,
+            last_contact: None,
+        }
+    }
+
+    /// Returns the altitude of this plane in meters, preferring barometric altitude because that
+    /// is what pilots fly by
+    pub fn altitude(&self) -> Option<f32> {
+        self.baro_altitude.or(self.geo_altitude)
+    }
+
+    /// Returns the callsign to label this plane with on the map, or `None` if OpenSky didn't send
+    /// one
+    pub fn label(&self) -> Option<&str> {
+        let callsign = self.callsign.trim();
+        match callsign {
+            "" | UNKNOWN_CALLSIGN => None,
+            callsign => Some(callsign),
         }
     }
+
+    /// Returns the compass heading of this plane in degrees, where 0 is north and 90 is east
+    pub fn heading_degrees(&self) -> Option<f32> {
+        //`track` is stored as a counterclockwise angle from east for the renderer
+        self.track
+            .map(|track| (90.0 - track.to_degrees()).rem_euclid(360.0))
+    }
 }
 
 pub struct PlaneBody {
@@ -63,77 +118,480 @@ impl PlaneBody {
     }
 }
 
+/// Returns the first plane in `bodies` with the callsign `callsign`
+pub fn find_plane<'a>(bodies: &'a [PlaneBody], callsign: &str) -> Option<&'a Plane> {
+    bodies
+        .iter()
+        .flat_map(|body| body.planes.iter())
+        .find(|plane| plane.callsign == callsign)
+}
+
+/// Maps the three letter ICAO prefix of a callsign to the airline that flies it.
+///
+/// The default registry knows about the airlines we have filters for, plus a table of common
+/// carriers. Callers can register more prefixes before handing the registry to
+/// [`PlaneRequester::with_airlines`].
+#[derive(Clone)]
+pub struct AirlineRegistry {
+    prefixes: HashMap<String, (Airline, PlaneType)>,
+}
+
+impl AirlineRegistry {
+    /// Creates a registry that knows about no airlines
+    pub fn empty() -> Self {
+        Self {
+            prefixes: HashMap::new(),
+        }
+    }
+
+    /// Associates callsigns starting with `prefix` with `airline`.
+    ///
+    /// Replaces any previous mapping for the same prefix, returning the airline and plane type it
+    /// was mapped to so that callers can warn about it
+    pub fn register(
+        &mut self,
+        prefix: &str,
+        airline: Airline,
+        plane_type: PlaneType,
+    ) -> Option<(Airline, PlaneType)> {
+        self.prefixes
+            .insert(prefix.to_ascii_uppercase(), (airline, plane_type))
+    }
+
+    /// Registers an airline that has no [`BasicAirline`] variant under `prefix`, returning the
+    /// mapping it replaced like [`AirlineRegistry::register`]
+    pub fn register_dynamic(
+        &mut self,
+        prefix: &str,
+        name: &str,
+        plane_type: PlaneType,
+    ) -> Option<(Airline, PlaneType)> {
+        let airline = Airline::Dynamic(DynamicAirline {
+            callsign: prefix.to_ascii_uppercase(),
+            name: name.to_owned(),
+        });
+        self.register(prefix, airline, plane_type)
+    }
+
+    /// Returns the airline and plane type for a flight with `callsign`, if the prefix is known
+    pub fn lookup(&self, callsign: &str) -> Option<&(Airline, PlaneType)> {
+        if callsign.len() <= 3 {
+            return None;
+        }
+        let prefix = callsign.get(0..3)?;
+        self.prefixes.get(prefix)
+    }
+
+    /// Returns the airline registered under the three letter `prefix`
+    pub fn airline_for_prefix(&self, prefix: &str) -> Option<&Airline> {
+        self.prefixes
+            .get(&prefix.to_ascii_uppercase())
+            .map(|(airline, _)| airline)
+    }
+}
+
+impl Default for AirlineRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        for (airline, plane_type) in get_dynamic_plane_types() {
+            let prefix = airline.callsign.clone();
+            registry.register(&prefix, Airline::Dynamic(airline), plane_type);
+        }
+
+        //Airlines we have filters for take priority over the dynamic table
+        let basic = [
+            ("NKS", BasicAirline::Spirit),
+            ("AAL", BasicAirline::American),
+            ("SWA", BasicAirline::Southwest),
+            ("UAL", BasicAirline::United),
+            ("DAL", BasicAirline::Delta),
+        ];
+        for (prefix, airline) in basic {
+            registry.register(prefix, airline.into(), PlaneType::Commercial);
+        }
+
+        registry
+    }
+}
+
+/// How long [`PlaneRequester::shutdown`] waits for the plane loop to stop
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long to wait before restarting the plane loop after it panics
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// The fastest OpenSky updates its data for anonymous users, which is how we request planes.
+/// Polling faster only returns the same data again while using up quota
+pub const MIN_ANONYMOUS_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often planes are requested unless [`PlaneRequester::set_refresh_interval`] is called
+pub const DEFAULT_REFRESH_INTERVAL: Duration = MIN_ANONYMOUS_REFRESH_INTERVAL;
+
+/// Controls how often the plane loop polls, shared between the loop and [`PlaneRequester`]
+struct RefreshControl {
+    interval_ms: AtomicU64,
+    paused: AtomicBool,
+    /// Wakes the plane loop when the interval or pause state changes
+    changed: Notify,
+}
+
+impl RefreshControl {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval_ms: AtomicU64::new(interval.as_millis() as u64),
+            paused: AtomicBool::new(false),
+            changed: Notify::new(),
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms.load(Ordering::Relaxed))
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+}
+
+/// The latest plane data, shared between the plane loop and the UI
+type PlaneStorage = Mutex<Arc<Vec<PlaneBody>>>;
+
+/// Locks `storage`, recovering from poisoning.
+///
+/// The lock is only held to swap or clone the `Arc`, which can't leave the data half updated, so
+/// the data behind a poisoned lock is still the last good data
+fn lock_planes(storage: &PlaneStorage) -> MutexGuard<'_, Arc<Vec<PlaneBody>>> {
+    storage.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Locks `quota`, recovering from poisoning. The tracker is only a few counters, so the worst a
+/// panic can do is miss counting one request
+fn lock_quota(quota: &Mutex<QuotaTracker>) -> MutexGuard<'_, QuotaTracker> {
+    quota.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
 ///Structure to save te Plane data we request
 ///We put it into an Arc and Mutex to make it easier to read.
 pub struct PlaneRequester {
-    planes_storage: Arc<Mutex<Arc<Vec<PlaneBody>>>>,
+    planes_storage: Arc<PlaneStorage>,
+    /// Tells the plane loop to stop
+    shutdown: Arc<Notify>,
+    /// The plane loop, or `None` once it has been shut down
+    task: Option<JoinHandle<()>>,
+    refresh: Arc<RefreshControl>,
+    /// Set while in offline mode. Shared with the tile pipelines
+    offline: Arc<AtomicBool>,
+    /// The OpenSky credits spent, which the plane loop slows down to stay within
+    quota: Arc<Mutex<QuotaTracker>>,
+    /// When OpenSky says the latest plane data is from, in seconds since the unix epoch, or 0
+    /// before any has arrived
+    data_time: Arc<AtomicU64>,
+    /// The plane data held on screen while frozen, see [`PlaneRequester::set_frozen`]
+    frozen: Option<FrozenPlanes>,
+}
+
+/// The plane data on screen when the picture was frozen
+struct FrozenPlanes {
+    planes: Arc<Vec<PlaneBody>>,
+    data_time: Option<SystemTime>,
+    /// When the picture was frozen
+    at: SystemTime,
 }
 
 impl PlaneRequester {
     ///Constructor on how to request the plane data.
     pub fn new(runtime: &Runtime) -> Self {
+        Self::with_airlines(runtime, AirlineRegistry::default(), Arc::default())
+    }
+
+    /// Creates a requester that classifies planes using the callsign prefixes in `airlines`.
+    ///
+    /// Nothing is requested while `offline` is set
+    pub fn with_airlines(
+        runtime: &Runtime,
+        airlines: AirlineRegistry,
+        offline: Arc<AtomicBool>,
+    ) -> Self {
         let planes_storage = Arc::new(Mutex::new(Arc::new(Vec::new())));
 
-        runtime.spawn(plane_data_loop(planes_storage.clone()));
+        let shutdown = Arc::new(Notify::new());
+        let refresh = Arc::new(RefreshControl::new(DEFAULT_REFRESH_INTERVAL));
+        let quota = Arc::new(Mutex::new(QuotaTracker::new(ANONYMOUS_DAILY_CREDITS)));
+        let data_time = Arc::new(AtomicU64::new(0));
+
+        let task = runtime.spawn(plane_data_loop(
+            planes_storage.clone(),
+            data_time.clone(),
+            Arc::new(airlines),
+            refresh.clone(),
+            offline.clone(),
+            quota.clone(),
+            shutdown.clone(),
+        ));
+
+        PlaneRequester {
+            planes_storage,
+            shutdown,
+            task: Some(task),
+            refresh,
+            offline,
+            quota,
+            data_time,
+            frozen: None,
+        }
+    }
+
+    /// Sets how long to wait between plane requests.
+    ///
+    /// Intervals shorter than OpenSky updates its data for anonymous users are raised to
+    /// [`MIN_ANONYMOUS_REFRESH_INTERVAL`], since we don't log in. Returns the interval in use
+    pub fn set_refresh_interval(&self, interval: Duration) -> Duration {
+        let interval = interval.max(MIN_ANONYMOUS_REFRESH_INTERVAL);
+        self.refresh
+            .interval_ms
+            .store(interval.as_millis() as u64, Ordering::Relaxed);
+        self.refresh.changed.notify_one();
+        interval
+    }
 
-        PlaneRequester { planes_storage }
+    /// Returns how long the plane loop waits between requests
+    pub fn refresh_interval(&self) -> Duration {
+        self.refresh.interval()
+    }
+
+    /// Stops or resumes requesting planes. The last plane data stays available while paused, and
+    /// planes are requested immediately when resuming if the data is out of date
+    pub fn set_paused(&self, paused: bool) {
+        if self.refresh.paused.swap(paused, Ordering::Relaxed) != paused {
+            self.refresh.changed.notify_one();
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.refresh.is_paused()
+    }
+
+    /// Turns offline mode on or off. This sets the flag shared with everything else that was given
+    /// the same `offline` flag, and makes the plane loop request planes immediately when going
+    /// back online if the data is out of date
+    pub fn set_offline(&self, offline: bool) {
+        if self.offline.swap(offline, Ordering::Relaxed) != offline {
+            self.refresh.changed.notify_one();
+        }
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline.load(Ordering::Relaxed)
+    }
+
+    /// Returns how many of today's OpenSky credits are left. Once they run low planes are
+    /// requested less often than [`PlaneRequester::refresh_interval`] so that they last the day
+    pub fn quota(&self) -> QuotaStatus {
+        lock_quota(&self.quota).status(Instant::now().into_std())
+    }
+
+    /// Returns how long the plane loop currently waits between requests, which is longer than
+    /// [`PlaneRequester::refresh_interval`] while the OpenSky credits are running low
+    pub fn throttled_interval(&self) -> Duration {
+        lock_quota(&self.quota).interval(self.refresh.interval(), Instant::now().into_std())
+    }
+
+    /// Stops the plane loop and waits for it to finish.
+    ///
+    /// The loop stops at its next await point, so it is never interrupted halfway through
+    /// publishing new plane data. Calling this more than once does nothing
+    pub fn shutdown(&mut self, runtime: &Runtime) {
+        let task = match self.task.take() {
+            Some(task) => task,
+            None => return,
+        };
+        // `notify_one` stores a permit, so the loop sees this even if it isn't waiting yet
+        self.shutdown.notify_one();
+        let result = runtime.block_on(async { tokio::time::timeout(SHUTDOWN_TIMEOUT, task).await });
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => println!("Plane loop failed while shutting down: {:?}", err),
+            Err(_) => println!(
+                "Plane loop didn't stop within {:?}, abandoning it",
+                SHUTDOWN_TIMEOUT
+            ),
+        }
     }
 
     ///Returns a clone of the Mutex list of planes.
+    ///
+    /// While frozen this is the data from when the picture was frozen
     pub fn planes_storage(&self) -> Arc<Vec<PlaneBody>> {
-        let guard = self.planes_storage.lock().unwrap();
-        guard.clone()
+        match &self.frozen {
+            Some(frozen) => frozen.planes.clone(),
+            None => lock_planes(&self.planes_storage).clone(),
+        }
+    }
+
+    /// Returns the latest planes, even while the picture is frozen
+    pub fn live_planes(&self) -> Arc<Vec<PlaneBody>> {
+        lock_planes(&self.planes_storage).clone()
+    }
+
+    /// Holds the planes still on screen, or lets them update again.
+    ///
+    /// Planes keep being requested while frozen, so the latest data shows up as soon as the
+    /// picture is unfrozen
+    pub fn set_frozen(&mut self, frozen: bool) {
+        if frozen == self.frozen.is_some() {
+            return;
+        }
+        self.frozen = match frozen {
+            true => Some(FrozenPlanes {
+                planes: self.planes_storage(),
+                data_time: self.data_time(),
+                at: SystemTime::now(),
+            }),
+            false => None,
+        };
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.is_some()
+    }
+
+    /// Returns when the picture was frozen, or `None` while planes are updating. Anything that
+    /// changes with time, such as fading out planes that haven't been heard from, should treat
+    /// this as the current time
+    pub fn frozen_at(&self) -> Option<SystemTime> {
+        self.frozen.as_ref().map(|frozen| frozen.at)
+    }
+
+    /// Returns when OpenSky says the latest plane data is from, or `None` if none has arrived.
+    ///
+    /// This stops advancing if OpenSky keeps returning the same data, not just when requests fail.
+    /// While frozen this is when the data on screen is from
+    pub fn data_time(&self) -> Option<SystemTime> {
+        if let Some(frozen) = &self.frozen {
+            return frozen.data_time;
+        }
+        match self.data_time.load(Ordering::Relaxed) {
+            0 => None,
+            secs => Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+        }
     }
 }
 
-/// Loop to get plane data.
-/// Some math had to be done for the sleeping time.
+/// Loop to get plane data, until `shutdown` is notified.
 ///
-/// The OpenSky Api gets data every 5-6 seconds,
-/// the function must also follow that running time.
+/// If requesting planes panics, the panic is logged and requesting restarts after a short delay,
+/// so that a bug in one response doesn't stop planes from updating for the rest of the session
+async fn plane_data_loop(
+    list_of_planes: Arc<PlaneStorage>,
+    data_time: Arc<AtomicU64>,
+    airlines: Arc<AirlineRegistry>,
+    refresh: Arc<RefreshControl>,
+    offline: Arc<AtomicBool>,
+    quota: Arc<Mutex<QuotaTracker>>,
+    shutdown: Arc<Notify>,
+) {
+    loop {
+        let mut task = tokio::spawn(request_planes_forever(
+            list_of_planes.clone(),
+            data_time.clone(),
+            airlines.clone(),
+            refresh.clone(),
+            offline.clone(),
+            quota.clone(),
+        ));
+        tokio::select! {
+            _ = shutdown.notified() => {
+                task.abort();
+                return;
+            }
+            result = &mut task => match result {
+                Err(err) if err.is_panic() => {
+                    println!("Plane loop panicked, restarting in {:?}: {:?}", RESTART_DELAY, err);
+                }
+                _ => return,
+            },
+        }
+        tokio::select! {
+            _ = shutdown.notified() => return,
+            _ = tokio::time::sleep(RESTART_DELAY) => {}
+        }
+    }
+}
+
+/// The body of [`plane_data_loop`]. Never returns.
 ///
-async fn plane_data_loop(list_of_planes: Arc<Mutex<Arc<Vec<PlaneBody>>>>) {
+/// Requests planes once per refresh interval, measured from the start of each request so that slow
+/// responses don't push the schedule back. Nothing is requested while paused or offline, and the
+/// interval grows as the OpenSky credits in `quota` run low
+async fn request_planes_forever(
+    list_of_planes: Arc<PlaneStorage>,
+    data_time: Arc<AtomicU64>,
+    airlines: Arc<AirlineRegistry>,
+    refresh: Arc<RefreshControl>,
+    offline: Arc<AtomicBool>,
+    quota: Arc<Mutex<QuotaTracker>>,
+) {
+    let mut last_request: Option<Instant> = None;
     loop {
-        let start = Instant::now();
+        // Sleep until the next request is due, waking early if the settings change
+        loop {
+            if refresh.is_paused() || offline.load(Ordering::Relaxed) {
+                refresh.changed.notified().await;
+                continue;
+            }
+            let due = match last_request {
+                Some(last) => {
+                    last + lock_quota(&quota).interval(refresh.interval(), last.into_std())
+                }
+                None => break,
+            };
+            tokio::select! {
+                _ = tokio::time::sleep_until(due) => break,
+                _ = refresh.changed.notified() => {}
+            }
+        }
 
-        match request_plane_data().await {
-            Ok(plane_data) => {
-                let mut guard = list_of_planes.lock().unwrap();
-                *guard = Arc::new(plane_data);
+        let now = Instant::now();
+        last_request = Some(now);
+        let result = request_plane_data(&airlines).await;
+        let mut tracker = lock_quota(&quota);
+        match result {
+            Ok((plane_data, time)) => {
+                tracker.record_request(now.into_std());
+                *lock_planes(&list_of_planes) = Arc::new(plane_data);
+                data_time.store(time, Ordering::Relaxed);
+            }
+            Err(Error::Http(status)) if status.as_u16() == 429 => {
+                if tracker.record_refused(now.into_std()) {
+                    println!(
+                        "OpenSky plane quota used up, requesting planes every {:?} until it is replenished",
+                        tracker.interval(refresh.interval(), now.into_std())
+                    );
+                }
+            }
+            //The request never reached OpenSky, so it didn't cost anything
+            Err(Error::Reqwest(error)) => {
+                println!("Error at getting plane data: {:?}", error)
             }
             Err(error) => {
+                tracker.record_request(now.into_std());
                 println!("Error at getting plane data: {:?}", error)
             }
         }
-
-        if let Ok(plane_data) = request_plane_data().await {
-            let mut guard = list_of_planes.lock().unwrap();
-            *guard = Arc::new(plane_data);
-        };
-
-        let end = Instant::now();
-
-        let time_interval = tokio::time::Duration::from_secs(5);
-        let seconds = end - start;
-
-        let sleep_time = if seconds <= tokio::time::Duration::from_secs(5) {
-            time_interval - seconds
-        } else {
-            tokio::time::Duration::from_secs(0)
-        };
-
-        tokio::time::sleep(sleep_time).await;
     }
 }
 
 /// In here we call the OpenSky Api to get the data from planes.
 ///
 /// Request the plane data and makes it into a Vec.
-async fn request_plane_data() -> Result<Vec<PlaneBody>, Error> {
+/// Planes are grouped into one [`PlaneBody`] per airline, with the airlines that have filters
+/// first, then the other registered airlines sorted by callsign prefix, and everything
+/// unrecognized at the end. Returned with the time OpenSky says the data is
+/// from, in seconds since the unix epoch
+async fn request_plane_data(airlines: &AirlineRegistry) -> Result<(Vec<PlaneBody>, u64), Error> {
     let open_sky = opensky_api::OpenSkyApi::new();
 
     let state_request = open_sky.get_states();
-    let mut list_of_planes: Vec<PlaneBody> = Vec::new();
 
     let mut spirit_planes: PlaneBody = PlaneBody::empty_commercial(BasicAirline::Spirit.into());
     let mut american_al_planes: PlaneBody =
@@ -143,87 +601,78 @@ async fn request_plane_data() -> Result<Vec<PlaneBody>, Error> {
     let mut united_al_planes: PlaneBody = PlaneBody::empty_commercial(BasicAirline::United.into());
     let mut other_planes: PlaneBody = PlaneBody::empty_commercial(Airline::Unknown);
 
-    let dynamic_plane_types = get_dynamic_plane_types();
+    //Registered airlines without a filter of their own are grouped by callsign prefix. Kept sorted
+    //so that the groups are in the same order on every refresh
+    let mut dynamic_planes: BTreeMap<String, PlaneBody> = BTreeMap::new();
 
     let open_sky = state_request.send().await?;
+    let time = open_sky.time;
     for state in open_sky.states {
         let longitude = state.longitude;
         let latitude = state.latitude;
-        let track = (-state.true_track.unwrap_or(0.0) + 90.0) * (std::f32::consts::PI / 180.0);
+        let track = state
+            .true_track
+            .map(|track| (-track + 90.0) * (std::f32::consts::PI / 180.0));
 
         if !state.on_ground {
             if let Some(longitude) = longitude {
                 let latitude = latitude.unwrap();
-                let mut maybe_airline = None;
-                let mut maybe_callsign = None;
-                let mut maybe_plane_type = None;
-
-                if let Some(callsign) = &state.callsign {
-                    maybe_callsign = Some(callsign.clone());
-                    if callsign.len() > 3 {
-                        let callsign_header = &callsign[0..3];
-                        match callsign_header {
-                            "NKS" => maybe_airline = Some(BasicAirline::Spirit.into()),
-                            "AAL" => maybe_airline = Some(BasicAirline::American.into()),
-                            "SWA" => maybe_airline = Some(BasicAirline::Southwest.into()),
-                            "UAL" => maybe_airline = Some(BasicAirline::United.into()),
-                            "DAL" => maybe_airline = Some(BasicAirline::Delta.into()),
-                            _ => {
-                                //Try to match dynamic airlines
-                                for (dyn_airline, dyn_plane_type) in &dynamic_plane_types {
-                                    // println!(
-                                    //     "Matched callsign: {} - {}",
-                                    //     callsign_header, callsign
-                                    // );
-                                    if dyn_airline.callsign == callsign_header {
-                                        maybe_airline = Some(Airline::Dynamic(dyn_airline.clone()));
-                                        maybe_plane_type = Some(*dyn_plane_type);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
 
-                let plane_type = match (maybe_plane_type, &maybe_airline) {
-                    (Some(plane_type), _) => plane_type,
-                    (_, Some(Airline::Basic(_))) => PlaneType::Commercial,
-                    _ => PlaneType::Unknown,
+                let entry = state
+                    .callsign
+                    .as_deref()
+                    .and_then(|callsign| airlines.lookup(callsign));
+
+                let (airline, plane_type) = match entry {
+                    Some((airline, plane_type)) => (airline.clone(), *plane_type),
+                    None => (Airline::Unknown, PlaneType::Unknown),
                 };
+
                 let plane = Plane {
                     longitude,
                     latitude,
                     track,
-                    airline: maybe_airline.clone().unwrap_or(Airline::Unknown),
-                    //Default to commercial because we only set it in the case of spirit, american etc.
+                    airline: airline.clone(),
                     plane_type,
-                    callsign: maybe_callsign.unwrap_or("Unknown".to_owned()),
+                    callsign: state
+                        .callsign
+                        .unwrap_or_else(|| UNKNOWN_CALLSIGN.to_owned()),
+                    velocity: state.velocity,
+                    vertical_rate: state.vertical_rate,
+                    baro_altitude: state.baro_altitude,
+                    geo_altitude: state.geo_altitude,
+                    on_ground: state.on_ground,
+                    last_contact: Some(state.last_contact),
                 };
 
-                match maybe_airline {
-                    Some(Airline::Basic(BasicAirline::Spirit)) => spirit_planes.planes.push(plane),
-                    Some(Airline::Basic(BasicAirline::American)) => {
-                        american_al_planes.planes.push(plane)
-                    }
-                    Some(Airline::Basic(BasicAirline::Southwest)) => {
-                        southwest_planes.planes.push(plane)
-                    }
-                    Some(Airline::Basic(BasicAirline::United)) => {
-                        united_al_planes.planes.push(plane)
-                    }
+                match airline {
+                    Airline::Basic(BasicAirline::Spirit) => spirit_planes.planes.push(plane),
+                    Airline::Basic(BasicAirline::American) => american_al_planes.planes.push(plane),
+                    Airline::Basic(BasicAirline::Southwest) => southwest_planes.planes.push(plane),
+                    Airline::Basic(BasicAirline::United) => united_al_planes.planes.push(plane),
+                    Airline::Dynamic(dynamic) => dynamic_planes
+                        .entry(dynamic.callsign.clone())
+                        .or_insert_with(|| {
+                            PlaneBody::new(Vec::new(), Airline::Dynamic(dynamic), plane_type)
+                        })
+                        .planes
+                        .push(plane),
                     _ => other_planes.planes.push(plane),
                 }
             }
         }
     }
 
-    list_of_planes.push(spirit_planes);
-    list_of_planes.push(american_al_planes);
-    list_of_planes.push(southwest_planes);
-    list_of_planes.push(united_al_planes);
+    let mut list_of_planes: Vec<PlaneBody> = vec![
+        spirit_planes,
+        american_al_planes,
+        southwest_planes,
+        united_al_planes,
+    ];
+    list_of_planes.extend(dynamic_planes.into_values());
     list_of_planes.push(other_planes);
 
-    Ok(list_of_planes)
+    Ok((list_of_planes, time))
 }
 
 fn get_dynamic_plane_types() -> Vec<(DynamicAirline, PlaneType)> {
@@ -296,3 +745,141 @@ RPA - Republic Airlines - airline
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A requester without a plane loop, reading planes from `planes_storage`
+    fn test_requester(
+        planes_storage: Arc<PlaneStorage>,
+        data_time: Arc<AtomicU64>,
+    ) -> PlaneRequester {
+        PlaneRequester {
+            planes_storage,
+            shutdown: Arc::new(Notify::new()),
+            task: None,
+            refresh: Arc::new(RefreshControl::new(DEFAULT_REFRESH_INTERVAL)),
+            offline: Arc::default(),
+            quota: Arc::new(Mutex::new(QuotaTracker::new(ANONYMOUS_DAILY_CREDITS))),
+            data_time,
+            frozen: None,
+        }
+    }
+
+    #[test]
+    fn registered_prefixes_are_looked_up_by_callsign() {
+        let mut registry = AirlineRegistry::empty();
+        assert!(registry.lookup("JBU123").is_none());
+
+        assert!(registry
+            .register_dynamic("jbu", "JetBlue", PlaneType::Commercial)
+            .is_none());
+        let (airline, plane_type) = registry.lookup("JBU123").unwrap();
+        assert_eq!(airline.to_str(), "JetBlue");
+        assert_eq!(*plane_type, PlaneType::Commercial);
+        assert!(registry.airline_for_prefix("jbu").is_some());
+
+        //A prefix alone isn't a flight
+        assert!(registry.lookup("JBU").is_none());
+        assert!(registry.lookup("JB").is_none());
+        assert!(registry.lookup("AAL100").is_none());
+
+        //Registering the same prefix again replaces it and returns what it shadowed
+        let (shadowed, _) = registry
+            .register("JBU", BasicAirline::Spirit.into(), PlaneType::Cargo)
+            .unwrap();
+        assert_eq!(shadowed.to_str(), "JetBlue");
+        assert!(registry.lookup("JBU123").unwrap().0 == BasicAirline::Spirit.into());
+        assert_eq!(registry.lookup("JBU123").unwrap().1, PlaneType::Cargo);
+    }
+
+    #[test]
+    fn default_registry_prefers_filtered_airlines() {
+        let registry = AirlineRegistry::default();
+        assert!(registry.lookup("AAL100").unwrap().0 == BasicAirline::American.into());
+        assert!(registry.lookup("DAL5").unwrap().0 == BasicAirline::Delta.into());
+        let (atlas, plane_type) = registry.lookup("GTI8").unwrap();
+        assert_eq!(atlas.to_str(), "Atlas Air");
+        assert_eq!(*plane_type, PlaneType::Commercial);
+    }
+
+    #[test]
+    fn poisoned_storage_keeps_last_good_data() {
+        let planes = vec![PlaneBody::empty_commercial(Airline::Unknown)];
+        let storage = Arc::new(Mutex::new(Arc::new(planes)));
+        let requester = test_requester(storage.clone(), Arc::default());
+
+        //Panic while holding the lock, as a bug in the plane loop would
+        let result = std::thread::spawn(move || {
+            let _guard = storage.lock().unwrap();
+            panic!("Simulated plane loop panic");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(requester.planes_storage.is_poisoned());
+
+        assert_eq!(requester.planes_storage().len(), 1);
+    }
+
+    #[test]
+    fn refresh_interval_respects_minimum() {
+        let requester = test_requester(Arc::new(Mutex::new(Arc::new(Vec::new()))), Arc::default());
+        assert_eq!(requester.refresh_interval(), DEFAULT_REFRESH_INTERVAL);
+
+        let interval = requester.set_refresh_interval(Duration::from_secs(1));
+        assert_eq!(interval, MIN_ANONYMOUS_REFRESH_INTERVAL);
+        assert_eq!(requester.refresh_interval(), MIN_ANONYMOUS_REFRESH_INTERVAL);
+
+        requester.set_refresh_interval(Duration::from_secs(30));
+        assert_eq!(requester.refresh_interval(), Duration::from_secs(30));
+
+        assert!(!requester.is_paused());
+        requester.set_paused(true);
+        assert!(requester.is_paused());
+        assert_eq!(requester.quota().remaining, ANONYMOUS_DAILY_CREDITS);
+        assert_eq!(requester.data_time(), None);
+    }
+
+    #[test]
+    fn frozen_planes_hold_still() {
+        let storage = Arc::new(Mutex::new(Arc::new(Vec::new())));
+        let data_time = Arc::new(AtomicU64::new(100));
+        let mut requester = test_requester(storage.clone(), data_time.clone());
+        requester.set_frozen(true);
+        assert!(requester.frozen_at().is_some());
+
+        //New data arrives while frozen
+        *storage.lock().unwrap() = Arc::new(vec![PlaneBody::empty_commercial(Airline::Unknown)]);
+        data_time.store(110, Ordering::Relaxed);
+        assert!(requester.planes_storage().is_empty());
+        let old_time = SystemTime::UNIX_EPOCH + Duration::from_secs(100);
+        assert_eq!(requester.data_time(), Some(old_time));
+
+        requester.set_frozen(false);
+        assert_eq!(requester.frozen_at(), None);
+        assert_eq!(requester.planes_storage().len(), 1);
+        assert_eq!(
+            requester.data_time(),
+            Some(old_time + Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn missing_callsigns_have_no_label() {
+        let plane = |callsign: &str| {
+            Plane::new(
+                0.0,
+                0.0,
+                None,
+                callsign.to_owned(),
+                Airline::Unknown,
+                PlaneType::Unknown,
+            )
+        };
+        //OpenSky pads callsigns to eight characters
+        assert_eq!(plane("AAL123  ").label(), Some("AAL123"));
+        assert_eq!(plane("        ").label(), None);
+        assert_eq!(plane(UNKNOWN_CALLSIGN).label(), None);
+    }
+}
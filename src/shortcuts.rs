@@ -0,0 +1,203 @@
+//! The keyboard shortcuts, kept in one place so that the help overlay always lists what the keys
+//! actually do
+
+use glium::glutin::event::{ModifiersState, VirtualKeyCode};
+
+/// Something the user can do with a keyboard shortcut
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Action {
+    ToggleWeather,
+    ToggleDebug,
+    ToggleAirports,
+    ToggleHelp,
+    ToggleMinimap,
+    ToggleLegend,
+    ToggleClustering,
+    CyclePalette,
+    ToggleOffline,
+    Screenshot,
+    ToggleFullscreen,
+    ToggleTrace,
+    ToggleTrackRecording,
+    ExportTrack,
+    ToggleRegion,
+    ClearRegion,
+    ToggleTrackUp,
+    Quit,
+}
+
+impl Action {
+    /// What the action does, as shown in the help overlay
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::ToggleWeather => "Toggle weather",
+            Action::ToggleDebug => "Toggle the debug menu",
+            Action::ToggleAirports => "Toggle airports",
+            Action::ToggleHelp => "Show or hide this help",
+            Action::ToggleMinimap => "Toggle the minimap",
+            Action::ToggleLegend => "Toggle the airline legend",
+            Action::ToggleClustering => "Toggle grouping nearby planes",
+            Action::CyclePalette => "Switch the colorblind palette",
+            Action::ToggleOffline => "Toggle offline mode",
+            Action::Screenshot => "Save a screenshot",
+            Action::ToggleFullscreen => "Toggle fullscreen",
+            Action::ToggleTrace => "Start or stop recording a profiling trace",
+            Action::ToggleTrackRecording => "Start or stop recording the clicked plane's track",
+            Action::ExportTrack => "Export the recorded track as GPX and KML",
+            Action::ToggleRegion => "Start or stop drawing and using the region",
+            Action::ClearRegion => "Clear the region",
+            Action::ToggleTrackUp => "Switch between north-up and track-up",
+            Action::Quit => "Quit",
+        }
+    }
+}
+
+/// A key, and whether shift must be held while pressing it
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct KeyCombo {
+    pub key: VirtualKeyCode,
+    pub shift: bool,
+}
+
+impl KeyCombo {
+    pub const fn key(key: VirtualKeyCode) -> Self {
+        Self { key, shift: false }
+    }
+
+    pub const fn shift(key: VirtualKeyCode) -> Self {
+        Self { key, shift: true }
+    }
+
+    /// How the combo is written in the help overlay
+    pub fn name(self) -> String {
+        match (self.key, self.shift) {
+            (VirtualKeyCode::Slash, true) => String::from("?"),
+            (VirtualKeyCode::Escape, false) => String::from("Esc"),
+            (key, false) => format!("{:?}", key),
+            (key, true) => format!("Shift+{:?}", key),
+        }
+    }
+
+    /// Returns true if pressing this combo types a character, so that it should be ignored while
+    /// a text box has keyboard focus
+    fn types_text(self) -> bool {
+        use VirtualKeyCode::*;
+        !matches!(
+            self.key,
+            F1 | F2 | F3 | F4 | F5 | F6 | F7 | F8 | F9 | F10 | F11 | F12 | Escape
+        )
+    }
+}
+
+/// Every keyboard shortcut. An action may have more than one combo
+pub fn bindings() -> Vec<(KeyCombo, Action)> {
+    use VirtualKeyCode::*;
+    vec![
+        (KeyCombo::key(W), Action::ToggleWeather),
+        (KeyCombo::key(D), Action::ToggleDebug),
+        (KeyCombo::key(A), Action::ToggleAirports),
+        (KeyCombo::key(F1), Action::ToggleHelp),
+        (KeyCombo::shift(Slash), Action::ToggleHelp),
+        (KeyCombo::key(H), Action::ToggleHelp),
+        (KeyCombo::key(M), Action::ToggleMinimap),
+        (KeyCombo::key(L), Action::ToggleLegend),
+        (KeyCombo::key(C), Action::ToggleClustering),
+        (KeyCombo::key(P), Action::CyclePalette),
+        (KeyCombo::key(O), Action::ToggleOffline),
+        (KeyCombo::key(R), Action::ToggleTrackRecording),
+        (KeyCombo::key(E), Action::ExportTrack),
+        (KeyCombo::key(G), Action::ToggleRegion),
+        (KeyCombo::shift(G), Action::ClearRegion),
+        (KeyCombo::key(T), Action::ToggleTrackUp),
+        (KeyCombo::key(F10), Action::Screenshot),
+        (KeyCombo::key(F11), Action::ToggleFullscreen),
+        (KeyCombo::key(F12), Action::ToggleTrace),
+        (KeyCombo::key(Escape), Action::Quit),
+    ]
+}
+
+/// The mouse controls, listed in the help overlay after the keyboard shortcuts
+pub const MOUSE_CONTROLS: &[(&str, &str)] = &[
+    ("Drag", "Move the map"),
+    ("Scroll", "Zoom in and out"),
+    ("Click", "Show the details of a plane"),
+    ("Right click", "Delete a waypoint"),
+    ("Click minimap", "Center the map there"),
+    ("Click legend", "Show only that airline"),
+];
+
+/// Returns the action bound to `key` while `modifiers` are held.
+///
+/// `typing` should be true while a text box has keyboard focus, so that typing into it doesn't
+/// trigger shortcuts
+pub fn action_for(key: VirtualKeyCode, modifiers: ModifiersState, typing: bool) -> Option<Action> {
+    let pressed = KeyCombo {
+        key,
+        shift: modifiers.shift(),
+    };
+    if typing && pressed.types_text() {
+        return None;
+    }
+    bindings()
+        .into_iter()
+        .find(|(combo, _)| *combo == pressed)
+        .map(|(_, action)| action)
+}
+
+/// Returns the text of the help overlay, with one line per action followed by the mouse controls
+pub fn help_text() -> String {
+    let mut actions: Vec<(Vec<String>, Action)> = Vec::new();
+    for (combo, action) in bindings() {
+        match actions.iter_mut().find(|(_, other)| *other == action) {
+            Some((combos, _)) => combos.push(combo.name()),
+            None => actions.push((vec![combo.name()], action)),
+        }
+    }
+
+    let mut text = String::from("Keyboard shortcuts\n");
+    for (combos, action) in actions {
+        text += &format!("\n{:>11}  {}", combos.join(", "), action.description());
+    }
+    text += "\n\nMouse\n";
+    for (control, description) in MOUSE_CONTROLS {
+        text += &format!("\n{:>11}  {}", control, description);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortcuts_match_help() {
+        let none = ModifiersState::empty();
+        assert_eq!(
+            action_for(VirtualKeyCode::W, none, false),
+            Some(Action::ToggleWeather)
+        );
+        assert_eq!(
+            action_for(VirtualKeyCode::Slash, ModifiersState::SHIFT, false),
+            Some(Action::ToggleHelp)
+        );
+        assert_eq!(action_for(VirtualKeyCode::Slash, none, false), None);
+        assert_eq!(
+            action_for(VirtualKeyCode::G, ModifiersState::SHIFT, false),
+            Some(Action::ClearRegion)
+        );
+
+        //Letters go to the text box while typing, but function keys still work
+        assert_eq!(action_for(VirtualKeyCode::W, none, true), None);
+        assert_eq!(
+            action_for(VirtualKeyCode::F1, none, true),
+            Some(Action::ToggleHelp)
+        );
+
+        let help = help_text();
+        for (combo, action) in bindings() {
+            assert!(help.contains(&combo.name()));
+            assert!(help.contains(action.description()));
+        }
+        assert!(help.contains("F1, ?, H"));
+    }
+}
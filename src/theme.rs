@@ -0,0 +1,72 @@
+//! Color schemes for the map, so that it can be used at night without ruining the pilot's night
+//! vision
+
+use conrod_core::{color, Color};
+use serde::{Deserialize, Serialize};
+
+use crate::{tile::TileKind, GridStyle};
+
+/// The colors used to draw the map
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Theme {
+    #[default]
+    Day,
+    Night,
+}
+
+impl Theme {
+    /// Returns the other theme
+    pub fn toggle(self) -> Self {
+        match self {
+            Theme::Day => Theme::Night,
+            Theme::Night => Theme::Day,
+        }
+    }
+
+    /// A short human readable name for this theme
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Day => "Day",
+            Theme::Night => "Night",
+        }
+    }
+
+    /// The color the window is cleared to before anything is drawn, as rgba
+    pub fn clear_color(self) -> (f32, f32, f32, f32) {
+        match self {
+            Theme::Day => (0.21, 0.32, 0.4, 1.0),
+            Theme::Night => (0.05, 0.06, 0.08, 1.0),
+        }
+    }
+
+    /// How the lines of latitude and longitude are drawn
+    pub fn grid_style(self) -> GridStyle {
+        match self {
+            Theme::Day => GridStyle::default(),
+            Theme::Night => GridStyle {
+                line_color: color::LIGHT_GREY,
+                label_color: self.label_color(),
+                ..GridStyle::default()
+            },
+        }
+    }
+
+    /// The color of text drawn directly on top of the map
+    pub fn label_color(self) -> Color {
+        match self {
+            Theme::Day => color::WHITE,
+            Theme::Night => color::LIGHT_GREY,
+        }
+    }
+
+    /// Returns the imagery that should actually be drawn when the user picked `base_layer`.
+    ///
+    /// The night theme swaps the bright street map for a dark one. Satellite imagery is already
+    /// dark enough to be left alone
+    pub fn base_layer(self, base_layer: TileKind) -> TileKind {
+        match (self, base_layer) {
+            (Theme::Night, TileKind::Street) => TileKind::DarkStreet,
+            (_, kind) => kind,
+        }
+    }
+}
@@ -36,14 +36,27 @@ pub enum TileError {
     Io(#[from] std::io::Error),
     #[error("Image: {0}")]
     Image(#[from] image::ImageError),
+    #[error("Vector tile: {0}")]
+    Vector(#[from] super::MvtError),
     #[error("Join: {0}")]
     Join(#[from] tokio::task::JoinError),
     #[error("Maptiler: {0}")]
     Maptiler(#[from] maptiler_cloud::errors::Error),
+    #[error("Http: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("RainViewer: {0}")]
+    RainViewer(#[from] rain_viewer::Error),
+    #[error("No radar frames are available yet")]
+    NoRadarFrames,
 }
 
 pub type Texture = ImageBuffer<Rgba<u8>, Vec<u8>>;
 
+/// The default for [`Backend::request_timeout`]
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
 /// A low level construct for requesting map tiles form a single source, such as an api,
 /// disk cache, or memory cache.
 ///
@@ -98,6 +111,19 @@ pub trait Backend: Send + Sync {
     /// Returns `None` if unknown
     fn tile_size(&self) -> Option<u32>;
 
+    /// The deepest zoom level this backend can provide tiles for.
+    ///
+    /// Returns `None` if this backend has no limit of its own, such as a cache that holds whatever
+    /// another backend gave it
+    fn max_zoom(&self) -> Option<u32> {
+        None
+    }
+
+    /// The longest this backend is given to produce a tile before the request is abandoned
+    fn request_timeout(&self) -> Duration {
+        REQUEST_TIMEOUT
+    }
+
     /// Requests a tile from the this backend, returning the image bytes if the tile could be
     /// requested successfully
     async fn request_inner(&self, tile: TileId) -> Result<Option<Vec<u8>>, TileError>;
@@ -0,0 +1,153 @@
+//! Recolors weather radar tiles so that the weather layer can use a colorblind friendly palette
+
+/// The palette the weather layer is drawn with
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ColorRamp {
+    /// The colors the radar provider drew the tiles with
+    #[default]
+    Classic,
+    /// Dark purple for light rain through green to yellow for the heaviest, which is readable with
+    /// every common form of colorblindness
+    Viridis,
+    /// Light gray for light rain through to dark gray for the heaviest
+    Grayscale,
+}
+
+const VIRIDIS: [[u8; 3]; 8] = [
+    [0x44, 0x01, 0x54],
+    [0x46, 0x32, 0x7e],
+    [0x36, 0x5c, 0x8d],
+    [0x27, 0x7f, 0x8e],
+    [0x1f, 0xa1, 0x87],
+    [0x4a, 0xc1, 0x6d],
+    [0xa0, 0xda, 0x39],
+    [0xfd, 0xe7, 0x25],
+];
+
+const GRAYSCALE: [[u8; 3]; 2] = [[0xe6, 0xe6, 0xe6], [0x28, 0x28, 0x28]];
+
+impl ColorRamp {
+    /// Every ramp, in the order the ramp button cycles through them
+    pub const ALL: [ColorRamp; 3] = [ColorRamp::Classic, ColorRamp::Viridis, ColorRamp::Grayscale];
+
+    /// Returns the ramp after this one, wrapping around to the first
+    pub fn next(self) -> ColorRamp {
+        let index = Self::ALL.iter().position(|&ramp| ramp == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// A short human readable name for this ramp
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorRamp::Classic => "Classic",
+            ColorRamp::Viridis => "Viridis",
+            ColorRamp::Grayscale => "Grayscale",
+        }
+    }
+
+    /// Parses a ramp name as written on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "classic" => Some(ColorRamp::Classic),
+            "viridis" => Some(ColorRamp::Viridis),
+            "grayscale" | "greyscale" => Some(ColorRamp::Grayscale),
+            _ => None,
+        }
+    }
+
+    /// Returns the color for `intensity`, from 0 for the lightest precipitation to 1 for the
+    /// heaviest, or `None` if this ramp keeps the original colors
+    pub fn color_at(self, intensity: f32) -> Option<[u8; 3]> {
+        let stops: &[[u8; 3]] = match self {
+            ColorRamp::Classic => return None,
+            ColorRamp::Viridis => &VIRIDIS,
+            ColorRamp::Grayscale => &GRAYSCALE,
+        };
+        Some(crate::sample_ramp(stops, intensity).map(|channel| channel.round() as u8))
+    }
+
+    /// Replaces the color of every pixel in `image` with this ramp's color for the pixel's
+    /// [`radar_intensity`], keeping its transparency
+    pub fn recolor(self, image: &mut image::RgbaImage) {
+        if self == ColorRamp::Classic {
+            return;
+        }
+        for pixel in image.pixels_mut() {
+            if pixel[3] == 0 {
+                continue;
+            }
+            let rgb = [pixel[0], pixel[1], pixel[2]];
+            if let Some([r, g, b]) = self.color_at(radar_intensity(rgb)) {
+                *pixel = image::Rgba([r, g, b, pixel[3]]);
+            }
+        }
+    }
+}
+
+/// Estimates how heavy the precipitation drawn by a radar tile pixel is, from 0 to 1.
+///
+/// Radar palettes run from green for light rain through yellow and red to magenta and purple for
+/// the heaviest, so the estimate comes from the hue. Blues, used for light rain and snow, and
+/// grays count as the lightest
+pub fn radar_intensity([r, g, b]: [u8; 3]) -> f32 {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let chroma = max - min;
+    if chroma < 0.1 {
+        return 0.0;
+    }
+    let hue = if max == r {
+        60.0 * ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / chroma + 2.0)
+    } else {
+        60.0 * ((r - g) / chroma + 4.0)
+    };
+    if hue <= 120.0 {
+        //Green through yellow to red
+        (120.0 - hue) / 120.0 * 0.75
+    } else if hue >= 270.0 {
+        //Red through magenta to purple
+        0.75 + (360.0 - hue) / 90.0 * 0.25
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intensity_follows_radar_palette() {
+        let green = radar_intensity([0, 255, 0]);
+        let yellow = radar_intensity([255, 255, 0]);
+        let red = radar_intensity([255, 0, 0]);
+        let magenta = radar_intensity([255, 0, 255]);
+        assert_eq!(green, 0.0);
+        assert!(green < yellow && yellow < red && red < magenta);
+        assert!(magenta <= 1.0);
+        assert_eq!(radar_intensity([0, 0, 255]), 0.0);
+        assert_eq!(radar_intensity([128, 128, 128]), 0.0);
+    }
+
+    #[test]
+    fn ramps_interpolate_between_stops() {
+        assert_eq!(ColorRamp::Classic.color_at(0.5), None);
+        assert_eq!(ColorRamp::Viridis.color_at(0.0), Some(VIRIDIS[0]));
+        assert_eq!(ColorRamp::Viridis.color_at(1.0), Some(VIRIDIS[7]));
+        assert_eq!(ColorRamp::Viridis.color_at(2.0), Some(VIRIDIS[7]));
+        assert_eq!(ColorRamp::Grayscale.color_at(0.5), Some([0x87, 0x87, 0x87]));
+
+        let mut image = image::RgbaImage::from_pixel(2, 1, image::Rgba([255, 0, 0, 128]));
+        image.put_pixel(1, 0, image::Rgba([255, 0, 0, 0]));
+        ColorRamp::Grayscale.recolor(&mut image);
+        let gray = ColorRamp::Grayscale.color_at(0.75).unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, [gray[0], gray[1], gray[2], 128]);
+        assert_eq!(image.get_pixel(1, 0).0, [255, 0, 0, 0]);
+
+        assert_eq!(ColorRamp::Grayscale.next(), ColorRamp::Classic);
+        assert_eq!(ColorRamp::from_name(" Viridis"), Some(ColorRamp::Viridis));
+    }
+}
@@ -1,10 +1,14 @@
 use std::{
+    collections::HashMap,
     path::{Path, PathBuf},
+    sync::Arc,
     time::{Duration, SystemTime},
 };
 
 use super::{Backend, ReadinessStatus, TileError, TileId};
 use async_trait::async_trait;
+use parking_lot::Mutex;
+use tokio::runtime::Runtime;
 
 fn get_tile_path(folder_name: &str, extension: &str, tile: TileId) -> String {
     format!(
@@ -13,14 +17,166 @@ fn get_tile_path(folder_name: &str, extension: &str, tile: TileId) -> String {
     )
 }
 
+/// Once the cache grows past its maximum size, tiles are evicted until it is below this fraction
+/// of the maximum, so that we don't need to evict on every write
+const EVICTION_TARGET: f64 = 0.9;
+
 #[derive(Copy, Clone)]
+struct IndexEntry {
+    size: u64,
+    last_access: SystemTime,
+}
+
+/// Tracks the size and last access time of every tile on disk, so that the least recently used
+/// tiles can be evicted once the cache grows too large
+#[derive(Default)]
+struct CacheIndex {
+    entries: HashMap<TileId, IndexEntry>,
+    total_size: u64,
+}
+
+impl CacheIndex {
+    /// Builds an index from the tiles already on disk, using their modification time as the last
+    /// access time
+    fn scan(folder_name: &str, extension: &str) -> Self {
+        let mut index = Self::default();
+        let parse = |path: &Path| path.file_stem()?.to_str()?.parse::<u32>().ok();
+
+        let zoom_dirs = match std::fs::read_dir(format!("./{}", folder_name)) {
+            Ok(dirs) => dirs,
+            Err(_) => return index,
+        };
+        for zoom_dir in zoom_dirs.flatten() {
+            let zoom = match parse(&zoom_dir.path()) {
+                Some(zoom) => zoom,
+                None => continue,
+            };
+            for x_dir in std::fs::read_dir(zoom_dir.path())
+                .into_iter()
+                .flatten()
+                .flatten()
+            {
+                let x = match parse(&x_dir.path()) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                for file in std::fs::read_dir(x_dir.path())
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                {
+                    let path = file.path();
+                    if path.extension().and_then(|e| e.to_str()) != Some(extension) {
+                        continue;
+                    }
+                    let (y, metadata) = match (parse(&path), file.metadata()) {
+                        (Some(y), Ok(metadata)) => (y, metadata),
+                        _ => continue,
+                    };
+                    let last_access = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    index.insert(TileId::new(x, y, zoom), metadata.len(), last_access);
+                }
+            }
+        }
+        index
+    }
+
+    /// Adds the tiles found by [`Self::scan`], keeping the entries for tiles that were written or
+    /// read while the scan was running since they are more up to date
+    fn merge(&mut self, scanned: CacheIndex) {
+        for (tile, entry) in scanned.entries {
+            if !self.entries.contains_key(&tile) {
+                self.insert(tile, entry.size, entry.last_access);
+            }
+        }
+    }
+
+    fn insert(&mut self, tile: TileId, size: u64, last_access: SystemTime) {
+        let entry = IndexEntry { size, last_access };
+        if let Some(old) = self.entries.insert(tile, entry) {
+            self.total_size -= old.size;
+        }
+        self.total_size += size;
+    }
+
+    fn remove(&mut self, tile: TileId) {
+        if let Some(old) = self.entries.remove(&tile) {
+            self.total_size -= old.size;
+        }
+    }
+
+    fn touch(&mut self, tile: TileId) {
+        if let Some(entry) = self.entries.get_mut(&tile) {
+            entry.last_access = SystemTime::now();
+        }
+    }
+
+    /// Removes the least recently used tiles from the index until the total size is below
+    /// `target_size`, returning the tiles that were removed
+    fn evict_to(&mut self, target_size: u64) -> Vec<TileId> {
+        if self.total_size <= target_size {
+            return Vec::new();
+        }
+        let mut by_age: Vec<_> = self
+            .entries
+            .iter()
+            .map(|(tile, entry)| (entry.last_access, *tile))
+            .collect();
+        by_age.sort_unstable_by_key(|(last_access, _)| *last_access);
+
+        let mut evicted = Vec::new();
+        for (_, tile) in by_age {
+            if self.total_size <= target_size {
+                break;
+            }
+            self.remove(tile);
+            evicted.push(tile);
+        }
+        evicted
+    }
+}
+
+/// Describes where a kind of tile is cached on disk and how long and how much is kept.
+///
+/// Clones share the same index of cached tiles, so a requester writing tiles and the [`DiskCache`]
+/// reading them agree on what should be evicted
+#[derive(Clone)]
 pub struct DiskCacheData {
     pub folder_name: &'static str,
     pub image_extension: &'static str,
     pub invalidate_time: Duration,
+    /// The maximum number of bytes of tiles to keep on disk before the least recently used tiles
+    /// are deleted
+    pub max_size_bytes: u64,
+    index: Arc<Mutex<CacheIndex>>,
 }
 
 impl DiskCacheData {
+    /// Creates the cache data, scanning the tiles already on disk in the background on `runtime`.
+    ///
+    /// Until the scan finishes, only the tiles written or read since then are in the index
+    pub fn new(
+        folder_name: &'static str,
+        image_extension: &'static str,
+        invalidate_time: Duration,
+        max_size_bytes: u64,
+        runtime: &Runtime,
+    ) -> Self {
+        let index = Arc::new(Mutex::new(CacheIndex::default()));
+        let scan_index = Arc::clone(&index);
+        runtime.spawn_blocking(move || {
+            let scanned = CacheIndex::scan(folder_name, image_extension);
+            scan_index.lock().merge(scanned);
+        });
+        Self {
+            folder_name,
+            image_extension,
+            invalidate_time,
+            max_size_bytes,
+            index,
+        }
+    }
+
     pub async fn cache_tile(&self, tile: TileId, bytes: &[u8]) -> Result<(), std::io::Error> {
         let str_path = get_tile_path(self.folder_name, self.image_extension, tile);
         let path = Path::new(str_path.as_str());
@@ -32,7 +188,37 @@ impl DiskCacheData {
             }
         }
 
-        tokio::fs::write(path, bytes).await
+        tokio::fs::write(path, bytes).await?;
+
+        let evicted = {
+            let mut index = self.index.lock();
+            index.insert(tile, bytes.len() as u64, SystemTime::now());
+            if index.total_size > self.max_size_bytes {
+                index.evict_to((self.max_size_bytes as f64 * EVICTION_TARGET) as u64)
+            } else {
+                Vec::new()
+            }
+        };
+        for old_tile in evicted {
+            let old_path = get_tile_path(self.folder_name, self.image_extension, old_tile);
+            if let Err(err) = tokio::fs::remove_file(&old_path).await {
+                println!(
+                    "Failed to evict tile {:?} at {}: {:?}",
+                    old_tile, old_path, err
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Records that `tile` was just read from disk, so that it is evicted last
+    fn touch(&self, tile: TileId) {
+        self.index.lock().touch(tile);
+    }
+
+    /// Removes `tile` from the index after it has been deleted from disk
+    fn forget(&self, tile: TileId) {
+        self.index.lock().remove(tile);
     }
 }
 
@@ -67,11 +253,13 @@ impl Backend for DiskCache {
                                     err, tile, &path, age
                                 );
                             }
+                            self.inner.forget(tile);
                             return Ok(None);
                         }
                     }
                 }
 
+                self.inner.touch(tile);
                 Ok(Some(tokio::fs::read(path).await?))
             }
             Err(_) => Ok(None),
@@ -133,3 +321,61 @@ impl Backend for DiskCache {
         self.ignore_transparent_tiles
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let mut index = CacheIndex::default();
+        let oldest = TileId::new(0, 0, 1);
+        let middle = TileId::new(1, 0, 1);
+        let newest = TileId::new(0, 1, 1);
+        index.insert(newest, 100, seconds(30));
+        index.insert(oldest, 100, seconds(10));
+        index.insert(middle, 100, seconds(20));
+        assert_eq!(index.total_size, 300);
+
+        //Already small enough
+        assert_eq!(index.evict_to(300), Vec::new());
+
+        //Only as many tiles as needed to get down to the target are removed
+        assert_eq!(index.evict_to(150), vec![oldest, middle]);
+        assert_eq!(index.total_size, 100);
+        assert!(index.entries.contains_key(&newest));
+    }
+
+    #[test]
+    fn touched_tiles_are_evicted_last() {
+        let mut index = CacheIndex::default();
+        let first = TileId::new(0, 0, 1);
+        let second = TileId::new(1, 0, 1);
+        index.insert(first, 50, seconds(10));
+        index.insert(second, 50, seconds(20));
+        index.touch(first);
+
+        assert_eq!(index.evict_to(50), vec![second]);
+        assert_eq!(index.total_size, 50);
+    }
+
+    #[test]
+    fn merge_keeps_newer_entries() {
+        let mut index = CacheIndex::default();
+        let written = TileId::new(0, 0, 1);
+        let old = TileId::new(1, 0, 1);
+        index.insert(written, 10, seconds(20));
+
+        let mut scanned = CacheIndex::default();
+        scanned.insert(written, 5, seconds(10));
+        scanned.insert(old, 5, seconds(10));
+        index.merge(scanned);
+
+        assert_eq!(index.total_size, 15);
+        assert_eq!(index.entries[&written].last_access, seconds(20));
+    }
+}
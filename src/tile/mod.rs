@@ -1,19 +1,38 @@
 mod backend;
+mod color_ramp;
 mod disk_cache;
+mod mvt;
+mod offline;
 mod pipeline;
+mod radar_loop;
+mod request_queue;
+mod vector;
 
 mod satellite_requester;
+mod url_requester;
 mod weather_requester;
 
 pub use backend::*;
+pub use color_ramp::*;
+pub use mvt::*;
 pub use pipeline::*;
+pub use radar_loop::*;
+pub use request_queue::*;
+pub use vector::*;
+pub use weather_requester::{RadarFrame, WeatherIndex};
 
 use disk_cache::*;
+use offline::*;
 use satellite_requester::*;
+use url_requester::*;
 use weather_requester::*;
 
 use enum_map::{enum_map, Enum, EnumMap};
-use std::time::Duration;
+use serde::Deserialize;
+use std::{
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
+};
 use tokio::runtime::Runtime;
 
 /// Contains the coordinates of a unique tile, at any zoom level, anywhere in the world
@@ -32,40 +51,293 @@ impl TileId {
 }
 
 /// The kind of imagery this tile represents
-#[derive(Debug, Enum)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Enum)]
 pub enum TileKind {
     /// A satellite tile
     Satellite,
+    /// A street map tile from OpenStreetMap
+    Street,
+    /// A dark street map tile, used instead of [`TileKind::Street`] by the night theme
+    DarkStreet,
     /// A weather tile
     Weather,
+    /// Water and roads drawn from vector tiles by [`VectorPipeline`]. Its entry in
+    /// [`PipelineMap`] shares the street map's backends, and is used where vector tiles aren't
+    /// drawn, such as the minimap
+    Vector,
+}
+
+impl TileKind {
+    /// The kinds of imagery that can be drawn underneath everything else, in the order the base
+    /// layer button cycles through them
+    pub const BASE_LAYERS: [TileKind; 3] =
+        [TileKind::Satellite, TileKind::Street, TileKind::Vector];
+
+    /// Returns the base layer after this one, wrapping around to the first.
+    ///
+    /// Kinds which are not base layers return the first base layer
+    pub fn next_base_layer(self) -> TileKind {
+        let index = Self::BASE_LAYERS
+            .iter()
+            .position(|&kind| kind == self)
+            .map_or(0, |i| i + 1);
+        Self::BASE_LAYERS[index % Self::BASE_LAYERS.len()]
+    }
+
+    /// A short human readable name for this kind
+    pub fn name(self) -> &'static str {
+        match self {
+            TileKind::Satellite => "Satellite",
+            TileKind::Street => "Street",
+            TileKind::DarkStreet => "Dark Street",
+            TileKind::Weather => "Weather",
+            TileKind::Vector => "Vector",
+        }
+    }
 }
 
 /// A mapping between imagery kinds and the tile pipeline that provides access to tile images
 pub type PipelineMap = EnumMap<TileKind, TilePipeline>;
 
-/// Returns the default pipelines for `TileKinds`.
+/// The servers that street map tiles are downloaded from.
+///
+/// Each url must contain `{z}`, `{x}`, and `{y}`, which are replaced with the zoom, x, and y
+/// coordinates of the tile being downloaded
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TileUrls {
+    pub street: String,
+    pub dark_street: String,
+    /// Servers with the same tiles as `street`, used when it keeps failing
+    pub street_mirrors: Vec<String>,
+    /// Servers with the same tiles as `dark_street`, used when it keeps failing
+    pub dark_street_mirrors: Vec<String>,
+}
+
+impl Default for TileUrls {
+    fn default() -> Self {
+        Self {
+            street: "https://tile.openstreetmap.org/{z}/{x}/{y}.png".to_owned(),
+            dark_street: "https://basemaps.cartocdn.com/dark_all/{z}/{x}/{y}.png".to_owned(),
+            street_mirrors: Vec::new(),
+            dark_street_mirrors: Vec::new(),
+        }
+    }
+}
+
+impl TileUrls {
+    /// Every url in this list, main servers first
+    pub fn all(&self) -> impl Iterator<Item = &String> {
+        [&self.street, &self.dark_street]
+            .into_iter()
+            .chain(&self.street_mirrors)
+            .chain(&self.dark_street_mirrors)
+    }
+}
+
+/// Returns `url` followed by `mirrors`, in the order [`UrlRequester`] prefers them
+fn with_mirrors(url: &str, mirrors: &[String]) -> Vec<String> {
+    std::iter::once(url.to_owned())
+        .chain(mirrors.iter().cloned())
+        .collect()
+}
+
+/// The default for [`crate::Config::cache_max_mb`]. Gives satellite imagery 1 GiB, each street
+/// map 512 MiB, vector tiles 256 MiB and weather 128 MiB
+pub const DEFAULT_CACHE_MAX_MB: u32 = 2432;
+
+/// How the disk cache is shared between the kinds of tiles, in parts of [`CACHE_PARTS`]
+const SATELLITE_CACHE_PARTS: u64 = 8;
+const STREET_CACHE_PARTS: u64 = 4;
+const VECTOR_CACHE_PARTS: u64 = 2;
+const WEATHER_CACHE_PARTS: u64 = 1;
+/// Satellite imagery, both street maps, vector tiles and weather
+const CACHE_PARTS: u64 =
+    SATELLITE_CACHE_PARTS + 2 * STREET_CACHE_PARTS + VECTOR_CACHE_PARTS + WEATHER_CACHE_PARTS;
+
+/// Returns the size in bytes of a cache given `parts` of the `cache_max_mb` MiB shared by all of
+/// the tile caches
+fn cache_share(cache_max_mb: u32, parts: u64) -> u64 {
+    cache_max_mb as u64 * 1024 * 1024 * parts / CACHE_PARTS
+}
+
+/// Returns the default pipelines for `TileKinds`, downloading street maps from `urls` and the
+/// newest weather radar frame listed by `weather`.
 ///
 /// Each imagery type is backed by a disk cache and access to an api which retrieves the tiles if
-/// the disk cache misses
-pub fn pipelines(runtime: &Runtime) -> PipelineMap {
-    let satellite_cache = DiskCacheData {
-        folder_name: ".cache/satellite",
-        image_extension: "jpg",
-        invalidate_time: Duration::from_secs(60 * 60 * 24 * 30), //One month long cache
-    };
-    let weather_cache = DiskCacheData {
-        folder_name: ".cache/weather",
-        image_extension: "png",
-        invalidate_time: Duration::from_secs(60 * 5), //Five minute cache
-    };
+/// the disk cache misses. The caches share `cache_max_mb` MiB with the vector cache. The apis
+/// aren't used while `offline` is set
+pub fn pipelines(
+    runtime: &Runtime,
+    urls: &TileUrls,
+    weather: Arc<WeatherIndex>,
+    offline: &Arc<AtomicBool>,
+    cache_max_mb: u32,
+) -> PipelineMap {
+    let satellite_cache = DiskCacheData::new(
+        ".cache/satellite",
+        "jpg",
+        Duration::from_secs(60 * 60 * 24 * 30), //One month long cache
+        cache_share(cache_max_mb, SATELLITE_CACHE_PARTS),
+        runtime,
+    );
+    let street_cache = DiskCacheData::new(
+        ".cache/street",
+        "png",
+        Duration::from_secs(60 * 60 * 24 * 7), //One week long cache
+        cache_share(cache_max_mb, STREET_CACHE_PARTS),
+        runtime,
+    );
+    let dark_street_cache = DiskCacheData::new(
+        ".cache/dark_street",
+        "png",
+        Duration::from_secs(60 * 60 * 24 * 7), //One week long cache
+        cache_share(cache_max_mb, STREET_CACHE_PARTS),
+        runtime,
+    );
+    let weather_cache = DiskCacheData::new(
+        ".cache/weather",
+        "png",
+        Duration::from_secs(60 * 5), //Five minute cache
+        cache_share(cache_max_mb, WEATHER_CACHE_PARTS),
+        runtime,
+    );
+    let street = TilePipeline::new(
+        vec![
+            Box::new(DiskCache::new(street_cache.clone(), false)),
+            Box::new(OfflineGate::new(
+                UrlRequester::new(
+                    "Street Requester",
+                    with_mirrors(&urls.street, &urls.street_mirrors),
+                    256,
+                    19,
+                    street_cache,
+                ),
+                offline.clone(),
+            )),
+        ],
+        runtime,
+    );
+    let vector_fallback = street.share_backends(runtime);
     enum_map! {
         TileKind::Satellite => TilePipeline::new(vec![
-            Box::new(DiskCache::new(satellite_cache, false)),
-            Box::new(SatelliteRequester::new(satellite_cache))
+            Box::new(DiskCache::new(satellite_cache.clone(), false)),
+            Box::new(OfflineGate::new(SatelliteRequester::new(satellite_cache), offline.clone()))
+        ], runtime),
+        TileKind::Street => street,
+        TileKind::DarkStreet => TilePipeline::new(vec![
+            Box::new(DiskCache::new(dark_street_cache.clone(), false)),
+            Box::new(OfflineGate::new(
+                UrlRequester::new(
+                    "Dark Street Requester",
+                    with_mirrors(&urls.dark_street, &urls.dark_street_mirrors),
+                    256,
+                    20,
+                    dark_street_cache,
+                ),
+                offline.clone(),
+            ))
         ], runtime),
         TileKind::Weather => TilePipeline::new(vec![
-            Box::new(DiskCache::new(weather_cache, true)),
-            Box::new(WeatherRequester::new(weather_cache))
+            Box::new(DiskCache::new(weather_cache.clone(), true)),
+            Box::new(OfflineGate::new(
+                WeatherRequester::new(weather, RadarFrame::Newest, Some(weather_cache)),
+                offline.clone(),
+            ))
         ], runtime),
+        TileKind::Vector => vector_fallback,
+    }
+}
+
+/// Returns the pipeline for the vector base layer, downloading tiles as described by `settings`
+/// unless `offline` is set. Its disk cache gets its share of the `cache_max_mb` MiB shared with
+/// [`pipelines`]
+pub fn vector_pipeline(
+    runtime: &Runtime,
+    settings: &VectorSettings,
+    offline: &Arc<AtomicBool>,
+    cache_max_mb: u32,
+) -> VectorPipeline {
+    let vector_cache = DiskCacheData::new(
+        ".cache/vector",
+        "pbf",
+        Duration::from_secs(60 * 60 * 24 * 7), //One week long cache
+        cache_share(cache_max_mb, VECTOR_CACHE_PARTS),
+        runtime,
+    );
+    VectorPipeline::new(
+        vec![
+            Box::new(DiskCache::new(vector_cache.clone(), false)),
+            Box::new(OfflineGate::new(
+                UrlRequester::new(
+                    "Vector Requester",
+                    with_mirrors(&settings.url, &settings.mirrors),
+                    VECTOR_TILE_SIZE,
+                    settings.max_zoom,
+                    vector_cache,
+                ),
+                offline.clone(),
+            )),
+        ],
+        settings,
+        runtime,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers_are_keyed_by_kind() {
+        //Each kind's pipeline is told apart by the deepest zoom its backends provide
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let pipelines = pipelines(
+            &runtime,
+            &TileUrls::default(),
+            Arc::new(WeatherIndex::new()),
+            &Arc::default(),
+            DEFAULT_CACHE_MAX_MB,
+        );
+        assert_eq!(
+            pipelines[TileKind::Satellite].max_zoom(),
+            maptiler_cloud::TileSet::Satellite.max_zoom()
+        );
+        assert_eq!(pipelines[TileKind::Street].max_zoom(), 19);
+        assert_eq!(pipelines[TileKind::DarkStreet].max_zoom(), 20);
+        assert_eq!(
+            pipelines[TileKind::Weather].max_zoom(),
+            crate::MAX_ZOOM_LEVEL
+        );
+        //The vector layer falls back to street tiles until its own tiles load
+        assert_eq!(pipelines[TileKind::Vector].max_zoom(), 19);
+
+        //The default size keeps the sizes each cache had before they were configurable
+        let mib = |mb: u64| mb * 1024 * 1024;
+        assert_eq!(
+            cache_share(DEFAULT_CACHE_MAX_MB, SATELLITE_CACHE_PARTS),
+            mib(1024)
+        );
+        assert_eq!(
+            cache_share(DEFAULT_CACHE_MAX_MB, STREET_CACHE_PARTS),
+            mib(512)
+        );
+        assert_eq!(
+            cache_share(DEFAULT_CACHE_MAX_MB, VECTOR_CACHE_PARTS),
+            mib(256)
+        );
+        assert_eq!(
+            cache_share(DEFAULT_CACHE_MAX_MB, WEATHER_CACHE_PARTS),
+            mib(128)
+        );
+
+        //Cycling the base layer only visits base layers, never overlays such as weather
+        let mut kind = TileKind::Weather;
+        for &expected in TileKind::BASE_LAYERS.iter().cycle().take(4) {
+            kind = kind.next_base_layer();
+            assert_eq!(kind, expected);
+        }
     }
 }
@@ -0,0 +1,475 @@
+//! A decoder for Mapbox Vector Tiles, which are protocol buffers holding layers of points, lines
+//! and polygons.
+//!
+//! Only the parts of the format needed to draw the map are kept: geometry, and properties with
+//! string values. See <https://github.com/mapbox/vector-tile-spec/tree/master/2.1>
+
+use std::io::Read;
+
+use glam::DVec2;
+use thiserror::Error;
+
+/// An error produced if a vector tile can't be decoded
+#[derive(Error, Debug, PartialEq)]
+pub enum MvtError {
+    #[error("unexpected end of data")]
+    Truncated,
+    #[error("unsupported wire type {0}")]
+    WireType(u64),
+    #[error("invalid geometry command {0}")]
+    Command(u32),
+    #[error("invalid gzip data")]
+    Gzip,
+    #[error("tile is larger than {} bytes once inflated", MAX_TILE_BYTES)]
+    TooLarge,
+}
+
+/// The largest a tile may be once inflated. Real tiles are at most a few megabytes, so anything
+/// bigger is corrupt or a decompression bomb
+pub const MAX_TILE_BYTES: usize = 16 * 1024 * 1024;
+
+/// The kind of shape a feature holds
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum GeomType {
+    Unknown,
+    Point,
+    LineString,
+    Polygon,
+}
+
+/// A decoded vector tile
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VectorTile {
+    pub layers: Vec<VectorLayer>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VectorLayer {
+    pub name: String,
+    pub features: Vec<Feature>,
+}
+
+/// A single shape in a layer, such as a road or a lake
+#[derive(Clone, Debug, PartialEq)]
+pub struct Feature {
+    pub geom_type: GeomType,
+    /// The properties of this feature which have string values
+    pub properties: Vec<(String, String)>,
+    /// The points of each line or ring, where 0 is the top left of the tile and 1 is the bottom
+    /// right. Rings are closed implicitly, so the first point is not repeated at the end.
+    ///
+    /// Shapes may extend a little past the edges of the tile so that lines drawn along the edge
+    /// join up with the neighbouring tile
+    pub geometry: Vec<Vec<DVec2>>,
+}
+
+impl Feature {
+    /// Returns the value of the string property `key`
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl VectorTile {
+    /// Decodes a vector tile, inflating it first if it is gzip compressed.
+    ///
+    /// Servers often send tiles compressed without a `Content-Encoding` header, so the gzip
+    /// magic number is checked here rather than leaving it to the HTTP client
+    pub fn decode(bytes: &[u8]) -> Result<Self, MvtError> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            let mut inflated = Vec::new();
+            flate2::read::GzDecoder::new(bytes)
+                .take(MAX_TILE_BYTES as u64 + 1)
+                .read_to_end(&mut inflated)
+                .map_err(|_| MvtError::Gzip)?;
+            if inflated.len() > MAX_TILE_BYTES {
+                return Err(MvtError::TooLarge);
+            }
+            return Self::decode_uncompressed(&inflated);
+        }
+        Self::decode_uncompressed(bytes)
+    }
+
+    fn decode_uncompressed(bytes: &[u8]) -> Result<Self, MvtError> {
+        let mut tile = VectorTile::default();
+        let mut reader = Reader::new(bytes);
+        while let Some((number, field)) = reader.field()? {
+            if let (3, Field::Bytes(layer)) = (number, field) {
+                tile.layers.push(decode_layer(layer)?);
+            }
+        }
+        Ok(tile)
+    }
+
+    /// Returns the layer called `name`
+    pub fn layer(&self, name: &str) -> Option<&VectorLayer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+}
+
+/// The default size of a tile in the units used by its geometry
+const DEFAULT_EXTENT: u32 = 4096;
+
+fn decode_layer(bytes: &[u8]) -> Result<VectorLayer, MvtError> {
+    let mut name = String::new();
+    let mut extent = DEFAULT_EXTENT;
+    let mut keys = Vec::new();
+    let mut values = Vec::new();
+    let mut features = Vec::new();
+
+    //Features refer to keys and values which may come after them, so decode them last
+    let mut reader = Reader::new(bytes);
+    while let Some((number, field)) = reader.field()? {
+        match (number, field) {
+            (1, Field::Bytes(bytes)) => name = String::from_utf8_lossy(bytes).into_owned(),
+            (2, Field::Bytes(bytes)) => features.push(bytes),
+            (3, Field::Bytes(bytes)) => keys.push(String::from_utf8_lossy(bytes).into_owned()),
+            (4, Field::Bytes(bytes)) => values.push(decode_string_value(bytes)?),
+            (5, Field::Varint(value)) => extent = (value as u32).max(1),
+            _ => {}
+        }
+    }
+
+    let features = features
+        .into_iter()
+        .map(|bytes| decode_feature(bytes, extent, &keys, &values))
+        .collect::<Result<_, _>>()?;
+    Ok(VectorLayer { name, features })
+}
+
+/// Returns the value if it is a string, or `None` for numbers and booleans
+fn decode_string_value(bytes: &[u8]) -> Result<Option<String>, MvtError> {
+    let mut value = None;
+    let mut reader = Reader::new(bytes);
+    while let Some((number, field)) = reader.field()? {
+        if let (1, Field::Bytes(bytes)) = (number, field) {
+            value = Some(String::from_utf8_lossy(bytes).into_owned());
+        }
+    }
+    Ok(value)
+}
+
+fn decode_feature(
+    bytes: &[u8],
+    extent: u32,
+    keys: &[String],
+    values: &[Option<String>],
+) -> Result<Feature, MvtError> {
+    let mut geom_type = GeomType::Unknown;
+    let mut tags = Vec::new();
+    let mut commands = Vec::new();
+
+    let mut reader = Reader::new(bytes);
+    while let Some((number, field)) = reader.field()? {
+        match (number, field) {
+            (2, field) => field.push_packed(&mut tags)?,
+            (3, Field::Varint(value)) => {
+                geom_type = match value {
+                    1 => GeomType::Point,
+                    2 => GeomType::LineString,
+                    3 => GeomType::Polygon,
+                    _ => GeomType::Unknown,
+                }
+            }
+            (4, field) => field.push_packed(&mut commands)?,
+            _ => {}
+        }
+    }
+
+    let properties = tags
+        .chunks_exact(2)
+        .filter_map(|pair| {
+            let key = keys.get(pair[0] as usize)?;
+            let value = values.get(pair[1] as usize)?.as_ref()?;
+            Some((key.clone(), value.clone()))
+        })
+        .collect();
+
+    Ok(Feature {
+        geom_type,
+        properties,
+        geometry: decode_geometry(&commands, extent)?,
+    })
+}
+
+/// Runs the geometry commands of a feature, returning each line or ring in tile coordinates
+fn decode_geometry(commands: &[u32], extent: u32) -> Result<Vec<Vec<DVec2>>, MvtError> {
+    const MOVE_TO: u32 = 1;
+    const LINE_TO: u32 = 2;
+    const CLOSE_PATH: u32 = 7;
+
+    let scale = 1.0 / extent as f64;
+    let mut paths: Vec<Vec<DVec2>> = Vec::new();
+    let mut cursor = (0i64, 0i64);
+    let mut i = 0;
+    while i < commands.len() {
+        let command = commands[i];
+        let (id, count) = (command & 0x7, command >> 3);
+        i += 1;
+        match id {
+            MOVE_TO | LINE_TO => {
+                let end = i + count as usize * 2;
+                if end > commands.len() {
+                    return Err(MvtError::Truncated);
+                }
+                for pair in commands[i..end].chunks_exact(2) {
+                    cursor.0 += zigzag(pair[0]);
+                    cursor.1 += zigzag(pair[1]);
+                    let point = DVec2::new(cursor.0 as f64, cursor.1 as f64) * scale;
+                    match paths.last_mut() {
+                        Some(path) if id == LINE_TO => path.push(point),
+                        None if id == LINE_TO => return Err(MvtError::Command(command)),
+                        _ => paths.push(vec![point]),
+                    }
+                }
+                i = end;
+            }
+            //Rings are closed implicitly
+            CLOSE_PATH => {}
+            _ => return Err(MvtError::Command(command)),
+        }
+    }
+    Ok(paths)
+}
+
+fn zigzag(value: u32) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// The value of one field in a protocol buffer message
+enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    /// A fixed size number, which nothing here needs
+    Fixed,
+}
+
+impl Field<'_> {
+    /// Appends the numbers in a repeated `uint32` field, which may or may not be packed
+    fn push_packed(self, out: &mut Vec<u32>) -> Result<(), MvtError> {
+        match self {
+            Field::Varint(value) => out.push(value as u32),
+            Field::Bytes(bytes) => {
+                let mut reader = Reader::new(bytes);
+                while !reader.is_empty() {
+                    out.push(reader.varint()? as u32);
+                }
+            }
+            Field::Fixed => {}
+        }
+        Ok(())
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MvtError> {
+        if len > self.bytes.len() {
+            return Err(MvtError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn varint(&mut self) -> Result<u64, MvtError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(MvtError::Truncated)
+    }
+
+    /// Reads the next field number and value, or returns `None` at the end of the message
+    fn field(&mut self) -> Result<Option<(u64, Field<'a>)>, MvtError> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let field = match key & 0x7 {
+            0 => Field::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                Field::Fixed
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                Field::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                Field::Fixed
+            }
+            wire_type => return Err(MvtError::WireType(wire_type)),
+        };
+        Ok(Some((key >> 3, field)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(mut value: u64, out: &mut Vec<u8>) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn bytes_field(number: u64, bytes: &[u8], out: &mut Vec<u8>) {
+        varint(number << 3 | 2, out);
+        varint(bytes.len() as u64, out);
+        out.extend_from_slice(bytes);
+    }
+
+    fn packed(values: &[u32]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for &value in values {
+            varint(value as u64, &mut out);
+        }
+        out
+    }
+
+    fn encode_zigzag(value: i32) -> u32 {
+        ((value << 1) ^ (value >> 31)) as u32
+    }
+
+    /// A layer with an extent of 100 holding a square lake and a road with a string class
+    fn test_layer() -> Vec<u8> {
+        let square = [
+            9,
+            encode_zigzag(10),
+            encode_zigzag(10),
+            3 << 3 | 2,
+            encode_zigzag(20),
+            0,
+            0,
+            encode_zigzag(20),
+            encode_zigzag(-20),
+            0,
+            7 | 1 << 3,
+        ];
+        let mut lake = Vec::new();
+        varint(3 << 3, &mut lake);
+        varint(3, &mut lake);
+        bytes_field(4, &packed(&square), &mut lake);
+
+        let mut road = Vec::new();
+        bytes_field(2, &packed(&[0, 0]), &mut road);
+        varint(3 << 3, &mut road);
+        varint(2, &mut road);
+        let line = [9, 0, encode_zigzag(50), 1 << 3 | 2, encode_zigzag(100), 0];
+        bytes_field(4, &packed(&line), &mut road);
+
+        let mut value = Vec::new();
+        bytes_field(1, b"motorway", &mut value);
+
+        let mut layer = Vec::new();
+        bytes_field(1, b"water", &mut layer);
+        bytes_field(2, &lake, &mut layer);
+        bytes_field(2, &road, &mut layer);
+        bytes_field(3, b"class", &mut layer);
+        bytes_field(4, &value, &mut layer);
+        varint(5 << 3, &mut layer);
+        varint(100, &mut layer);
+        layer
+    }
+
+    #[test]
+    fn decodes_layers_and_geometry() {
+        let mut bytes = Vec::new();
+        bytes_field(3, &test_layer(), &mut bytes);
+        let tile = VectorTile::decode(&bytes).unwrap();
+        assert_eq!(tile.layers.len(), 1);
+        let layer = tile.layer("water").unwrap();
+        assert!(tile.layer("roads").is_none());
+
+        let lake = &layer.features[0];
+        assert_eq!(lake.geom_type, GeomType::Polygon);
+        assert_eq!(lake.property("class"), None);
+        assert_eq!(
+            lake.geometry,
+            vec![vec![
+                DVec2::new(0.1, 0.1),
+                DVec2::new(0.3, 0.1),
+                DVec2::new(0.3, 0.3),
+                DVec2::new(0.1, 0.3),
+            ]]
+        );
+
+        let road = &layer.features[1];
+        assert_eq!(road.geom_type, GeomType::LineString);
+        assert_eq!(road.property("class"), Some("motorway"));
+        assert_eq!(
+            road.geometry,
+            vec![vec![DVec2::new(0.0, 0.5), DVec2::new(1.0, 0.5)]]
+        );
+    }
+
+    #[test]
+    fn decodes_gzipped_tiles() {
+        use std::io::Write;
+
+        let mut bytes = Vec::new();
+        bytes_field(3, &test_layer(), &mut bytes);
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&bytes).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        assert_ne!(gzipped, bytes);
+        assert_eq!(
+            VectorTile::decode(&gzipped),
+            Ok(VectorTile::decode(&bytes).unwrap())
+        );
+        assert_eq!(VectorTile::decode(&gzipped).unwrap().layers.len(), 1);
+    }
+
+    #[test]
+    fn rejects_gzip_bombs() {
+        use std::io::Write;
+
+        //Compresses to a few kilobytes
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&vec![0; MAX_TILE_BYTES + 1]).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        assert!(gzipped.len() < MAX_TILE_BYTES / 100);
+        assert_eq!(VectorTile::decode(&gzipped), Err(MvtError::TooLarge));
+    }
+
+    #[test]
+    fn rejects_bad_tiles() {
+        let mut bytes = Vec::new();
+        bytes_field(3, &test_layer(), &mut bytes);
+        assert_eq!(
+            VectorTile::decode(&bytes[..bytes.len() - 3]),
+            Err(MvtError::Truncated)
+        );
+        assert_eq!(VectorTile::decode(&[0x1f, 0x8b, 8, 0]), Err(MvtError::Gzip));
+        //A line that starts without moving to its first point
+        assert_eq!(
+            decode_geometry(&[1 << 3 | 2, 2, 2], 4096),
+            Err(MvtError::Command(1 << 3 | 2))
+        );
+        assert_eq!(VectorTile::decode(&[]), Ok(VectorTile::default()));
+    }
+}
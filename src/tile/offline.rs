@@ -0,0 +1,71 @@
+//! Switches off the backends that download tiles while the app is in offline mode
+
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::{Backend, ReadinessStatus, Texture, TileError, TileId};
+
+/// Wraps a backend that downloads tiles, so that it acts as if it has no tiles while `offline` is
+/// set. Tiles then only come from the backends that read a cache
+pub struct OfflineGate {
+    inner: Box<dyn Backend>,
+    offline: Arc<AtomicBool>,
+}
+
+impl OfflineGate {
+    pub fn new(inner: impl Backend + 'static, offline: Arc<AtomicBool>) -> Self {
+        Self {
+            inner: Box::new(inner),
+            offline,
+        }
+    }
+
+    fn is_offline(&self) -> bool {
+        self.offline.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl Backend for OfflineGate {
+    async fn request(&self, tile: TileId) -> Result<Option<Texture>, TileError> {
+        if self.is_offline() {
+            return Ok(None);
+        }
+        self.inner.request(tile).await
+    }
+
+    fn ignore_transparent_tiles(&self) -> bool {
+        self.inner.ignore_transparent_tiles()
+    }
+
+    async fn readiness(&self, tile: TileId) -> ReadinessStatus {
+        if self.is_offline() {
+            return ReadinessStatus::NotAvailable;
+        }
+        self.inner.readiness(tile).await
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn tile_size(&self) -> Option<u32> {
+        self.inner.tile_size()
+    }
+
+    fn max_zoom(&self) -> Option<u32> {
+        self.inner.max_zoom()
+    }
+
+    fn request_timeout(&self) -> std::time::Duration {
+        self.inner.request_timeout()
+    }
+
+    async fn request_inner(&self, tile: TileId) -> Result<Option<Vec<u8>>, TileError> {
+        if self.is_offline() {
+            return Ok(None);
+        }
+        self.inner.request_inner(tile).await
+    }
+}
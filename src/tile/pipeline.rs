@@ -3,16 +3,21 @@ use crate::{TileId, WorldViewport};
 
 use simple_moving_average::SMA;
 use tokio::runtime::Runtime;
-use tokio::sync::mpsc::{Receiver, Sender, UnboundedReceiver, UnboundedSender};
+use tokio::sync::mpsc::{Receiver, Sender};
 
+use glam::DVec2;
 use intmap::IntMap;
+use serde::Deserialize;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 struct MemoryTile {
     pub id: TileId,
     pub image: Option<image::RgbaImage>,
+    /// True if `image` is `None` because a backend failed, rather than because no backend has
+    /// the tile
+    pub failed: bool,
 }
 
 /// Holds multiple levels of cache for requesting tiles in a generic manner.
@@ -24,15 +29,125 @@ pub struct TilePipeline {
     /// The cache of tiles on the GPU
     cache: IntMap<CachedTile>,
     upload_rx: Receiver<MemoryTile>,
-    request_tx: Arc<UnboundedSender<TileId>>,
+    /// Requests waiting to be sent by this pipeline's requester task
+    dispatch: Arc<RequestDispatch>,
+    /// The middle of the screen in world coordinates as of the last [`TilePipeline::update`].
+    /// Requests closer to it are sent first
+    center: DVec2,
     tile_size: AtomicU32,
+    /// The deepest zoom level every backend can provide
+    max_zoom: u32,
+
+    /// The number of `CachedTile::Cached` entries in `cache`, which is the number of textures this
+    /// pipeline owns on the GPU
+    texture_count: usize,
+    /// The sum of `bytes` over the `CachedTile::Cached` entries in `cache`
+    texture_bytes: usize,
+    /// The maximum number of textures to keep on the GPU before the least recently rendered are
+    /// freed
+    max_textures: usize,
+    /// Incremented on each call to [`TilePipeline::update`]. Used to track when a tile was last
+    /// rendered
+    frame: u64,
+    /// The number of `CachedTile::Pending` entries in `cache`
+    pending_count: usize,
+    /// The earliest `retry_at` of the `CachedTile::Failed` entries still on screen, so that
+    /// they are retried even if nothing else redraws the map
+    next_retry: Option<Instant>,
+    /// The palette tiles are recolored with before they are uploaded
+    color_ramp: ColorRamp,
+    prefetch: PrefetchSettings,
+    /// The deepest zoom level passed to [`TilePipeline::get_tile`] since the last
+    /// [`TilePipeline::update`], which is the zoom level on screen
+    frame_zoom: Option<u32>,
+}
+
+/// Which tiles a [`TilePipeline`] loads before they are on screen, so that panning doesn't show
+/// blank areas
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct PrefetchSettings {
+    /// How many tiles beyond the edges of the screen are loaded
+    pub radius: u32,
+    /// Whether the tiles one zoom level deeper than the screen are loaded too
+    pub next_zoom: bool,
+}
+
+impl Default for PrefetchSettings {
+    fn default() -> Self {
+        Self {
+            radius: 1,
+            next_zoom: false,
+        }
+    }
 }
 
+/// The most requests a pipeline has in flight at once. Further requests wait in its
+/// [`RequestQueue`] so that the most important ones can go first
+const MAX_REQUESTS_IN_FLIGHT: usize = 16;
+
+/// The most prefetch requests a pipeline has in flight at once, so that they never crowd out
+/// requests for tiles on screen
+const MAX_PREFETCHES_IN_FLIGHT: usize = 4;
+
+/// How many times a tile is requested before it is given up on when every request fails
+const MAX_TILE_ATTEMPTS: u32 = 6;
+
+/// How long to wait before retrying a tile after its first failed request. Each further failure
+/// doubles the wait, up to [`MAX_RETRY_DELAY`]
+const BASE_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// The queue shared between a [`TilePipeline`] and its requester task
+#[derive(Default)]
+struct RequestDispatch {
+    queue: parking_lot::Mutex<RequestQueue>,
+    /// Wakes the requester when a request is queued or a request slot frees up
+    wake: tokio::sync::Notify,
+}
+
+/// The default value for [`TilePipeline::set_max_textures`].
+///
+/// A 1080p screen shows around 100 512px tiles including lower zoom fallbacks, so this leaves
+/// plenty of room for panning back and forth without re-uploading
+pub const DEFAULT_MAX_TEXTURES: usize = 1024;
+
 #[derive(Debug, Copy, Clone)]
 enum CachedTile {
     NotAvailable,
-    Pending,
-    Cached(conrod_core::image::Id),
+    Pending {
+        /// The value of `TilePipeline::frame` the last time this tile was fetched with
+        /// [`TilePipeline::get_tile`]. Requests that haven't been sent by the time the tile
+        /// leaves the screen are cancelled
+        last_wanted: u64,
+        /// The number of earlier requests for this tile that failed
+        failures: u32,
+    },
+    /// Requested by the prefetch pass. Becomes `Pending` if it is needed on screen before it
+    /// arrives
+    Prefetching {
+        /// The number of earlier requests for this tile that failed
+        failures: u32,
+    },
+    /// The last request failed. The tile is requested again if it is still needed after
+    /// `retry_at`
+    Failed {
+        failures: u32,
+        retry_at: Instant,
+        /// The value of `TilePipeline::frame` the last time this tile was fetched with
+        /// [`TilePipeline::get_tile`], or `None` if it was only prefetched. Only tiles still on
+        /// screen are retried on their own
+        last_wanted: Option<u64>,
+    },
+    Cached {
+        id: conrod_core::image::Id,
+        /// The value of `TilePipeline::frame` the last time this tile was fetched with
+        /// [`TilePipeline::get_tile`]
+        last_used: u64,
+        /// The size of the texture's pixels, assuming four bytes per pixel
+        bytes: usize,
+    },
 }
 
 impl TilePipeline {
@@ -40,46 +155,175 @@ impl TilePipeline {
     ///
     /// Uses `runtime` to spawn required asynchronous background tasks
     pub fn new(backends: Vec<Box<dyn Backend>>, runtime: &Runtime) -> Self {
+        let max_zoom = backends
+            .iter()
+            .filter_map(|backend| backend.max_zoom())
+            .fold(crate::MAX_ZOOM_LEVEL, u32::min);
+
+        Self::with_backends(Arc::new(backends), max_zoom, runtime)
+    }
+
+    /// Creates a pipeline which requests tiles through the same backends as this one, but keeps
+    /// its own textures on the GPU.
+    ///
+    /// Useful for drawing the same imagery at a very different zoom level without the two views
+    /// evicting each other's textures
+    pub fn share_backends(&self, runtime: &Runtime) -> Self {
+        Self::with_backends(self.backends.clone(), self.max_zoom, runtime)
+    }
+
+    fn with_backends(
+        backends: Arc<Vec<Box<dyn Backend>>>,
+        max_zoom: u32,
+        runtime: &Runtime,
+    ) -> Self {
         //Use large initial size here because we will have a few hundred tiles on the GPU at
         //minimum, and rehashing is EXPENSIVE
         let (upload_tx, upload_rx) = tokio::sync::mpsc::channel(24);
-        let (request_tx, request_rx) = tokio::sync::mpsc::unbounded_channel();
+        let dispatch = Arc::new(RequestDispatch::default());
 
-        let backends = Arc::new(backends);
-        runtime.spawn(tile_requester(upload_tx, request_rx, backends.clone()));
+        runtime.spawn(tile_requester(
+            upload_tx,
+            dispatch.clone(),
+            backends.clone(),
+        ));
         Self {
             cache: IntMap::with_capacity(1024),
             upload_rx,
-            request_tx: Arc::new(request_tx),
+            dispatch,
+            center: DVec2::splat(0.5),
             backends,
             tile_size: AtomicU32::new(0),
+            max_zoom,
+            texture_count: 0,
+            texture_bytes: 0,
+            max_textures: DEFAULT_MAX_TEXTURES,
+            frame: 0,
+            pending_count: 0,
+            next_retry: None,
+            color_ramp: ColorRamp::Classic,
+            prefetch: PrefetchSettings::default(),
+            frame_zoom: None,
+        }
+    }
+
+    /// Sets the maximum number of tile textures this pipeline keeps on the GPU.
+    ///
+    /// Once exceeded, the least recently rendered textures are freed during
+    /// [`TilePipeline::update`]. Tiles rendered in the last frame are never freed, so the real
+    /// number of textures may exceed this if the screen shows more tiles than `max_textures`
+    pub fn set_max_textures(&mut self, max_textures: usize) {
+        self.max_textures = max_textures;
+    }
+
+    /// Sets which tiles are loaded before they are on screen. A radius of 0 without `next_zoom`
+    /// only loads the tiles on screen
+    pub fn set_prefetch(&mut self, prefetch: PrefetchSettings) {
+        self.prefetch = prefetch;
+    }
+
+    /// Recolors this pipeline's tiles with `color_ramp`.
+    ///
+    /// Changing the ramp frees every texture so that tiles are loaded again with the new colors
+    pub fn set_color_ramp(
+        &mut self,
+        color_ramp: ColorRamp,
+        image_map: &mut conrod_core::image::Map<glium::Texture2d>,
+    ) {
+        if self.color_ramp != color_ramp {
+            self.color_ramp = color_ramp;
+            self.clear(image_map);
         }
     }
 
     /// Fetches the image id of `tile`, or starts loading the texture,
     /// returning None on this frame and subsequent frames until the asynchronous request finishes
     pub fn get_tile(&mut self, tile: TileId) -> Option<conrod_core::image::Id> {
+        self.frame_zoom = self.frame_zoom.max(Some(tile.zoom));
+        let mut retry_failures = 0;
         //TODO: Have the caller pass the lock in so that we dont lock, unlock, then lock again
         {
-            match self.cache.get(tile_coord_to_u64(tile)) {
-                Some(&CachedTile::Cached(id)) => {
-                    return Some(id);
+            match self.cache.get_mut(tile_coord_to_u64(tile)) {
+                Some(CachedTile::Cached { id, last_used, .. }) => {
+                    *last_used = self.frame;
+                    return Some(*id);
+                }
+                Some(CachedTile::NotAvailable) => return None,
+                Some(CachedTile::Pending { last_wanted, .. }) => {
+                    *last_wanted = self.frame;
+                    return None;
+                }
+                Some(entry @ CachedTile::Prefetching { .. }) => {
+                    //Already on its way, but now the screen is waiting for it
+                    if let CachedTile::Prefetching { failures } = *entry {
+                        *entry = CachedTile::Pending {
+                            last_wanted: self.frame,
+                            failures,
+                        };
+                    }
+                    self.pending_count += 1;
+                    let distance = tile_distance(tile, self.center);
+                    self.dispatch.queue.lock().promote(tile, distance);
+                    return None;
+                }
+                Some(CachedTile::Failed {
+                    failures,
+                    retry_at,
+                    last_wanted,
+                }) => {
+                    let was_prefetch = last_wanted.replace(self.frame).is_none();
+                    if Instant::now() < *retry_at {
+                        if was_prefetch {
+                            //A failed prefetch that is now on screen, so retry it on its own
+                            let at = *retry_at;
+                            self.next_retry = Some(self.next_retry.map_or(at, |next| next.min(at)));
+                        }
+                        return None;
+                    }
+                    crate::MAP_PERF_DATA.lock().tile_retries += 1;
+                    retry_failures = *failures;
                 }
-                Some(&CachedTile::NotAvailable) => return None,
-                Some(&CachedTile::Pending) => return None,
                 None => {}
             };
         }
-        assert!(
-            self.request_tx.send(tile).is_ok(),
-            "Tile request channel closed! Cannot fetch more tiles"
-        );
+        self.dispatch
+            .queue
+            .lock()
+            .push(tile, false, tile_distance(tile, self.center));
+        self.dispatch.wake.notify_one();
 
-        self.cache
-            .insert(tile_coord_to_u64(tile), CachedTile::Pending);
+        let pending = CachedTile::Pending {
+            last_wanted: self.frame,
+            failures: retry_failures,
+        };
+        match self.cache.get_mut(tile_coord_to_u64(tile)) {
+            Some(value) => *value = pending,
+            None => {
+                self.cache.insert(tile_coord_to_u64(tile), pending);
+            }
+        }
+        self.pending_count += 1;
         None
     }
 
+    /// Returns true if tiles have been requested which haven't arrived yet, or if a failed tile
+    /// on screen is due to be requested again.
+    ///
+    /// [`TilePipeline::update`] must keep being called while this is true so that the tiles are
+    /// uploaded once they arrive, and retried without waiting for the map to be redrawn
+    pub fn has_pending(&self) -> bool {
+        self.pending_count > 0 || self.next_retry.map_or(false, |at| at <= Instant::now())
+    }
+
+    /// Returns true if `tile` has finished loading, either successfully or by failing. Tiles which
+    /// were never requested with [`TilePipeline::get_tile`] are not resolved
+    pub fn is_resolved(&self, tile: TileId) -> bool {
+        matches!(
+            self.cache.get(tile_coord_to_u64(tile)),
+            Some(CachedTile::Cached { .. }) | Some(CachedTile::NotAvailable)
+        )
+    }
+
     /// Returns the size of tiles returned by this pipeline, or `None` or unknown
     pub fn tile_size(&self) -> Option<u32> {
         let cached_size = self.tile_size.load(Ordering::Relaxed);
@@ -97,21 +341,40 @@ impl TilePipeline {
         None
     }
 
+    /// Returns the deepest zoom level tiles should be requested at. Views zoomed in further than
+    /// this should stretch these tiles instead
+    pub fn max_zoom(&self) -> u32 {
+        self.max_zoom
+    }
+
     /// Called each frame to allow the pipeline to upload newly fetched tiles to the GPU.
     ///
-    /// `viewport`: The viewport of the currently rendered scene. This is used for preemption
+    /// `viewport`: The viewport of the currently rendered scene. Tiles around it are prefetched
+    /// once everything on screen has loaded
     pub fn update(
         &mut self,
-        _viewport: &WorldViewport,
+        viewport: &WorldViewport,
         display: &glium::Display,
         image_map: &mut conrod_core::image::Map<glium::Texture2d>,
     ) {
-        //TODO: Pass viewport to preemption code
+        self.frame += 1;
+        self.evict_textures(image_map);
+        self.center = (viewport.top_left + viewport.bottom_right) / 2.0;
+        self.cancel_offscreen_requests();
+        self.retry_failed(Instant::now());
+        if let Some(zoom) = self.frame_zoom.take() {
+            //Tiles on screen come first
+            if self.pending_count == 0 {
+                self.prefetch(viewport, zoom);
+            }
+        }
+
         const MAX_PROCESS_TIME: Duration = Duration::from_millis(50);
         let start = std::time::Instant::now();
         let mut tiles_processed = 0;
 
-        while let Ok(tile) = self.upload_rx.try_recv() {
+        loop {
+            //Check the time before receiving so that a received tile is never dropped
             let time_spent = start.elapsed();
             if time_spent > MAX_PROCESS_TIME {
                 println!(
@@ -121,27 +384,44 @@ impl TilePipeline {
                 );
                 break;
             }
+            let tile = match self.upload_rx.try_recv() {
+                Ok(tile) => tile,
+                Err(_) => break,
+            };
             let tile_id = tile.id;
-
             match tile.image {
-                None => {
-                    let _ = self
-                        .cache
-                        .insert(tile_coord_to_u64(tile_id), CachedTile::NotAvailable);
-                }
-                Some(image) => {
+                None => self.store_missing(tile_id, tile.failed),
+                Some(mut image) => {
+                    self.finish_request(tile_id);
+                    self.color_ramp.recolor(&mut image);
+                    let bytes = image.as_raw().len();
                     let texture = create_texture(display, image);
                     let image_id = image_map.insert(texture);
 
                     let id = tile_coord_to_u64(tile_id);
-                    match self.cache.get_mut(id) {
-                        Some(value) => {
-                            *value = CachedTile::Cached(image_id);
-                        }
+                    let cached = CachedTile::Cached {
+                        id: image_id,
+                        last_used: self.frame,
+                        bytes,
+                    };
+                    let old = match self.cache.get_mut(id) {
+                        Some(value) => Some(std::mem::replace(value, cached)),
                         None => {
-                            self.cache.insert(id, CachedTile::Cached(image_id));
+                            self.cache.insert(id, cached);
+                            None
                         }
+                    };
+                    if let Some(CachedTile::Cached {
+                        id: old_id,
+                        bytes: old_bytes,
+                        ..
+                    }) = old
+                    {
+                        //Free the old texture so that it doesn't leak
+                        let _ = image_map.remove(old_id);
+                        self.track_free(old_bytes);
                     }
+                    self.track_upload(bytes);
 
                     tiles_processed += 1;
                 }
@@ -150,49 +430,499 @@ impl TilePipeline {
     }
 }
 
-/// An infinite async loop that waits for tile requests, and dispatches them through the levels of
-/// cache to produce a texture
+impl Drop for TilePipeline {
+    fn drop(&mut self) {
+        //Stops the requester task
+        self.dispatch.queue.lock().close();
+        self.dispatch.wake.notify_one();
+    }
+}
+
+impl TilePipeline {
+    /// Marks the request for `tile` as answered, returning the number of earlier failures and the
+    /// frame it was last wanted on screen, or `None` if it was only prefetched
+    fn finish_request(&mut self, tile: TileId) -> (u32, Option<u64>) {
+        match self.cache.get(tile_coord_to_u64(tile)) {
+            Some(CachedTile::Pending {
+                failures,
+                last_wanted,
+            }) => {
+                self.pending_count -= 1;
+                (*failures, Some(*last_wanted))
+            }
+            Some(CachedTile::Prefetching { failures }) => (*failures, None),
+            _ => (0, None),
+        }
+    }
+
+    /// Stores the result of a request for `tile` that came back without an image. `failed` is
+    /// true if a backend failed, rather than no backend having the tile
+    fn store_missing(&mut self, tile: TileId, failed: bool) {
+        let (failures, wanted) = self.finish_request(tile);
+        let entry = match failed {
+            true => self.after_failure(tile, failures + 1, wanted),
+            false => CachedTile::NotAvailable,
+        };
+        //`insert` keeps existing entries, so replace the pending entry in place
+        let id = tile_coord_to_u64(tile);
+        match self.cache.get_mut(id) {
+            Some(value) => *value = entry,
+            None => {
+                self.cache.insert(id, entry);
+            }
+        }
+    }
+
+    /// Returns the cache entry for `tile` after its request failed for the `failures`th time in a
+    /// row: a retry after a growing delay, or [`CachedTile::NotAvailable`] once
+    /// [`MAX_TILE_ATTEMPTS`] requests have failed.
+    ///
+    /// Only tiles wanted on screen are retried on their own. Failed prefetches keep
+    /// `last_wanted: None` and are queued again by the prefetch pass, at prefetch priority
+    fn after_failure(
+        &mut self,
+        tile: TileId,
+        failures: u32,
+        last_wanted: Option<u64>,
+    ) -> CachedTile {
+        let retry_at = match next_attempt(tile, failures) {
+            Some(retry_at) => retry_at,
+            None => return CachedTile::NotAvailable,
+        };
+        if last_wanted.is_some() {
+            self.next_retry = Some(self.next_retry.map_or(retry_at, |next| next.min(retry_at)));
+        }
+        CachedTile::Failed {
+            failures,
+            retry_at,
+            last_wanted,
+        }
+    }
+
+    /// Requests the failed tiles that were on screen last frame again once their `retry_at` has
+    /// passed, so that they load without the map having to be moved
+    fn retry_failed(&mut self, now: Instant) {
+        match self.next_retry {
+            Some(next_retry) if next_retry <= now => {}
+            _ => return,
+        }
+        let frame = self.frame;
+        let mut next_retry = None;
+        let mut retried = Vec::new();
+        for (key, tile) in self.cache.iter_mut() {
+            if let CachedTile::Failed {
+                failures,
+                retry_at,
+                last_wanted,
+            } = *tile
+            {
+                //Tiles that left the screen are retried by `get_tile` if they come back, and
+                //failed prefetches by the prefetch pass
+                let last_wanted = match last_wanted {
+                    Some(last_wanted) if last_wanted + 1 >= frame => last_wanted,
+                    _ => continue,
+                };
+                if retry_at <= now {
+                    *tile = CachedTile::Pending {
+                        last_wanted,
+                        failures,
+                    };
+                    retried.push(u64_to_tile_coord(*key));
+                } else {
+                    next_retry =
+                        Some(next_retry.map_or(retry_at, |next: Instant| next.min(retry_at)));
+                }
+            }
+        }
+        self.next_retry = next_retry;
+        if retried.is_empty() {
+            return;
+        }
+
+        self.pending_count += retried.len();
+        crate::MAP_PERF_DATA.lock().tile_retries += retried.len() as u64;
+        let mut queue = self.dispatch.queue.lock();
+        for tile in retried {
+            queue.push(tile, false, tile_distance(tile, self.center));
+        }
+        drop(queue);
+        self.dispatch.wake.notify_one();
+    }
+
+    /// Queues low priority requests for the tiles around `viewport` at `zoom` that haven't been
+    /// requested yet, and for the failed prefetches among them whose `retry_at` has passed
+    fn prefetch(&mut self, viewport: &WorldViewport, zoom: u32) {
+        let next_zoom = self.prefetch.next_zoom && zoom < self.max_zoom;
+        let now = Instant::now();
+        let mut queue = self.dispatch.queue.lock();
+        for tile in prefetch_tiles(viewport, zoom, self.prefetch.radius, next_zoom) {
+            let key = tile_coord_to_u64(tile);
+            let failures = match self.cache.get(key) {
+                None => 0,
+                Some(CachedTile::Failed {
+                    failures,
+                    retry_at,
+                    last_wanted: None,
+                }) if *retry_at <= now => *failures,
+                Some(_) => continue,
+            };
+            queue.push(tile, true, tile_distance(tile, self.center));
+            let entry = CachedTile::Prefetching { failures };
+            match self.cache.get_mut(key) {
+                Some(value) => *value = entry,
+                None => {
+                    self.cache.insert(key, entry);
+                }
+            }
+        }
+        if !queue.is_empty() {
+            self.dispatch.wake.notify_one();
+        }
+    }
+
+    /// Drops the queued requests for tiles that weren't fetched last frame, so that the
+    /// connections go to tiles still on screen. Queued prefetches are dropped too, since the
+    /// prefetch pass queues them again around the new view.
+    ///
+    /// The rest of the queue is reordered around the new middle of the screen
+    fn cancel_offscreen_requests(&mut self) {
+        let frame = self.frame;
+        let cache = &self.cache;
+        let mut queue = self.dispatch.queue.lock();
+        let cancelled = queue.cancel(|request| {
+            !request.prefetch
+                && matches!(
+                    cache.get(tile_coord_to_u64(request.tile)),
+                    Some(CachedTile::Pending { last_wanted, .. }) if last_wanted + 1 >= frame
+                )
+        });
+        queue.reprioritize(self.center);
+        drop(queue);
+
+        let mut offscreen = 0;
+        for request in cancelled {
+            //Forget the tile so that it is requested again if it comes back on screen
+            match self.cache.remove(tile_coord_to_u64(request.tile)) {
+                Some(CachedTile::Pending { .. }) => {
+                    self.pending_count -= 1;
+                    offscreen += 1;
+                }
+                Some(CachedTile::Prefetching { .. }) | None => {}
+                Some(other) => {
+                    self.cache.insert(tile_coord_to_u64(request.tile), other);
+                }
+            }
+        }
+        if offscreen > 0 {
+            crate::MAP_PERF_DATA.lock().cancelled_requests += offscreen;
+        }
+    }
+
+    /// Forgets the tiles that no backend had and the tiles waiting to be retried, so that they are
+    /// requested again the next time they are needed. Used when going back online, since
+    /// downloads were skipped while offline
+    pub fn retry_unavailable(&mut self) {
+        let keys: Vec<u64> = self
+            .cache
+            .iter()
+            .filter(|(_, tile)| {
+                matches!(tile, CachedTile::NotAvailable | CachedTile::Failed { .. })
+            })
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            self.cache.remove(key);
+        }
+        self.next_retry = None;
+    }
+
+    /// Frees every texture owned by this pipeline and forgets all tiles, so they are requested
+    /// again the next time they are needed
+    pub fn clear(&mut self, image_map: &mut conrod_core::image::Map<glium::Texture2d>) {
+        for (_, tile) in self.cache.iter() {
+            if let CachedTile::Cached { id, .. } = tile {
+                let _ = image_map.remove(*id);
+            }
+        }
+        self.cache.clear();
+        self.dispatch.queue.lock().clear();
+        {
+            let mut guard = crate::MAP_PERF_DATA.lock();
+            guard.tiles_on_gpu -= self.texture_count;
+            guard.tile_texture_bytes -= self.texture_bytes;
+        }
+        self.texture_count = 0;
+        self.texture_bytes = 0;
+        self.pending_count = 0;
+        self.next_retry = None;
+    }
+
+    /// Counts a new texture of `bytes` here and in [`crate::MAP_PERF_DATA`]
+    fn track_upload(&mut self, bytes: usize) {
+        self.texture_count += 1;
+        self.texture_bytes += bytes;
+        let mut guard = crate::MAP_PERF_DATA.lock();
+        guard.tiles_on_gpu += 1;
+        guard.tile_texture_bytes += bytes;
+    }
+
+    /// Stops counting a freed texture of `bytes`
+    fn track_free(&mut self, bytes: usize) {
+        self.texture_count -= 1;
+        self.texture_bytes -= bytes;
+        let mut guard = crate::MAP_PERF_DATA.lock();
+        guard.tiles_on_gpu -= 1;
+        guard.tile_texture_bytes -= bytes;
+    }
+
+    /// Frees the least recently rendered textures if there are more than `max_textures`
+    fn evict_textures(&mut self, image_map: &mut conrod_core::image::Map<glium::Texture2d>) {
+        if self.texture_count <= self.max_textures {
+            return;
+        }
+        let cached = self.cache.iter().filter_map(|(key, tile)| match tile {
+            CachedTile::Cached { last_used, .. } => Some((*key, *last_used)),
+            _ => None,
+        });
+        //Tiles used last frame are likely still on screen
+        let keep_after = self.frame.saturating_sub(2);
+        let evicted = select_evictions(cached, self.texture_count - self.max_textures, keep_after);
+
+        for key in evicted {
+            if let Some(CachedTile::Cached { id, bytes, .. }) = self.cache.remove(key) {
+                let _ = image_map.remove(id);
+                self.track_free(bytes);
+            }
+        }
+    }
+}
+
+/// Returns when `tile` should be requested again after its request failed for the `failures`th
+/// time in a row, or `None` once [`MAX_TILE_ATTEMPTS`] requests have failed
+pub fn next_attempt(tile: TileId, failures: u32) -> Option<Instant> {
+    if failures >= MAX_TILE_ATTEMPTS {
+        println!("Giving up on tile {:?} after {} failures", tile, failures);
+        crate::MAP_PERF_DATA.lock().failed_tiles += 1;
+        return None;
+    }
+    Some(Instant::now() + retry_delay(failures))
+}
+
+/// Returns how long to wait before requesting a tile again after `failures` requests for it
+/// failed in a row
+pub fn retry_delay(failures: u32) -> Duration {
+    let doublings = failures.saturating_sub(1).min(16);
+    (BASE_RETRY_DELAY * 2u32.pow(doublings)).min(MAX_RETRY_DELAY)
+}
+
+/// Returns the tiles within `radius` tiles of the edges of `viewport` at `zoom`, nearest rings
+/// first, followed by the tiles covering `viewport` one zoom level deeper if `next_zoom` is set.
+///
+/// Tiles on screen are included, since they may not have been requested yet either
+pub fn prefetch_tiles(
+    viewport: &WorldViewport,
+    zoom: u32,
+    radius: u32,
+    next_zoom: bool,
+) -> Vec<TileId> {
+    let mut tiles = Vec::new();
+    let covering = |zoom: u32, radius: u32| {
+        let tiles_across = 2u64.pow(zoom) as i64;
+        let scale = tiles_across as f64;
+        let radius = radius as i64;
+        let left = (viewport.top_left.x * scale).floor() as i64 - radius;
+        let right = (viewport.bottom_right.x * scale).floor() as i64 + radius;
+        //The world wraps horizontally, so never visit the same column twice
+        let right = right.min(left + tiles_across - 1);
+        let top = ((viewport.top_left.y * scale).floor() as i64 - radius).max(0);
+        let bottom =
+            ((viewport.bottom_right.y * scale).floor() as i64 + radius).min(tiles_across - 1);
+        (left, right, top, bottom, tiles_across)
+    };
+
+    let (left, right, top, bottom, tiles_across) = covering(zoom, radius);
+    let (inner_left, inner_right, inner_top, inner_bottom, _) = covering(zoom, 0);
+    for ring in 0..=radius as i64 {
+        for y in top..=bottom {
+            for x in left..=right {
+                let distance = (inner_left - x)
+                    .max(x - inner_right)
+                    .max(inner_top - y)
+                    .max(y - inner_bottom)
+                    .max(0);
+                if distance == ring {
+                    tiles.push(TileId::new(
+                        x.rem_euclid(tiles_across) as u32,
+                        y as u32,
+                        zoom,
+                    ));
+                }
+            }
+        }
+    }
+    if next_zoom {
+        let (left, right, top, bottom, tiles_across) = covering(zoom + 1, 0);
+        for y in top..=bottom {
+            for x in left..=right {
+                tiles.push(TileId::new(
+                    x.rem_euclid(tiles_across) as u32,
+                    y as u32,
+                    zoom + 1,
+                ));
+            }
+        }
+    }
+    tiles
+}
+
+/// Picks the `count` least recently used tiles from `tiles` (pairs of key and last used frame),
+/// never picking tiles used after frame `keep_after`.
+///
+/// Fewer than `count` tiles are returned if there aren't enough old tiles
+pub fn select_evictions(
+    tiles: impl Iterator<Item = (u64, u64)>,
+    count: usize,
+    keep_after: u64,
+) -> Vec<u64> {
+    let mut candidates: Vec<_> = tiles
+        .filter(|&(_, last_used)| last_used <= keep_after)
+        .collect();
+    candidates.sort_unstable_by_key(|&(_, last_used)| last_used);
+    candidates
+        .into_iter()
+        .take(count)
+        .map(|(key, _)| key)
+        .collect()
+}
+
+/// Waits up to `timeout` for `request`, a request to the backend called `name`, recording the
+/// outcome in [`crate::MAP_PERF_DATA`]
+pub async fn request_with_stats<T>(
+    name: &'static str,
+    timeout: Duration,
+    request: impl std::future::Future<Output = Result<Option<T>, TileError>>,
+) -> Result<Option<T>, TileError> {
+    crate::MAP_PERF_DATA
+        .lock()
+        .backend_request_stats
+        .entry(name)
+        .or_default()
+        .pending += 1;
+
+    let result = match tokio::time::timeout(timeout, request).await {
+        Ok(result) => result,
+        Err(_) => Err(TileError::Timeout(timeout)),
+    };
+
+    let mut guard = crate::MAP_PERF_DATA.lock();
+    let stats = guard.backend_request_stats.entry(name).or_default();
+    stats.pending -= 1;
+    match &result {
+        Ok(Some(_)) => stats.succeeded += 1,
+        Ok(None) => stats.not_found += 1,
+        Err(TileError::Http(_) | TileError::Maptiler(_) | TileError::RainViewer(_)) => {
+            stats.http_errors += 1
+        }
+        Err(TileError::Image(_) | TileError::Vector(_)) => stats.decode_errors += 1,
+        Err(TileError::Timeout(_)) => stats.timeouts += 1,
+        Err(TileError::Io(_) | TileError::Join(_) | TileError::NoRadarFrames) => {
+            stats.other_errors += 1
+        }
+    }
+    result
+}
+
+/// An async loop that waits for tile requests, and dispatches them through the levels of
+/// cache to produce a texture.
+///
+/// At most [`MAX_REQUESTS_IN_FLIGHT`] requests run at once, taken from `dispatch` most important
+/// first. Only [`MAX_PREFETCHES_IN_FLIGHT`] of them may be prefetches. Stops once the pipeline
+/// closes the queue
 async fn tile_requester(
     upload_tx: Sender<MemoryTile>,
-    mut request_rx: UnboundedReceiver<TileId>,
+    dispatch: Arc<RequestDispatch>,
     backends: Arc<Vec<Box<dyn Backend>>>,
 ) {
     //TODO: Reduce Arcing here with some king of task queue that we select so that the lifetimes
     //work out
     let upload_tx = Arc::new(upload_tx);
-    while let Some(tile) = request_rx.recv().await {
-        //TODO: Limit concurrent requests. Maybe use some kind of convar or custom atomicint?
+    let slots = Arc::new(tokio::sync::Semaphore::new(MAX_REQUESTS_IN_FLIGHT));
+    let prefetch_slots = Arc::new(tokio::sync::Semaphore::new(MAX_PREFETCHES_IN_FLIGHT));
+    loop {
+        let slot = match slots.clone().acquire_owned().await {
+            Ok(slot) => slot,
+            Err(_) => return,
+        };
+        let (request, prefetch_slot) = loop {
+            //Created before checking the queue so that a wake up in between isn't missed
+            let woken = dispatch.wake.notified();
+            {
+                let mut queue = dispatch.queue.lock();
+                if queue.is_closed() {
+                    return;
+                }
+                let prefetch_slot = prefetch_slots.clone().try_acquire_owned().ok();
+                if let Some(request) = queue.pop(prefetch_slot.is_some()) {
+                    break (request, prefetch_slot.filter(|_| request.prefetch));
+                }
+            }
+            woken.await;
+        };
+        if !request.prefetch {
+            crate::MAP_PERF_DATA
+                .lock()
+                .tile_queue_time
+                .add_sample(request.queued_at.elapsed());
+        }
+
         let upload_tx = upload_tx.clone();
         let backends = backends.clone();
+        let dispatch = dispatch.clone();
         tokio::spawn(async move {
-            for backend in backends.iter() {
-                //Go through each level of cache and try to obtain tile
-                match backend.request(tile).await {
-                    Ok(Some(image)) => {
-                        let _ = upload_tx
-                            .send(MemoryTile {
-                                image: Some(image),
-                                id: tile,
-                            })
-                            .await;
-                        return;
-                    }
-                    Ok(None) => {}
-                    Err(err) => {
-                        println!("Error getting tile {:?}: {}", tile, err);
-                    }
-                }
-            }
-            let _ = upload_tx
-                .send(MemoryTile {
-                    image: None,
-                    id: tile,
-                })
-                .await;
+            fetch_tile(request.tile, &backends, &upload_tx).await;
+            //Free the slots before waking the requester so that it can use them
+            drop((slot, prefetch_slot));
+            dispatch.wake.notify_one();
         });
     }
 }
 
+/// Goes through each backend in order until one has `tile`, sending the result to `upload_tx`.
+///
+/// If no backend has the tile and any of them failed, the tile is sent as failed so that it is
+/// retried
+async fn fetch_tile(tile: TileId, backends: &[Box<dyn Backend>], upload_tx: &Sender<MemoryTile>) {
+    let mut failed = false;
+    for backend in backends.iter() {
+        //Go through each level of cache and try to obtain tile
+        let request = backend.request(tile);
+        match request_with_stats(backend.name(), backend.request_timeout(), request).await {
+            Ok(Some(image)) => {
+                let _ = upload_tx
+                    .send(MemoryTile {
+                        image: Some(image),
+                        id: tile,
+                        failed: false,
+                    })
+                    .await;
+                return;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                println!("Error getting tile {:?}: {}", tile, err);
+                failed = true;
+            }
+        }
+    }
+    let _ = upload_tx
+        .send(MemoryTile {
+            image: None,
+            id: tile,
+            failed,
+        })
+        .await;
+}
+
 /// Uploads an RGBA texture to the GPU
 fn create_texture(display: &glium::Display, image: image::RgbaImage) -> glium::Texture2d {
     let image_dimensions = image.dimensions();
@@ -303,6 +1033,184 @@ mod tests {
         }
     }
 
+    #[test]
+    fn evictions_are_oldest_first() {
+        let tiles = [(1, 10), (2, 3), (3, 7), (4, 1), (5, 12)];
+        assert_eq!(select_evictions(tiles.into_iter(), 2, 100), vec![4, 2]);
+        assert_eq!(
+            select_evictions(tiles.into_iter(), 0, 100),
+            Vec::<u64>::new()
+        );
+    }
+
+    #[test]
+    fn evictions_keep_recent_tiles() {
+        let tiles = [(1, 10), (2, 3), (3, 11), (4, 12)];
+        //Only tile 2 and 1 are old enough to evict, even though 3 were requested
+        assert_eq!(select_evictions(tiles.into_iter(), 3, 10), vec![2, 1]);
+        assert!(select_evictions(tiles.into_iter(), 3, 0).is_empty());
+    }
+
+    #[test]
+    fn prefetch_rings_wrap_and_clamp() {
+        let viewport = WorldViewport {
+            top_left: glam::DVec2::new(0.3, 0.1),
+            bottom_right: glam::DVec2::new(0.7, 0.4),
+        };
+        let tiles = prefetch_tiles(&viewport, 2, 1, false);
+        //The 4 tiles on screen, then the ring around them minus the row above the world
+        assert_eq!(tiles.len(), 12);
+        assert_eq!(
+            tiles[..4],
+            [
+                TileId::new(1, 0, 2),
+                TileId::new(2, 0, 2),
+                TileId::new(1, 1, 2),
+                TileId::new(2, 1, 2)
+            ]
+        );
+        assert!(tiles[4..].contains(&TileId::new(0, 2, 2)));
+        assert!(tiles[4..].contains(&TileId::new(3, 0, 2)));
+
+        let tiles = prefetch_tiles(&viewport, 2, 0, true);
+        assert_eq!(tiles.len(), 4 + 16);
+        assert!(tiles[4..].iter().all(|tile| tile.zoom == 3));
+
+        //Zoomed out past the edges of the world, every column is still only visited once
+        let viewport = WorldViewport {
+            top_left: glam::DVec2::new(-0.5, 0.0),
+            bottom_right: glam::DVec2::new(1.5, 1.0),
+        };
+        let tiles = prefetch_tiles(&viewport, 1, 2, false);
+        assert_eq!(tiles.len(), 4);
+        assert!(tiles.contains(&TileId::new(1, 0, 1)));
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_cap() {
+        assert_eq!(retry_delay(1), BASE_RETRY_DELAY);
+        assert_eq!(retry_delay(2), BASE_RETRY_DELAY * 2);
+        assert_eq!(retry_delay(4), BASE_RETRY_DELAY * 8);
+        assert_eq!(retry_delay(MAX_TILE_ATTEMPTS * 10), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u32::MAX), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn failed_tiles_on_screen_retry_on_their_own() {
+        //The requester task never runs on this runtime, so requests stay in the queue
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut pipeline = TilePipeline::new(Vec::new(), &runtime);
+        let on_screen = TileId::new(1, 1, 2);
+        let offscreen = TileId::new(2, 1, 2);
+        pipeline.frame = 5;
+        for (tile, last_wanted) in [(on_screen, 5), (offscreen, 1)] {
+            let entry = pipeline.after_failure(tile, 1, Some(last_wanted));
+            pipeline.cache.insert(tile_coord_to_u64(tile), entry);
+        }
+        assert!(!pipeline.has_pending());
+
+        //The next frame after the delay, without `get_tile` being called
+        pipeline.frame = 6;
+        let later = Instant::now() + retry_delay(1);
+        pipeline.retry_failed(later);
+        assert!(matches!(
+            pipeline.cache.get(tile_coord_to_u64(on_screen)),
+            Some(CachedTile::Pending { failures: 1, .. })
+        ));
+        assert!(matches!(
+            pipeline.cache.get(tile_coord_to_u64(offscreen)),
+            Some(CachedTile::Failed { .. })
+        ));
+        assert_eq!(pipeline.dispatch.queue.lock().len(), 1);
+        assert!(pipeline.has_pending());
+        assert_eq!(pipeline.next_retry, None);
+    }
+
+    #[test]
+    fn failed_prefetches_stay_prefetches() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut pipeline = TilePipeline::new(Vec::new(), &runtime);
+        let tile = TileId::new(1, 1, 2);
+        pipeline.frame = 5;
+        pipeline.cache.insert(
+            tile_coord_to_u64(tile),
+            CachedTile::Prefetching { failures: 0 },
+        );
+
+        pipeline.store_missing(tile, true);
+        assert!(matches!(
+            pipeline.cache.get(tile_coord_to_u64(tile)),
+            Some(CachedTile::Failed {
+                failures: 1,
+                last_wanted: None,
+                ..
+            })
+        ));
+        assert_eq!(pipeline.pending_count, 0);
+        assert_eq!(pipeline.next_retry, None);
+
+        //Not retried as if it had been on screen
+        pipeline.frame = 6;
+        pipeline.retry_failed(Instant::now() + retry_delay(1));
+        assert!(matches!(
+            pipeline.cache.get(tile_coord_to_u64(tile)),
+            Some(CachedTile::Failed { .. })
+        ));
+        assert_eq!(pipeline.dispatch.queue.lock().len(), 0);
+
+        //Once on screen it retries on its own like any other tile
+        assert!(pipeline.get_tile(tile).is_none());
+        assert!(pipeline.next_retry.is_some());
+    }
+
+    #[test]
+    fn failed_prefetches_are_not_pending() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut pipeline = TilePipeline::new(Vec::new(), &runtime);
+        pipeline.set_prefetch(PrefetchSettings {
+            radius: 0,
+            next_zoom: false,
+        });
+        //Only covers tile (1, 0) at zoom 2
+        let viewport = WorldViewport {
+            top_left: glam::DVec2::new(0.3, 0.1),
+            bottom_right: glam::DVec2::new(0.45, 0.2),
+        };
+        let tile = TileId::new(1, 0, 2);
+        pipeline.prefetch(&viewport, 2);
+        assert_eq!(pipeline.dispatch.queue.lock().pop(true).unwrap().tile, tile);
+
+        pipeline.store_missing(tile, true);
+        assert_eq!(pipeline.pending_count, 0);
+        assert!(!pipeline.has_pending());
+
+        //Not queued again until the delay has passed
+        pipeline.prefetch(&viewport, 2);
+        assert_eq!(pipeline.dispatch.queue.lock().len(), 0);
+
+        if let Some(CachedTile::Failed { retry_at, .. }) =
+            pipeline.cache.get_mut(tile_coord_to_u64(tile))
+        {
+            *retry_at = Instant::now();
+        }
+        pipeline.prefetch(&viewport, 2);
+        let request = pipeline.dispatch.queue.lock().pop(true).unwrap();
+        assert_eq!(request.tile, tile);
+        assert!(request.prefetch);
+        assert!(matches!(
+            pipeline.cache.get(tile_coord_to_u64(tile)),
+            Some(CachedTile::Prefetching { failures: 1 })
+        ));
+        assert_eq!(pipeline.pending_count, 0);
+        assert!(!pipeline.has_pending());
+    }
+
     #[test]
     fn tile_and_intmap() {
         let tile = TileId {
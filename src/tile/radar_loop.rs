@@ -0,0 +1,243 @@
+use super::{
+    offline::OfflineGate, weather_requester::WeatherRequester, ColorRamp, RadarFrame, TilePipeline,
+    WeatherIndex,
+};
+
+use std::sync::{atomic::AtomicBool, Arc};
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+
+/// How long each radar frame is shown while the loop is playing
+pub const RADAR_FRAME_TIME: Duration = Duration::from_millis(500);
+
+/// The most textures each radar frame keeps on the GPU. Lower than
+/// [`super::DEFAULT_MAX_TEXTURES`] since every frame covers the same area
+const FRAME_MAX_TEXTURES: usize = 256;
+
+/// The pipeline of a single radar frame
+struct LoopFrame {
+    /// The unix time in seconds of the frame
+    time: u64,
+    pipeline: TilePipeline,
+    /// True if every tile on screen had loaded as of the last [`RadarLoop::prefetch`]
+    loaded: bool,
+}
+
+/// Animates the weather layer through the recent radar frames listed by a [`WeatherIndex`].
+///
+/// Each frame has its own [`TilePipeline`], so tiles are only downloaded once per frame no matter
+/// how many times the loop repeats. While playing, the tiles on screen are loaded for every frame,
+/// and frames are skipped until they have loaded. Frames are freed once RainViewer stops listing
+/// them
+pub struct RadarLoop {
+    index: Arc<WeatherIndex>,
+    /// Stops new frames from being downloaded while set
+    offline: Arc<AtomicBool>,
+    /// The pipeline for each frame, sorted oldest first
+    frames: Vec<LoopFrame>,
+    /// The time of the frame on screen, or `None` for the newest frame, which is drawn by the
+    /// regular [`super::TileKind::Weather`] pipeline
+    displayed: Option<u64>,
+    playing: bool,
+    last_step: Instant,
+    /// The palette every frame is recolored with
+    color_ramp: ColorRamp,
+}
+
+impl RadarLoop {
+    pub fn new(index: Arc<WeatherIndex>, offline: Arc<AtomicBool>) -> Self {
+        Self {
+            index,
+            offline,
+            frames: Vec::new(),
+            displayed: None,
+            playing: false,
+            last_step: Instant::now(),
+            color_ramp: ColorRamp::Classic,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Starts or pauses the animation. Pausing keeps the current frame on screen
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+    }
+
+    /// The unix time in seconds of the frame on screen, or `None` when showing the newest frame
+    pub fn displayed_time(&self) -> Option<u64> {
+        self.displayed
+    }
+
+    /// Returns the pipeline of the frame on screen, or `None` if the newest frame should be drawn
+    pub fn displayed_pipeline(&mut self) -> Option<&mut TilePipeline> {
+        let displayed = self.displayed?;
+        self.frames
+            .iter_mut()
+            .find(|frame| frame.time == displayed)
+            .map(|frame| &mut frame.pipeline)
+    }
+
+    /// Returns true if the frame on screen is waiting for tiles, or while playing, if any frame is
+    pub fn has_pending(&self) -> bool {
+        self.frames.iter().any(|frame| {
+            (self.playing || Some(frame.time) == self.displayed) && frame.pipeline.has_pending()
+        })
+    }
+
+    /// Called every frame while the weather layer is shown to load the tiles on screen.
+    ///
+    /// `load` is called with the pipeline of every frame while playing, or only the frame on
+    /// screen while paused. It requests the tiles on screen and returns true if they have all
+    /// loaded, so that frames still loading are skipped
+    pub fn prefetch(&mut self, mut load: impl FnMut(&mut TilePipeline) -> bool) {
+        for frame in &mut self.frames {
+            if self.playing || Some(frame.time) == self.displayed {
+                frame.loaded = load(&mut frame.pipeline);
+            }
+        }
+    }
+
+    /// Recolors every frame with `color_ramp`.
+    ///
+    /// Frames are only cleared when the ramp changes, and are skipped by the animation until
+    /// they load again with the new colors. New frames start with the current ramp
+    pub fn set_color_ramp(
+        &mut self,
+        color_ramp: ColorRamp,
+        image_map: &mut conrod_core::image::Map<glium::Texture2d>,
+    ) {
+        if self.color_ramp == color_ramp {
+            return;
+        }
+        self.color_ramp = color_ramp;
+        for frame in &mut self.frames {
+            frame.pipeline.set_color_ramp(color_ramp, image_map);
+            frame.loaded = false;
+        }
+    }
+
+    /// Requests the tiles of every frame that weren't available again, such as after going back
+    /// online
+    pub fn retry_unavailable(&mut self) {
+        for frame in &mut self.frames {
+            frame.pipeline.retry_unavailable();
+        }
+    }
+
+    /// Called every frame while the weather layer is shown.
+    ///
+    /// Frees frames that are no longer listed, and while playing, creates pipelines for newly
+    /// listed frames and advances the animation to the next frame that has loaded. Returns true
+    /// if a different frame should be drawn
+    pub fn update(
+        &mut self,
+        now: Instant,
+        runtime: &Runtime,
+        image_map: &mut conrod_core::image::Map<glium::Texture2d>,
+    ) -> bool {
+        let times = self.index.frame_times();
+
+        let (kept, expired): (Vec<_>, Vec<_>) = std::mem::take(&mut self.frames)
+            .into_iter()
+            .partition(|frame| times.contains(&frame.time));
+        self.frames = kept;
+        let mut changed = false;
+        for mut frame in expired {
+            frame.pipeline.clear(image_map);
+            if Some(frame.time) == self.displayed {
+                //Fall back to the newest frame instead of leaving the weather layer blank
+                self.displayed = None;
+                changed = true;
+            }
+        }
+
+        if !self.playing {
+            return changed;
+        }
+        for &time in &times {
+            if self.frames.iter().all(|frame| frame.time != time) {
+                let requester =
+                    WeatherRequester::new(self.index.clone(), RadarFrame::At(time), None);
+                let requester = OfflineGate::new(requester, self.offline.clone());
+                let mut pipeline = TilePipeline::new(vec![Box::new(requester)], runtime);
+                pipeline.set_max_textures(FRAME_MAX_TEXTURES);
+                //Nothing is cached yet, so this doesn't clear anything
+                pipeline.set_color_ramp(self.color_ramp, image_map);
+                self.frames.push(LoopFrame {
+                    time,
+                    pipeline,
+                    loaded: false,
+                });
+            }
+        }
+        self.frames.sort_unstable_by_key(|frame| frame.time);
+
+        if now.duration_since(self.last_step) < RADAR_FRAME_TIME {
+            return changed;
+        }
+        self.last_step = now;
+        let loaded: Vec<u64> = self
+            .frames
+            .iter()
+            .filter(|frame| frame.loaded)
+            .map(|frame| frame.time)
+            .collect();
+        let next = next_frame(&times, &loaded, self.displayed);
+        changed |= next != self.displayed;
+        self.displayed = next;
+        changed
+    }
+}
+
+/// Returns the first frame after `current` in `times` which is in `loaded`, wrapping around to the
+/// oldest. Starts from the oldest frame if `current` isn't listed, and stays on `current` if no
+/// frame has loaded
+fn next_frame(times: &[u64], loaded: &[u64], current: Option<u64>) -> Option<u64> {
+    let start = current
+        .and_then(|current| times.iter().position(|&time| time == current))
+        .map_or(0, |i| i + 1);
+    (0..times.len())
+        .map(|offset| times[(start + offset) % times.len()])
+        .find(|time| loaded.contains(time))
+        .or(current)
+}
+
+/// Formats a unix time in seconds as `HH:MM UTC`
+pub fn format_utc_time(unix_secs: u64) -> String {
+    let secs_today = unix_secs % (60 * 60 * 24);
+    format!("{:02}:{:02} UTC", secs_today / 3600, secs_today % 3600 / 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_loop_oldest_to_newest() {
+        let times = [600, 1200, 1800];
+        assert_eq!(next_frame(&times, &times, None), Some(600));
+        assert_eq!(next_frame(&times, &times, Some(600)), Some(1200));
+        assert_eq!(next_frame(&times, &times, Some(1800)), Some(600));
+        //The frame on screen expired, so start over
+        assert_eq!(next_frame(&times, &times, Some(0)), Some(600));
+        assert_eq!(next_frame(&[], &[], None), None);
+    }
+
+    #[test]
+    fn frames_still_loading_are_skipped() {
+        let times = [600, 1200, 1800];
+        assert_eq!(next_frame(&times, &[1800], Some(600)), Some(1800));
+        assert_eq!(next_frame(&times, &[600, 1800], Some(600)), Some(1800));
+        assert_eq!(next_frame(&times, &[1200], None), Some(1200));
+        //Nothing else has loaded, so keep showing the same frame
+        assert_eq!(next_frame(&times, &[600], Some(600)), Some(600));
+        assert_eq!(next_frame(&times, &[], Some(1200)), Some(1200));
+        assert_eq!(next_frame(&times, &[], None), None);
+
+        assert_eq!(format_utc_time(0), "00:00 UTC");
+        assert_eq!(format_utc_time(1_650_000_000), "05:20 UTC");
+    }
+}
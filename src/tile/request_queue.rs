@@ -0,0 +1,155 @@
+//! Orders tile requests that are waiting for a free connection, so that tiles in the middle of the
+//! screen load first and prefetched tiles never hold up tiles on screen
+
+use std::time::Instant;
+
+use glam::DVec2;
+
+use super::TileId;
+
+/// A tile request that hasn't been sent yet
+#[derive(Copy, Clone, Debug)]
+pub struct QueuedRequest {
+    pub tile: TileId,
+    /// True if the tile isn't on screen, but was requested ahead of time
+    pub prefetch: bool,
+    /// How far the middle of the tile is from the middle of the screen, in world units
+    pub distance: f64,
+    pub queued_at: Instant,
+}
+
+/// Tile requests waiting to be sent, popped most important first: requests for tiles on screen
+/// before prefetches, then the tiles closest to the middle of the screen
+#[derive(Debug, Default)]
+pub struct RequestQueue {
+    requests: Vec<QueuedRequest>,
+    /// Set once the pipeline that owns this queue is dropped, so that its requester stops
+    closed: bool,
+}
+
+impl RequestQueue {
+    pub fn push(&mut self, tile: TileId, prefetch: bool, distance: f64) {
+        self.requests.push(QueuedRequest {
+            tile,
+            prefetch,
+            distance,
+            queued_at: Instant::now(),
+        });
+    }
+
+    /// Removes and returns the most important request. Prefetches are skipped unless
+    /// `allow_prefetch` is set
+    pub fn pop(&mut self, allow_prefetch: bool) -> Option<QueuedRequest> {
+        let index = self
+            .requests
+            .iter()
+            .enumerate()
+            .filter(|(_, request)| allow_prefetch || !request.prefetch)
+            .min_by(|(_, a), (_, b)| {
+                a.prefetch
+                    .cmp(&b.prefetch)
+                    .then(a.distance.total_cmp(&b.distance))
+            })
+            .map(|(i, _)| i)?;
+        Some(self.requests.swap_remove(index))
+    }
+
+    /// Turns the queued prefetch of `tile`, if there is one, into a request for a tile on screen
+    pub fn promote(&mut self, tile: TileId, distance: f64) {
+        if let Some(request) = self.requests.iter_mut().find(|r| r.tile == tile) {
+            request.prefetch = false;
+            request.distance = distance;
+        }
+    }
+
+    /// Removes the requests that `keep` returns false for, returning them
+    pub fn cancel(&mut self, mut keep: impl FnMut(&QueuedRequest) -> bool) -> Vec<QueuedRequest> {
+        let (kept, cancelled) = std::mem::take(&mut self.requests)
+            .into_iter()
+            .partition(|request| keep(request));
+        self.requests = kept;
+        cancelled
+    }
+
+    /// Recomputes how far each request is from the middle of the screen
+    pub fn reprioritize(&mut self, center: DVec2) {
+        for request in &mut self.requests {
+            request.distance = tile_distance(request.tile, center);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.requests.clear();
+    }
+
+    pub fn close(&mut self) {
+        self.closed = true;
+        self.requests.clear();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+/// Returns the distance in world units between the middle of `tile` and `center`, going the short
+/// way around the world
+pub fn tile_distance(tile: TileId, center: DVec2) -> f64 {
+    let tiles_across = 2f64.powi(tile.zoom as i32);
+    let middle = DVec2::new(tile.x as f64 + 0.5, tile.y as f64 + 0.5) / tiles_across;
+    let dx = (middle.x - center.x + 0.5).rem_euclid(1.0) - 0.5;
+    DVec2::new(dx, middle.y - center.y).length()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn visible_tiles_pop_first_nearest_first() {
+        let center = DVec2::new(0.5, 0.5);
+        let mut queue = RequestQueue::default();
+        for (x, prefetch) in [(0, false), (3, true), (2, false), (1, false)] {
+            let tile = TileId::new(x, 1, 2);
+            queue.push(tile, prefetch, tile_distance(tile, center));
+        }
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop(true))
+            .map(|request| request.tile.x)
+            .collect();
+        //Tiles 1 and 2 touch the middle of the screen, tile 3 is only a prefetch
+        assert_eq!(order[2..], [0, 3]);
+
+        queue.push(TileId::new(3, 1, 2), true, 0.0);
+        assert!(queue.pop(false).is_none());
+        queue.promote(TileId::new(3, 1, 2), 0.1);
+        assert!(!queue.pop(false).unwrap().prefetch);
+    }
+
+    #[test]
+    fn cancel_and_wrap() {
+        let mut queue = RequestQueue::default();
+        for x in 0..4 {
+            queue.push(TileId::new(x, 0, 2), false, 0.0);
+        }
+        let cancelled = queue.cancel(|request| request.tile.x % 2 == 0);
+        assert_eq!(cancelled.len(), 2);
+        assert_eq!(queue.len(), 2);
+
+        //The leftmost tile is right next to a center just past the antimeridian
+        let center = DVec2::new(1.0, 0.125);
+        assert!((tile_distance(TileId::new(0, 0, 2), center) - 0.125).abs() < 1e-9);
+        queue.reprioritize(center);
+        assert_eq!(queue.pop(true).unwrap().tile.x, 0);
+
+        queue.close();
+        assert!(queue.is_empty() && queue.is_closed());
+    }
+}
@@ -53,6 +53,10 @@ impl Backend for SatelliteRequester {
         Some(128)
     }
 
+    fn max_zoom(&self) -> Option<u32> {
+        Some(maptiler_cloud::TileSet::Satellite.max_zoom())
+    }
+
     fn ignore_transparent_tiles(&self) -> bool {
         false
     }
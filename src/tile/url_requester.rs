@@ -0,0 +1,298 @@
+use super::{
+    disk_cache::DiskCacheData, Backend, ReadinessStatus, TileError, TileId, REQUEST_TIMEOUT,
+};
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
+
+/// How far each request moves a mirror's error rate towards 0 or 1
+const ERROR_RATE_WEIGHT: f32 = 0.2;
+
+/// How long it takes a mirror's error rate to halve without any requests, so that a mirror which
+/// stopped being used after failing is tried again once it has had time to recover
+const ERROR_RATE_HALF_LIFE: Duration = Duration::from_secs(60);
+
+/// How long to wait for a mirror before moving on to the next one
+const MIRROR_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How recently a mirror has been failing
+#[derive(Copy, Clone, Debug)]
+struct MirrorHealth {
+    error_rate: f32,
+    updated: Instant,
+}
+
+impl MirrorHealth {
+    fn error_rate(&self, now: Instant) -> f32 {
+        let half_lives = now.saturating_duration_since(self.updated).as_secs_f32()
+            / ERROR_RATE_HALF_LIFE.as_secs_f32();
+        self.error_rate * 0.5f32.powf(half_lives)
+    }
+}
+
+/// Url templates for servers that host the same tiles, and the recent error rate of each
+pub struct Mirrors {
+    templates: Vec<String>,
+    health: Mutex<Vec<MirrorHealth>>,
+}
+
+impl Mirrors {
+    /// Creates a set of mirrors, preferring earlier templates while they are all healthy
+    pub fn new(templates: Vec<String>) -> Self {
+        assert!(!templates.is_empty(), "At least one url template is needed");
+        let healthy = MirrorHealth {
+            error_rate: 0.0,
+            updated: Instant::now(),
+        };
+        Self {
+            health: Mutex::new(vec![healthy; templates.len()]),
+            templates,
+        }
+    }
+
+    pub fn template(&self, index: usize) -> &str {
+        &self.templates[index]
+    }
+
+    /// Returns the index of every mirror, the ones that have failed least recently first.
+    ///
+    /// Error rates are compared in steps of a quarter, so a single failure doesn't move a mirror
+    /// behind the others, but failing repeatedly does
+    pub fn order(&self, now: Instant) -> Vec<usize> {
+        let health = self.health.lock();
+        let mut order: Vec<usize> = (0..self.templates.len()).collect();
+        order.sort_by_key(|&i| (health[i].error_rate(now) * 4.0) as u32);
+        order
+    }
+
+    /// Records whether a request to mirror `index` failed. Returns true if the mirror was the
+    /// preferred one and isn't anymore
+    pub fn record(&self, index: usize, failed: bool, now: Instant) -> bool {
+        let was_preferred = self.order(now)[0] == index;
+        {
+            let mut health = self.health.lock();
+            let rate = health[index].error_rate(now);
+            let target = if failed { 1.0 } else { 0.0 };
+            health[index] = MirrorHealth {
+                error_rate: rate + (target - rate) * ERROR_RATE_WEIGHT,
+                updated: now,
+            };
+        }
+        was_preferred && self.order(now)[0] != index
+    }
+}
+
+/// Requests raster tiles from any server that follows the common `{z}/{x}/{y}` url scheme, such as
+/// OpenStreetMap.
+///
+/// Tiles are downloaded from the healthiest of its mirrors, moving on to the next mirror if a
+/// request fails
+pub struct UrlRequester {
+    client: reqwest::Client,
+    mirrors: Mirrors,
+    name: &'static str,
+    tile_size: u32,
+    max_zoom: u32,
+    cache_data: DiskCacheData,
+    /// How long to wait for each mirror but the last before moving on to the next one
+    mirror_timeout: Duration,
+}
+
+impl UrlRequester {
+    /// Creates a new requester.
+    ///
+    /// Each of `url_templates` must contain `{z}`, `{x}`, and `{y}`, which are replaced with the
+    /// tile's zoom, x, and y coordinates. The first template is used while its server is healthy,
+    /// and the rest are mirrors of it. `max_zoom` is the deepest zoom level the servers provide
+    pub fn new(
+        name: &'static str,
+        url_templates: Vec<String>,
+        tile_size: u32,
+        max_zoom: u32,
+        cache_data: DiskCacheData,
+    ) -> Self {
+        //Public tile servers (notably OpenStreetMap) reject requests without a valid user agent
+        let client = reqwest::Client::builder()
+            .user_agent(concat!(
+                env!("CARGO_PKG_NAME"),
+                "/",
+                env!("CARGO_PKG_VERSION")
+            ))
+            .build()
+            .expect("Failed to create http client!");
+
+        Self {
+            client,
+            mirrors: Mirrors::new(url_templates),
+            name,
+            tile_size,
+            max_zoom,
+            cache_data,
+            mirror_timeout: MIRROR_TIMEOUT,
+        }
+    }
+
+    fn tile_url(&self, mirror: usize, tile: TileId) -> String {
+        self.mirrors
+            .template(mirror)
+            .replace("{z}", &tile.zoom.to_string())
+            .replace("{x}", &tile.x.to_string())
+            .replace("{y}", &tile.y.to_string())
+    }
+
+    /// Downloads `tile` from `mirror`, returning `None` if the server doesn't have it
+    async fn download(&self, mirror: usize, tile: TileId) -> Result<Option<Vec<u8>>, TileError> {
+        let response = self.client.get(self.tile_url(mirror, tile)).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.bytes().await?.to_vec()))
+    }
+}
+
+#[async_trait]
+impl Backend for UrlRequester {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    async fn request_inner(&self, tile: TileId) -> Result<Option<Vec<u8>>, TileError> {
+        let order = self.mirrors.order(Instant::now());
+        let mut last_error = None;
+        for (i, &mirror) in order.iter().enumerate() {
+            let download = self.download(mirror, tile);
+            //Give up on slow mirrors while there are others left to try
+            let result = if i + 1 < order.len() {
+                match tokio::time::timeout(self.mirror_timeout, download).await {
+                    Ok(result) => result,
+                    Err(_) => Err(TileError::Timeout(self.mirror_timeout)),
+                }
+            } else {
+                download.await
+            };
+            if self.mirrors.record(mirror, result.is_err(), Instant::now()) {
+                println!(
+                    "{}: {} keeps failing, switching to a mirror",
+                    self.name,
+                    self.mirrors.template(mirror)
+                );
+            }
+            match result {
+                Ok(bytes) => {
+                    if let Some(bytes) = &bytes {
+                        let _ = self.cache_data.cache_tile(tile, bytes.as_slice()).await;
+                    }
+                    return Ok(bytes);
+                }
+                Err(err) => last_error = Some(err),
+            }
+        }
+        Err(last_error.expect("Mirrors are never empty"))
+    }
+
+    async fn readiness(&self, _tile: TileId) -> ReadinessStatus {
+        ReadinessStatus::Unknown
+    }
+
+    fn tile_size(&self) -> Option<u32> {
+        Some(self.tile_size)
+    }
+
+    fn max_zoom(&self) -> Option<u32> {
+        Some(self.max_zoom)
+    }
+
+    /// Every mirror but the last can use up its own timeout before the last is tried, so they
+    /// are added on top of the usual timeout
+    fn request_timeout(&self) -> Duration {
+        REQUEST_TIMEOUT + self.mirror_timeout * (self.mirrors.templates.len() as u32 - 1)
+    }
+
+    fn ignore_transparent_tiles(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_failures_move_mirror_back() {
+        let mirrors = Mirrors::new(vec!["a".to_owned(), "b".to_owned(), "c".to_owned()]);
+        let start = Instant::now();
+        assert_eq!(mirrors.order(start), [0, 1, 2]);
+
+        //One failure isn't enough to give up on the main server
+        assert!(!mirrors.record(0, true, start));
+        assert_eq!(mirrors.order(start), [0, 1, 2]);
+        assert!(mirrors.record(0, true, start));
+        assert_eq!(mirrors.order(start), [1, 2, 0]);
+        mirrors.record(1, true, start);
+        mirrors.record(1, true, start);
+        assert_eq!(mirrors.order(start), [2, 0, 1]);
+
+        //Failures are forgotten over time, so the main server is tried again
+        let later = start + ERROR_RATE_HALF_LIFE * 2;
+        assert_eq!(mirrors.order(later), [0, 1, 2]);
+        mirrors.record(2, false, later);
+        assert_eq!(mirrors.order(later), [0, 1, 2]);
+    }
+
+    #[test]
+    fn hanging_mirrors_fail_over_within_the_request_timeout() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let cache_data = DiskCacheData::new(
+            "test_mirror_cache",
+            "png",
+            Duration::from_secs(60),
+            0,
+            &runtime,
+        );
+        runtime.block_on(async {
+            //The first two mirrors accept connections but never answer
+            let mut urls = Vec::new();
+            for _ in 0..2 {
+                let hanging = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                urls.push(format!(
+                    "http://{}/{{z}}/{{x}}/{{y}}",
+                    hanging.local_addr().unwrap()
+                ));
+                tokio::spawn(async move {
+                    let _connection = hanging.accept().await;
+                    std::future::pending::<()>().await
+                });
+            }
+            let working = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            urls.push(format!(
+                "http://{}/{{z}}/{{x}}/{{y}}",
+                working.local_addr().unwrap()
+            ));
+            tokio::spawn(async move {
+                let (mut socket, _) = working.accept().await.unwrap();
+                let mut request = [0; 1024];
+                let _ = socket.read(&mut request).await;
+                let response = b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n";
+                socket.write_all(response).await.unwrap();
+            });
+
+            let mut requester = UrlRequester::new("Test", urls, 256, 19, cache_data);
+            requester.mirror_timeout = Duration::from_millis(200);
+            let timeout = requester.request_timeout();
+            assert!(timeout > requester.mirror_timeout * 2);
+
+            let start = Instant::now();
+            let request = requester.request_inner(TileId::new(0, 0, 0));
+            let result = super::super::request_with_stats("Test", timeout, request).await;
+            assert!(matches!(result, Ok(None)));
+            assert!(start.elapsed() >= requester.mirror_timeout * 2);
+        });
+    }
+}
@@ -0,0 +1,793 @@
+//! The vector base layer. Water and roads are read from vector tiles and drawn as triangles, so
+//! they stay sharp at any zoom level instead of being stretched like raster tiles
+
+use super::mvt::{GeomType, MvtError, VectorTile};
+use super::*;
+
+use conrod_core::color::Rgba;
+use enum_map::{enum_map, Enum, EnumMap};
+use glam::DVec2;
+use intmap::IntMap;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc::{Receiver, Sender, UnboundedReceiver, UnboundedSender};
+
+/// The size of a vector tile in logical pixels. Vector tiles are designed to be shown at 512
+/// pixels, so the text and road widths of the data match other maps at the same zoom
+pub const VECTOR_TILE_SIZE: u32 = 512;
+
+/// The layers that water polygons are read from
+const WATER_LAYERS: [&str; 1] = ["water"];
+
+/// The layers that roads are read from. `transportation` is used by OpenMapTiles, and `road` by
+/// Mapbox Streets
+const ROAD_LAYERS: [&str; 2] = ["transportation", "road"];
+
+/// The most decoded tiles kept in memory before the least recently drawn are dropped
+const MAX_VECTOR_TILES: usize = 512;
+
+/// The kinds of lines drawn by the vector layer, in the order they are drawn
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Enum)]
+pub enum LineKind {
+    /// The edges of water
+    Coastline,
+    MinorRoad,
+    MajorRoad,
+}
+
+impl LineKind {
+    /// Returns the kind of road with the `class` property `class`, or `None` for things that
+    /// aren't drawn such as paths and railways
+    fn road(class: &str) -> Option<Self> {
+        match class {
+            "motorway" | "trunk" | "primary" => Some(LineKind::MajorRoad),
+            "secondary" | "tertiary" | "minor" | "street" | "service" => Some(LineKind::MinorRoad),
+            _ => None,
+        }
+    }
+}
+
+/// How a kind of line is drawn
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LineStyle {
+    /// A color written as `#rrggbb`
+    pub color: String,
+    /// The width of the line in logical pixels
+    pub width: f64,
+}
+
+impl LineStyle {
+    fn new(color: &str, width: f64) -> Self {
+        Self {
+            color: color.to_owned(),
+            width,
+        }
+    }
+}
+
+/// Where vector tiles are downloaded from, and the stylesheet used to draw them. Colors are
+/// written as `#rrggbb`
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct VectorSettings {
+    /// Must contain `{z}`, `{x}`, and `{y}`. The tiles should follow the OpenMapTiles schema
+    pub url: String,
+    /// Servers with the same tiles as `url`, used when it keeps failing
+    pub mirrors: Vec<String>,
+    /// The deepest zoom level the server provides
+    pub max_zoom: u32,
+    pub land: String,
+    pub water: String,
+    pub coastline: LineStyle,
+    pub minor_road: LineStyle,
+    pub major_road: LineStyle,
+}
+
+impl Default for VectorSettings {
+    fn default() -> Self {
+        Self {
+            url: "https://api.maptiler.com/tiles/v3/{z}/{x}/{y}.pbf?key=GBnoGxmU64rzYqypBLp9"
+                .to_owned(),
+            mirrors: Vec::new(),
+            max_zoom: 14,
+            land: "#f2efe9".to_owned(),
+            water: "#aad3df".to_owned(),
+            coastline: LineStyle::new("#7fa7b8", 1.0),
+            minor_road: LineStyle::new("#ffffff", 1.5),
+            major_road: LineStyle::new("#f9b25c", 3.0),
+        }
+    }
+}
+
+impl VectorSettings {
+    /// Every color in the stylesheet, named as they are in the config file
+    pub fn colors(&self) -> [(&'static str, &str); 5] {
+        [
+            ("land", self.land.as_str()),
+            ("water", self.water.as_str()),
+            ("coastline.color", self.coastline.color.as_str()),
+            ("minor-road.color", self.minor_road.color.as_str()),
+            ("major-road.color", self.major_road.color.as_str()),
+        ]
+    }
+
+    /// The style of each kind of line
+    pub fn lines(&self) -> EnumMap<LineKind, &LineStyle> {
+        enum_map! {
+            LineKind::Coastline => &self.coastline,
+            LineKind::MinorRoad => &self.minor_road,
+            LineKind::MajorRoad => &self.major_road,
+        }
+    }
+}
+
+/// The colors and line widths of [`VectorSettings`], ready to draw with
+#[derive(Clone, Debug)]
+pub struct VectorPalette {
+    pub land: Rgba,
+    pub water: Rgba,
+    /// The color and width in logical pixels of each kind of line
+    pub lines: EnumMap<LineKind, (Rgba, f64)>,
+}
+
+impl VectorPalette {
+    /// Reads the stylesheet in `settings`. Invalid colors are drawn black
+    pub fn new(settings: &VectorSettings) -> Self {
+        let color = |text: &str| {
+            let [r, g, b] = crate::parse_hex_color(text).unwrap_or([0.0; 3]);
+            Rgba(r, g, b, 1.0)
+        };
+        let lines = settings.lines();
+        Self {
+            land: color(&settings.land),
+            water: color(&settings.water),
+            lines: enum_map! { kind => (color(&lines[kind].color), lines[kind].width) },
+        }
+    }
+}
+
+/// The shapes of one vector tile, grouped by how they are drawn.
+///
+/// Points are in tile coordinates, where 0 is the top left of the tile and 1 is the bottom right
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TileGeometry {
+    pub water: Vec<[DVec2; 3]>,
+    /// Land inside of water, such as islands, drawn on top of `water`
+    pub islands: Vec<[DVec2; 3]>,
+    pub lines: EnumMap<LineKind, Vec<Vec<DVec2>>>,
+}
+
+impl TileGeometry {
+    /// Picks out the water and roads of `tile`, splitting areas into triangles
+    pub fn new(tile: &VectorTile) -> Self {
+        let mut geometry = Self::default();
+        let layers = |names: &'static [&'static str]| {
+            tile.layers
+                .iter()
+                .filter(move |layer| names.contains(&layer.name.as_str()))
+                .flat_map(|layer| layer.features.iter())
+        };
+
+        for feature in layers(&WATER_LAYERS) {
+            if feature.geom_type != GeomType::Polygon {
+                continue;
+            }
+            for ring in &feature.geometry {
+                //Outer rings have a positive area, and holes have a negative area
+                if signed_area(ring) > 0.0 {
+                    geometry.water.extend(triangulate(ring));
+                } else {
+                    geometry.islands.extend(triangulate(ring));
+                }
+                geometry.lines[LineKind::Coastline].extend(coastline_segments(ring));
+            }
+        }
+
+        for feature in layers(&ROAD_LAYERS) {
+            if feature.geom_type != GeomType::LineString {
+                continue;
+            }
+            if let Some(kind) = feature.property("class").and_then(LineKind::road) {
+                geometry.lines[kind].extend(feature.geometry.iter().cloned());
+            }
+        }
+        geometry
+    }
+}
+
+/// Returns twice the area of `ring` using the surveyor's formula. Positive if the points go
+/// clockwise on screen, where y points down
+fn signed_area(ring: &[DVec2]) -> f64 {
+    let next = ring.iter().cycle().skip(1);
+    ring.iter().zip(next).map(|(a, b)| a.perp_dot(*b)).sum()
+}
+
+/// Splits the polygon `ring` into triangles by clipping ears.
+///
+/// The ring must not cross itself. If it does, whatever can't be split into ears is filled as a
+/// fan, which may draw outside of the ring
+pub fn triangulate(ring: &[DVec2]) -> Vec<[DVec2; 3]> {
+    let mut indices: Vec<usize> = (0..ring.len()).collect();
+    if signed_area(ring) < 0.0 {
+        indices.reverse();
+    }
+    let mut triangles = Vec::with_capacity(ring.len().saturating_sub(2));
+
+    let mut i = 0;
+    let mut since_last_ear = 0;
+    while indices.len() > 3 && since_last_ear < indices.len() {
+        let len = indices.len();
+        let a = ring[indices[(i + len - 1) % len]];
+        let b = ring[indices[i]];
+        let c = ring[indices[(i + 1) % len]];
+        if is_ear(a, b, c, ring, &indices) {
+            triangles.push([a, b, c]);
+            indices.remove(i);
+            i %= indices.len();
+            since_last_ear = 0;
+        } else {
+            i = (i + 1) % len;
+            since_last_ear += 1;
+        }
+    }
+
+    for pair in indices.windows(2).skip(1) {
+        triangles.push([ring[indices[0]], ring[pair[0]], ring[pair[1]]]);
+    }
+    triangles
+}
+
+/// Returns true if the corner `a`, `b`, `c` of a clockwise ring can be cut off without cutting
+/// through any of the other points
+fn is_ear(a: DVec2, b: DVec2, c: DVec2, ring: &[DVec2], indices: &[usize]) -> bool {
+    //Points of a clockwise ring turn right at convex corners, which is positive with y down
+    if (b - a).perp_dot(c - b) <= 0.0 {
+        return false;
+    }
+    let inside = |p: DVec2| {
+        (b - a).perp_dot(p - a) >= 0.0
+            && (c - b).perp_dot(p - b) >= 0.0
+            && (a - c).perp_dot(p - c) >= 0.0
+    };
+    indices
+        .iter()
+        .map(|&index| ring[index])
+        .all(|p| p == a || p == b || p == c || !inside(p))
+}
+
+/// Returns the parts of the outline of `ring` that should be drawn as coastline.
+///
+/// Water polygons are cut off a little past the edges of their tile, and these cuts aren't
+/// coastline. Edges lying completely past one side of the tile are left out
+pub fn coastline_segments(ring: &[DVec2]) -> Vec<Vec<DVec2>> {
+    let outside = |a: DVec2, b: DVec2| {
+        (a.x <= 0.0 && b.x <= 0.0)
+            || (a.x >= 1.0 && b.x >= 1.0)
+            || (a.y <= 0.0 && b.y <= 0.0)
+            || (a.y >= 1.0 && b.y >= 1.0)
+    };
+    let mut segments: Vec<Vec<DVec2>> = Vec::new();
+    let mut current: Vec<DVec2> = Vec::new();
+    let next = ring.iter().cycle().skip(1);
+    for (&a, &b) in ring.iter().zip(next) {
+        if outside(a, b) {
+            if current.len() > 1 {
+                segments.push(std::mem::take(&mut current));
+            }
+            current.clear();
+            continue;
+        }
+        if current.is_empty() {
+            current.push(a);
+        }
+        current.push(b);
+    }
+    if current.len() > 1 {
+        segments.push(current);
+    }
+    segments
+}
+
+/// Appends two triangles for each segment of the line through `points`, making it `width` wide.
+///
+/// The ends of each segment are extended by half of the width, so that corners don't leave gaps
+pub fn line_triangles(points: &[DVec2], width: f64, out: &mut Vec<[DVec2; 3]>) {
+    for pair in points.windows(2) {
+        let direction = pair[1] - pair[0];
+        let length = direction.length();
+        if length == 0.0 {
+            continue;
+        }
+        let along = direction * (width / 2.0 / length);
+        let normal = along.perp();
+        let (a, b) = (pair[0] - along, pair[1] + along);
+        out.push([a + normal, a - normal, b - normal]);
+        out.push([a + normal, b - normal, b + normal]);
+    }
+}
+
+enum CachedGeometry {
+    NotAvailable,
+    Pending {
+        /// The number of earlier requests for this tile that failed
+        failures: u32,
+    },
+    /// The last request failed. The tile is requested again if it is still needed after
+    /// `retry_at`
+    Failed {
+        failures: u32,
+        retry_at: Instant,
+    },
+    Cached {
+        geometry: TileGeometry,
+        /// The value of `VectorPipeline::frame` the last time this tile was fetched with
+        /// [`VectorPipeline::get_tile`]
+        last_used: u64,
+    },
+}
+
+/// The result of a vector tile request
+struct LoadedGeometry {
+    tile: TileId,
+    geometry: Option<TileGeometry>,
+    /// True if `geometry` is `None` because a backend failed, rather than because no backend has
+    /// the tile
+    failed: bool,
+}
+
+/// Requests vector tiles and keeps the decoded geometry in memory, like [`TilePipeline`] does for
+/// raster tiles
+pub struct VectorPipeline {
+    cache: IntMap<CachedGeometry>,
+    upload_rx: Receiver<LoadedGeometry>,
+    request_tx: UnboundedSender<TileId>,
+    /// The deepest zoom level every backend can provide
+    max_zoom: u32,
+    palette: VectorPalette,
+    /// The number of `CachedGeometry::Cached` entries in `cache`
+    tile_count: usize,
+    /// The number of `CachedGeometry::Pending` entries in `cache`
+    pending_count: usize,
+    /// The earliest `retry_at` of the `CachedGeometry::Failed` entries in `cache`
+    next_retry: Option<Instant>,
+    /// Incremented on each call to [`VectorPipeline::update`]
+    frame: u64,
+}
+
+impl VectorPipeline {
+    /// Creates a pipeline which requests tiles from `backends`, with lower indices queried first,
+    /// and draws them using `settings`
+    pub fn new(
+        backends: Vec<Box<dyn Backend>>,
+        settings: &VectorSettings,
+        runtime: &Runtime,
+    ) -> Self {
+        let max_zoom = backends
+            .iter()
+            .filter_map(|backend| backend.max_zoom())
+            .fold(crate::MAX_ZOOM_LEVEL, u32::min);
+        let (upload_tx, upload_rx) = tokio::sync::mpsc::channel(24);
+        let (request_tx, request_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        runtime.spawn(vector_requester(upload_tx, request_rx, Arc::new(backends)));
+        Self {
+            cache: IntMap::with_capacity(1024),
+            upload_rx,
+            request_tx,
+            max_zoom,
+            palette: VectorPalette::new(settings),
+            tile_count: 0,
+            pending_count: 0,
+            next_retry: None,
+            frame: 0,
+        }
+    }
+
+    /// Returns the geometry of `tile`, or starts loading it, returning None on this frame and
+    /// subsequent frames until the asynchronous request finishes
+    pub fn get_tile(&mut self, tile: TileId) -> Option<&TileGeometry> {
+        let key = tile_coord_to_u64(tile);
+        let failures = match self.cache.get(key) {
+            None => Some(0),
+            Some(CachedGeometry::Failed { failures, retry_at }) if *retry_at <= Instant::now() => {
+                crate::MAP_PERF_DATA.lock().tile_retries += 1;
+                Some(*failures)
+            }
+            Some(_) => None,
+        };
+        if let Some(failures) = failures {
+            assert!(
+                self.request_tx.send(tile).is_ok(),
+                "Vector tile request channel closed! Cannot fetch more tiles"
+            );
+            let pending = CachedGeometry::Pending { failures };
+            match self.cache.get_mut(key) {
+                Some(value) => *value = pending,
+                None => {
+                    self.cache.insert(key, pending);
+                }
+            }
+            self.pending_count += 1;
+            return None;
+        }
+        match self.cache.get_mut(key) {
+            Some(CachedGeometry::Cached {
+                geometry,
+                last_used,
+            }) => {
+                *last_used = self.frame;
+                Some(geometry)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the geometry of `tile` if it has loaded, without requesting it or marking it as
+    /// used
+    pub fn geometry(&self, tile: TileId) -> Option<&TileGeometry> {
+        match self.cache.get(tile_coord_to_u64(tile)) {
+            Some(CachedGeometry::Cached { geometry, .. }) => Some(geometry),
+            _ => None,
+        }
+    }
+
+    /// Returns true if tiles have been requested which haven't arrived yet, or if a failed tile
+    /// is due to be requested again
+    pub fn has_pending(&self) -> bool {
+        self.pending_count > 0 || self.next_retry.map_or(false, |at| at <= Instant::now())
+    }
+
+    /// Returns true if `tile` has finished loading, either successfully or by failing
+    pub fn is_resolved(&self, tile: TileId) -> bool {
+        matches!(
+            self.cache.get(tile_coord_to_u64(tile)),
+            Some(CachedGeometry::Cached { .. }) | Some(CachedGeometry::NotAvailable)
+        )
+    }
+
+    /// Returns the deepest zoom level tiles should be requested at. Views zoomed in further than
+    /// this scale these tiles up instead
+    pub fn max_zoom(&self) -> u32 {
+        self.max_zoom
+    }
+
+    /// The colors and line widths to draw with
+    pub fn palette(&self) -> &VectorPalette {
+        &self.palette
+    }
+
+    /// Forgets the tiles that no backend had and the tiles waiting to be retried, so that they are
+    /// requested again the next time they are needed. Used when going back online, since
+    /// downloads were skipped while offline
+    pub fn retry_unavailable(&mut self) {
+        let keys: Vec<u64> = self
+            .cache
+            .iter()
+            .filter(|(_, tile)| {
+                matches!(
+                    tile,
+                    CachedGeometry::NotAvailable | CachedGeometry::Failed { .. }
+                )
+            })
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            self.cache.remove(key);
+        }
+        self.next_retry = None;
+    }
+
+    /// Stores the result of a request for `tile`
+    fn store(&mut self, loaded: LoadedGeometry) {
+        let key = tile_coord_to_u64(loaded.tile);
+        let mut failures = 0;
+        if let Some(CachedGeometry::Pending { failures: earlier }) = self.cache.get(key) {
+            self.pending_count -= 1;
+            failures = *earlier;
+        }
+        let cached = match loaded.geometry {
+            Some(geometry) => {
+                self.tile_count += 1;
+                CachedGeometry::Cached {
+                    geometry,
+                    last_used: self.frame,
+                }
+            }
+            None if loaded.failed => match next_attempt(loaded.tile, failures + 1) {
+                Some(retry_at) => {
+                    self.next_retry =
+                        Some(self.next_retry.map_or(retry_at, |next| next.min(retry_at)));
+                    CachedGeometry::Failed {
+                        failures: failures + 1,
+                        retry_at,
+                    }
+                }
+                None => CachedGeometry::NotAvailable,
+            },
+            None => CachedGeometry::NotAvailable,
+        };
+        let old = match self.cache.get_mut(key) {
+            Some(value) => Some(std::mem::replace(value, cached)),
+            None => {
+                self.cache.insert(key, cached);
+                None
+            }
+        };
+        if let Some(CachedGeometry::Cached { .. }) = old {
+            self.tile_count -= 1;
+        }
+    }
+
+    /// Called each frame to store newly decoded tiles and drop old ones
+    pub fn update(&mut self) {
+        self.frame += 1;
+        //Failed tiles that are due are requested again by `get_tile` this frame if they are still
+        //on screen, so only wait for the ones that aren't due yet
+        let now = Instant::now();
+        if self.next_retry.map_or(false, |at| at <= now) {
+            self.next_retry = self
+                .cache
+                .iter()
+                .filter_map(|(_, tile)| match tile {
+                    CachedGeometry::Failed { retry_at, .. } if *retry_at > now => Some(*retry_at),
+                    _ => None,
+                })
+                .min();
+        }
+        while let Ok(loaded) = self.upload_rx.try_recv() {
+            self.store(loaded);
+        }
+
+        if self.tile_count > MAX_VECTOR_TILES {
+            let cached = self.cache.iter().filter_map(|(key, tile)| match tile {
+                CachedGeometry::Cached { last_used, .. } => Some((*key, *last_used)),
+                _ => None,
+            });
+            //Tiles used last frame are likely still on screen
+            let keep_after = self.frame.saturating_sub(2);
+            let evicted = select_evictions(cached, self.tile_count - MAX_VECTOR_TILES, keep_after);
+            for key in evicted {
+                self.cache.remove(key);
+                self.tile_count -= 1;
+            }
+        }
+    }
+}
+
+/// Requests `tile` from `backend` and decodes it on a blocking thread
+async fn load_geometry(
+    backend: &dyn Backend,
+    tile: TileId,
+) -> Result<Option<TileGeometry>, TileError> {
+    let bytes = match backend.request_inner(tile).await? {
+        Some(bytes) => bytes,
+        None => return Ok(None),
+    };
+    let geometry = tokio::task::spawn_blocking(move || -> Result<_, MvtError> {
+        Ok(TileGeometry::new(&VectorTile::decode(&bytes)?))
+    })
+    .await??;
+    Ok(Some(geometry))
+}
+
+/// An infinite async loop that waits for tile requests, and tries each backend in turn to produce
+/// the geometry of the tile
+async fn vector_requester(
+    upload_tx: Sender<LoadedGeometry>,
+    mut request_rx: UnboundedReceiver<TileId>,
+    backends: Arc<Vec<Box<dyn Backend>>>,
+) {
+    while let Some(tile) = request_rx.recv().await {
+        let upload_tx = upload_tx.clone();
+        let backends = backends.clone();
+        tokio::spawn(async move {
+            let mut geometry = None;
+            let mut failed = false;
+            for backend in backends.iter() {
+                let request = load_geometry(backend.as_ref(), tile);
+                let timeout = backend.request_timeout();
+                match request_with_stats(backend.name(), timeout, request).await {
+                    Ok(Some(loaded)) => {
+                        geometry = Some(loaded);
+                        break;
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        println!("Error getting vector tile {:?}: {}", tile, err);
+                        failed = true;
+                    }
+                }
+            }
+            let loaded = LoadedGeometry {
+                tile,
+                failed: failed && geometry.is_none(),
+                geometry,
+            };
+            let _ = upload_tx.send(loaded).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(points: &[(f64, f64)]) -> Vec<DVec2> {
+        points.iter().map(|&(x, y)| DVec2::new(x, y)).collect()
+    }
+
+    fn area(triangles: &[[DVec2; 3]]) -> f64 {
+        triangles
+            .iter()
+            .map(|[a, b, c]| (*b - *a).perp_dot(*c - *a).abs() / 2.0)
+            .sum()
+    }
+
+    #[test]
+    fn triangulates_concave_rings() {
+        //An L shape with an area of 3, in both directions
+        let ring = points(&[
+            (0.0, 0.0),
+            (2.0, 0.0),
+            (2.0, 1.0),
+            (1.0, 1.0),
+            (1.0, 2.0),
+            (0.0, 2.0),
+        ]);
+        for ring in [ring.clone(), ring.into_iter().rev().collect()] {
+            let triangles = triangulate(&ring);
+            assert_eq!(triangles.len(), 4);
+            assert!((area(&triangles) - 3.0).abs() < 1e-9);
+            //No triangle covers the missing corner
+            let corner = DVec2::new(1.5, 1.5);
+            for [a, b, c] in &triangles {
+                let d1 = (*b - *a).perp_dot(corner - *a);
+                let d2 = (*c - *b).perp_dot(corner - *b);
+                let d3 = (*a - *c).perp_dot(corner - *c);
+                assert!(!(d1 > 0.0 && d2 > 0.0 && d3 > 0.0));
+                assert!(!(d1 < 0.0 && d2 < 0.0 && d3 < 0.0));
+            }
+        }
+        assert!(triangulate(&points(&[(0.0, 0.0), (1.0, 1.0)])).is_empty());
+    }
+
+    #[test]
+    fn coastlines_skip_tile_edges() {
+        //Water covering the left of the tile, cut off past the top, left and bottom edges
+        let ring = points(&[
+            (-0.1, -0.1),
+            (0.5, -0.1),
+            (0.4, 0.5),
+            (0.5, 1.1),
+            (-0.1, 1.1),
+        ]);
+        assert!(signed_area(&ring) > 0.0);
+        assert_eq!(
+            coastline_segments(&ring),
+            vec![points(&[(0.5, -0.1), (0.4, 0.5), (0.5, 1.1)])]
+        );
+
+        //A lake in the middle of the tile is outlined all the way around
+        let lake = points(&[(0.2, 0.2), (0.4, 0.2), (0.4, 0.4)]);
+        assert_eq!(
+            coastline_segments(&lake),
+            vec![points(&[(0.2, 0.2), (0.4, 0.2), (0.4, 0.4), (0.2, 0.2)])]
+        );
+    }
+
+    #[test]
+    fn lines_have_width() {
+        let mut triangles = Vec::new();
+        line_triangles(
+            &points(&[(0.0, 0.0), (10.0, 0.0), (10.0, 0.0)]),
+            2.0,
+            &mut triangles,
+        );
+        //The repeated point adds nothing, and the ends are extended by half the width
+        assert_eq!(triangles.len(), 2);
+        assert!((area(&triangles) - 24.0).abs() < 1e-9);
+        for point in triangles.iter().flatten() {
+            assert!((point.y.abs() - 1.0).abs() < 1e-9);
+            assert!(point.x >= -1.0 && point.x <= 11.0);
+        }
+    }
+
+    #[test]
+    fn failed_tiles_are_retried_after_a_delay() {
+        //The requester task never runs on this runtime, so requests are never answered
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut pipeline = VectorPipeline::new(Vec::new(), &VectorSettings::default(), &runtime);
+        let tile = TileId::new(1, 1, 2);
+        let key = tile_coord_to_u64(tile);
+        assert!(pipeline.get_tile(tile).is_none());
+        assert!(pipeline.has_pending());
+
+        pipeline.store(LoadedGeometry {
+            tile,
+            geometry: None,
+            failed: true,
+        });
+        assert!(matches!(
+            pipeline.cache.get(key),
+            Some(CachedGeometry::Failed { failures: 1, .. })
+        ));
+        assert!(!pipeline.has_pending());
+        assert!(!pipeline.is_resolved(tile));
+
+        //Not requested again until the delay has passed
+        assert!(pipeline.get_tile(tile).is_none());
+        assert_eq!(pipeline.pending_count, 0);
+        if let Some(CachedGeometry::Failed { retry_at, .. }) = pipeline.cache.get_mut(key) {
+            *retry_at = Instant::now();
+        }
+        assert!(pipeline.get_tile(tile).is_none());
+        assert!(matches!(
+            pipeline.cache.get(key),
+            Some(CachedGeometry::Pending { failures: 1 })
+        ));
+        assert_eq!(pipeline.pending_count, 1);
+
+        //Tiles no backend has aren't retried
+        pipeline.store(LoadedGeometry {
+            tile,
+            geometry: None,
+            failed: false,
+        });
+        assert!(pipeline.is_resolved(tile));
+        assert_eq!(pipeline.pending_count, 0);
+    }
+
+    #[test]
+    fn geometry_is_styled_by_layer() {
+        use super::super::mvt::{Feature, VectorLayer};
+
+        let feature = |geom_type, class: Option<&str>, geometry: Vec<Vec<DVec2>>| Feature {
+            geom_type,
+            properties: class
+                .map(|class| vec![("class".to_owned(), class.to_owned())])
+                .unwrap_or_default(),
+            geometry,
+        };
+        let square = points(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]);
+        let island = points(&[(0.4, 0.4), (0.4, 0.6), (0.6, 0.6), (0.6, 0.4)]);
+        let road = points(&[(0.0, 0.5), (1.0, 0.5)]);
+        let tile = VectorTile {
+            layers: vec![
+                VectorLayer {
+                    name: "water".to_owned(),
+                    features: vec![feature(
+                        GeomType::Polygon,
+                        Some("ocean"),
+                        vec![square, island],
+                    )],
+                },
+                VectorLayer {
+                    name: "transportation".to_owned(),
+                    features: vec![
+                        feature(GeomType::LineString, Some("motorway"), vec![road.clone()]),
+                        feature(GeomType::LineString, Some("tertiary"), vec![road.clone()]),
+                        feature(GeomType::LineString, Some("rail"), vec![road.clone()]),
+                        feature(GeomType::LineString, None, vec![road.clone()]),
+                    ],
+                },
+                VectorLayer {
+                    name: "building".to_owned(),
+                    features: vec![feature(GeomType::LineString, Some("motorway"), vec![road])],
+                },
+            ],
+        };
+
+        let geometry = TileGeometry::new(&tile);
+        assert!((area(&geometry.water) - 1.0).abs() < 1e-9);
+        assert!((area(&geometry.islands) - 0.04).abs() < 1e-9);
+        //Only the island is coastline, since the square lies on the tile edges
+        assert_eq!(geometry.lines[LineKind::Coastline].len(), 1);
+        assert_eq!(geometry.lines[LineKind::MajorRoad].len(), 1);
+        assert_eq!(geometry.lines[LineKind::MinorRoad].len(), 1);
+    }
+}
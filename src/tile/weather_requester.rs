@@ -10,7 +10,7 @@ use rain_viewer::RequestArguments;
 use simple_moving_average::SMA;
 
 use std::{
-    sync::atomic::Ordering,
+    sync::{atomic::Ordering, Arc},
     time::{Duration, Instant},
 };
 
@@ -36,42 +36,73 @@ struct WeatherData {
     time: Instant,
 }
 
-pub struct WeatherRequester {
+/// Which radar frame a [`WeatherRequester`] downloads tiles for
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RadarFrame {
+    /// The newest frame, which changes as RainViewer publishes new data
+    Newest,
+    /// The frame taken at this unix time in seconds. Once RainViewer stops listing the frame, no
+    /// more tiles are available
+    At(u64),
+}
+
+/// The list of radar frames RainViewer has available, shared by every [`WeatherRequester`] so that
+/// it is only downloaded once
+pub struct WeatherIndex {
     available: tokio::sync::RwLock<Option<WeatherData>>,
     state: AtomicWeatherDataState,
-    tile_size: u32,
-    cache_data: DiskCacheData,
     req: rain_viewer::WeatherRequester,
 }
 
-impl WeatherRequester {
-    pub fn new(cache_data: DiskCacheData) -> Self {
+impl Default for WeatherIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WeatherIndex {
+    pub fn new() -> Self {
         Self {
             available: tokio::sync::RwLock::new(None),
             state: AtomicWeatherDataState::new(WeatherDataState::Uninitialized),
-            tile_size: 512,
-            cache_data,
             req: rain_viewer::WeatherRequester::new(),
         }
     }
-}
 
-impl WeatherRequester {
+    /// Returns the times of the available radar frames in unix seconds, oldest first.
+    ///
+    /// Empty until the list has been downloaded, which happens the first time a weather tile is
+    /// requested
+    pub fn frame_times(&self) -> Vec<u64> {
+        let guard = match self.available.try_read() {
+            Ok(guard) => guard,
+            Err(_) => return Vec::new(),
+        };
+        let mut times: Vec<u64> = match guard.as_ref() {
+            Some(available) => available
+                .data
+                .past_radar
+                .iter()
+                .chain(available.data.nowcast_radar.iter())
+                .map(|frame| frame.time)
+                .collect(),
+            None => Vec::new(),
+        };
+        times.sort_unstable();
+        times.dedup();
+        times
+    }
+
     async fn update_maps(&self) -> Result<WeatherData, rain_viewer::Error> {
         self.req.available().await.map(|data| WeatherData {
             data,
             time: Instant::now(),
         })
     }
-}
 
-#[async_trait]
-impl Backend for WeatherRequester {
-    fn name(&self) -> &'static str {
-        "Weather Requester"
-    }
-
-    async fn request_inner(&self, tile: TileId) -> Result<Option<Vec<u8>>, TileError> {
+    /// Returns the list of available frames, downloading it if this is the first call or if it is
+    /// out of date
+    async fn data(&self) -> WeatherData {
         loop {
             let state = self.state.load(Ordering::Acquire);
             match state {
@@ -151,32 +182,84 @@ impl Backend for WeatherRequester {
                             continue;
                         }
                     }
-
-                    if let Some(last_frame) = available.data.nowcast_radar.last() {
-                        if let Ok(mut args) = RequestArguments::new_tile(tile.x, tile.y, tile.zoom)
-                        {
-                            args.set_size(self.tile_size).unwrap();
-                            args.set_color(rain_viewer::ColorKind::TheWeatherChannel);
-                            match self.req.get_tile(&available.data, last_frame, args).await {
-                                Ok(bytes) => {
-                                    if bytes.len() == 125 {
-                                        //Found transparent image
-                                        return Ok(None);
-                                    }
-                                    let _ =
-                                        self.cache_data.cache_tile(tile, bytes.as_slice()).await;
-                                    return Ok(Some(bytes));
-                                }
-                                Err(err) => {
-                                    println!("failed to get tile {:?}: {:?}", tile, err);
-                                }
-                            }
-                        }
-                    }
+                    return available.clone();
                 }
             }
         }
     }
+}
+
+pub struct WeatherRequester {
+    index: Arc<WeatherIndex>,
+    frame: RadarFrame,
+    tile_size: u32,
+    /// Where downloaded tiles are saved, if they should be saved to the disk at all
+    cache_data: Option<DiskCacheData>,
+}
+
+impl WeatherRequester {
+    /// Creates a requester for tiles of `frame`, saving them to `cache_data` if it is given
+    pub fn new(
+        index: Arc<WeatherIndex>,
+        frame: RadarFrame,
+        cache_data: Option<DiskCacheData>,
+    ) -> Self {
+        Self {
+            index,
+            frame,
+            tile_size: 512,
+            cache_data,
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for WeatherRequester {
+    fn name(&self) -> &'static str {
+        "Weather Requester"
+    }
+
+    async fn request_inner(&self, tile: TileId) -> Result<Option<Vec<u8>>, TileError> {
+        let available = self.index.data().await;
+        let frame = match self.frame {
+            RadarFrame::Newest => available.data.nowcast_radar.last(),
+            RadarFrame::At(time) => available
+                .data
+                .past_radar
+                .iter()
+                .chain(available.data.nowcast_radar.iter())
+                .find(|frame| frame.time == time),
+        };
+        let frame = match frame {
+            Some(frame) => frame,
+            //Fail so that the pipeline retries after a delay, by which time the index may have
+            //been refreshed
+            None if self.frame == RadarFrame::Newest => return Err(TileError::NoRadarFrames),
+            //The frame is too old to be listed anymore
+            None => return Ok(None),
+        };
+
+        let mut args = match RequestArguments::new_tile(tile.x, tile.y, tile.zoom) {
+            Ok(args) => args,
+            //Outside of the world
+            Err(_) => return Ok(None),
+        };
+        args.set_size(self.tile_size).unwrap();
+        args.set_color(rain_viewer::ColorKind::TheWeatherChannel);
+        let bytes = self
+            .index
+            .req
+            .get_tile(&available.data, frame, args)
+            .await?;
+        if bytes.len() == 125 {
+            //Found transparent image
+            return Ok(None);
+        }
+        if let Some(cache_data) = &self.cache_data {
+            let _ = cache_data.cache_tile(tile, bytes.as_slice()).await;
+        }
+        Ok(Some(bytes))
+    }
 
     async fn readiness(&self, _tile: TileId) -> ReadinessStatus {
         ReadinessStatus::Unknown
@@ -0,0 +1,146 @@
+//! Turns raw touchscreen events into taps, pinches and two finger pans
+
+use std::collections::HashMap;
+
+use glam::DVec2;
+use glium::glutin::event::TouchPhase;
+
+/// How far in pixels a finger can move before it is no longer considered a tap
+const TAP_MAX_DISTANCE: f64 = 10.0;
+
+/// A gesture recognized from touch events
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TouchGesture {
+    /// A single finger was pressed and released at this location without moving
+    Tap(DVec2),
+    /// Two fingers moved.
+    ///
+    /// `zoom` is the amount to multiply the pixel size by, so spreading the fingers apart gives a
+    /// value less than one. `pan` is how far the map should move in pixels, and `center` is the
+    /// point between the two fingers
+    Pinch {
+        zoom: f64,
+        pan: DVec2,
+        center: DVec2,
+    },
+}
+
+/// Tracks the fingers currently touching the screen
+#[derive(Debug, Default)]
+pub struct TouchTracker {
+    /// The last known location of each finger, keyed by touch id
+    touches: HashMap<u64, DVec2>,
+    /// Where the current touch started, if it could still become a tap
+    tap_start: Option<DVec2>,
+}
+
+impl TouchTracker {
+    /// Handles a single touch event, returning the gesture it completes, if any.
+    ///
+    /// Locations are in physical pixels from the top left of the window
+    pub fn handle(&mut self, id: u64, phase: TouchPhase, location: DVec2) -> Option<TouchGesture> {
+        match phase {
+            TouchPhase::Started => {
+                self.touches.insert(id, location);
+                // Only a lone finger can tap
+                self.tap_start = if self.touches.len() == 1 {
+                    Some(location)
+                } else {
+                    None
+                };
+                None
+            }
+            TouchPhase::Moved => {
+                if self
+                    .tap_start
+                    .is_some_and(|start| start.distance(location) > TAP_MAX_DISTANCE)
+                {
+                    self.tap_start = None;
+                }
+
+                let previous = self.touches.insert(id, location)?;
+                if self.touches.len() != 2 {
+                    return None;
+                }
+                let other = self
+                    .touches
+                    .iter()
+                    .find(|(&other_id, _)| other_id != id)
+                    .map(|(_, &other)| other)?;
+
+                let previous_distance = previous.distance(other);
+                let distance = location.distance(other);
+                if previous_distance <= 0.0 || distance <= 0.0 {
+                    return None;
+                }
+                let previous_center = (previous + other) / 2.0;
+                let center = (location + other) / 2.0;
+                Some(TouchGesture::Pinch {
+                    zoom: previous_distance / distance,
+                    pan: previous_center - center,
+                    center,
+                })
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.touches.remove(&id);
+                let tap_start = self.tap_start.take();
+                if phase == TouchPhase::Ended && self.touches.is_empty() {
+                    tap_start.map(|_| TouchGesture::Tap(location))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tap() {
+        let mut tracker = TouchTracker::default();
+        let location = DVec2::new(100.0, 200.0);
+        assert_eq!(tracker.handle(0, TouchPhase::Started, location), None);
+        let moved = location + DVec2::new(3.0, 0.0);
+        assert_eq!(tracker.handle(0, TouchPhase::Moved, moved), None);
+        assert_eq!(
+            tracker.handle(0, TouchPhase::Ended, moved),
+            Some(TouchGesture::Tap(moved))
+        );
+
+        //Dragging is not a tap
+        tracker.handle(1, TouchPhase::Started, location);
+        tracker.handle(1, TouchPhase::Moved, location + DVec2::new(50.0, 0.0));
+        assert_eq!(tracker.handle(1, TouchPhase::Ended, location), None);
+    }
+
+    #[test]
+    fn pinch() {
+        let mut tracker = TouchTracker::default();
+        tracker.handle(0, TouchPhase::Started, DVec2::new(100.0, 100.0));
+        tracker.handle(1, TouchPhase::Started, DVec2::new(200.0, 100.0));
+
+        //Spreading the fingers apart zooms in around the point between them
+        let gesture = tracker.handle(1, TouchPhase::Moved, DVec2::new(300.0, 100.0));
+        assert_eq!(
+            gesture,
+            Some(TouchGesture::Pinch {
+                zoom: 0.5,
+                pan: DVec2::new(-50.0, 0.0),
+                center: DVec2::new(200.0, 100.0),
+            })
+        );
+
+        //Lifting both fingers after a pinch is not a tap
+        assert_eq!(
+            tracker.handle(1, TouchPhase::Ended, DVec2::new(300.0, 100.0)),
+            None
+        );
+        assert_eq!(
+            tracker.handle(0, TouchPhase::Ended, DVec2::new(100.0, 100.0)),
+            None
+        );
+    }
+}
@@ -0,0 +1,300 @@
+//! Records the positions of one plane over time, and exports them as a GPX or KML track that can
+//! be opened in other tools such as Google Earth
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+
+use crate::{find_plane, PlaneBody};
+
+/// The name written as the creator of exported files
+const CREATOR: &str = "Flight Tracker";
+
+/// A file format tracks can be exported in
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TrackFormat {
+    Gpx,
+    Kml,
+}
+
+impl TrackFormat {
+    pub const ALL: [TrackFormat; 2] = [TrackFormat::Gpx, TrackFormat::Kml];
+
+    /// The file extension for this format, without the dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            TrackFormat::Gpx => "gpx",
+            TrackFormat::Kml => "kml",
+        }
+    }
+}
+
+/// Where a plane was at one moment
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TrackPoint {
+    pub latitude: f32,
+    pub longitude: f32,
+    /// Altitude above sea level in meters, or `None` if OpenSky doesn't know it
+    pub altitude: Option<f32>,
+    pub time: DateTime<Utc>,
+}
+
+/// Appends the positions of the plane being recorded each time new plane data arrives
+pub struct TrackRecorder {
+    /// The callsign of the plane being recorded, exactly as OpenSky sends it
+    callsign: String,
+    points: Vec<TrackPoint>,
+    recording: bool,
+    /// The plane data the track was last updated from
+    last_update: Option<Arc<Vec<PlaneBody>>>,
+}
+
+impl Default for TrackRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TrackRecorder {
+    pub fn new() -> Self {
+        Self {
+            callsign: String::new(),
+            points: Vec::new(),
+            recording: false,
+            last_update: None,
+        }
+    }
+
+    /// Returns true if positions passed to [`TrackRecorder::update`] are being kept
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Starts recording the plane with `callsign`, discarding any previous track
+    pub fn start(&mut self, callsign: &str) {
+        self.callsign = callsign.to_owned();
+        self.points.clear();
+        self.recording = true;
+        self.last_update = None;
+    }
+
+    /// Stops recording. The track is kept so that it can still be exported
+    pub fn stop(&mut self) {
+        self.recording = false;
+    }
+
+    /// The callsign of the plane being recorded, or last recorded, without padding
+    pub fn callsign(&self) -> &str {
+        self.callsign.trim()
+    }
+
+    /// The recorded positions, oldest first
+    pub fn points(&self) -> &[TrackPoint] {
+        &self.points
+    }
+
+    /// Records the position of the plane being recorded in `planes`, at the time OpenSky last
+    /// heard from it, or `now` if that is unknown.
+    ///
+    /// Does nothing if not recording, if `planes` is the same data passed to the last call, or if
+    /// the plane hasn't moved, so this can be called every frame. Planes that leave the feed keep
+    /// being recorded in case they come back
+    pub fn update(&mut self, planes: &Arc<Vec<PlaneBody>>, now: SystemTime) {
+        if !self.recording {
+            return;
+        }
+        if let Some(last) = &self.last_update {
+            if Arc::ptr_eq(last, planes) {
+                return;
+            }
+        }
+        self.last_update = Some(planes.clone());
+
+        let plane = match find_plane(planes, &self.callsign) {
+            Some(plane) => plane,
+            None => return,
+        };
+        let time = match plane.last_contact {
+            Some(secs) => Utc.timestamp_opt(secs as i64, 0).single(),
+            None => None,
+        };
+        let point = TrackPoint {
+            latitude: plane.latitude,
+            longitude: plane.longitude,
+            altitude: plane.altitude(),
+            time: time.unwrap_or_else(|| now.into()),
+        };
+        // OpenSky repeats the last position until it hears from the plane again
+        if let Some(last) = self.points.last() {
+            let moved = [last.latitude, last.longitude] != [point.latitude, point.longitude];
+            if !moved || point.time <= last.time {
+                return;
+            }
+        }
+        self.points.push(point);
+    }
+
+    /// Returns the track as a GPX 1.1 document
+    pub fn to_gpx(&self) -> String {
+        let mut gpx = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        gpx += &format!(
+            "<gpx version=\"1.1\" creator=\"{}\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n",
+            CREATOR
+        );
+        gpx += "  <trk>\n";
+        gpx += &format!("    <name>{}</name>\n", escape_xml(self.callsign()));
+        gpx += "    <trkseg>\n";
+        for point in &self.points {
+            gpx += &format!(
+                "      <trkpt lat=\"{:.5}\" lon=\"{:.5}\">",
+                point.latitude, point.longitude
+            );
+            if let Some(altitude) = point.altitude {
+                gpx += &format!("<ele>{:.1}</ele>", altitude);
+            }
+            gpx += &format!("<time>{}</time></trkpt>\n", format_time(point.time));
+        }
+        gpx += "    </trkseg>\n";
+        gpx += "  </trk>\n";
+        gpx += "</gpx>\n";
+        gpx
+    }
+
+    /// Returns the track as a KML 2.2 document, using a `gx:Track` so that Google Earth can play
+    /// it back over time.
+    ///
+    /// Altitudes are only used if every point has one, since a missing altitude would otherwise
+    /// drop the track to sea level
+    pub fn to_kml(&self) -> String {
+        let has_altitudes =
+            !self.points.is_empty() && self.points.iter().all(|point| point.altitude.is_some());
+        let altitude_mode = match has_altitudes {
+            true => "absolute",
+            false => "clampToGround",
+        };
+
+        let mut kml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        kml += "<kml xmlns=\"http://www.opengis.net/kml/2.2\" \
+                xmlns:gx=\"http://www.google.com/kml/ext/2.2\">\n";
+        kml += "  <Document>\n";
+        kml += &format!("    <name>{}</name>\n", escape_xml(self.callsign()));
+        kml += "    <Placemark>\n";
+        kml += &format!("      <name>{}</name>\n", escape_xml(self.callsign()));
+        kml += "      <gx:Track>\n";
+        kml += &format!("        <altitudeMode>{}</altitudeMode>\n", altitude_mode);
+        for point in &self.points {
+            kml += &format!("        <when>{}</when>\n", format_time(point.time));
+        }
+        for point in &self.points {
+            kml += &format!(
+                "        <gx:coord>{:.5} {:.5} {:.1}</gx:coord>\n",
+                point.longitude,
+                point.latitude,
+                point.altitude.unwrap_or(0.0)
+            );
+        }
+        kml += "      </gx:Track>\n";
+        kml += "    </Placemark>\n";
+        kml += "  </Document>\n";
+        kml += "</kml>\n";
+        kml
+    }
+
+    /// Writes the track to `path` in `format`
+    pub fn export(&self, path: &Path, format: TrackFormat) -> std::io::Result<()> {
+        let contents = match format {
+            TrackFormat::Gpx => self.to_gpx(),
+            TrackFormat::Kml => self.to_kml(),
+        };
+        std::fs::write(path, contents)
+    }
+}
+
+/// Formats `time` as an ISO 8601 timestamp in UTC, as both GPX and KML expect
+fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Escapes the characters in `text` that have a special meaning in XML
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Airline, Plane, PlaneType};
+    use std::time::Duration;
+
+    fn feed(longitude: f32, altitude: Option<f32>, last_contact: u64) -> Arc<Vec<PlaneBody>> {
+        let mut plane = Plane::new(
+            longitude,
+            29.18,
+            None,
+            String::from("AAL123  "),
+            Airline::Unknown,
+            PlaneType::Commercial,
+        );
+        plane.baro_altitude = altitude;
+        plane.last_contact = Some(last_contact);
+        Arc::new(vec![PlaneBody::new(
+            vec![plane],
+            Airline::Unknown,
+            PlaneType::Commercial,
+        )])
+    }
+
+    #[test]
+    fn records_and_exports_tracks() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_636_077_784);
+        let mut recorder = TrackRecorder::new();
+        recorder.update(&feed(-81.0, Some(1000.0), 1_636_077_784), now);
+        assert!(recorder.points().is_empty());
+
+        recorder.start("AAL123  ");
+        let first = feed(-81.0, Some(1000.0), 1_636_077_784);
+        recorder.update(&first, now);
+        recorder.update(&first, now);
+        //OpenSky repeating a position isn't a new point
+        recorder.update(&feed(-81.0, Some(1000.0), 1_636_077_784), now);
+        recorder.update(&feed(-81.1, Some(1200.0), 1_636_077_794), now);
+        recorder.stop();
+        recorder.update(&feed(-81.2, Some(1400.0), 1_636_077_804), now);
+        assert_eq!(recorder.points().len(), 2);
+        assert_eq!(recorder.callsign(), "AAL123");
+
+        let gpx = recorder.to_gpx();
+        assert!(gpx.contains("<name>AAL123</name>"));
+        assert!(gpx.contains(
+            "<trkpt lat=\"29.18000\" lon=\"-81.00000\"><ele>1000.0</ele>\
+             <time>2021-11-05T02:03:04Z</time></trkpt>"
+        ));
+        assert!(gpx.trim_end().ends_with("</gpx>"));
+
+        let kml = recorder.to_kml();
+        assert!(kml.contains("<altitudeMode>absolute</altitudeMode>"));
+        assert!(kml.contains("<when>2021-11-05T02:03:14Z</when>"));
+        assert!(kml.contains("<gx:coord>-81.10000 29.18000 1200.0</gx:coord>"));
+
+        //One unknown altitude puts the whole track on the ground
+        recorder.start("AAL123  ");
+        recorder.update(&feed(-81.0, None, 1_636_077_784), now);
+        assert!(recorder
+            .to_kml()
+            .contains("<altitudeMode>clampToGround</altitudeMode>"));
+        assert_eq!(escape_xml("A&B<\"C\">"), "A&amp;B&lt;&quot;C&quot;&gt;");
+    }
+}
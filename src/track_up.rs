@@ -0,0 +1,257 @@
+//! Track-up mode, which turns the map so that the followed plane's track points up the screen.
+//!
+//! Conrod can only draw axis aligned images, so while the map is turned the map and planes are
+//! drawn unrotated into an offscreen canvas large enough to cover the window at any angle. The
+//! canvas is then drawn turned onto the window, and the overlay is drawn upright on top of it
+
+use conrod_core::{
+    widget::{Circle, Polygon, Text},
+    Colorable, Positionable, UiCell, Widget,
+};
+use glam::DVec2;
+use glium::{implement_vertex, uniform, Program, Surface};
+
+use crate::map::TileView;
+
+/// The radius in pixels of the background behind the north arrow
+const NORTH_INDICATOR_RADIUS: f64 = 20.0;
+
+#[derive(Debug, Clone, Copy)]
+struct Vertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+}
+
+implement_vertex!(Vertex, position, tex_coords);
+
+/// Returns the size in physical pixels of the canvas the map is drawn into for a window of
+/// `dimensions`.
+///
+/// A view that isn't turned is drawn straight onto the window, so its canvas is the window.
+/// Otherwise the canvas is a square as wide as the window's diagonal
+pub fn canvas_dimensions(view: &TileView, dimensions: (u32, u32)) -> (u32, u32) {
+    if view.rotation() == 0.0 {
+        return dimensions;
+    }
+    let (width, height) = dimensions;
+    let side = (width as f64).hypot(height as f64).ceil() as u32;
+    (side, side)
+}
+
+/// Converts `point` on the unrotated map to where it is shown on the turned screen. Both use
+/// conrod's coordinates, centered with y up
+pub fn map_point_to_screen(view: &TileView, point: DVec2) -> DVec2 {
+    //The view measures offsets with y down
+    let offset = view.map_offset_to_screen(DVec2::new(point.x, -point.y));
+    DVec2::new(offset.x, -offset.y)
+}
+
+/// The inverse of [`map_point_to_screen`]
+pub fn screen_point_to_map(view: &TileView, point: DVec2) -> DVec2 {
+    let offset = view.screen_offset_to_map(DVec2::new(point.x, -point.y));
+    DVec2::new(offset.x, -offset.y)
+}
+
+/// Lets conrod fill its vertices for a canvas, since it otherwise assumes that it draws onto the
+/// whole window
+pub struct CanvasDisplay<'a> {
+    pub display: &'a glium::Display,
+    /// The size of the canvas in physical pixels
+    pub dimensions: (u32, u32),
+}
+
+impl conrod_glium::Display for CanvasDisplay<'_> {
+    fn opengl_version(&self) -> &glium::Version {
+        self.display.get_opengl_version()
+    }
+
+    fn framebuffer_dimensions(&self) -> (u32, u32) {
+        self.dimensions
+    }
+
+    fn hidpi_factor(&self) -> f64 {
+        self.display.gl_window().window().scale_factor()
+    }
+}
+
+/// Draws canvases onto the window turned by the rotation of the view
+pub struct RotatedCanvas {
+    program: Program,
+    /// Kept between frames while the size of the canvas stays the same
+    texture: Option<glium::Texture2d>,
+}
+
+impl RotatedCanvas {
+    pub fn new(display: &glium::Display) -> Self {
+        let vertex_shader_src = r#"
+            #version 140
+
+            in vec2 position;
+            in vec2 tex_coords;
+
+            out vec2 v_tex_coords;
+
+            void main() {
+                v_tex_coords = tex_coords;
+                gl_Position = vec4(position, 0.0, 1.0);
+            }
+        "#;
+
+        let fragment_shader_src = r#"
+            #version 140
+
+            in vec2 v_tex_coords;
+            out vec4 color;
+
+            uniform sampler2D tex;
+
+            void main() {
+                color = texture(tex, v_tex_coords);
+            }
+        "#;
+
+        let program =
+            glium::Program::from_source(display, vertex_shader_src, fragment_shader_src, None)
+                .unwrap();
+
+        Self {
+            program,
+            texture: None,
+        }
+    }
+
+    /// Calls `draw` to draw the unrotated map into a canvas of `dimensions` from
+    /// [`canvas_dimensions`], then draws the canvas centered on `target` and turned by the
+    /// rotation of `view`.
+    ///
+    /// Returns what `draw` returns
+    pub fn draw<S: Surface, R>(
+        &mut self,
+        display: &glium::Display,
+        target: &mut S,
+        view: &TileView,
+        dimensions: (u32, u32),
+        draw: impl FnOnce(&mut glium::framebuffer::SimpleFrameBuffer) -> R,
+    ) -> R {
+        if self.texture.as_ref().map(|texture| texture.dimensions()) != Some(dimensions) {
+            let texture = glium::texture::Texture2d::empty_with_format(
+                display,
+                glium::texture::UncompressedFloatFormat::U8U8U8U8,
+                glium::texture::MipmapsOption::NoMipmap,
+                dimensions.0,
+                dimensions.1,
+            )
+            .unwrap();
+            self.texture = Some(texture);
+        }
+        let texture = self.texture.as_ref().unwrap();
+
+        let result = {
+            let mut canvas = glium::framebuffer::SimpleFrameBuffer::new(display, texture).unwrap();
+            draw(&mut canvas)
+        };
+
+        let (width, height) = target.get_dimensions();
+        let half_screen = DVec2::new(width as f64, height as f64) / 2.0;
+        let half_canvas = DVec2::new(dimensions.0 as f64, dimensions.1 as f64) / 2.0;
+        //`x` and `y` pick a corner of the canvas, with y down like the view's offsets
+        let corner = |x: f64, y: f64| {
+            let screen = view.map_offset_to_screen(DVec2::new(x, y) * half_canvas) / half_screen;
+            Vertex {
+                position: [screen.x as f32, -screen.y as f32],
+                tex_coords: [((x + 1.0) / 2.0) as f32, ((1.0 - y) / 2.0) as f32],
+            }
+        };
+        let vertices = [
+            corner(-1.0, -1.0),
+            corner(1.0, -1.0),
+            corner(1.0, 1.0),
+            corner(1.0, 1.0),
+            corner(-1.0, 1.0),
+            corner(-1.0, -1.0),
+        ];
+        let vertex_buffer = glium::VertexBuffer::new(display, &vertices).unwrap();
+
+        let uniforms = uniform! {
+            tex: texture
+                .sampled()
+                .minify_filter(glium::uniforms::MinifySamplerFilter::Linear)
+                .magnify_filter(glium::uniforms::MagnifySamplerFilter::Linear),
+        };
+        // The canvas covers the whole window, so it replaces what is there instead of blending
+        target
+            .draw(
+                &vertex_buffer,
+                glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
+                &self.program,
+                &uniforms,
+                &Default::default(),
+            )
+            .unwrap();
+
+        result
+    }
+}
+
+/// Draws an arrow centered on `center` that points north on the turned screen, labeled with an N
+pub fn draw_north_indicator(
+    view: &TileView,
+    center: DVec2,
+    ui: &mut UiCell<'_>,
+    ids: &mut crate::Ids,
+    font: conrod_core::text::font::Id,
+) {
+    let north = map_point_to_screen(view, DVec2::Y);
+    let east = DVec2::new(north.y, -north.x);
+    let point = |along: f64, across: f64| {
+        let point = center + north * along + east * across;
+        [point.x, point.y]
+    };
+
+    Circle::fill(NORTH_INDICATOR_RADIUS)
+        .x_y(center.x, center.y)
+        .color(conrod_core::color::BLACK.alpha(0.6))
+        .set(ids.north_indicator_background, ui);
+    Polygon::abs_fill([point(14.0, 0.0), point(0.0, 6.0), point(0.0, -6.0)])
+        .color(conrod_core::color::LIGHT_RED)
+        .set(ids.north_indicator_needle, ui);
+    Polygon::abs_fill([point(-14.0, 0.0), point(0.0, -6.0), point(0.0, 6.0)])
+        .color(conrod_core::color::WHITE)
+        .set(ids.north_indicator_tail, ui);
+
+    let [x, y] = point(NORTH_INDICATOR_RADIUS + 9.0, 0.0);
+    Text::new("N")
+        .x_y(x, y)
+        .color(conrod_core::color::WHITE)
+        .font_size(12)
+        .font_id(font)
+        .set(ids.north_indicator_label, ui);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canvas_covers_the_turned_window() {
+        let mut view = TileView::new(29.18796, -81.04923, 8.0, 1280.0);
+        assert_eq!(canvas_dimensions(&view, (1280, 720)), (1280, 720));
+
+        view.set_rotation(1.0);
+        assert_eq!(canvas_dimensions(&view, (1280, 720)), (1469, 1469));
+    }
+
+    #[test]
+    fn screen_points_round_trip() {
+        let mut view = TileView::new(29.18796, -81.04923, 8.0, 1280.0);
+        view.set_rotation(90f64.to_radians());
+
+        //Facing east, north is to the left
+        let north = map_point_to_screen(&view, DVec2::new(0.0, 10.0));
+        assert!((north - DVec2::new(-10.0, 0.0)).length() < 1e-9);
+
+        let point = DVec2::new(123.0, -45.0);
+        let back = screen_point_to_map(&view, map_point_to_screen(&view, point));
+        assert!((back - point).length() < 1e-9);
+    }
+}
@@ -0,0 +1,161 @@
+//! Remembers where each plane has been so that a trail can be drawn behind it
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use crate::{PlaneBody, UNKNOWN_CALLSIGN};
+
+/// The trail lengths the trail button cycles through, in points. Zero hides trails
+pub const TRAIL_LENGTHS: [usize; 4] = [0, 10, 30, 60];
+
+/// A bounded history of positions for every plane in the feed, keyed by callsign.
+///
+/// At most `max_points` positions are kept per plane, and planes that leave the feed are
+/// forgotten, so memory use is bounded by the size of the feed
+pub struct TrailStore {
+    /// The positions of each plane as (longitude, latitude), oldest first
+    trails: HashMap<String, VecDeque<[f32; 2]>>,
+    max_points: usize,
+    /// The plane data the trails were last updated from
+    last_update: Option<Arc<Vec<PlaneBody>>>,
+}
+
+impl Default for TrailStore {
+    fn default() -> Self {
+        Self::new(TRAIL_LENGTHS[0])
+    }
+}
+
+impl TrailStore {
+    /// Creates an empty store that keeps up to `max_points` positions per plane
+    pub fn new(max_points: usize) -> Self {
+        Self {
+            trails: HashMap::new(),
+            max_points,
+            last_update: None,
+        }
+    }
+
+    /// The number of positions kept per plane. Zero means trails are disabled
+    pub fn max_points(&self) -> usize {
+        self.max_points
+    }
+
+    /// Changes how many positions are kept per plane, dropping the oldest positions of existing
+    /// trails if needed
+    pub fn set_max_points(&mut self, max_points: usize) {
+        self.max_points = max_points;
+        for trail in self.trails.values_mut() {
+            while trail.len() > max_points {
+                trail.pop_front();
+            }
+        }
+        self.trails.retain(|_, trail| !trail.is_empty());
+    }
+
+    /// Records the current position of every plane in `planes`.
+    ///
+    /// Does nothing if `planes` is the same data passed to the last call, so this can be called
+    /// every frame
+    pub fn update(&mut self, planes: &Arc<Vec<PlaneBody>>) {
+        if let Some(last) = &self.last_update {
+            if Arc::ptr_eq(last, planes) {
+                return;
+            }
+        }
+        self.last_update = Some(planes.clone());
+        if self.max_points == 0 {
+            return;
+        }
+
+        let mut trails = HashMap::with_capacity(self.trails.len());
+        for plane in planes.iter().flat_map(|body| body.planes.iter()) {
+            // Planes without a callsign all share this name, so their trails would jump between
+            // unrelated planes
+            if plane.callsign == UNKNOWN_CALLSIGN {
+                continue;
+            }
+            // Moving trails out of the old map drops the trails of planes that left the feed
+            let mut trail = self.trails.remove(&plane.callsign).unwrap_or_default();
+            let position = [plane.longitude, plane.latitude];
+            // OpenSky repeats the last position until it hears from the plane again
+            if trail.back() != Some(&position) {
+                if trail.len() == self.max_points {
+                    trail.pop_front();
+                }
+                trail.push_back(position);
+            }
+            trails.insert(plane.callsign.clone(), trail);
+        }
+        self.trails = trails;
+    }
+
+    /// Returns the recorded positions of the plane with `callsign` as (longitude, latitude),
+    /// oldest first
+    pub fn trail(&self, callsign: &str) -> Option<&VecDeque<[f32; 2]>> {
+        self.trails.get(callsign)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Airline, Plane, PlaneType};
+
+    fn feed(planes: &[(&str, f32, f32)]) -> Arc<Vec<PlaneBody>> {
+        let planes = planes
+            .iter()
+            .map(|&(callsign, longitude, latitude)| {
+                Plane::new(
+                    longitude,
+                    latitude,
+                    None,
+                    callsign.to_owned(),
+                    Airline::Unknown,
+                    PlaneType::Unknown,
+                )
+            })
+            .collect();
+        Arc::new(vec![PlaneBody::new(
+            planes,
+            Airline::Unknown,
+            PlaneType::Unknown,
+        )])
+    }
+
+    #[test]
+    fn trails_are_bounded() {
+        let mut store = TrailStore::new(3);
+        for i in 0..5 {
+            store.update(&feed(&[("AAL1", i as f32, 0.0)]));
+        }
+        let trail: Vec<_> = store.trail("AAL1").unwrap().iter().copied().collect();
+        assert_eq!(trail, vec![[2.0, 0.0], [3.0, 0.0], [4.0, 0.0]]);
+
+        store.set_max_points(1);
+        assert_eq!(store.trail("AAL1").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn repeated_data_is_ignored() {
+        let mut store = TrailStore::new(10);
+        let planes = feed(&[("AAL1", 1.0, 2.0)]);
+        store.update(&planes);
+        store.update(&planes);
+        //The same position in new data is not recorded twice either
+        store.update(&feed(&[("AAL1", 1.0, 2.0)]));
+        assert_eq!(store.trail("AAL1").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn planes_leaving_the_feed_are_forgotten() {
+        let mut store = TrailStore::new(10);
+        store.update(&feed(&[("AAL1", 1.0, 2.0), ("Unknown", 3.0, 4.0)]));
+        assert!(store.trail("AAL1").is_some());
+        assert!(store.trail("Unknown").is_none());
+
+        store.update(&feed(&[("UAL2", 1.0, 2.0)]));
+        assert!(store.trail("AAL1").is_none());
+        assert!(store.trail("UAL2").is_some());
+    }
+}
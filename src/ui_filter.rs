@@ -277,3 +277,92 @@ pub fn draw(
         .set(widget_id, ui)
         .is_some()
 }
+
+/// The distance between the centers of two rows of buttons drawn with [`draw`]
+pub const ROW_SPACING: f64 = 40.0;
+
+/// The distance between the centers of two columns of buttons drawn with [`draw`]
+pub const COLUMN_SPACING: f64 = 160.0;
+
+/// Lays out a fixed number of buttons in rows down the right side of the window, starting a new
+/// column to the left whenever the next row would go past the bottom.
+///
+/// Each button keeps its row even while it isn't drawn, so that buttons don't jump around as
+/// others appear and disappear
+pub struct ButtonColumns {
+    positions: Vec<[f64; 2]>,
+    /// The x position of the leftmost column
+    left: f64,
+}
+
+impl ButtonColumns {
+    /// Lays out `rows` buttons, with the first column at `x` starting from `top`. Later columns
+    /// start from `wrap_top`, which can be higher since they are clear of the round buttons
+    /// above the first column. No button is centered below `bottom` unless even a single row
+    /// doesn't fit
+    pub fn new(rows: usize, x: f64, top: f64, wrap_top: f64, bottom: f64) -> Self {
+        let mut positions = Vec::with_capacity(rows);
+        let mut column = 0;
+        let mut column_x = x;
+        let mut y = top;
+        let mut column_rows = 0;
+        while positions.len() < rows {
+            //The first column may be left empty, but later ones always get at least one row
+            if y < bottom && (column_rows > 0 || column == 0) {
+                column += 1;
+                column_x -= COLUMN_SPACING;
+                y = wrap_top;
+                column_rows = 0;
+                continue;
+            }
+            positions.push([column_x, y]);
+            column_rows += 1;
+            y -= ROW_SPACING;
+        }
+        let left = positions
+            .iter()
+            .map(|position| position[0])
+            .fold(x, f64::min);
+        Self { positions, left }
+    }
+
+    /// Returns where the button in `row` is centered
+    pub fn position(&self, row: usize) -> [f64; 2] {
+        self.positions[row]
+    }
+
+    /// Returns the x position of the `n`th extra control belonging to a button, such as a
+    /// slider. These go to the left of every column so that they never cover another button
+    pub fn side_x(&self, n: usize) -> f64 {
+        self.left - COLUMN_SPACING * (n + 1) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_wrap_at_the_bottom() {
+        //Three rows fit in the first column, and six from the higher `wrap_top` in the next
+        let columns = ButtonColumns::new(11, 500.0, -100.0, 20.0, -200.0);
+        assert_eq!(columns.position(0), [500.0, -100.0]);
+        assert_eq!(columns.position(2), [500.0, -180.0]);
+        assert_eq!(columns.position(3), [340.0, 20.0]);
+        assert_eq!(columns.position(8), [340.0, -180.0]);
+        assert_eq!(columns.position(9), [180.0, 20.0]);
+        assert_eq!(columns.side_x(0), 20.0);
+        assert_eq!(columns.side_x(1), -140.0);
+
+        //A window too short for even one row skips the first column, then still places every
+        //button
+        let columns = ButtonColumns::new(2, 500.0, -100.0, -100.0, 0.0);
+        assert_eq!(columns.position(0), [340.0, -100.0]);
+        assert_eq!(columns.position(1), [180.0, -100.0]);
+
+        //Everything fits in one column
+        let columns = ButtonColumns::new(3, 500.0, 0.0, 100.0, -1000.0);
+        assert_eq!(columns.position(2), [500.0, -80.0]);
+        assert_eq!(columns.side_x(0), 340.0);
+    }
+}
@@ -0,0 +1,96 @@
+//! Formatting of latitudes and longitudes for display
+
+/// How coordinates are written on the map
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CoordFormat {
+    /// Decimal degrees, like `33.6°N`
+    Decimal,
+    /// Degrees, minutes, and seconds, like `33°36'15"N`
+    Dms,
+}
+
+impl CoordFormat {
+    /// Returns the other format
+    pub fn toggle(self) -> Self {
+        match self {
+            CoordFormat::Decimal => CoordFormat::Dms,
+            CoordFormat::Dms => CoordFormat::Decimal,
+        }
+    }
+
+    /// A short human readable name for this format
+    pub fn name(self) -> &'static str {
+        match self {
+            CoordFormat::Decimal => "Decimal",
+            CoordFormat::Dms => "DMS",
+        }
+    }
+}
+
+/// Formats a latitude in degrees.
+///
+/// `precision` is the number of decimal places used by [`CoordFormat::Decimal`]
+pub fn format_latitude(lat: f64, precision: usize, format: CoordFormat) -> String {
+    let hemisphere = if lat >= 0.0 { 'N' } else { 'S' };
+    format_coord(lat.abs(), hemisphere, precision, format)
+}
+
+/// Formats a longitude in degrees.
+///
+/// `precision` is the number of decimal places used by [`CoordFormat::Decimal`]
+pub fn format_longitude(lng: f64, precision: usize, format: CoordFormat) -> String {
+    let hemisphere = if lng >= 0.0 { 'E' } else { 'W' };
+    format_coord(lng.abs(), hemisphere, precision, format)
+}
+
+fn format_coord(degrees: f64, hemisphere: char, precision: usize, format: CoordFormat) -> String {
+    match format {
+        CoordFormat::Decimal => format!("{:.1$}°{2}", degrees, precision, hemisphere),
+        CoordFormat::Dms => {
+            //Round to the nearest second first so that we never print 60 seconds
+            let total_seconds = (degrees * 3600.0).round() as u64;
+            let (d, m, s) = (
+                total_seconds / 3600,
+                (total_seconds / 60) % 60,
+                total_seconds % 60,
+            );
+            //Leave off trailing zero components so that round grid lines stay short
+            if s != 0 {
+                format!("{}°{:02}'{:02}\"{}", d, m, s, hemisphere)
+            } else if m != 0 {
+                format!("{}°{:02}'{}", d, m, hemisphere)
+            } else {
+                format!("{}°{}", d, hemisphere)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimal() {
+        assert_eq!(format_latitude(33.6, 1, CoordFormat::Decimal), "33.6°N");
+        assert_eq!(format_latitude(-33.6, 2, CoordFormat::Decimal), "33.60°S");
+        assert_eq!(format_longitude(-81.0, 0, CoordFormat::Decimal), "81°W");
+        assert_eq!(format_longitude(0.0, 0, CoordFormat::Decimal), "0°E");
+    }
+
+    #[test]
+    fn dms() {
+        assert_eq!(
+            format_latitude(33.604167, 0, CoordFormat::Dms),
+            "33°36'15\"N"
+        );
+        assert_eq!(format_latitude(-33.5, 0, CoordFormat::Dms), "33°30'S");
+        assert_eq!(format_longitude(45.0, 0, CoordFormat::Dms), "45°E");
+        assert_eq!(format_longitude(-0.1, 0, CoordFormat::Dms), "0°06'W");
+        //59.9999 seconds rounds up into the next minute instead of printing 60"
+        assert_eq!(
+            format_longitude(10.0 + 59.9999 / 3600.0, 0, CoordFormat::Dms),
+            "10°01'E"
+        );
+    }
+}
@@ -25,23 +25,48 @@ where
     (value - a) / (b - a)
 }
 
-/// Maps a value linearly in one range to another
+/// Maps a value linearly in one range to another.
+///
+/// Either range may be reversed (`min` greater than `max`), which flips the mapping. For example
+/// mapping 0..1 onto 1..0 turns 0.25 into 0.75. Values outside the input range are extrapolated,
+/// not clamped.
+///
+/// If the input range is empty (`left_min == left_max`) every value maps to the middle of the
+/// output range, instead of dividing by zero and returning NaN or infinity
 pub fn map<S, D, F>(left_min: S, left_max: S, value: S, right_min: D, right_max: D) -> D
 where
     S: Copy,
+    S: PartialEq,
     S: std::ops::Sub<Output = S>,
     S: std::ops::Div<Output = F>,
     D: Copy,
     D: std::ops::Sub<Output = D>,
     D: std::ops::Add<Output = D>,
     D: std::ops::Mul<F, Output = D>,
+    F: From<f32>,
 {
+    if left_min == left_max {
+        return lerp(right_min, right_max, F::from(0.5));
+    }
     //Figure out how 'wide' each range is
     let f: F = normalize(left_min, left_max, value);
 
     lerp(right_min, right_max, f)
 }
 
+/// Returns the color `t` of the way along the evenly spaced color stops `stops`, where `t` is
+/// from 0 to 1. Values outside of that are clamped to the first or last stop
+pub fn sample_ramp<T>(stops: &[[T; 3]], t: f32) -> [f32; 3]
+where
+    T: Copy + Into<f32>,
+{
+    let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+    let index = (position as usize).min(stops.len() - 2);
+    let t = position - index as f32;
+    let (a, b) = (stops[index], stops[index + 1]);
+    [0, 1, 2].map(|i| lerp(a[i].into(), b[i].into(), t))
+}
+
 /// Rounds a number up to the nearest multiple of `multiple`
 pub fn round_up<T>(to_round: T, multiple: T) -> T
 where
@@ -110,11 +135,31 @@ pub fn longitude_from_x(x: f64) -> f64 {
     map(0.0, 1.0, x, -180.0, 180.0)
 }
 
+/// Wraps a longitude in degrees into the range (-180, 180]
+pub fn wrap_longitude(lng: f64) -> f64 {
+    let wrapped = (lng + 180.0).rem_euclid(360.0) - 180.0;
+    if wrapped == -180.0 {
+        180.0
+    } else {
+        wrapped
+    }
+}
+
 /// Rounds a number down to the nearest multiple of `modulo`
 pub fn modulo_floor(val: f64, modulo: f64) -> f64 {
     val - (val.rem_euclid(modulo))
 }
 
+/// Rotates `vector` by `angle` radians. With y increasing downwards, like pixels on screen,
+/// positive angles turn clockwise
+pub fn rotate(vector: glam::DVec2, angle: f64) -> glam::DVec2 {
+    let (sin, cos) = angle.sin_cos();
+    glam::DVec2::new(
+        vector.x * cos - vector.y * sin,
+        vector.x * sin + vector.y * cos,
+    )
+}
+
 /// Rounds a number up to the nearest multiple of `modulo`
 pub fn modulo_ceil(val: f64, modulo: f64) -> f64 {
     if val % modulo == 0.0 {
@@ -124,20 +169,269 @@ pub fn modulo_ceil(val: f64, modulo: f64) -> f64 {
     }
 }
 
+/// The circumference of the earth at the equator in meters
+pub const EARTH_CIRCUMFERENCE_METERS: f64 = 40_075_016.686;
+
+/// Returns how many meters on the ground one world unit in the x direction covers at latitude
+/// `lat_degrees`.
+///
+/// The mercator projection stretches everything east-west by `1 / cos(lat)`, so the same world
+/// distance covers less ground further from the equator
+pub fn meters_per_world_x(lat_degrees: f64) -> f64 {
+    EARTH_CIRCUMFERENCE_METERS * lat_degrees.to_radians().cos()
+}
+
+/// The mean radius of the earth in meters, used for great circle calculations
+pub const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+
+/// Returns the great circle distance in meters between two points given in degrees, using the
+/// haversine formula
+pub fn haversine_distance(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lng = (lng2 - lng1).to_radians();
+
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lng / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_METERS * a.sqrt().min(1.0).asin()
+}
+
+/// Returns the initial bearing in degrees (0..360, clockwise from north) when travelling along
+/// the great circle from the first point to the second
+pub fn initial_bearing(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lng = (lng2 - lng1).to_radians();
+
+    let y = d_lng.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lng.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+/// Returns `segments + 1` evenly spaced points in degrees along the great circle from `a` to `b`,
+/// including both endpoints. Points are `(latitude, longitude)` pairs
+pub fn great_circle_points(a: (f64, f64), b: (f64, f64), segments: usize) -> Vec<(f64, f64)> {
+    let to_vector = |(lat, lng): (f64, f64)| {
+        let (lat, lng) = (lat.to_radians(), lng.to_radians());
+        [lat.cos() * lng.cos(), lat.cos() * lng.sin(), lat.sin()]
+    };
+    let start = to_vector(a);
+    let end = to_vector(b);
+
+    let angle = haversine_distance(a.0, a.1, b.0, b.1) / EARTH_RADIUS_METERS;
+    let segments = segments.max(1);
+    if angle.sin().abs() < 1e-12 {
+        //The points are the same (or exactly opposite, where every great circle works), so there
+        //is nothing to interpolate
+        return vec![a; segments + 1];
+    }
+
+    (0..=segments)
+        .map(|i| {
+            //Spherical linear interpolation between the two unit vectors
+            let f = i as f64 / segments as f64;
+            let start_weight = ((1.0 - f) * angle).sin() / angle.sin();
+            let end_weight = (f * angle).sin() / angle.sin();
+            let [x, y, z] = [0, 1, 2].map(|i| start[i] * start_weight + end[i] * end_weight);
+
+            let lat = z.atan2((x * x + y * y).sqrt()).to_degrees();
+            let lng = y.atan2(x).to_degrees();
+            (lat, lng)
+        })
+        .collect()
+}
+
+/// Returns the latitude in radians stretched the way the mercator projection stretches it, which
+/// rhumb lines are straight in
+fn stretched_latitude(lat: f64) -> f64 {
+    (std::f64::consts::FRAC_PI_4 + lat / 2.0).tan().ln()
+}
+
+/// Returns the distance in meters between two points given in degrees along the rhumb line
+/// joining them, going the short way around the world.
+///
+/// A rhumb line crosses every meridian at the same angle, so it can be flown on a constant
+/// heading, but it is longer than the great circle between the same points
+pub fn rhumb_line_distance(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lng = wrap_longitude(lng2 - lng1).to_radians();
+    let d_stretched = stretched_latitude(lat2) - stretched_latitude(lat1);
+
+    //Lines running east-west have no change in latitude to compare against
+    let q = if d_stretched.abs() > 1e-12 {
+        d_lat / d_stretched
+    } else {
+        lat1.cos()
+    };
+    (d_lat * d_lat + q * q * d_lng * d_lng).sqrt() * EARTH_RADIUS_METERS
+}
+
+/// Returns `segments + 1` evenly spaced points in degrees along the rhumb line from `a` to `b`,
+/// going the short way around the world and including both endpoints. Points are
+/// `(latitude, longitude)` pairs, like [`great_circle_points`]
+pub fn rhumb_line_points(a: (f64, f64), b: (f64, f64), segments: usize) -> Vec<(f64, f64)> {
+    let segments = segments.max(1);
+    let (lat1, lat2) = (a.0.to_radians(), b.0.to_radians());
+    let d_lng = wrap_longitude(b.1 - a.1);
+    let start_stretched = stretched_latitude(lat1);
+    let d_stretched = stretched_latitude(lat2) - start_stretched;
+
+    (0..=segments)
+        .map(|i| {
+            //Distance along a rhumb line is proportional to the change in latitude, and it is
+            //straight on the mercator map
+            let f = i as f64 / segments as f64;
+            let lat = lat1 + (lat2 - lat1) * f;
+            let along = if d_stretched.abs() > 1e-12 {
+                (stretched_latitude(lat) - start_stretched) / d_stretched
+            } else {
+                f
+            };
+            (lat.to_degrees(), wrap_longitude(a.1 + d_lng * along))
+        })
+        .collect()
+}
+
+/// Returns the point in degrees reached by travelling `distance` meters from `(lat, lng)` along
+/// the great circle leaving at `bearing` degrees clockwise from north
+pub fn destination_point(lat: f64, lng: f64, bearing: f64, distance: f64) -> (f64, f64) {
+    let (lat, lng) = (lat.to_radians(), lng.to_radians());
+    let bearing = bearing.to_radians();
+    let angle = distance / EARTH_RADIUS_METERS;
+
+    let end_lat = (lat.sin() * angle.cos() + lat.cos() * angle.sin() * bearing.cos())
+        .clamp(-1.0, 1.0)
+        .asin();
+    let end_lng = lng
+        + (bearing.sin() * angle.sin() * lat.cos()).atan2(angle.cos() - lat.sin() * end_lat.sin());
+    (end_lat.to_degrees(), wrap_longitude(end_lng.to_degrees()))
+}
+
+/// Returns `segments + 1` points in degrees around the circle of points `radius` meters from
+/// `center` on the ground, starting and ending due north of `center`.
+///
+/// The mercator projection stretches this circle into an egg shape, which gets noticeable once
+/// the radius is a few hundred kilometers
+pub fn geodesic_circle(center: (f64, f64), radius: f64, segments: usize) -> Vec<(f64, f64)> {
+    let segments = segments.max(3);
+    let mut points: Vec<_> = (0..segments)
+        .map(|i| {
+            let bearing = i as f64 * 360.0 / segments as f64;
+            destination_point(center.0, center.1, bearing, radius)
+        })
+        .collect();
+    points.push(points[0]);
+    points
+}
+
+/// Splits a line of `(latitude, longitude)` points wherever it crosses the antimeridian, so that
+/// each piece can be drawn without a line jumping across the whole map
+pub fn split_at_antimeridian(points: &[(f64, f64)]) -> Vec<Vec<(f64, f64)>> {
+    let mut pieces: Vec<Vec<(f64, f64)>> = Vec::new();
+    let mut last_lng: Option<f64> = None;
+    for &point in points {
+        match (last_lng, pieces.last_mut()) {
+            (Some(last), Some(piece)) if (point.1 - last).abs() <= 180.0 => piece.push(point),
+            _ => pieces.push(vec![point]),
+        }
+        last_lng = Some(point.1);
+    }
+    pieces
+}
+
+/// Rounds a positive number down to the nearest "nice" value of the form 1, 2, or 5 times a power
+/// of ten
+pub fn round_down_nice(value: f64) -> f64 {
+    if value <= 0.0 || !value.is_finite() {
+        return 0.0;
+    }
+    let power = 10.0f64.powf(value.log10().floor());
+    let part = value / power;
+
+    if part >= 5.0 {
+        5.0 * power
+    } else if part >= 2.0 {
+        2.0 * power
+    } else {
+        power
+    }
+}
+
+/// Returns true if `point` is inside the polygon with the corners `polygon`, using the even-odd
+/// rule. The polygon is closed automatically, so the last corner shouldn't repeat the first.
+///
+/// Points are `(x, y)` on a flat surface, such as world coordinates
+pub fn point_in_polygon(point: (f64, f64), polygon: &[(f64, f64)]) -> bool {
+    let (x, y) = point;
+    let mut inside = false;
+    let mut previous = match polygon.last() {
+        Some(&last) => last,
+        None => return false,
+    };
+    for &corner in polygon {
+        let ((x1, y1), (x2, y2)) = (previous, corner);
+        //Count the edges crossed by a ray going right from the point
+        if (y1 > y) != (y2 > y) && x < x1 + (y - y1) / (y2 - y1) * (x2 - x1) {
+            inside = !inside;
+        }
+        previous = corner;
+    }
+    inside
+}
+
+/// Panics unless `value` is within 0.00001 of `expected`. Shared by the tests of every module
+#[cfg(test)]
+pub fn ish(value: f64, expected: f64) {
+    ish_bounded(value, expected, 0.00001);
+}
+
+/// Panics unless `value` is within `bound` of `expected`
+#[cfg(test)]
+pub fn ish_bounded(value: f64, expected: f64, bound: f64) {
+    if (value - expected).abs() > bound {
+        panic!("Expected: {}, {} is of range", expected, value);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    fn ish(value: f64, expected: f64) {
-        if (value - expected).abs() > 0.00001 {
-            panic!("Expected: {}, {} is of range", expected, value);
-        }
+    #[test]
+    fn map_test() {
+        ish(map(0.0, 10.0, 2.5, 100.0, 200.0), 125.0);
+        //Outside the input range is extrapolated
+        ish(map(0.0, 10.0, 20.0, 100.0, 200.0), 300.0);
+
+        //Reversed ranges flip the mapping
+        ish(map(0.0, 1.0, 0.25, 1.0, 0.0), 0.75);
+        ish(map(1.0, 0.0, 0.25, 0.0, 1.0), 0.75);
+        ish(map(10.0, 0.0, 10.0, -1.0, 1.0), -1.0);
+
+        //An empty input range maps to the middle of the output range instead of NaN
+        ish(map(5.0, 5.0, 5.0, 0.0, 10.0), 5.0);
+        ish(map(5.0, 5.0, 7.0, -2.0, -4.0), -3.0);
+        assert!(map(0.0, 0.0, 1.0, 0.0, 1.0_f64).is_finite());
+
+        //An empty output range is fine
+        ish(map(0.0, 1.0, 0.3, 4.0, 4.0), 4.0);
     }
 
-    fn ish_bounded(value: f64, expected: f64, bound: f64) {
-        if (value - expected).abs() > bound {
-            panic!("Expected: {}, {} is of range", expected, value);
-        }
+    #[test]
+    fn sample_ramp_test() {
+        let stops = [[0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [1.0, 1.0, 1.0]];
+        assert_eq!(sample_ramp(&stops, 0.0), stops[0]);
+        assert_eq!(sample_ramp(&stops, 0.5), stops[1]);
+        assert_eq!(sample_ramp(&stops, 0.25), [0.5, 0.0, 0.5]);
+        assert_eq!(sample_ramp(&stops, 1.0), stops[2]);
+        //Clamped to the ends
+        assert_eq!(sample_ramp(&stops, -1.0), stops[0]);
+        assert_eq!(sample_ramp(&stops, 2.0), stops[2]);
+
+        //Byte stops are interpolated the same way
+        assert_eq!(
+            sample_ramp(&[[0u8, 100, 255], [255, 0, 255]], 0.5),
+            [127.5, 50.0, 255.0]
+        );
     }
 
     #[test]
@@ -159,6 +453,18 @@ mod tests {
         ish_bounded(latitude_from_y(0.25), 66.5, 0.05);
     }
 
+    #[test]
+    fn test_wrap_longitude() {
+        ish(wrap_longitude(0.0), 0.0);
+        ish(wrap_longitude(179.0), 179.0);
+        ish(wrap_longitude(180.0), 180.0);
+        ish(wrap_longitude(-180.0), 180.0);
+        ish(wrap_longitude(181.0), -179.0);
+        ish(wrap_longitude(-181.0), 179.0);
+        ish(wrap_longitude(540.0), 180.0);
+        ish(wrap_longitude(-721.0), -1.0);
+    }
+
     #[test]
     fn test_modulo_floor() {
         assert_eq!(modulo_floor(4.5, 2.0), 4.0);
@@ -167,6 +473,190 @@ mod tests {
         assert_eq!(modulo_floor(-4.5, 2.0), -6.0);
     }
 
+    #[test]
+    fn rotate_test() {
+        //A quarter turn clockwise takes up (negative y) to right
+        let right = rotate(glam::DVec2::new(0.0, -2.0), std::f64::consts::FRAC_PI_2);
+        ish(right.x, 2.0);
+        ish(right.y, 0.0);
+        let back = rotate(right, -std::f64::consts::FRAC_PI_2);
+        ish(back.x, 0.0);
+        ish(back.y, -2.0);
+    }
+
+    #[test]
+    fn meters_per_world_x_test() {
+        ish(meters_per_world_x(0.0), EARTH_CIRCUMFERENCE_METERS);
+        ish(meters_per_world_x(60.0), EARTH_CIRCUMFERENCE_METERS / 2.0);
+        ish(meters_per_world_x(-60.0), EARTH_CIRCUMFERENCE_METERS / 2.0);
+    }
+
+    #[test]
+    fn haversine_distance_test() {
+        //One degree along the equator
+        ish_bounded(haversine_distance(0.0, 0.0, 0.0, 1.0), 111_195.0, 1.0);
+        ish(haversine_distance(12.0, 34.0, 12.0, 34.0), 0.0);
+
+        //Daytona Beach to Orlando is roughly 87 km
+        let distance = haversine_distance(29.18, -81.05, 28.43, -81.31);
+        ish_bounded(distance, 87_000.0, 2_000.0);
+        ish(distance, haversine_distance(28.43, -81.31, 29.18, -81.05));
+    }
+
+    #[test]
+    fn initial_bearing_test() {
+        ish(initial_bearing(0.0, 0.0, 1.0, 0.0), 0.0);
+        ish(initial_bearing(0.0, 0.0, 0.0, 1.0), 90.0);
+        ish(initial_bearing(1.0, 0.0, 0.0, 0.0), 180.0);
+        ish(initial_bearing(0.0, 1.0, 0.0, 0.0), 270.0);
+
+        //Great circles from New York to London start out heading north east
+        ish_bounded(initial_bearing(40.64, -73.78, 51.47, -0.45), 51.0, 1.0);
+    }
+
+    #[test]
+    fn great_circle_points_test() {
+        let points = great_circle_points((0.0, 0.0), (0.0, 90.0), 3);
+        assert_eq!(points.len(), 4);
+        for (point, lng) in points.iter().zip([0.0, 30.0, 60.0, 90.0]) {
+            ish(point.0, 0.0);
+            ish(point.1, lng);
+        }
+
+        //The route from New York to London bows towards the pole, north of both airports
+        let points = great_circle_points((40.64, -73.78), (51.47, -0.45), 10);
+        ish(points[0].0, 40.64);
+        ish(points[0].1, -73.78);
+        ish(points[10].0, 51.47);
+        ish(points[10].1, -0.45);
+        assert!(points[5].0 > 51.47);
+
+        assert_eq!(
+            great_circle_points((1.0, 2.0), (1.0, 2.0), 4),
+            vec![(1.0, 2.0); 5]
+        );
+    }
+
+    #[test]
+    fn rhumb_line_test() {
+        //Along the equator and meridians, rhumb lines are great circles
+        ish_bounded(
+            rhumb_line_distance(0.0, 0.0, 0.0, 1.0),
+            haversine_distance(0.0, 0.0, 0.0, 1.0),
+            1e-3,
+        );
+        ish_bounded(
+            rhumb_line_distance(10.0, 5.0, 50.0, 5.0),
+            haversine_distance(10.0, 5.0, 50.0, 5.0),
+            1e-3,
+        );
+
+        //New York to London follows the 40th parallel's heading instead of bowing north, and is
+        //noticeably longer
+        let (a, b) = ((40.64, -73.78), (51.47, -0.45));
+        let rhumb = rhumb_line_distance(a.0, a.1, b.0, b.1);
+        let great_circle = haversine_distance(a.0, a.1, b.0, b.1);
+        assert!(
+            rhumb > great_circle + 100_000.0,
+            "{} {}",
+            rhumb,
+            great_circle
+        );
+
+        let points = rhumb_line_points(a, b, 10);
+        assert_eq!(points.len(), 11);
+        ish(points[0].0, a.0);
+        ish(points[0].1, a.1);
+        ish(points[10].0, b.0);
+        ish(points[10].1, b.1);
+        assert!(points[5].0 < 51.47);
+        //The points lie on a straight line on the mercator map
+        let to_world = |(lat, lng): (f64, f64)| (x_from_longitude(lng), y_from_latitude(lat));
+        let (x0, y0) = to_world(a);
+        let (x1, y1) = to_world(b);
+        for &point in &points {
+            let (x, y) = to_world(point);
+            ish((x - x0) * (y1 - y0) - (y - y0) * (x1 - x0), 0.0);
+        }
+
+        //Lines of latitude are rhumb lines, and the short way crosses the antimeridian
+        let points = rhumb_line_points((35.0, 170.0), (35.0, -170.0), 4);
+        for (point, lng) in points.iter().zip([170.0, 175.0, 180.0, -175.0, -170.0]) {
+            ish(point.0, 35.0);
+            ish(point.1, lng);
+        }
+        ish_bounded(
+            rhumb_line_distance(35.0, 170.0, 35.0, -170.0),
+            20.0 * 111_195.0 * 35f64.to_radians().cos(),
+            100.0,
+        );
+    }
+
+    #[test]
+    fn geodesic_circle_test() {
+        //One degree of latitude is about 111 km
+        let (lat, lng) = destination_point(29.0, -81.0, 0.0, 111_195.0);
+        assert!((lat - 30.0).abs() < 0.001, "{}", lat);
+        ish(lng, -81.0);
+
+        //Every point is the same distance away, even for a ring thousands of kilometers wide
+        //that crosses the antimeridian
+        for &(center, radius) in &[((29.0, -81.0), 18_520.0), ((60.0, 175.0), 2_000_000.0)] {
+            let points = geodesic_circle(center, radius, 64);
+            assert_eq!(points.len(), 65);
+            assert_eq!(points[0], points[64]);
+            for &(lat, lng) in &points {
+                let distance = haversine_distance(center.0, center.1, lat, lng);
+                assert!((distance - radius).abs() < 1.0, "{}", distance);
+            }
+        }
+
+        //Far from the equator, the ring is wider than it is tall in degrees
+        let points = geodesic_circle((60.0, 0.0), 500_000.0, 4);
+        assert!(points[1].1 > (points[0].0 - 60.0) * 1.5);
+    }
+
+    #[test]
+    fn split_at_antimeridian_test() {
+        let points = great_circle_points((35.0, 170.0), (35.0, -170.0), 4);
+        let pieces = split_at_antimeridian(&points);
+        assert_eq!(pieces.len(), 2);
+        assert_eq!(pieces[0].len() + pieces[1].len(), points.len());
+        assert!(pieces[0].iter().all(|p| p.1 > 0.0));
+        assert!(pieces[1].iter().all(|p| p.1 < 0.0));
+
+        let points = great_circle_points((0.0, 0.0), (10.0, 10.0), 4);
+        assert_eq!(split_at_antimeridian(&points), vec![points]);
+        assert!(split_at_antimeridian(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_round_down_nice() {
+        ish(round_down_nice(1.0), 1.0);
+        ish(round_down_nice(1.9), 1.0);
+        ish(round_down_nice(4.99), 2.0);
+        ish(round_down_nice(7.5), 5.0);
+        ish(round_down_nice(12.0), 10.0);
+        ish(round_down_nice(3400.0), 2000.0);
+        ish(round_down_nice(0.07), 0.05);
+        assert_eq!(round_down_nice(0.0), 0.0);
+    }
+
+    #[test]
+    fn point_in_polygon_test() {
+        let square = [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)];
+        assert!(point_in_polygon((1.0, 1.0), &square));
+        assert!(!point_in_polygon((3.0, 1.0), &square));
+        assert!(!point_in_polygon((1.0, -0.5), &square));
+
+        //The notch of a concave polygon is outside
+        let notched = [(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (2.0, 1.0), (0.0, 4.0)];
+        assert!(point_in_polygon((1.0, 1.0), &notched));
+        assert!(!point_in_polygon((2.0, 3.0), &notched));
+        assert!(!point_in_polygon((1.0, 1.0), &square[..2]));
+        assert!(!point_in_polygon((1.0, 1.0), &[]));
+    }
+
     #[test]
     fn test_modulo_ceil() {
         assert_eq!(modulo_ceil(4.5, 2.0), 6.0);
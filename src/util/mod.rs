@@ -1,9 +1,15 @@
+mod coord;
 mod math;
 mod perf;
 mod profiler;
 mod string;
+mod trace;
+mod units;
 
+pub use coord::*;
 pub use math::*;
 pub use perf::*;
 pub use profiler::*;
 pub use string::*;
+pub use trace::*;
+pub use units::*;
@@ -7,12 +7,45 @@ use simple_moving_average::{SumTreeSMA, SMA};
 /// The performance data recorded across the entire application relating to tiles
 pub struct PerformanceData {
     pub tiles_rendered: usize,
+    /// The number of tile textures held by every [`crate::TilePipeline`]
     pub tiles_on_gpu: usize,
     pub tiles_in_memory: usize,
+    /// An estimate of the memory used by the textures counted in `tiles_on_gpu`, assuming four
+    /// bytes per pixel
+    pub tile_texture_bytes: usize,
     pub zoom: u32,
     pub backend_request_secs: HashMap<&'static str, SumTreeSMA<Duration, u32, 16>>,
+    pub backend_request_stats: HashMap<&'static str, BackendRequestStats>,
     pub tile_decode_time: SumTreeSMA<Duration, u32, 16>,
     pub tile_upload_time: SumTreeSMA<Duration, u32, 16>,
+    /// How long requests for tiles on screen wait in a [`crate::RequestQueue`] before they are
+    /// sent
+    pub tile_queue_time: SumTreeSMA<Duration, u32, 16>,
+    /// The number of queued requests dropped because their tile left the screen first
+    pub cancelled_requests: u64,
+    /// The number of times a tile was requested again after its last request failed
+    pub tile_retries: u64,
+    /// The number of tiles given up on after every retry failed
+    pub failed_tiles: u64,
+}
+
+/// Counts the outcomes of tile requests made to a single backend
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BackendRequestStats {
+    /// Requests which have been sent but have not finished yet
+    pub pending: usize,
+    /// Requests that returned a tile
+    pub succeeded: u64,
+    /// Requests that finished successfully, but the backend doesn't have the tile
+    pub not_found: u64,
+    /// Requests that failed because of a network or server error
+    pub http_errors: u64,
+    /// Requests that returned data which wasn't a valid image
+    pub decode_errors: u64,
+    /// Requests that took too long and were abandoned
+    pub timeouts: u64,
+    /// Requests that failed for any other reason, such as an I/O error
+    pub other_errors: u64,
 }
 
 /// Largely the same as [`PerformanceData`], but is clone for getting a snapshot out of the mutex
@@ -22,10 +55,16 @@ pub struct PerformanceDataSnapshot {
     pub tiles_rendered: usize,
     pub tiles_on_gpu: usize,
     pub tiles_in_memory: usize,
+    pub tile_texture_bytes: usize,
     pub zoom: u32,
     pub backend_request_secs: Vec<(&'static str, Duration)>,
+    pub backend_request_stats: Vec<(&'static str, BackendRequestStats)>,
     pub tile_decode_time: Duration,
     pub tile_upload_time: Duration,
+    pub tile_queue_time: Duration,
+    pub cancelled_requests: u64,
+    pub tile_retries: u64,
+    pub failed_tiles: u64,
 }
 
 lazy_static! {
@@ -40,14 +79,24 @@ impl PerformanceData {
             tiles_rendered: self.tiles_rendered,
             tiles_on_gpu: self.tiles_on_gpu,
             tiles_in_memory: self.tiles_in_memory,
+            tile_texture_bytes: self.tile_texture_bytes,
             zoom: self.zoom,
             tile_decode_time: self.tile_decode_time.get_average(),
             tile_upload_time: self.tile_upload_time.get_average(),
+            tile_queue_time: self.tile_queue_time.get_average(),
+            cancelled_requests: self.cancelled_requests,
+            tile_retries: self.tile_retries,
+            failed_tiles: self.failed_tiles,
             backend_request_secs: self
                 .backend_request_secs
                 .iter()
                 .map(|(k, v)| (*k, v.get_average()))
                 .collect(),
+            backend_request_stats: self
+                .backend_request_stats
+                .iter()
+                .map(|(k, v)| (*k, *v))
+                .collect(),
         }
     }
 }
@@ -58,10 +107,16 @@ impl Default for PerformanceData {
             tiles_rendered: Default::default(),
             tiles_on_gpu: Default::default(),
             tiles_in_memory: Default::default(),
+            tile_texture_bytes: Default::default(),
             zoom: Default::default(),
             backend_request_secs: Default::default(),
+            backend_request_stats: Default::default(),
             tile_decode_time: SumTreeSMA::from_zero(Duration::ZERO),
             tile_upload_time: SumTreeSMA::from_zero(Duration::ZERO),
+            tile_queue_time: SumTreeSMA::from_zero(Duration::ZERO),
+            cancelled_requests: 0,
+            tile_retries: 0,
+            failed_tiles: 0,
         }
     }
 }
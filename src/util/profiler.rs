@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     sync::Mutex,
     time::{Duration, Instant},
 };
@@ -8,6 +8,8 @@ use std::{
 #[derive(Clone, Debug)]
 pub struct NamedSample {
     completed: Vec<Duration>,
+    /// When each sample in `completed` began
+    start_times: Vec<Instant>,
     in_progress: Option<Instant>,
 }
 
@@ -18,6 +20,14 @@ impl NamedSample {
         }
         &self.completed
     }
+
+    /// Returns the start time and duration of each completed sample
+    pub fn get_timed_samples(&self) -> impl Iterator<Item = (Instant, Duration)> + '_ {
+        self.start_times
+            .iter()
+            .copied()
+            .zip(self.completed.iter().copied())
+    }
 }
 
 struct Samples(Mutex<HashMap<&'static str, NamedSample>>);
@@ -54,6 +64,103 @@ pub fn take_profile_data() -> HashMap<&'static str, NamedSample> {
     })
 }
 
+/// Summary statistics for a named scope over the frames kept by [`ProfileHistory`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ScopeStats {
+    /// The number of samples in the window
+    pub count: usize,
+    pub average: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// Keeps the samples from the last few frames of [`take_profile_data`] so that percentiles can be
+/// computed, while letting old spikes age out
+pub struct ProfileHistory {
+    /// The number of frames to keep samples for
+    window: usize,
+    frame: u64,
+    /// Samples for each scope tagged with the frame they were recorded in, oldest first
+    samples: HashMap<&'static str, VecDeque<(u64, Duration)>>,
+}
+
+impl ProfileHistory {
+    /// Creates a history that keeps samples from the last `window` frames
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            frame: 0,
+            samples: HashMap::new(),
+        }
+    }
+
+    /// Adds a frame worth of samples, and forgets samples older than the window
+    pub fn push_frame(&mut self, data: &HashMap<&'static str, NamedSample>) {
+        self.frame += 1;
+        for (&name, sample) in data {
+            let history = self.samples.entry(name).or_default();
+            history.extend(sample.completed.iter().map(|&d| (self.frame, d)));
+        }
+
+        let oldest = self.frame.saturating_sub(self.window as u64 - 1);
+        self.samples.retain(|_, history| {
+            while history.front().is_some_and(|&(frame, _)| frame < oldest) {
+                history.pop_front();
+            }
+            !history.is_empty()
+        });
+    }
+
+    /// Returns the statistics for every scope with samples in the window, sorted by name
+    pub fn stats(&self) -> Vec<(&'static str, ScopeStats)> {
+        let mut result: Vec<_> = self
+            .samples
+            .iter()
+            .map(|(&name, history)| {
+                let durations: Vec<Duration> = history.iter().map(|&(_, d)| d).collect();
+                (name, compute_stats(durations))
+            })
+            .collect();
+        result.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        result
+    }
+}
+
+/// Computes the statistics for `durations`, which must not be empty
+fn compute_stats(mut durations: Vec<Duration>) -> ScopeStats {
+    let count = durations.len();
+    if count == 1 {
+        //Fast path when the scope only ran once
+        let d = durations[0];
+        return ScopeStats {
+            count,
+            average: d,
+            p50: d,
+            p95: d,
+            p99: d,
+            max: d,
+        };
+    }
+    durations.sort_unstable();
+    let average = durations.iter().sum::<Duration>() / count as u32;
+    ScopeStats {
+        count,
+        average,
+        p50: percentile(&durations, 50.0),
+        p95: percentile(&durations, 95.0),
+        p99: percentile(&durations, 99.0),
+        max: durations[count - 1],
+    }
+}
+
+/// Returns the `p`th percentile of `sorted` using the nearest rank method
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
 /// A kind of profiling guard that captures the length `self` is alive for
 pub struct ScopeSampler {
     name: &'static str,
@@ -92,6 +199,7 @@ impl Samples {
             .unwrap_or_else(|| panic!("No sample started!"));
 
         sample.completed.push(end - start);
+        sample.start_times.push(start);
     }
 
     fn start(&self, name: &'static str) {
@@ -101,6 +209,7 @@ impl Samples {
         let mut guard = self.0.lock().unwrap();
         let sample = guard.entry(name).or_insert(NamedSample {
             completed: Vec::new(),
+            start_times: Vec::new(),
             in_progress: None,
         });
 
@@ -110,3 +219,46 @@ impl Samples {
         sample.in_progress = Some(start);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn percentiles() {
+        let durations: Vec<_> = (1..=100).map(ms).collect();
+        let stats = compute_stats(durations);
+        assert_eq!(stats.count, 100);
+        assert_eq!(stats.p50, ms(50));
+        assert_eq!(stats.p95, ms(95));
+        assert_eq!(stats.p99, ms(99));
+        assert_eq!(stats.max, ms(100));
+        assert_eq!(stats.average, Duration::from_micros(50_500));
+
+        let stats = compute_stats(vec![ms(7)]);
+        assert_eq!(stats.p99, ms(7));
+        assert_eq!(stats.max, ms(7));
+    }
+
+    #[test]
+    fn spikes_age_out() {
+        let mut history = ProfileHistory::new(3);
+
+        drop(profile_scope("Frame"));
+        history.push_frame(&take_profile_data());
+        assert_eq!(history.stats().len(), 1);
+
+        //Two more frames without the scope keeps it in the window
+        history.push_frame(&take_profile_data());
+        history.push_frame(&take_profile_data());
+        assert_eq!(history.stats()[0].1.count, 1);
+
+        //The fourth frame pushes the first one out
+        history.push_frame(&take_profile_data());
+        assert!(history.stats().is_empty());
+    }
+}
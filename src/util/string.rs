@@ -1,8 +1,15 @@
 use std::mem::MaybeUninit;
 
+/// A reusable buffer for formatting strings without allocating.
+///
+/// Text is written into an inline buffer of `N` bytes. If a string doesn't fit, the formatter
+/// spills to a heap allocated `String` and keeps using it for the rest of its life, so writes never
+/// fail or truncate. Size `N` so that spilling is rare
 pub struct StringFormatter<const N: usize> {
     buf: [u8; N],
     index: usize,
+    /// Holds the text once it has outgrown `buf`
+    spilled: Option<String>,
 }
 
 impl<const N: usize> Default for StringFormatter<N> {
@@ -21,14 +28,26 @@ impl<const N: usize> StringFormatter<N> {
             // to before reading. These are simply bytes which can have any value
             buf: unsafe { buf.assume_init() },
             index: 0,
+            spilled: None,
         }
     }
 
     pub fn clear(&mut self) {
         self.index = 0;
+        if let Some(spilled) = &mut self.spilled {
+            spilled.clear();
+        }
+    }
+
+    /// Returns true if this formatter has outgrown its inline buffer and now writes to the heap
+    pub fn is_spilled(&self) -> bool {
+        self.spilled.is_some()
     }
 
     pub fn as_str(&self) -> &str {
+        if let Some(spilled) = &self.spilled {
+            return spilled.as_str();
+        }
         // # Safety
         //
         // 1. `self.buf` is guaranteed to be valid for writes in range `0..self.index` by the
@@ -41,14 +60,27 @@ impl<const N: usize> StringFormatter<N> {
 
 impl<const N: usize> std::fmt::Write for StringFormatter<N> {
     fn write_str(&mut self, s: &str) -> Result<(), std::fmt::Error> {
-        let src = s.as_ptr();
-        let dst = &mut self.buf[self.index] as *mut u8;
-        let len = s.len();
+        if let Some(spilled) = &mut self.spilled {
+            spilled.push_str(s);
+            return Ok(());
+        }
 
+        let len = s.len();
         if self.index + len > self.buf.len() {
-            //Would overflow
-            return Err(std::fmt::Error);
+            //Would overflow, so move everything to the heap
+            let mut spilled = String::with_capacity((self.index + len).max(N * 2));
+            spilled.push_str(self.as_str());
+            spilled.push_str(s);
+            self.spilled = Some(spilled);
+            return Ok(());
         }
+
+        let src = s.as_ptr();
+        // # Safety
+        //
+        // `self.index + len <= self.buf.len()` by the check above, so this is at most one past the
+        // end of `self.buf`
+        let dst = unsafe { self.buf.as_mut_ptr().add(self.index) };
         // # Safety
         //
         // 1. `src` is valid for reads length `len`, by the bounds check above
@@ -62,3 +94,36 @@ impl<const N: usize> std::fmt::Write for StringFormatter<N> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Write;
+
+    #[test]
+    fn fits_inline() {
+        let mut buf: StringFormatter<8> = StringFormatter::new();
+        let number = 1234;
+        write!(buf, "abcd{}", number).unwrap();
+        assert_eq!(buf.as_str(), "abcd1234");
+        assert!(!buf.is_spilled());
+
+        //Writing nothing into a full buffer is fine
+        write!(buf, "").unwrap();
+        assert_eq!(buf.as_str(), "abcd1234");
+    }
+
+    #[test]
+    fn overflow_spills_to_heap() {
+        let mut buf: StringFormatter<8> = StringFormatter::new();
+        write!(buf, "Frame time: {:.2} ms", 16.6667).unwrap();
+        assert_eq!(buf.as_str(), "Frame time: 16.67 ms");
+        assert!(buf.is_spilled());
+
+        //The heap buffer is reused after clearing
+        buf.clear();
+        assert_eq!(buf.as_str(), "");
+        write!(buf, "{}", "x".repeat(100)).unwrap();
+        assert_eq!(buf.as_str().len(), 100);
+    }
+}
@@ -0,0 +1,137 @@
+//! Exports profiling samples in the Chrome tracing format, which can be opened in
+//! `chrome://tracing` or <https://ui.perfetto.dev>
+
+use std::{collections::HashMap, path::Path, time::Instant};
+
+use serde::Serialize;
+
+use super::NamedSample;
+
+/// A single complete event in the Chrome tracing format
+#[derive(Serialize)]
+struct TraceEvent {
+    name: &'static str,
+    /// The event type. Always "X" for complete events, which have a start and a duration
+    ph: &'static str,
+    /// Start time in microseconds
+    ts: f64,
+    /// Duration in microseconds
+    dur: f64,
+    pid: u32,
+    tid: u32,
+}
+
+#[derive(Serialize)]
+struct TraceFile<'a> {
+    #[serde(rename = "traceEvents")]
+    trace_events: &'a [TraceEvent],
+}
+
+/// Accumulates profiling samples over many frames so they can be written to a trace file
+pub struct TraceRecorder {
+    /// Timestamps in the trace are relative to this instant
+    epoch: Instant,
+    events: Vec<TraceEvent>,
+    recording: bool,
+}
+
+impl TraceRecorder {
+    pub fn new() -> Self {
+        Self {
+            epoch: Instant::now(),
+            events: Vec::new(),
+            recording: false,
+        }
+    }
+
+    /// Returns true if samples passed to [`TraceRecorder::record`] are being kept
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Starts keeping samples, discarding any from a previous recording
+    pub fn start(&mut self) {
+        self.epoch = Instant::now();
+        self.events.clear();
+        self.recording = true;
+    }
+
+    /// Adds one frame's worth of samples from [`super::take_profile_data`] to the trace. Does
+    /// nothing if not recording
+    pub fn record(&mut self, data: &HashMap<&'static str, NamedSample>) {
+        if !self.recording {
+            return;
+        }
+        for (&name, sample) in data {
+            for (start, duration) in sample.get_timed_samples() {
+                //Samples started before the recording get negative timestamps, so skip them
+                if start < self.epoch {
+                    continue;
+                }
+                self.events.push(TraceEvent {
+                    name,
+                    ph: "X",
+                    ts: (start - self.epoch).as_secs_f64() * 1_000_000.0,
+                    dur: duration.as_secs_f64() * 1_000_000.0,
+                    pid: 1,
+                    tid: 1,
+                });
+            }
+        }
+    }
+
+    /// Serializes the recorded samples to a JSON string
+    pub fn to_json(&self) -> String {
+        let file = TraceFile {
+            trace_events: &self.events,
+        };
+        serde_json::to_string(&file).expect("Trace events are always serializable")
+    }
+
+    /// Stops recording and writes the trace to `path`
+    pub fn finish(&mut self, path: &Path) -> std::io::Result<()> {
+        self.recording = false;
+        let result = std::fs::write(path, self.to_json());
+        self.events.clear();
+        result
+    }
+}
+
+impl Default for TraceRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_only_while_recording() {
+        let mut recorder = TraceRecorder::new();
+
+        drop(crate::util::profile_scope("Before"));
+        recorder.record(&crate::util::take_profile_data());
+        assert!(recorder.events.is_empty());
+
+        recorder.start();
+        {
+            let _outer = crate::util::profile_scope("Outer");
+            drop(crate::util::profile_scope("Inner"));
+        }
+        recorder.record(&crate::util::take_profile_data());
+
+        let json: serde_json::Value = serde_json::from_str(&recorder.to_json()).unwrap();
+        let events = json["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 2);
+
+        let outer = events.iter().find(|e| e["name"] == "Outer").unwrap();
+        let inner = events.iter().find(|e| e["name"] == "Inner").unwrap();
+        assert_eq!(outer["ph"], "X");
+        //The inner scope must be nested inside the outer one for the trace viewer to stack them
+        let start = |e: &serde_json::Value| e["ts"].as_f64().unwrap();
+        let end = |e: &serde_json::Value| start(e) + e["dur"].as_f64().unwrap();
+        assert!(start(outer) <= start(inner) && end(inner) <= end(outer));
+    }
+}
@@ -0,0 +1,203 @@
+//! The units distances, altitudes and speeds are shown in, and the conversions between them
+
+pub const METERS_PER_FOOT: f64 = 0.3048;
+const METERS_PER_MILE: f64 = 1609.344;
+const METERS_PER_NAUTICAL_MILE: f64 = 1852.0;
+const SECONDS_PER_HOUR: f64 = 3600.0;
+
+/// Converts `feet` into meters. Altitudes from ADS-B, the altitude filter and airport elevations
+/// are all in feet
+pub fn feet_to_meters(feet: f64) -> f64 {
+    feet * METERS_PER_FOOT
+}
+
+/// Converts `meters` into feet
+pub fn meters_to_feet(meters: f64) -> f64 {
+    meters / METERS_PER_FOOT
+}
+
+/// A system of units used for every number shown on screen
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Units {
+    /// Kilometers, meters and km/h
+    Metric,
+    /// Miles, feet and mph
+    Imperial,
+    /// Nautical miles, feet and knots, as used in aviation
+    Nautical,
+}
+
+impl Units {
+    pub const ALL: [Units; 3] = [Units::Metric, Units::Imperial, Units::Nautical];
+
+    /// Returns the units after this one, wrapping around to the first
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&units| units == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// A human readable name for these units
+    pub fn name(self) -> &'static str {
+        match self {
+            Units::Metric => "Metric",
+            Units::Imperial => "Imperial",
+            Units::Nautical => "Nautical",
+        }
+    }
+
+    /// Parses units from their name, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|units| units.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// The length of the unit long distances are measured in, in meters
+    fn meters_per_distance(self) -> f64 {
+        match self {
+            Units::Metric => 1000.0,
+            Units::Imperial => METERS_PER_MILE,
+            Units::Nautical => METERS_PER_NAUTICAL_MILE,
+        }
+    }
+
+    /// The abbreviation of the unit long distances are measured in
+    pub fn distance_suffix(self) -> &'static str {
+        match self {
+            Units::Metric => "km",
+            Units::Imperial => "mi",
+            Units::Nautical => "nm",
+        }
+    }
+
+    /// Converts `meters` into the unit long distances are measured in
+    pub fn distance_from_meters(self, meters: f64) -> f64 {
+        meters / self.meters_per_distance()
+    }
+
+    /// Converts `distance` in the unit long distances are measured in into meters
+    pub fn distance_to_meters(self, distance: f64) -> f64 {
+        distance * self.meters_per_distance()
+    }
+
+    /// Formats a distance of `meters`, such as "12.5 km"
+    pub fn format_distance(self, meters: f64, decimals: usize) -> String {
+        format!(
+            "{:.*} {}",
+            decimals,
+            self.distance_from_meters(meters),
+            self.distance_suffix()
+        )
+    }
+
+    /// The length of the unit altitudes and short distances are measured in, in meters
+    fn meters_per_altitude(self) -> f64 {
+        match self {
+            Units::Metric => 1.0,
+            Units::Imperial | Units::Nautical => METERS_PER_FOOT,
+        }
+    }
+
+    /// The abbreviation of the unit altitudes and short distances are measured in
+    pub fn altitude_suffix(self) -> &'static str {
+        match self {
+            Units::Metric => "m",
+            Units::Imperial | Units::Nautical => "ft",
+        }
+    }
+
+    /// Converts `meters` into the unit altitudes are measured in
+    pub fn altitude_from_meters(self, meters: f64) -> f64 {
+        meters / self.meters_per_altitude()
+    }
+
+    /// Converts `altitude` in the unit altitudes are measured in into meters
+    pub fn altitude_to_meters(self, altitude: f64) -> f64 {
+        altitude * self.meters_per_altitude()
+    }
+
+    /// Formats an altitude of `meters` to the nearest whole unit, such as "35000 ft"
+    pub fn format_altitude(self, meters: f64) -> String {
+        format!(
+            "{:.0} {}",
+            self.altitude_from_meters(meters),
+            self.altitude_suffix()
+        )
+    }
+
+    /// Formats a speed of `meters_per_second` to the nearest whole unit, such as "450 kt"
+    pub fn format_speed(self, meters_per_second: f64) -> String {
+        let speed = meters_per_second * SECONDS_PER_HOUR / self.meters_per_distance();
+        let suffix = match self {
+            Units::Metric => "km/h",
+            Units::Imperial => "mph",
+            Units::Nautical => "kt",
+        };
+        format!("{:.0} {}", speed, suffix)
+    }
+
+    /// Formats a rate of climb of `meters_per_second`, signed so that descents are negative, such
+    /// as "+1200 ft/min"
+    pub fn format_vertical_rate(self, meters_per_second: f64) -> String {
+        match self {
+            Units::Metric => format!("{:+.1} m/s", meters_per_second),
+            Units::Imperial | Units::Nautical => {
+                format!("{:+.0} ft/min", meters_per_second / METERS_PER_FOOT * 60.0)
+            }
+        }
+    }
+
+    /// Returns the longest round distance no longer than `max_meters` in meters, and its label.
+    ///
+    /// Distances shorter than one of the long distance unit are given in the short unit, such as
+    /// "500 m" instead of "0.5 km"
+    pub fn round_distance(self, max_meters: f64) -> (f64, String) {
+        let long = crate::util::round_down_nice(self.distance_from_meters(max_meters));
+        if long >= 1.0 {
+            return (
+                self.distance_to_meters(long),
+                format!("{} {}", long, self.distance_suffix()),
+            );
+        }
+        let short = crate::util::round_down_nice(self.altitude_from_meters(max_meters));
+        (
+            self.altitude_to_meters(short),
+            format!("{} {}", short, self.altitude_suffix()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions_and_formatting() {
+        assert_eq!(Units::from_name("NAUTICAL"), Some(Units::Nautical));
+        assert_eq!(Units::from_name("cubits"), None);
+        assert_eq!(Units::Nautical.next(), Units::Metric);
+
+        assert_eq!(Units::Metric.format_distance(12_345.0, 1), "12.3 km");
+        assert_eq!(Units::Imperial.format_distance(1609.344, 2), "1.00 mi");
+        assert_eq!(Units::Nautical.format_distance(3704.0, 0), "2 nm");
+        assert!((Units::Nautical.distance_to_meters(10.0) - 18_520.0).abs() < 1e-9);
+
+        assert_eq!(Units::Metric.format_altitude(3048.0), "3048 m");
+        assert_eq!(Units::Nautical.format_altitude(3048.0), "10000 ft");
+        assert!((feet_to_meters(10_000.0) - 3048.0).abs() < 1e-9);
+        assert!((meters_to_feet(feet_to_meters(123.0)) - 123.0).abs() < 1e-9);
+        assert_eq!(Units::Metric.format_speed(100.0), "360 km/h");
+        assert_eq!(Units::Imperial.format_speed(100.0), "224 mph");
+        assert_eq!(Units::Nautical.format_speed(100.0), "194 kt");
+        assert_eq!(Units::Metric.format_vertical_rate(-2.5), "-2.5 m/s");
+        assert_eq!(Units::Imperial.format_vertical_rate(5.08), "+1000 ft/min");
+
+        let (meters, label) = Units::Metric.round_distance(2_600.0);
+        assert_eq!((meters, label.as_str()), (2000.0, "2 km"));
+        let (meters, label) = Units::Metric.round_distance(600.0);
+        assert_eq!((meters, label.as_str()), (500.0, "500 m"));
+        let (meters, label) = Units::Imperial.round_distance(400.0);
+        assert!((meters - 1000.0 * METERS_PER_FOOT).abs() < 1e-9);
+        assert_eq!(label, "1000 ft");
+    }
+}
@@ -0,0 +1,372 @@
+//! An editor for a flight plan made of waypoints joined by great circle or rhumb line legs
+
+use glam::DVec2;
+
+/// How close in pixels the cursor must be to a waypoint to grab or delete it
+const HIT_RADIUS: f64 = 10.0;
+
+/// How the legs between waypoints are drawn and measured
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum RouteMode {
+    /// The shortest path, which changes heading along the way
+    #[default]
+    GreatCircle,
+    /// The path flown on a constant heading, which is straight on the map but longer
+    RhumbLine,
+}
+
+impl RouteMode {
+    /// Every mode, in the order the route mode button cycles through them
+    pub const ALL: [RouteMode; 2] = [RouteMode::GreatCircle, RouteMode::RhumbLine];
+
+    /// Returns the mode after this one, wrapping around to the first
+    pub fn next(self) -> RouteMode {
+        let index = Self::ALL.iter().position(|&mode| mode == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// A short human readable name for this mode
+    pub fn name(self) -> &'static str {
+        match self {
+            RouteMode::GreatCircle => "Great circle",
+            RouteMode::RhumbLine => "Rhumb line",
+        }
+    }
+
+    /// Returns `segments + 1` points in degrees along the leg from `a` to `b`
+    pub fn points(self, a: (f64, f64), b: (f64, f64), segments: usize) -> Vec<(f64, f64)> {
+        match self {
+            RouteMode::GreatCircle => crate::util::great_circle_points(a, b, segments),
+            RouteMode::RhumbLine => crate::util::rhumb_line_points(a, b, segments),
+        }
+    }
+
+    /// Returns the length in meters of the leg from `a` to `b`
+    pub fn distance(self, (lat1, lng1): (f64, f64), (lat2, lng2): (f64, f64)) -> f64 {
+        match self {
+            RouteMode::GreatCircle => crate::util::haversine_distance(lat1, lng1, lat2, lng2),
+            RouteMode::RhumbLine => crate::util::rhumb_line_distance(lat1, lng1, lat2, lng2),
+        }
+    }
+}
+
+/// An ordered list of waypoints in degrees of latitude and longitude, the waypoint being
+/// dragged, if any, and how the legs between them are drawn
+#[derive(Debug, Default)]
+pub struct WaypointRoute {
+    points: Vec<(f64, f64)>,
+    dragging: Option<usize>,
+    mode: RouteMode,
+}
+
+impl WaypointRoute {
+    /// Adds a waypoint to the end of the route
+    pub fn push(&mut self, lat: f64, lng: f64) {
+        self.points.push((lat, lng));
+    }
+
+    /// Removes the waypoint at `index`, returning it if it existed
+    pub fn remove(&mut self, index: usize) -> Option<(f64, f64)> {
+        if index >= self.points.len() {
+            return None;
+        }
+        self.dragging = None;
+        Some(self.points.remove(index))
+    }
+
+    /// Removes every waypoint
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.dragging = None;
+    }
+
+    pub fn points(&self) -> &[(f64, f64)] {
+        &self.points
+    }
+
+    /// Starts dragging the waypoint at `index`. Returns false if there is no such waypoint
+    pub fn start_drag(&mut self, index: usize) -> bool {
+        if index < self.points.len() {
+            self.dragging = Some(index);
+        }
+        self.dragging.is_some()
+    }
+
+    /// Moves the waypoint being dragged to `lat`, `lng`
+    pub fn drag_to(&mut self, lat: f64, lng: f64) {
+        if let Some(index) = self.dragging {
+            self.points[index] = (lat, lng);
+        }
+    }
+
+    /// Stops dragging, returning true if a waypoint was being dragged
+    pub fn end_drag(&mut self) -> bool {
+        self.dragging.take().is_some()
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.dragging.is_some()
+    }
+
+    pub fn mode(&self) -> RouteMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: RouteMode) {
+        self.mode = mode;
+    }
+
+    /// Returns the distance in meters along the route from the first waypoint to each waypoint,
+    /// measured in the route's [`RouteMode`]. The first entry is always zero
+    pub fn cumulative_distances(&self) -> Vec<f64> {
+        let mut total = 0.0;
+        let mut previous: Option<(f64, f64)> = None;
+        self.points
+            .iter()
+            .map(|&point| {
+                if let Some(previous) = previous {
+                    total += self.mode.distance(previous, point);
+                }
+                previous = Some(point);
+                total
+            })
+            .collect()
+    }
+
+    /// The length of the whole route in meters, in the route's [`RouteMode`]
+    pub fn total_distance(&self) -> f64 {
+        self.total_distance_in(self.mode)
+    }
+
+    /// The length of the whole route in meters if its legs followed `mode`
+    pub fn total_distance_in(&self, mode: RouteMode) -> f64 {
+        self.points
+            .windows(2)
+            .map(|leg| mode.distance(leg[0], leg[1]))
+            .sum()
+    }
+
+    /// Returns the index of the waypoint closest to `pixel`, if any are within [`HIT_RADIUS`].
+    ///
+    /// `pixel` is measured from the top left of the window in logical pixels, like
+    /// [`crate::TileView::pixel_to_world`]
+    pub fn hit_test(
+        &self,
+        view: &crate::map::TileView,
+        pixel: DVec2,
+        screen_width: f64,
+        screen_height: f64,
+    ) -> Option<usize> {
+        hit_test_points(&self.points, view, pixel, screen_width, screen_height)
+    }
+}
+
+/// Returns the index of the point in `points`, in degrees of latitude and longitude, closest to
+/// `pixel`, if any are within [`HIT_RADIUS`].
+///
+/// `pixel` is measured from the top left of the window in logical pixels, like
+/// [`crate::TileView::pixel_to_world`]
+pub fn hit_test_points(
+    points: &[(f64, f64)],
+    view: &crate::map::TileView,
+    pixel: DVec2,
+    screen_width: f64,
+    screen_height: f64,
+) -> Option<usize> {
+    let cursor_world = view.pixel_to_world(pixel, screen_width, screen_height);
+    points
+        .iter()
+        .enumerate()
+        .map(|(i, &(lat, lng))| {
+            let world_x = crate::util::x_from_longitude(lng);
+            //Use the copy of the point closest to the cursor
+            let world_x = world_x + (cursor_world.x - world_x).round();
+            let world = DVec2::new(world_x, crate::util::y_from_latitude(lat));
+            let point_pixel = view.world_to_pixel(world, screen_width, screen_height);
+            (i, point_pixel.distance(pixel))
+        })
+        .filter(|&(_, distance)| distance <= HIT_RADIUS)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+}
+
+/// Useful functions for rendering a [`WaypointRoute`] on the map
+pub mod waypoint_renderer {
+    use conrod_core::{
+        widget::{Circle, Line, Text},
+        Colorable, Positionable, UiCell, Widget,
+    };
+
+    use super::{RouteMode, WaypointRoute};
+    use crate::Units;
+
+    /// The number of line segments used to draw each leg of the route
+    const LEG_SEGMENTS: usize = 32;
+
+    const WAYPOINT_COLOR: conrod_core::Color = conrod_core::color::LIGHT_ORANGE;
+
+    /// Draws the waypoints, the legs between them in the route's [`super::RouteMode`], and a panel
+    /// in the bottom left listing the cumulative distance to each waypoint. The panel also lists
+    /// the total distance in the other modes, to compare them
+    pub fn draw(
+        route: &WaypointRoute,
+        view: &crate::map::TileView,
+        ids: &mut crate::Ids,
+        font: conrod_core::text::font::Id,
+        units: Units,
+        ui: &mut UiCell,
+    ) {
+        let viewport = view.get_world_viewport(ui.win_w, ui.win_h);
+        let center_x = (viewport.top_left.x + viewport.bottom_right.x) / 2.0;
+        let points = route.points();
+
+        //========== Draw Legs ==========
+        let mut lines = Vec::new();
+        for leg in points.windows(2) {
+            let leg_points = route.mode().points(leg[0], leg[1], LEG_SEGMENTS);
+            for piece in crate::util::split_at_antimeridian(&leg_points) {
+                //Shift the whole piece by the same amount so it stays connected, using the copy
+                //of the world closest to the center of the screen
+                let first_x = crate::util::x_from_longitude(piece[0].1);
+                let shift = (center_x - first_x).round();
+
+                let pixels: Vec<[f64; 2]> = piece
+                    .iter()
+                    .map(|&(lat, lng)| {
+                        let world_x = crate::util::x_from_longitude(lng) + shift;
+                        let world_y = crate::util::y_from_latitude(lat);
+                        [
+                            crate::world_x_to_pixel_x(world_x, &viewport, ui.win_w),
+                            crate::world_y_to_pixel_y(world_y, &viewport, ui.win_h),
+                        ]
+                    })
+                    .collect();
+                lines.extend(pixels.windows(2).map(|pair| (pair[0], pair[1])));
+            }
+        }
+
+        ids.waypoint_lines
+            .resize(lines.len(), &mut ui.widget_id_generator());
+        for (i, (start, end)) in lines.into_iter().enumerate() {
+            Line::new(start, end)
+                .x_y(0.0, 0.0)
+                .color(WAYPOINT_COLOR)
+                .thickness(2.0)
+                .set(ids.waypoint_lines[i], ui);
+        }
+
+        //========== Draw Waypoints ==========
+        ids.waypoint_markers
+            .resize(points.len(), &mut ui.widget_id_generator());
+        ids.waypoint_labels
+            .resize(points.len(), &mut ui.widget_id_generator());
+        for (i, &(lat, lng)) in points.iter().enumerate() {
+            let world_x = crate::util::x_from_longitude(lng);
+            let world_x = world_x + (center_x - world_x).round();
+            let world_y = crate::util::y_from_latitude(lat);
+            let x = crate::world_x_to_pixel_x(world_x, &viewport, ui.win_w);
+            let y = crate::world_y_to_pixel_y(world_y, &viewport, ui.win_h);
+
+            Circle::fill(6.0)
+                .x_y(x, y)
+                .color(WAYPOINT_COLOR)
+                .set(ids.waypoint_markers[i], ui);
+            Text::new(&(i + 1).to_string())
+                .x_y(x, y + 14.0)
+                .color(conrod_core::color::WHITE)
+                .font_size(12)
+                .font_id(font)
+                .set(ids.waypoint_labels[i], ui);
+        }
+
+        //========== Draw Distance Panel ==========
+        if points.is_empty() {
+            return;
+        }
+        let mut text = String::new();
+        for (i, meters) in route.cumulative_distances().into_iter().enumerate() {
+            text += &format!("{:>2}: {}\n", i + 1, units.format_distance(meters, 1));
+        }
+        text += &format!(
+            "Total: {} ({})",
+            units.format_distance(route.total_distance(), 1),
+            route.mode().name()
+        );
+        for mode in RouteMode::ALL {
+            if mode != route.mode() {
+                text += &format!(
+                    "\n{}: {}",
+                    mode.name(),
+                    units.format_distance(route.total_distance_in(mode), 1)
+                );
+            }
+        }
+        Text::new(&text)
+            .bottom_left_with_margins_on(ui.window, 60.0, 12.0)
+            .color(conrod_core::color::WHITE)
+            .font_size(14)
+            .font_id(font)
+            .set(ids.waypoint_panel, ui);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cumulative_distance() {
+        let mut route = WaypointRoute::default();
+        assert_eq!(route.total_distance(), 0.0);
+
+        route.push(0.0, 0.0);
+        route.push(0.0, 1.0);
+        route.push(1.0, 1.0);
+        let distances = route.cumulative_distances();
+        assert_eq!(distances.len(), 3);
+        assert_eq!(distances[0], 0.0);
+        //One degree along the equator is about 111km
+        assert!((distances[1] - 111_195.0).abs() < 100.0);
+        assert!(distances[2] > distances[1]);
+        assert_eq!(route.total_distance(), distances[2]);
+
+        //Rhumb lines are never shorter, and the route measures itself in its own mode
+        let great_circle = route.total_distance();
+        let rhumb = route.total_distance_in(RouteMode::RhumbLine);
+        assert!(rhumb >= great_circle - 1e-6);
+        route.set_mode(RouteMode::GreatCircle.next());
+        assert_eq!(route.mode(), RouteMode::RhumbLine);
+        assert_eq!(route.total_distance(), rhumb);
+        assert_eq!(route.cumulative_distances()[2], rhumb);
+
+        assert_eq!(route.remove(1), Some((0.0, 1.0)));
+        assert_eq!(route.remove(5), None);
+        assert_eq!(route.points().len(), 2);
+    }
+
+    #[test]
+    fn drag_and_hit_test() {
+        let (width, height) = (1000.0, 800.0);
+        let view = crate::map::TileView::new(10.0, 20.0, 10.0, width);
+        let mut route = WaypointRoute::default();
+        route.push(10.0, 20.0);
+
+        let center = DVec2::new(width, height) / 2.0;
+        assert_eq!(
+            route.hit_test(&view, center + DVec2::new(3.0, -4.0), width, height),
+            Some(0)
+        );
+        assert_eq!(
+            route.hit_test(&view, center + DVec2::new(30.0, 0.0), width, height),
+            None
+        );
+
+        assert!(route.start_drag(0));
+        let (lat, lng) = view.pixel_to_lat_long(DVec2::new(0.0, 0.0), width, height);
+        route.drag_to(lat, lng);
+        assert!(route.end_drag());
+        assert!(!route.end_drag());
+        assert_eq!(route.points()[0], (lat, lng));
+        assert!(!route.start_drag(1));
+    }
+}